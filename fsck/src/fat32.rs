@@ -0,0 +1,801 @@
+//! FAT32 volumes in an image, and the consistency check of one
+//!
+//! The check walks the boot sector, every FAT copy and the directory tree
+//! from the root. A volume is consistent when its FAT copies agree, every
+//! chain reached from the tree stays in the data clusters and ends with an
+//! end of chain mark, no cluster is in two chains, every allocated cluster
+//! is reached, the size of a file matches the length of its chain, the
+//! `.` and `..` entries of a directory point at it and its parent, every
+//! long name entry belongs to a short one, and FSInfo counts the free
+//! clusters right.
+//!
+//! Each [`Problem`] knows how to repair itself, mostly by giving up data:
+//! broken chains are cut where they break, and clusters nothing reaches
+//! are freed.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+pub const SECTOR_SZ: usize = 512;
+const DIRENT_SZ: usize = 32;
+const DIRENT_FREE: u8 = 0xe5;
+const DIRENT_END: u8 = 0x00;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const LAST_LONG_ENTRY: u8 = 0x40;
+const LFN_CHAR_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const FAT_MASK: u32 = 0x0fff_ffff;
+/// FAT entries from here on mark the end of a chain
+const FAT_EOC: u32 = 0x0fff_fff8;
+const FAT_BAD: u32 = 0x0fff_fff7;
+/// FSInfo's way of saying the free count is unknown
+const UNKNOWN_FREE: u32 = 0xffff_ffff;
+/// Leaked clusters listed one by one, the rest are only counted
+const LEAKS_LISTED: usize = 8;
+
+pub fn le16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+pub fn le32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Bytes to write at a byte position of the image
+pub type Patch = (usize, Vec<u8>);
+
+/// Geometry of a FAT32 volume
+pub struct Volume<'a> {
+    img: &'a [u8],
+    sectors_per_cluster: usize,
+    pub reserved: usize,
+    num_fats: usize,
+    fat_sectors: usize,
+    data_start: usize,
+    root_cluster: u32,
+    cluster_count: u32,
+    fsinfo: usize,
+}
+
+impl<'a> Volume<'a> {
+    /// Parse the boot sector of `img`
+    pub fn parse(img: &'a [u8]) -> Result<Self, String> {
+        if img.len() < SECTOR_SZ || le16(img, 510) != 0xaa55 {
+            return Err("no boot sector".into());
+        }
+        if le16(img, 11) as usize != SECTOR_SZ {
+            return Err("only 512 byte sectors are supported".into());
+        }
+        let sectors_per_cluster = img[13] as usize;
+        let reserved = le16(img, 14) as usize;
+        let num_fats = img[16] as usize;
+        let fat_sectors = le32(img, 36) as usize;
+        if !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || num_fats == 0
+            || le16(img, 17) != 0
+            || le16(img, 22) != 0
+            || fat_sectors == 0
+        {
+            return Err("not a FAT32 volume".into());
+        }
+        let total_sectors = match le16(img, 19) {
+            0 => le32(img, 32) as usize,
+            n => n as usize,
+        }
+        .min(img.len() / SECTOR_SZ);
+        let data_start = reserved + num_fats * fat_sectors;
+        if total_sectors <= data_start {
+            return Err("no room for data clusters".into());
+        }
+        let cluster_count = ((total_sectors - data_start) / sectors_per_cluster)
+            .min(fat_sectors * SECTOR_SZ / 4 - 2) as u32;
+        let root_cluster = le32(img, 44);
+        if !(2..cluster_count + 2).contains(&root_cluster) {
+            return Err(format!("root cluster {} out of range", root_cluster));
+        }
+        Ok(Self {
+            img,
+            sectors_per_cluster,
+            reserved,
+            num_fats,
+            fat_sectors,
+            data_start,
+            root_cluster,
+            cluster_count,
+            fsinfo: le16(img, 48) as usize,
+        })
+    }
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster * SECTOR_SZ
+    }
+    fn in_range(&self, cluster: u32) -> bool {
+        (2..self.cluster_count + 2).contains(&cluster)
+    }
+    /// Byte position of the FAT entry of `cluster` in FAT copy `copy`
+    fn fat_pos(&self, copy: usize, cluster: u32) -> usize {
+        (self.reserved + copy * self.fat_sectors) * SECTOR_SZ + cluster as usize * 4
+    }
+    /// FAT entry of `cluster` in FAT copy `copy`
+    fn fat(&self, copy: usize, cluster: u32) -> u32 {
+        le32(self.img, self.fat_pos(copy, cluster)) & FAT_MASK
+    }
+    /// Set the FAT entry of `cluster` in every copy
+    fn set_fat(&self, cluster: u32, value: u32) -> Vec<Patch> {
+        (0..self.num_fats)
+            .map(|copy| {
+                let pos = self.fat_pos(copy, cluster);
+                // the high 4 bits are reserved and kept as they are
+                let entry = (le32(self.img, pos) & !FAT_MASK) | value;
+                (pos, entry.to_le_bytes().to_vec())
+            })
+            .collect()
+    }
+    fn cluster_pos(&self, cluster: u32) -> usize {
+        (self.data_start + (cluster as usize - 2) * self.sectors_per_cluster) * SECTOR_SZ
+    }
+    /// Clusters of the chain starting at `first`, and what breaks it if
+    /// it's broken. A free or bad cluster isn't part of any chain.
+    fn chain(&self, first: u32) -> (Vec<u32>, Option<String>) {
+        let mut clusters = Vec::new();
+        let mut seen = BTreeSet::new();
+        let mut cluster = first;
+        let broken = loop {
+            if !self.in_range(cluster) {
+                break format!("cluster {} is out of range", cluster);
+            }
+            if !seen.insert(cluster) {
+                break format!("chain loops at cluster {}", cluster);
+            }
+            match self.fat(0, cluster) {
+                0 => break format!("cluster {} is free", cluster),
+                FAT_BAD => break format!("cluster {} is bad", cluster),
+                next => {
+                    clusters.push(cluster);
+                    if next >= FAT_EOC {
+                        return (clusters, None);
+                    }
+                    cluster = next;
+                }
+            }
+        };
+        (clusters, Some(broken))
+    }
+}
+
+/// An entry of a directory
+struct Entry {
+    name: String,
+    attr: u8,
+    first_cluster: u32,
+    size: u32,
+    /// byte positions of the short entry and of its long name entries
+    pos: usize,
+    long_slots: Vec<usize>,
+}
+
+impl Entry {
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+}
+
+/// What a directory holds
+struct Listing {
+    entries: Vec<Entry>,
+    /// byte positions and first clusters of the first two entries, if
+    /// they're `.` and `..`
+    dots: Option<[(usize, u32); 2]>,
+    /// long name entries belonging to no short entry
+    orphans: Vec<usize>,
+}
+
+fn first_cluster(raw: &[u8]) -> u32 {
+    (le16(raw, 20) as u32) << 16 | le16(raw, 26) as u32
+}
+
+/// Read the directory whose clusters are `clusters`
+fn list(volume: &Volume, clusters: &[u32]) -> Listing {
+    let mut listing = Listing {
+        entries: Vec::new(),
+        dots: None,
+        orphans: Vec::new(),
+    };
+    let mut dots = Vec::new();
+    // long name pieces seen since the last short entry
+    let mut pieces: Vec<(usize, u8, u8, Vec<u16>)> = Vec::new();
+    let slots = clusters.iter().flat_map(|cluster| {
+        let base = volume.cluster_pos(*cluster);
+        (0..volume.cluster_size() / DIRENT_SZ).map(move |i| base + i * DIRENT_SZ)
+    });
+    for (i, pos) in slots.enumerate() {
+        let raw = &volume.img[pos..pos + DIRENT_SZ];
+        if raw[0] == DIRENT_END || raw[0] == DIRENT_FREE {
+            listing
+                .orphans
+                .extend(pieces.drain(..).map(|piece| piece.0));
+            if raw[0] == DIRENT_END {
+                break;
+            }
+            continue;
+        }
+        if raw[11] & 0x3f == ATTR_LONG_NAME {
+            if raw[0] & LAST_LONG_ENTRY != 0 {
+                listing
+                    .orphans
+                    .extend(pieces.drain(..).map(|piece| piece.0));
+            }
+            let chars = LFN_CHAR_OFFSETS.iter().map(|o| le16(raw, *o)).collect();
+            pieces.push((pos, raw[0], raw[13], chars));
+            continue;
+        }
+        let short = &raw[..11];
+        if (i == 0 && short == b".          ") || (i == 1 && short == b"..         ") {
+            dots.push((pos, first_cluster(raw)));
+        }
+        let sum = short
+            .iter()
+            .fold(0u8, |sum, c| (sum >> 1 | sum << 7).wrapping_add(*c));
+        // a full set comes last first, counting down to 1
+        let count = pieces.len();
+        let complete = count > 0
+            && pieces[0].1 & LAST_LONG_ENTRY != 0
+            && pieces.iter().enumerate().all(|(i, (_, seq, checksum, _))| {
+                (*seq & 0x1f) as usize == count - i && *checksum == sum
+            });
+        let pieces = std::mem::take(&mut pieces);
+        if !complete {
+            listing.orphans.extend(pieces.iter().map(|piece| piece.0));
+        }
+        if raw[11] & ATTR_VOLUME_ID != 0 || raw[0] == b'.' {
+            continue;
+        }
+        let name = if complete {
+            let units = pieces
+                .iter()
+                .rev()
+                .flat_map(|piece| piece.3.iter().copied())
+                .take_while(|unit| *unit != 0);
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        } else {
+            let base = String::from_utf8_lossy(&short[..8]).trim_end().to_string();
+            let ext = String::from_utf8_lossy(&short[8..]).trim_end().to_string();
+            if ext.is_empty() {
+                base
+            } else {
+                format!("{}.{}", base, ext)
+            }
+        };
+        listing.entries.push(Entry {
+            name,
+            attr: raw[11],
+            first_cluster: first_cluster(raw),
+            size: le32(raw, 28),
+            pos,
+            long_slots: if complete {
+                pieces.iter().map(|piece| piece.0).collect()
+            } else {
+                Vec::new()
+            },
+        });
+    }
+    if let [dot, dotdot] = dots[..] {
+        listing.dots = Some([dot, dotdot]);
+    }
+    listing
+}
+
+/// Where an entry is, for repairing it
+#[derive(Clone)]
+pub struct Slot {
+    path: String,
+    /// byte position of the short entry, `None` for the root directory
+    pos: Option<usize>,
+    long_slots: Vec<usize>,
+    is_dir: bool,
+    size: u32,
+}
+
+/// Something wrong with a volume
+pub enum Problem {
+    /// A FAT copy differs from FAT #1
+    FatMismatch { copy: usize, cluster: u32 },
+    /// The chain of an entry leaves the data clusters, runs into a free or
+    /// bad cluster, or loops; `keep` is the part before that
+    Dangling {
+        slot: Slot,
+        reason: String,
+        keep: Vec<u32>,
+    },
+    /// A cluster of an entry's chain is in another chain too; `keep` is
+    /// the part before it
+    CrossLinked {
+        slot: Slot,
+        cluster: u32,
+        other: String,
+        keep: Vec<u32>,
+    },
+    /// The size of a file doesn't match the length of its chain
+    WrongSize { slot: Slot, clusters: Vec<u32> },
+    /// `.` or `..` of a directory points elsewhere, or is missing
+    WrongDots {
+        path: String,
+        first: u32,
+        parent: u32,
+    },
+    /// Long name entries belonging to no short entry
+    OrphanLongNames { path: String, slots: Vec<usize> },
+    /// Allocated clusters in no chain
+    Leaked(Vec<u32>),
+    /// FSInfo has the free cluster count wrong
+    FreeCount { counted: u32, free: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FatMismatch { copy, cluster } => write!(
+                f,
+                "FAT #{} differs from FAT #1 at cluster {}",
+                copy + 1,
+                cluster
+            ),
+            Self::Dangling { slot, reason, .. } => write!(f, "{}: {}", slot.path, reason),
+            Self::CrossLinked {
+                slot,
+                cluster,
+                other,
+                ..
+            } => write!(f, "{}: cluster {} is also in {}", slot.path, cluster, other),
+            Self::WrongSize { slot, clusters } => write!(
+                f,
+                "{}: size {} with {} clusters",
+                slot.path,
+                slot.size,
+                clusters.len()
+            ),
+            Self::WrongDots { path, .. } => write!(f, "{}: wrong `.` or `..`", path),
+            Self::OrphanLongNames { path, slots } => {
+                write!(f, "{}: {} stray long name entries", path, slots.len())
+            }
+            Self::Leaked(clusters) => {
+                let listed: Vec<String> = clusters
+                    .iter()
+                    .take(LEAKS_LISTED)
+                    .map(|c| c.to_string())
+                    .collect();
+                write!(
+                    f,
+                    "{} clusters allocated but not in use: {}",
+                    clusters.len(),
+                    listed.join(", ")
+                )?;
+                if clusters.len() > LEAKS_LISTED {
+                    write!(f, ", ...")?;
+                }
+                Ok(())
+            }
+            Self::FreeCount { counted, free } => write!(
+                f,
+                "FSInfo counts {} free clusters, there are {}",
+                counted, free
+            ),
+        }
+    }
+}
+
+impl Problem {
+    /// Writes repairing the problem on `volume`, `None` if it can't be
+    /// repaired
+    pub fn repair(&self, volume: &Volume) -> Option<Vec<Patch>> {
+        let cluster_size = volume.cluster_size() as u32;
+        let patches = match self {
+            Self::FatMismatch { copy, .. } => {
+                let len = volume.fat_sectors * SECTOR_SZ;
+                let first = volume.fat_pos(0, 0);
+                let fat = volume.img[first..first + len].to_vec();
+                vec![(volume.fat_pos(*copy, 0), fat)]
+            }
+            Self::Dangling { slot, keep, .. } | Self::CrossLinked { slot, keep, .. } => truncate(
+                volume,
+                slot,
+                keep,
+                slot.size.min(keep.len() as u32 * cluster_size),
+            )?,
+            Self::WrongSize { slot, clusters } => {
+                let needed = ((slot.size + cluster_size - 1) / cluster_size) as usize;
+                if needed < clusters.len() {
+                    truncate(volume, slot, &clusters[..needed], slot.size)?
+                } else {
+                    let size = clusters.len() as u32 * cluster_size;
+                    truncate(volume, slot, clusters, size)?
+                }
+            }
+            Self::WrongDots { first, parent, .. } => {
+                let base = volume.cluster_pos(*first);
+                let dot = short_entry(b".          ", *first);
+                let dotdot = short_entry(b"..         ", *parent);
+                vec![(base, dot.to_vec()), (base + DIRENT_SZ, dotdot.to_vec())]
+            }
+            Self::OrphanLongNames { slots, .. } => {
+                slots.iter().map(|pos| (*pos, vec![DIRENT_FREE])).collect()
+            }
+            Self::Leaked(clusters) => clusters
+                .iter()
+                .flat_map(|cluster| volume.set_fat(*cluster, 0))
+                .collect(),
+            Self::FreeCount { free, .. } => {
+                vec![(volume.fsinfo * SECTOR_SZ + 488, free.to_le_bytes().to_vec())]
+            }
+        };
+        Some(patches)
+    }
+}
+
+/// A directory entry named `name` starting at `first`
+fn short_entry(name: &[u8; 11], first: u32) -> [u8; DIRENT_SZ] {
+    let mut raw = [0u8; DIRENT_SZ];
+    raw[..11].copy_from_slice(name);
+    raw[11] = ATTR_DIRECTORY;
+    raw[20..22].copy_from_slice(&((first >> 16) as u16).to_le_bytes());
+    raw[26..28].copy_from_slice(&(first as u16).to_le_bytes());
+    raw
+}
+
+/// Cut the chain of the entry at `slot` down to `keep` and set its size,
+/// dropping a directory left without clusters. The clusters cut off are
+/// leaked, for the next pass to free.
+fn truncate(volume: &Volume, slot: &Slot, keep: &[u32], size: u32) -> Option<Vec<Patch>> {
+    let mut patches = Vec::new();
+    if let Some(last) = keep.last() {
+        patches.extend(volume.set_fat(*last, FAT_MASK));
+    }
+    match (slot.pos, keep.first()) {
+        // the root can lose clusters but not its first
+        (None, first) => {
+            first?;
+        }
+        (Some(pos), None) if slot.is_dir => {
+            for pos in slot.long_slots.iter().chain(Some(&pos)) {
+                patches.push((*pos, vec![DIRENT_FREE]));
+            }
+        }
+        (Some(pos), first) => {
+            let first = first.copied().unwrap_or(0);
+            let size = if slot.is_dir { 0 } else { size };
+            patches.push((pos + 20, ((first >> 16) as u16).to_le_bytes().to_vec()));
+            patches.push((pos + 26, (first as u16).to_le_bytes().to_vec()));
+            patches.push((pos + 28, size.to_le_bytes().to_vec()));
+        }
+    }
+    Some(patches)
+}
+
+/// Problems found on `volume`, empty if it's consistent
+pub fn check(volume: &Volume) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for copy in 1..volume.num_fats {
+        if let Some(cluster) =
+            (0..volume.cluster_count + 2).find(|c| volume.fat(copy, *c) != volume.fat(0, *c))
+        {
+            problems.push(Problem::FatMismatch { copy, cluster });
+        }
+    }
+    // path owning each cluster reached
+    let mut owners: HashMap<u32, String> = HashMap::new();
+    let root = Slot {
+        path: String::from("/"),
+        pos: None,
+        long_slots: Vec::new(),
+        is_dir: true,
+        size: 0,
+    };
+    // directories to walk, with the first cluster of their parent
+    let mut dirs = vec![(root, volume.root_cluster, 0)];
+    while let Some((slot, first, parent)) = dirs.pop() {
+        // what's left of a broken directory is still walked
+        let (clusters, _) = claim(volume, &slot, first, &mut owners, &mut problems);
+        if clusters.is_empty() {
+            continue;
+        }
+        let listing = list(volume, &clusters);
+        if slot.pos.is_some() {
+            let right = [
+                (volume.cluster_pos(first), first),
+                (volume.cluster_pos(first) + DIRENT_SZ, parent),
+            ];
+            if listing.dots != Some(right) {
+                problems.push(Problem::WrongDots {
+                    path: slot.path.clone(),
+                    first,
+                    parent,
+                });
+            }
+        }
+        if !listing.orphans.is_empty() {
+            problems.push(Problem::OrphanLongNames {
+                path: slot.path.clone(),
+                slots: listing.orphans,
+            });
+        }
+        // `..` in a directory of the root points at cluster 0
+        let parent = if slot.pos.is_some() { first } else { 0 };
+        for entry in listing.entries {
+            let child = Slot {
+                path: format!("{}{}", slot.path, entry.name),
+                pos: Some(entry.pos),
+                long_slots: entry.long_slots.clone(),
+                is_dir: entry.is_dir(),
+                size: entry.size,
+            };
+            if entry.is_dir() {
+                let child = Slot {
+                    path: child.path + "/",
+                    ..child
+                };
+                dirs.push((child, entry.first_cluster, parent));
+                continue;
+            }
+            let (clusters, sound) = if entry.first_cluster == 0 {
+                (Vec::new(), true)
+            } else {
+                claim(
+                    volume,
+                    &child,
+                    entry.first_cluster,
+                    &mut owners,
+                    &mut problems,
+                )
+            };
+            let cluster_size = volume.cluster_size();
+            if sound && clusters.len() != (entry.size as usize + cluster_size - 1) / cluster_size {
+                problems.push(Problem::WrongSize {
+                    slot: child,
+                    clusters,
+                });
+            }
+        }
+    }
+    let clusters = 2..volume.cluster_count + 2;
+    let leaked: Vec<u32> = clusters
+        .clone()
+        .filter(|c| volume.fat(0, *c) != 0 && !owners.contains_key(c))
+        .collect();
+    if !leaked.is_empty() {
+        problems.push(Problem::Leaked(leaked));
+    }
+    let free = clusters.filter(|c| volume.fat(0, *c) == 0).count() as u32;
+    if volume.fsinfo != 0 && volume.fsinfo < volume.reserved {
+        let info = &volume.img[volume.fsinfo * SECTOR_SZ..(volume.fsinfo + 1) * SECTOR_SZ];
+        let counted = le32(info, 488);
+        if le32(info, 0) == 0x4161_5252 && counted != UNKNOWN_FREE && counted != free {
+            problems.push(Problem::FreeCount { counted, free });
+        }
+    }
+    problems
+}
+
+/// Follow the chain of the entry at `slot` from `first` and record the
+/// entry as the owner of its clusters. Return the clusters it keeps, and
+/// whether that's the whole chain.
+fn claim(
+    volume: &Volume,
+    slot: &Slot,
+    first: u32,
+    owners: &mut HashMap<u32, String>,
+    problems: &mut Vec<Problem>,
+) -> (Vec<u32>, bool) {
+    let (mut clusters, broken) = volume.chain(first);
+    let sound = broken.is_none();
+    let shared = clusters
+        .iter()
+        .position(|cluster| owners.contains_key(cluster));
+    if let Some(i) = shared {
+        let cluster = clusters[i];
+        clusters.truncate(i);
+        problems.push(Problem::CrossLinked {
+            slot: slot.clone(),
+            cluster,
+            other: owners[&cluster].clone(),
+            keep: clusters.clone(),
+        });
+    } else if let Some(reason) = broken {
+        problems.push(Problem::Dangling {
+            slot: slot.clone(),
+            reason,
+            keep: clusters.clone(),
+        });
+    }
+    for cluster in clusters.iter() {
+        owners.insert(*cluster, slot.path.clone());
+    }
+    (clusters, sound && shared.is_none())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Reserved sectors of a test volume without a journal
+    pub const RESERVED: usize = 32;
+    const CLUSTERS: usize = 64;
+    const ATTR_ARCHIVE: u8 = 0x20;
+
+    /// An empty volume of one sector clusters with two one sector FATs,
+    /// and FSInfo in sector 1
+    pub fn format(reserved: usize) -> Vec<u8> {
+        let total = reserved + 2 + CLUSTERS;
+        let mut img = vec![0u8; total * SECTOR_SZ];
+        img[..3].copy_from_slice(&[0xeb, 0x58, 0x90]);
+        img[11..13].copy_from_slice(&(SECTOR_SZ as u16).to_le_bytes());
+        img[13] = 1;
+        img[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+        img[16] = 2;
+        img[21] = 0xf8;
+        img[32..36].copy_from_slice(&(total as u32).to_le_bytes());
+        img[36..40].copy_from_slice(&1u32.to_le_bytes());
+        img[44..48].copy_from_slice(&2u32.to_le_bytes());
+        img[48..50].copy_from_slice(&1u16.to_le_bytes());
+        img[510..512].copy_from_slice(&[0x55, 0xaa]);
+        let info = &mut img[SECTOR_SZ..2 * SECTOR_SZ];
+        info[..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+        info[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+        info[510..512].copy_from_slice(&[0x55, 0xaa]);
+        set_fat(&mut img, 0, 0x0fff_fff8);
+        set_fat(&mut img, 1, FAT_MASK);
+        // the root directory
+        set_fat(&mut img, 2, FAT_MASK);
+        sync_free(&mut img);
+        img
+    }
+
+    fn apply(img: &mut [u8], patches: Vec<Patch>) {
+        for (pos, bytes) in patches {
+            img[pos..pos + bytes.len()].copy_from_slice(&bytes);
+        }
+    }
+
+    /// Set the FAT entry of `cluster` in both copies
+    pub fn set_fat(img: &mut [u8], cluster: u32, value: u32) {
+        let patches = Volume::parse(img).unwrap().set_fat(cluster, value);
+        apply(img, patches);
+    }
+
+    /// Make FSInfo count the free clusters right
+    pub fn sync_free(img: &mut [u8]) {
+        let volume = Volume::parse(img).unwrap();
+        let free = (2..volume.cluster_count + 2)
+            .filter(|c| volume.fat(0, *c) == 0)
+            .count() as u32;
+        img[SECTOR_SZ + 488..SECTOR_SZ + 492].copy_from_slice(&free.to_le_bytes());
+    }
+
+    /// Link `clusters` into a chain
+    pub fn link(img: &mut [u8], clusters: &[u32]) {
+        for pair in clusters.windows(2) {
+            set_fat(img, pair[0], pair[1]);
+        }
+        set_fat(img, *clusters.last().unwrap(), FAT_MASK);
+    }
+
+    /// Put a file entry in slot `index` of the root directory
+    pub fn add_file(img: &mut [u8], index: usize, name: &[u8; 11], first: u32, size: u32) {
+        let volume = Volume::parse(img).unwrap();
+        let pos = volume.cluster_pos(volume.root_cluster) + index * DIRENT_SZ;
+        let mut raw = short_entry(name, first);
+        raw[11] = ATTR_ARCHIVE;
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        img[pos..pos + DIRENT_SZ].copy_from_slice(&raw);
+    }
+
+    /// A volume with files `A` in clusters 3 and 4 and `B` in clusters 5
+    /// and 6
+    fn two_files() -> Vec<u8> {
+        let mut img = format(RESERVED);
+        link(&mut img, &[3, 4]);
+        link(&mut img, &[5, 6]);
+        add_file(&mut img, 0, b"A          ", 3, 1000);
+        add_file(&mut img, 1, b"B          ", 5, 1000);
+        sync_free(&mut img);
+        img
+    }
+
+    fn problems(img: &[u8]) -> Vec<Problem> {
+        check(&Volume::parse(img).unwrap())
+    }
+
+    /// Repair pass after pass like `fsck check --repair`, and return
+    /// whether the volume ended up consistent
+    fn repair(img: &mut [u8]) -> bool {
+        for _ in 0..8 {
+            let patches: Vec<Patch> = {
+                let volume = Volume::parse(img).unwrap();
+                check(&volume)
+                    .iter()
+                    .filter_map(|problem| problem.repair(&volume))
+                    .flatten()
+                    .collect()
+            };
+            if patches.is_empty() {
+                break;
+            }
+            apply(img, patches);
+        }
+        problems(img).is_empty()
+    }
+
+    #[test]
+    fn consistent() {
+        assert!(problems(&format(RESERVED)).is_empty());
+        assert!(problems(&two_files()).is_empty());
+    }
+
+    #[test]
+    fn cross_linked() {
+        let mut img = two_files();
+        // B runs into the second cluster of A
+        set_fat(&mut img, 5, 4);
+        set_fat(&mut img, 6, 0);
+        sync_free(&mut img);
+        let found = problems(&img);
+        assert!(found.iter().any(|problem| matches!(
+            problem,
+            Problem::CrossLinked { cluster: 4, keep, .. } if keep == &[5]
+        )));
+        assert!(repair(&mut img));
+        let volume = Volume::parse(&img).unwrap();
+        assert_eq!(volume.chain(3), (vec![3, 4], None));
+        assert_eq!(volume.chain(5), (vec![5], None));
+    }
+
+    #[test]
+    fn bad_chain_end() {
+        let mut img = two_files();
+        // A runs into a free cluster, and B loops
+        set_fat(&mut img, 4, 7);
+        set_fat(&mut img, 6, 5);
+        let found = problems(&img);
+        assert_eq!(
+            found
+                .iter()
+                .filter(|problem| matches!(problem, Problem::Dangling { .. }))
+                .count(),
+            2
+        );
+        assert!(repair(&mut img));
+        let volume = Volume::parse(&img).unwrap();
+        assert_eq!(volume.chain(3), (vec![3, 4], None));
+        assert_eq!(volume.chain(5), (vec![5, 6], None));
+    }
+
+    #[test]
+    fn free_count() {
+        let mut img = two_files();
+        img[SECTOR_SZ + 488..SECTOR_SZ + 492].copy_from_slice(&7u32.to_le_bytes());
+        let found = problems(&img);
+        assert!(matches!(
+            found[..],
+            [Problem::FreeCount {
+                counted: 7,
+                free: 59
+            }]
+        ));
+        assert!(repair(&mut img));
+        // a count FSInfo doesn't know is no problem
+        img[SECTOR_SZ + 488..SECTOR_SZ + 492].copy_from_slice(&UNKNOWN_FREE.to_le_bytes());
+        assert!(problems(&img).is_empty());
+    }
+
+    #[test]
+    fn long_chain() {
+        let mut img = format(RESERVED);
+        let clusters: Vec<u32> = (3..CLUSTERS as u32 + 2).collect();
+        link(&mut img, &clusters);
+        let size = (clusters.len() * SECTOR_SZ) as u32;
+        add_file(&mut img, 0, b"BIG        ", 3, size);
+        sync_free(&mut img);
+        assert!(problems(&img).is_empty());
+    }
+}
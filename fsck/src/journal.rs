@@ -0,0 +1,238 @@
+//! The kernel's metadata journal in the reserved sectors of a volume
+//!
+//! ```text
+//! | superblock | descriptor (ids ...) | block images ... | commit | unused |
+//! ```
+//!
+//! The superblock holds the log size and the sequence number the logged
+//! transaction must carry to count. This has to match
+//! `os/src/fs/journal.rs`.
+use crate::fat32::{le32, Volume, SECTOR_SZ};
+
+/// Sector of the superblock
+const JOURNAL_START: usize = 16;
+const JOURNAL_MAGIC: u32 = 0x4c4e_524a;
+const DESCRIPTOR_MAGIC: u32 = 0x4353_4544;
+const COMMIT_MAGIC: u32 = 0x544d_4f43;
+const DESCRIPTOR_HEADER: usize = 12;
+const MIN_LOG_BLOCKS: usize = 64;
+
+fn put32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn descriptor_blocks(count: usize) -> usize {
+    (DESCRIPTOR_HEADER + count * 4 + SECTOR_SZ - 1) / SECTOR_SZ
+}
+
+fn log_len(count: usize) -> usize {
+    descriptor_blocks(count) + count + 1
+}
+
+fn checksum<'a>(sequence: u32, blocks: impl Iterator<Item = (u32, &'a [u8])>) -> u32 {
+    let mut hash = 0x811c_9dc5u32;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash = (hash ^ *byte as u32).wrapping_mul(0x0100_0193);
+        }
+    };
+    feed(&sequence.to_le_bytes());
+    for (block_id, data) in blocks {
+        feed(&block_id.to_le_bytes());
+        feed(data);
+    }
+    hash
+}
+
+fn sector(img: &[u8], sector: usize) -> &[u8] {
+    &img[sector * SECTOR_SZ..(sector + 1) * SECTOR_SZ]
+}
+
+fn sector_mut(img: &mut [u8], sector: usize) -> &mut [u8] {
+    &mut img[sector * SECTOR_SZ..(sector + 1) * SECTOR_SZ]
+}
+
+/// What replaying the journal did
+#[derive(Debug, PartialEq, Eq)]
+pub enum Replay {
+    /// The volume has no journal
+    NoJournal,
+    /// Nothing was logged, or the log is stale
+    Clean,
+    /// A transaction without a valid commit was dropped
+    Discarded,
+    /// A committed transaction of this many blocks was copied home
+    Replayed(usize),
+}
+
+/// The journal of a volume
+pub struct Journal {
+    start: usize,
+    blocks: usize,
+    sequence: u32,
+}
+
+impl Journal {
+    /// The journal of the volume in `img`, `None` if it has none
+    pub fn find(img: &[u8]) -> Result<Option<Self>, String> {
+        let volume = Volume::parse(img)?;
+        let superblock = sector(img, JOURNAL_START);
+        if volume.reserved < JOURNAL_START + 1 + MIN_LOG_BLOCKS
+            || le32(superblock, 0) != JOURNAL_MAGIC
+        {
+            return Ok(None);
+        }
+        let blocks = (le32(superblock, 4) as usize)
+            .clamp(MIN_LOG_BLOCKS, volume.reserved - JOURNAL_START - 1);
+        Ok(Some(Self {
+            start: JOURNAL_START,
+            blocks,
+            sequence: le32(superblock, 8),
+        }))
+    }
+    /// Length in blocks of the logged transaction, `None` if the log is
+    /// stale
+    pub fn logged(&self, img: &[u8]) -> Option<usize> {
+        let descriptor = sector(img, self.start + 1);
+        let count = le32(descriptor, 8) as usize;
+        (le32(descriptor, 0) == DESCRIPTOR_MAGIC
+            && le32(descriptor, 4) == self.sequence
+            && log_len(count) <= self.blocks)
+            .then(|| log_len(count))
+    }
+    /// Zero the log from block `keep` on, as if the kernel had stopped
+    /// writing there
+    pub fn truncate(&self, img: &mut [u8], keep: usize) {
+        for i in keep..self.blocks {
+            sector_mut(img, self.start + 1 + i).fill(0);
+        }
+    }
+    /// Copy a committed transaction home and leave the log stale, like the
+    /// kernel does at mount
+    pub fn replay(img: &mut [u8]) -> Result<Replay, String> {
+        let journal = match Self::find(img)? {
+            Some(journal) => journal,
+            None => return Ok(Replay::NoJournal),
+        };
+        let result = journal.replay_log(img);
+        put32(sector_mut(img, journal.start), 8, journal.sequence + 1);
+        Ok(result)
+    }
+    fn replay_log(&self, img: &mut [u8]) -> Replay {
+        if self.logged(img).is_none() {
+            return Replay::Clean;
+        }
+        let count = le32(sector(img, self.start + 1), 8) as usize;
+        let descriptor =
+            img[(self.start + 1) * SECTOR_SZ..][..descriptor_blocks(count) * SECTOR_SZ].to_vec();
+        let images: Vec<(u32, Vec<u8>)> = (0..count)
+            .map(|i| {
+                let id = le32(&descriptor, DESCRIPTOR_HEADER + i * 4);
+                let log_block = self.start + 1 + descriptor_blocks(count) + i;
+                (id, sector(img, log_block).to_vec())
+            })
+            .collect();
+        let commit = sector(img, self.start + log_len(count));
+        let sum = checksum(
+            self.sequence,
+            images.iter().map(|(id, data)| (*id, data.as_slice())),
+        );
+        if le32(commit, 0) != COMMIT_MAGIC
+            || le32(commit, 4) != self.sequence
+            || le32(commit, 8) as usize != count
+            || le32(commit, 12) != sum
+        {
+            return Replay::Discarded;
+        }
+        if images
+            .iter()
+            .any(|(id, _)| (*id as usize + 1) * SECTOR_SZ > img.len())
+        {
+            return Replay::Discarded;
+        }
+        for (id, data) in images {
+            sector_mut(img, id as usize).copy_from_slice(&data);
+        }
+        Replay::Replayed(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fat32::tests::{add_file, format, link, sync_free};
+    use crate::fat32::{check, Volume};
+
+    const RESERVED: usize = 96;
+    const SEQUENCE: u32 = 5;
+
+    /// A volume with a journal, holding a file whose allocation is on disk
+    /// but for the FSInfo update, which is in the log. The log is cut off
+    /// after the commit block, or has it with a wrong checksum if
+    /// `!committed`.
+    fn logged(committed: bool) -> Vec<u8> {
+        let mut img = format(RESERVED);
+        let superblock = sector_mut(&mut img, JOURNAL_START);
+        put32(superblock, 0, JOURNAL_MAGIC);
+        put32(superblock, 4, (RESERVED - JOURNAL_START - 1) as u32);
+        put32(superblock, 8, SEQUENCE);
+        let stale = sector(&img, 1).to_vec();
+        link(&mut img, &[3]);
+        add_file(&mut img, 0, b"A          ", 3, 100);
+        sync_free(&mut img);
+        let fsinfo = sector(&img, 1).to_vec();
+        sector_mut(&mut img, 1).copy_from_slice(&stale);
+        let descriptor = sector_mut(&mut img, JOURNAL_START + 1);
+        put32(descriptor, 0, DESCRIPTOR_MAGIC);
+        put32(descriptor, 4, SEQUENCE);
+        put32(descriptor, 8, 1);
+        put32(descriptor, DESCRIPTOR_HEADER, 1);
+        sector_mut(&mut img, JOURNAL_START + 2).copy_from_slice(&fsinfo);
+        let mut sum = checksum(SEQUENCE, [(1, fsinfo.as_slice())].into_iter());
+        if !committed {
+            sum = !sum;
+        }
+        let commit = sector_mut(&mut img, JOURNAL_START + 3);
+        put32(commit, 0, COMMIT_MAGIC);
+        put32(commit, 4, SEQUENCE);
+        put32(commit, 8, 1);
+        put32(commit, 12, sum);
+        img
+    }
+
+    fn consistent(img: &[u8]) -> bool {
+        check(&Volume::parse(img).unwrap()).is_empty()
+    }
+
+    #[test]
+    fn replayed() {
+        let mut img = logged(true);
+        assert!(!consistent(&img));
+        assert_eq!(Journal::replay(&mut img), Ok(Replay::Replayed(1)));
+        assert!(consistent(&img));
+        // the log is stale now
+        assert_eq!(Journal::replay(&mut img), Ok(Replay::Clean));
+    }
+
+    #[test]
+    fn discarded() {
+        let mut img = logged(false);
+        let before = img.clone();
+        assert_eq!(Journal::replay(&mut img), Ok(Replay::Discarded));
+        assert_eq!(sector(&img, 1), sector(&before, 1));
+    }
+
+    #[test]
+    fn cut_short() {
+        let img = logged(true);
+        let journal = Journal::find(&img).unwrap().unwrap();
+        assert_eq!(journal.logged(&img), Some(3));
+        for keep in 0..3 {
+            let mut copy = img.clone();
+            journal.truncate(&mut copy, keep);
+            let replay = Journal::replay(&mut copy).unwrap();
+            assert_ne!(replay, Replay::Replayed(1));
+            assert_eq!(sector(&copy, 1), sector(&img, 1));
+        }
+    }
+}
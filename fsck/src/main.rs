@@ -0,0 +1,178 @@
+//! Checker of the FAT32 images the kernel writes
+//!
+//! ```text
+//! fsck check IMAGE                  replay the journal in memory and check
+//! fsck check --repair IMAGE         replay the journal, repair what's wrong
+//!                                   and write the image back
+//! fsck crash-test IMAGE ROUNDS [SEED]
+//!                                   cut the logged transaction short at
+//!                                   random points and check each result
+//! ```
+//!
+//! The image is only written by `--repair`. The exit
+//! status is 1 when a volume is inconsistent, or still is after a repair,
+//! and 2 on bad usage or unreadable images.
+mod fat32;
+mod journal;
+
+use fat32::{Patch, Problem, Volume};
+use journal::{Journal, Replay};
+use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Repairs may leave problems for the next pass, e.g. clusters cut off a
+/// chain are only freed once nothing reaches them
+const REPAIR_PASSES: usize = 8;
+
+/// xorshift64, good enough to pick cut points
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: fsck check [--repair] IMAGE");
+    eprintln!("       fsck crash-test IMAGE ROUNDS [SEED]");
+    exit(2)
+}
+
+fn fail(err: impl std::fmt::Display) -> ! {
+    eprintln!("fsck: {}", err);
+    exit(2)
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&String>) -> T {
+    arg.and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(|| usage())
+}
+
+/// Problems of the volume in `img`
+fn check(img: &[u8]) -> Vec<Problem> {
+    fat32::check(&Volume::parse(img).unwrap_or_else(|err| fail(err)))
+}
+
+/// Repair the volume in `img` pass after pass, printing what's repaired
+fn repair(img: &mut [u8]) {
+    for _ in 0..REPAIR_PASSES {
+        let patches: Vec<Patch> = {
+            let volume = Volume::parse(img).unwrap_or_else(|err| fail(err));
+            let problems = fat32::check(&volume);
+            if problems.is_empty() {
+                return;
+            }
+            problems
+                .iter()
+                .filter_map(|problem| {
+                    let patches = problem.repair(&volume)?;
+                    println!("  repaired: {}", problem);
+                    Some(patches)
+                })
+                .flatten()
+                .collect()
+        };
+        if patches.is_empty() {
+            return;
+        }
+        for (pos, bytes) in patches {
+            img[pos..pos + bytes.len()].copy_from_slice(&bytes);
+        }
+    }
+}
+
+/// Replay the journal of `img` and check it, repairing it if `repair`,
+/// print what was found and return whether it's consistent
+fn replay_and_check(img: &mut [u8], label: &str, repair: bool) -> bool {
+    let replay = Journal::replay(img).unwrap_or_else(|err| fail(err));
+    if repair {
+        self::repair(img);
+    }
+    let problems = check(img);
+    let replay = match replay {
+        Replay::NoJournal => String::from("no journal"),
+        Replay::Clean => String::from("journal clean"),
+        Replay::Discarded => String::from("uncommitted transaction discarded"),
+        Replay::Replayed(count) => format!("{} blocks replayed", count),
+    };
+    if problems.is_empty() {
+        println!("{}: {}, consistent", label, replay);
+        return true;
+    }
+    println!("{}: {}, {} problems", label, replay, problems.len());
+    for problem in problems {
+        println!("  {}", problem);
+    }
+    false
+}
+
+fn crash_test(img: &[u8], rounds: usize, seed: u64) -> bool {
+    let journal = match Journal::find(img).unwrap_or_else(|err| fail(err)) {
+        Some(journal) => journal,
+        None => fail("the image has no journal"),
+    };
+    let len = match journal.logged(img) {
+        Some(len) => len,
+        None => {
+            println!("no transaction in the log, the kernel didn't crash");
+            return replay_and_check(&mut img.to_vec(), "as is", false);
+        }
+    };
+    println!("{} blocks logged, seed {}", len, seed);
+    let mut rng = Rng(seed.max(1));
+    // the whole log and none of it are always tried
+    let cuts = [0, len]
+        .into_iter()
+        .chain((0..rounds).map(|_| rng.next() as usize % (len + 1)));
+    let mut consistent = true;
+    for keep in cuts {
+        let mut copy = img.to_vec();
+        journal.truncate(&mut copy, keep);
+        let label = format!("{}/{} blocks", keep, len);
+        consistent &= replay_and_check(&mut copy, &label, false);
+    }
+    consistent
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let repair = args.get(1).map(String::as_str) == Some("--repair");
+    if repair {
+        args.remove(1);
+    }
+    if args.len() < 2 {
+        usage();
+    }
+    let path = &args[1];
+    let mut img = std::fs::read(path).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+    let write = |img: &[u8]| {
+        std::fs::write(path, img).unwrap_or_else(|err| fail(format!("{}: {}", path, err)))
+    };
+    let consistent = match args[0].as_str() {
+        "check" => {
+            let consistent = replay_and_check(&mut img, path, repair);
+            if repair {
+                write(&img);
+            }
+            consistent
+        }
+        "crash-test" => {
+            let rounds = parse(args.get(2));
+            let seed = match args.get(3) {
+                Some(_) => parse(args.get(3)),
+                None => SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(1, |time| time.as_nanos() as u64),
+            };
+            crash_test(&img, rounds, seed)
+        }
+        _ => usage(),
+    };
+    if !consistent {
+        exit(1);
+    }
+}
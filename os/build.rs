@@ -1,9 +1,12 @@
+use std::env;
 use std::fs::{read_dir, File};
 use std::io::{Result, Write};
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    println!("cargo:rerun-if-env-changed=INITRAMFS");
+    println!("cargo:rerun-if-env-changed=JOURNAL_CRASH_AFTER");
     insert_app_data().unwrap();
 }
 
@@ -63,5 +66,26 @@ app_{0}_end:"#,
             idx, app, TARGET_PATH
         )?;
     }
+
+    // a cpio archive unpacked as the root file system, when given
+    let initramfs = match env::var("INITRAMFS") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={}", path);
+            format!(r#"    .incbin "{}""#, path)
+        }
+        _ => String::new(),
+    };
+    writeln!(
+        f,
+        r#"
+    .section .data
+    .global _initramfs_start
+    .global _initramfs_end
+    .align 3
+_initramfs_start:
+{}
+_initramfs_end:"#,
+        initramfs
+    )?;
     Ok(())
 }
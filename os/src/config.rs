@@ -1,12 +1,21 @@
 //! Constants used in rCore
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// Largest user stack `RLIMIT_STACK` gets a thread
+pub const MAX_USER_STACK_SIZE: usize = 4096 * 256;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
+/// fds of a process are below this, the highest `RLIMIT_NOFILE` there is
+pub const MAX_FD: usize = 1024;
 
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
-pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// Trap context of thread 0, those of other threads are in the pages below
+pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
+/// `mmap` places mappings from here up, unless told otherwise
+pub const MMAP_BASE: usize = 0x10_0000_0000;
+/// End of the lower half of the address space, where user mappings live
+pub const USER_SPACE_END: usize = 0x40_0000_0000;
 
 pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
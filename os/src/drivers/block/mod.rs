@@ -0,0 +1,29 @@
+//! Block devices
+//!
+//! File systems on disk only see the [`BlockDevice`] trait, which reads and
+//! writes whole blocks of [`BLOCK_SZ`] bytes.
+mod virtio_blk;
+
+pub use virtio_blk::VirtIOBlock;
+
+use crate::board::BlockDeviceImpl;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Size of a block in bytes
+pub const BLOCK_SZ: usize = 512;
+
+/// Trait for block devices
+/// which reads and writes data in the unit of blocks
+pub trait BlockDevice: Send + Sync {
+    ///Read data form block to buffer
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    ///Write data from buffer to block
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
+
+lazy_static! {
+    /// The block device of the board, `None` if no disk is attached
+    pub static ref BLOCK_DEVICE: Option<Arc<dyn BlockDevice>> =
+        BlockDeviceImpl::probe().map(|dev| Arc::new(dev) as Arc<dyn BlockDevice>);
+}
@@ -0,0 +1,96 @@
+//! Block device of the QEMU virt machine, driven by `virtio-drivers`
+use super::BlockDevice;
+use crate::mm::{
+    frame_alloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum, VirtAddr,
+};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
+
+/// MMIO base of the first virtio device
+const VIRTIO0: usize = 0x10001000;
+/// `MagicValue` register, reads as "virt"
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+/// `DeviceID` of a block device
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+/// VirtIO block device
+pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
+
+lazy_static! {
+    /// Frames of the virtqueues
+    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.0
+            .exclusive_access()
+            .read_block(block_id, buf)
+            .expect("Error when reading VirtIOBlk");
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.0
+            .exclusive_access()
+            .write_block(block_id, buf)
+            .expect("Error when writing VirtIOBlk");
+    }
+}
+
+impl VirtIOBlock {
+    /// Initialize the device at `VIRTIO0`, `None` if no disk is attached there
+    pub fn probe() -> Option<Self> {
+        // QEMU leaves a virtio-mmio slot without a device with DeviceID 0
+        let regs = VIRTIO0 as *const u32;
+        let (magic, device_id) = unsafe { (regs.read_volatile(), regs.add(2).read_volatile()) };
+        if magic != VIRTIO_MAGIC || device_id != VIRTIO_DEVICE_BLOCK {
+            return None;
+        }
+        unsafe {
+            VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader))
+                .ok()
+                .map(|blk| Self(UPSafeCell::new(blk)))
+        }
+    }
+}
+
+/// Memory allocation and address translation for virtqueues
+pub struct VirtioHal;
+
+impl Hal for VirtioHal {
+    fn dma_alloc(pages: usize) -> usize {
+        let mut ppn_base = PhysPageNum(0);
+        for i in 0..pages {
+            let frame = frame_alloc().unwrap();
+            if i == 0 {
+                ppn_base = frame.ppn;
+            }
+            assert_eq!(frame.ppn.0, ppn_base.0 + i);
+            QUEUE_FRAMES.exclusive_access().push(frame);
+        }
+        let pa: PhysAddr = ppn_base.into();
+        pa.0
+    }
+
+    fn dma_dealloc(pa: usize, pages: usize) -> i32 {
+        let ppn_base: PhysPageNum = PhysAddr::from(pa).into();
+        let range = ppn_base.0..ppn_base.0 + pages;
+        // dropping the trackers gives the frames back to the allocator
+        QUEUE_FRAMES
+            .exclusive_access()
+            .retain(|frame| !range.contains(&frame.ppn.0));
+        0
+    }
+
+    fn phys_to_virt(addr: usize) -> usize {
+        addr
+    }
+
+    fn virt_to_phys(vaddr: usize) -> usize {
+        PageTable::from_token(kernel_token())
+            .translate_va(VirtAddr::from(vaddr))
+            .unwrap()
+            .0
+    }
+}
@@ -0,0 +1,91 @@
+//! Reading the flattened device tree the SBI passes to the kernel
+//!
+//! Only the properties of `/chosen` are looked up, which is where QEMU puts
+//! the location of the `-initrd` image. Nothing is allocated, so this works
+//! before the heap is set up.
+
+/// Magic number in the header of a device tree blob
+const FDT_MAGIC: u32 = 0xd00d_feed;
+/// Structure block tokens
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// Read a big-endian `u32` at `addr`
+fn be32(addr: usize) -> u32 {
+    u32::from_be(unsafe { (addr as *const u32).read_volatile() })
+}
+
+/// Length of the NUL-terminated string at `addr`
+fn strlen(addr: usize) -> usize {
+    let mut len = 0;
+    while unsafe { ((addr + len) as *const u8).read_volatile() } != 0 {
+        len += 1;
+    }
+    len
+}
+
+/// Whether the NUL-terminated string at `addr` is `s`
+fn str_eq(addr: usize, s: &str) -> bool {
+    let len = strlen(addr);
+    len == s.len() && unsafe { core::slice::from_raw_parts(addr as *const u8, len) } == s.as_bytes()
+}
+
+/// Value of property `name` of `/chosen` in the device tree at `dtb`
+fn chosen_property(dtb: usize, name: &str) -> Option<&'static [u8]> {
+    if dtb == 0 || dtb % 4 != 0 || be32(dtb) != FDT_MAGIC {
+        return None;
+    }
+    let strings = dtb + be32(dtb + 12) as usize;
+    let mut pos = dtb + be32(dtb + 8) as usize;
+    let end = pos + be32(dtb + 36) as usize;
+    let mut depth = 0;
+    let mut in_chosen = false;
+    while pos < end {
+        let token = be32(pos);
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                depth += 1;
+                in_chosen = depth == 2 && str_eq(pos, "chosen");
+                pos += (strlen(pos) + 4) & !3;
+            }
+            FDT_END_NODE => {
+                depth -= 1;
+                in_chosen = false;
+            }
+            FDT_PROP => {
+                let len = be32(pos) as usize;
+                let name_off = be32(pos + 4) as usize;
+                pos += 8;
+                if in_chosen && str_eq(strings + name_off, name) {
+                    return Some(unsafe { core::slice::from_raw_parts(pos as *const u8, len) });
+                }
+                pos += (len + 3) & !3;
+            }
+            FDT_NOP => {}
+            _ => break,
+        }
+    }
+    None
+}
+
+/// A 32 or 64 bit big-endian cell value
+fn cell_value(value: &[u8]) -> Option<usize> {
+    match value.len() {
+        4 | 8 => Some(value.iter().fold(0usize, |v, b| v << 8 | *b as usize)),
+        _ => None,
+    }
+}
+
+/// Physical address range of the initrd loaded by the boot loader
+pub fn initrd_range(dtb: usize) -> Option<(usize, usize)> {
+    let start = cell_value(chosen_property(dtb, "linux,initrd-start")?)?;
+    let end = cell_value(chosen_property(dtb, "linux,initrd-end")?)?;
+    if start < end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,92 @@
+//! Implementation of [`AppFs`], the applications linked into the kernel
+//!
+//! Each application built in `user/` appears as a read-only file named
+//! after it, so `sys_exec` loads them through the VFS like any other file.
+use super::{FileSystem, Inode, InodeType, Metadata};
+use crate::loader::{app_names, get_app_data_by_name};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The file system of linked applications
+pub struct AppFs;
+
+impl AppFs {
+    /// Create an appfs instance
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self)
+    }
+}
+
+impl FileSystem for AppFs {
+    fn fs_type(&self) -> &'static str {
+        "appfs"
+    }
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(AppInode::Root)
+    }
+}
+
+/// The root directory of an appfs or one of its applications
+pub enum AppInode {
+    /// The directory of applications
+    Root,
+    /// The ELF image of an application
+    App(&'static [u8]),
+}
+
+impl Inode for AppInode {
+    fn kind(&self) -> InodeType {
+        match self {
+            Self::Root => InodeType::Directory,
+            Self::App(_) => InodeType::File,
+        }
+    }
+    fn size(&self) -> usize {
+        match self {
+            Self::Root => 0,
+            Self::App(data) => data.len(),
+        }
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        match self {
+            Self::Root => 0,
+            Self::App(data) => {
+                let start = offset.min(data.len());
+                let len = buf.len().min(data.len() - start);
+                buf[..len].copy_from_slice(&data[start..start + len]);
+                len
+            }
+        }
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn clear(&self) {}
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        match self {
+            Self::Root => get_app_data_by_name(name).map(|data| Arc::new(Self::App(data)) as _),
+            Self::App(_) => None,
+        }
+    }
+    fn create(&self, _name: &str, _kind: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    fn ls(&self) -> Vec<String> {
+        match self {
+            Self::Root => app_names().iter().map(|name| name.to_string()).collect(),
+            Self::App(_) => Vec::new(),
+        }
+    }
+    fn metadata(&self) -> Metadata {
+        // anyone may run the applications
+        Metadata {
+            mode: 0o555,
+            uid: 0,
+            gid: 0,
+        }
+    }
+}
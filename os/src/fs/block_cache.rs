@@ -0,0 +1,156 @@
+//! A write-back cache of disk blocks shared by the disk file systems
+use crate::drivers::block::{BlockDevice, BLOCK_SZ};
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Cached block inside memory
+pub struct BlockCache {
+    /// cached block data
+    cache: [u8; BLOCK_SZ],
+    /// underlying block id
+    block_id: usize,
+    /// underlying block device
+    block_device: Arc<dyn BlockDevice>,
+    /// whether the block is dirty
+    modified: bool,
+}
+
+impl BlockCache {
+    /// Load a new BlockCache from disk.
+    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
+        let mut cache = [0u8; BLOCK_SZ];
+        block_device.read_block(block_id, &mut cache);
+        Self {
+            cache,
+            block_id,
+            block_device,
+            modified: false,
+        }
+    }
+    /// Read the block data through a closure
+    pub fn read<V>(&self, f: impl FnOnce(&[u8; BLOCK_SZ]) -> V) -> V {
+        f(&self.cache)
+    }
+    /// Modify the block data through a closure, marking the block dirty
+    pub fn modify<V>(&mut self, f: impl FnOnce(&mut [u8; BLOCK_SZ]) -> V) -> V {
+        self.modified = true;
+        f(&mut self.cache)
+    }
+    /// Write the block back if it is dirty
+    pub fn sync(&mut self) {
+        if self.modified {
+            self.modified = false;
+            self.block_device.write_block(self.block_id, &self.cache);
+        }
+    }
+}
+
+impl Drop for BlockCache {
+    fn drop(&mut self) {
+        self.sync()
+    }
+}
+
+/// Use a block cache of 16 blocks
+const BLOCK_CACHE_SIZE: usize = 16;
+
+/// Block caches indexed by device and block id, least recently used first
+#[derive(Default)]
+pub struct BlockCacheManager {
+    queue: VecDeque<(usize, usize, Arc<UPSafeCell<BlockCache>>)>,
+}
+
+impl BlockCacheManager {
+    /// Get the cache of a block, loading it and evicting an unused one if needed
+    pub fn get_block_cache(
+        &mut self,
+        block_id: usize,
+        block_device: Arc<dyn BlockDevice>,
+    ) -> Arc<UPSafeCell<BlockCache>> {
+        let device = Arc::as_ptr(&block_device) as *const u8 as usize;
+        if let Some(idx) = self
+            .queue
+            .iter()
+            .position(|(dev, id, _)| *dev == device && *id == block_id)
+        {
+            let entry = self.queue.remove(idx).unwrap();
+            let block_cache = entry.2.clone();
+            self.queue.push_back(entry);
+            return block_cache;
+        }
+        // substitute
+        if self.queue.len() == BLOCK_CACHE_SIZE {
+            // from front to tail
+            if let Some((idx, _)) = self
+                .queue
+                .iter()
+                .enumerate()
+                .find(|(_, pair)| Arc::strong_count(&pair.2) == 1)
+            {
+                self.queue.drain(idx..=idx);
+            } else {
+                panic!("Run out of BlockCache!");
+            }
+        }
+        // load block into mem and push back
+        let block_cache =
+            Arc::new(unsafe { UPSafeCell::new(BlockCache::new(block_id, block_device)) });
+        self.queue
+            .push_back((device, block_id, block_cache.clone()));
+        block_cache
+    }
+}
+
+lazy_static! {
+    /// The global block cache manager
+    pub static ref BLOCK_CACHE_MANAGER: UPSafeCell<BlockCacheManager> =
+        unsafe { UPSafeCell::new(BlockCacheManager::default()) };
+}
+
+/// Get the block cache corresponding to the given block id and block device
+pub fn get_block_cache(
+    block_id: usize,
+    block_device: Arc<dyn BlockDevice>,
+) -> Arc<UPSafeCell<BlockCache>> {
+    BLOCK_CACHE_MANAGER
+        .exclusive_access()
+        .get_block_cache(block_id, block_device)
+}
+
+/// Sync all block caches to their block devices
+pub fn block_cache_sync_all() {
+    let manager = BLOCK_CACHE_MANAGER.exclusive_access();
+    for (_, _, cache) in manager.queue.iter() {
+        cache.exclusive_access().sync();
+    }
+}
+
+/// Read bytes at a byte position of `block_device`, across blocks
+pub fn read_bytes(block_device: &Arc<dyn BlockDevice>, mut pos: usize, buf: &mut [u8]) {
+    let mut done = 0;
+    while done < buf.len() {
+        let offset = pos % BLOCK_SZ;
+        let len = (BLOCK_SZ - offset).min(buf.len() - done);
+        get_block_cache(pos / BLOCK_SZ, block_device.clone())
+            .exclusive_access()
+            .read(|data| buf[done..done + len].copy_from_slice(&data[offset..offset + len]));
+        done += len;
+        pos += len;
+    }
+}
+
+/// Write bytes at a byte position of `block_device`, across blocks
+pub fn write_bytes(block_device: &Arc<dyn BlockDevice>, mut pos: usize, buf: &[u8]) {
+    let mut done = 0;
+    while done < buf.len() {
+        let offset = pos % BLOCK_SZ;
+        let len = (BLOCK_SZ - offset).min(buf.len() - done);
+        get_block_cache(pos / BLOCK_SZ, block_device.clone())
+            .exclusive_access()
+            .modify(|data| data[offset..offset + len].copy_from_slice(&buf[done..done + len]));
+        done += len;
+        pos += len;
+    }
+}
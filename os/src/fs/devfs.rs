@@ -0,0 +1,315 @@
+//! Implementation of [`DevFs`], giving device files a path
+//!
+//! ```text
+//! /dev/console   the SBI console, read one char at a time
+//! /dev/null      discards writes, reads hit end of file
+//! /dev/zero      reads as an endless run of zero bytes
+//! /dev/random    reads as bytes from the kernel PRNG
+//! ```
+//!
+//! Opening a device node hands out the device itself as the [`File`].
+//!
+//! Console input is gathered on every timer tick as well as when it's read,
+//! so that typing the interrupt char, Ctrl-C, sends `SIGINT` to the
+//! foreground process group even while nobody reads, and the suspend char,
+//! Ctrl-Z, `SIGTSTP`. A process of another group trying to read is stopped
+//! by `SIGTTIN`.
+use super::{File, FileSystem, Inode, InodeType, PollEvents};
+use crate::mm::UserBuffer;
+use crate::random::fill_random;
+use crate::sbi::{console_getchar, console_putchar};
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EINTR, EIO};
+use crate::task::{
+    current_process, current_takes, send_group_signal, suspend_interruptible, SignalFlags,
+    TaskControlBlock,
+};
+use crate::timer::{add_timer, get_time_ms, MSEC_PER_TICK};
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// The interrupt char, Ctrl-C
+const INTR: u8 = 0x03;
+/// The suspend char, Ctrl-Z
+const SUSP: u8 = 0x1a;
+
+/// The console, over SBI `console_getchar`/`console_putchar`
+pub struct Console {
+    /// chars taken from SBI, not read yet
+    input: UPSafeCell<VecDeque<u8>>,
+    /// The process group signalled by the interrupt and suspend chars, and
+    /// allowed to read
+    foreground: UPSafeCell<usize>,
+}
+
+impl Console {
+    fn new() -> Self {
+        Self {
+            input: unsafe { UPSafeCell::new(VecDeque::new()) },
+            // the group of the first task
+            foreground: unsafe { UPSafeCell::new(0) },
+        }
+    }
+    /// Take the chars typed so far from SBI. The interrupt and suspend
+    /// chars aren't kept but send `SIGINT` and `SIGTSTP` to the foreground
+    /// process group.
+    pub fn gather_input(&self) {
+        let mut signals = SignalFlags::empty();
+        let mut input = self.input.exclusive_access();
+        while let Some(ch) = sbi_getchar() {
+            match ch {
+                INTR => signals |= SignalFlags::SIGINT,
+                SUSP => signals |= SignalFlags::SIGTSTP,
+                ch => input.push_back(ch),
+            }
+        }
+        drop(input);
+        for signal in [SignalFlags::SIGINT, SignalFlags::SIGTSTP] {
+            if signals.contains(signal) {
+                send_group_signal(self.foreground(), signal);
+            }
+        }
+    }
+    /// Send `SIGTTIN` to the process group of the caller, for reading while
+    /// it isn't in the foreground. Return `Err(EINTR)` to stop it then, or
+    /// `Err(EIO)` if the signal wouldn't.
+    fn check_foreground(&self) -> Result<(), isize> {
+        let pgid = current_process().inner_exclusive_access().pgid;
+        let foreground = self.foreground();
+        // nobody took the console yet
+        if foreground == 0 || pgid == foreground {
+            return Ok(());
+        }
+        if !current_takes(SignalFlags::SIGTTIN) {
+            return Err(EIO);
+        }
+        send_group_signal(pgid, SignalFlags::SIGTTIN);
+        Err(EINTR)
+    }
+    /// Take the next input char, if there is one
+    fn getchar(&self) -> Option<u8> {
+        self.gather_input();
+        self.input.exclusive_access().pop_front()
+    }
+    /// The foreground process group
+    pub fn foreground(&self) -> usize {
+        *self.foreground.exclusive_access()
+    }
+    /// Make `pgid` the foreground process group
+    pub fn set_foreground(&self, pgid: usize) {
+        *self.foreground.exclusive_access() = pgid;
+    }
+}
+
+/// The next char from the SBI console, if one has been typed
+fn sbi_getchar() -> Option<u8> {
+    match console_getchar() {
+        0 => None,
+        c => Some(c as u8),
+    }
+}
+
+/// `/dev/null`
+pub struct Null;
+
+/// `/dev/zero`
+pub struct Zero;
+
+/// `/dev/random`
+pub struct Random;
+
+impl File for Console {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> isize {
+        if user_buf.len() == 0 {
+            return 0;
+        }
+        if let Err(errno) = self.check_foreground() {
+            return -errno;
+        }
+        // busy loop
+        let ch = loop {
+            if let Some(ch) = self.getchar() {
+                break ch;
+            }
+            if let Err(errno) = suspend_interruptible() {
+                return -errno;
+            }
+        };
+        unsafe {
+            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
+        }
+        1
+    }
+    fn write(&self, user_buf: UserBuffer) -> isize {
+        // raw bytes: a UTF-8 sequence may be split across writes
+        for buffer in user_buf.buffers.iter() {
+            for &byte in buffer.iter() {
+                console_putchar(byte as usize);
+            }
+        }
+        user_buf.len() as isize
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        self.gather_input();
+        let mut ready = PollEvents::OUT;
+        if !self.input.exclusive_access().is_empty() {
+            ready |= PollEvents::IN;
+        }
+        events & ready
+    }
+    fn register_waker(&self, task: &Arc<TaskControlBlock>) {
+        // input doesn't interrupt, so look again on the next tick
+        add_timer(get_time_ms() + MSEC_PER_TICK, task.clone());
+    }
+    fn as_console(self: Arc<Self>) -> Option<Arc<Console>> {
+        Some(self)
+    }
+}
+
+impl File for Null {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _user_buf: UserBuffer) -> isize {
+        0
+    }
+    fn write(&self, user_buf: UserBuffer) -> isize {
+        user_buf.len() as isize
+    }
+}
+
+impl File for Zero {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> isize {
+        for buffer in user_buf.buffers.iter_mut() {
+            buffer.fill(0);
+        }
+        user_buf.len() as isize
+    }
+    fn write(&self, user_buf: UserBuffer) -> isize {
+        user_buf.len() as isize
+    }
+}
+
+impl File for Random {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> isize {
+        for buffer in user_buf.buffers.iter_mut() {
+            fill_random(buffer);
+        }
+        user_buf.len() as isize
+    }
+    fn write(&self, user_buf: UserBuffer) -> isize {
+        user_buf.len() as isize
+    }
+}
+
+lazy_static! {
+    /// The console shared by every task's stdin/stdout/stderr
+    pub static ref CONSOLE: Arc<Console> = Arc::new(Console::new());
+    /// Device nodes of a devfs, sorted by name
+    static ref DEVICES: Vec<(&'static str, Arc<dyn File>)> = {
+        let console: Arc<dyn File> = CONSOLE.clone();
+        let mut devices: Vec<(&'static str, Arc<dyn File>)> = Vec::new();
+        devices.push(("console", console));
+        devices.push(("null", Arc::new(Null)));
+        devices.push(("random", Arc::new(Random)));
+        devices.push(("zero", Arc::new(Zero)));
+        devices
+    };
+}
+
+/// The device file system
+pub struct DevFs;
+
+impl DevFs {
+    /// Create a devfs instance
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self)
+    }
+}
+
+impl FileSystem for DevFs {
+    fn fs_type(&self) -> &'static str {
+        "devfs"
+    }
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(DevInode::Root)
+    }
+}
+
+/// The root directory of a devfs or one of its device nodes
+pub enum DevInode {
+    /// `/dev`
+    Root,
+    /// A device node
+    Device(Arc<dyn File>),
+}
+
+impl Inode for DevInode {
+    fn kind(&self) -> InodeType {
+        match self {
+            Self::Root => InodeType::Directory,
+            Self::Device(_) => InodeType::CharDevice,
+        }
+    }
+    fn size(&self) -> usize {
+        0
+    }
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn clear(&self) {}
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        match self {
+            Self::Root => DEVICES
+                .iter()
+                .find(|(dev_name, _)| *dev_name == name)
+                .map(|(_, dev)| Arc::new(Self::Device(dev.clone())) as Arc<dyn Inode>),
+            Self::Device(_) => None,
+        }
+    }
+    fn create(&self, _name: &str, _kind: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    fn ls(&self) -> Vec<String> {
+        match self {
+            Self::Root => DEVICES.iter().map(|(name, _)| name.to_string()).collect(),
+            Self::Device(_) => Vec::new(),
+        }
+    }
+    fn device(&self) -> Option<Arc<dyn File>> {
+        match self {
+            Self::Root => None,
+            Self::Device(dev) => Some(dev.clone()),
+        }
+    }
+}
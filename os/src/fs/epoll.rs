@@ -0,0 +1,167 @@
+//! Implementation of [`Epoll`], a set of files watched for readiness
+//!
+//! An epoll instance is itself a [`File`] in the fd table. It remembers the
+//! files added to it by fd, with the events of interest and a word of user
+//! data, and reports them level-triggered: a file is reported for as long
+//! as it's ready. A file closed everywhere drops out of the set.
+use super::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EEXIST, EINVAL, ELOOP, ENOENT};
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+/// Operation of `epoll_ctl`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EpollCtl {
+    /// Watch a new fd
+    Add = 1,
+    /// Stop watching an fd
+    Del = 2,
+    /// Change the events and data of a watched fd
+    Mod = 3,
+}
+
+impl EpollCtl {
+    /// The operation numbered `op`
+    pub fn from_op(op: usize) -> Option<Self> {
+        match op {
+            1 => Some(Self::Add),
+            2 => Some(Self::Del),
+            3 => Some(Self::Mod),
+            _ => None,
+        }
+    }
+}
+
+/// `struct epoll_event`: events of interest or ready, and user data
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct EpollEvent {
+    /// [`PollEvents`] bits
+    pub events: u32,
+    /// Handed back with the events, e.g. the fd
+    pub data: u64,
+}
+
+/// A watched file
+struct Interest {
+    file: Weak<dyn File>,
+    event: EpollEvent,
+}
+
+/// An epoll instance
+pub struct Epoll {
+    /// watched files by fd
+    interests: UPSafeCell<BTreeMap<usize, Interest>>,
+}
+
+impl Epoll {
+    /// Create an epoll instance watching nothing
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            interests: unsafe { UPSafeCell::new(BTreeMap::new()) },
+        })
+    }
+    /// Add, change or remove the file `file` open at `fd`
+    pub fn ctl(
+        &self,
+        op: EpollCtl,
+        fd: usize,
+        file: Arc<dyn File>,
+        event: EpollEvent,
+    ) -> Result<(), isize> {
+        // an instance can't watch itself, or an instance watching it
+        if let Some(epoll) = file.clone().as_epoll() {
+            if core::ptr::eq(epoll.as_ref(), self) {
+                return Err(EINVAL);
+            }
+            if op == EpollCtl::Add && epoll.reaches(self) {
+                return Err(ELOOP);
+            }
+        }
+        let mut interests = self.interests.exclusive_access();
+        // an fd that was closed and opened again is a new file
+        let watched = interests
+            .get(&fd)
+            .and_then(|interest| interest.file.upgrade())
+            .map_or(false, |watched| Arc::ptr_eq(&watched, &file));
+        match op {
+            EpollCtl::Add if watched => return Err(EEXIST),
+            EpollCtl::Del | EpollCtl::Mod if !watched => return Err(ENOENT),
+            EpollCtl::Del => {
+                interests.remove(&fd);
+            }
+            EpollCtl::Add | EpollCtl::Mod => {
+                let file = Arc::downgrade(&file);
+                interests.insert(fd, Interest { file, event });
+            }
+        }
+        Ok(())
+    }
+    /// The watched files that are still open, with their events of interest
+    fn files(&self) -> Vec<(Arc<dyn File>, EpollEvent)> {
+        let mut interests = self.interests.exclusive_access();
+        interests.retain(|_, interest| interest.file.strong_count() > 0);
+        interests
+            .values()
+            .filter_map(|interest| Some((interest.file.upgrade()?, interest.event)))
+            .collect()
+    }
+    /// Events of at most `max` ready files
+    pub fn ready(&self, max: usize) -> Vec<EpollEvent> {
+        // polled without holding the set, which a nested instance may poll
+        self.files()
+            .into_iter()
+            .filter_map(|(file, event)| {
+                let events = file.poll(PollEvents::from_bits_truncate(event.events));
+                (!events.is_empty()).then(|| EpollEvent {
+                    events: events.bits(),
+                    data: event.data,
+                })
+            })
+            .take(max)
+            .collect()
+    }
+    /// Whether `target` is watched by this instance or an instance it
+    /// watches
+    fn reaches(&self, target: &Epoll) -> bool {
+        self.files().into_iter().any(|(file, _)| {
+            file.as_epoll().map_or(false, |epoll| {
+                core::ptr::eq(epoll.as_ref(), target) || epoll.reaches(target)
+            })
+        })
+    }
+}
+
+impl File for Epoll {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> isize {
+        -EINVAL
+    }
+    fn write(&self, _buf: UserBuffer) -> isize {
+        -EINVAL
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        if self.ready(1).is_empty() {
+            PollEvents::empty()
+        } else {
+            events & PollEvents::IN
+        }
+    }
+    fn register_waker(&self, task: &Arc<TaskControlBlock>) {
+        for (file, _) in self.files() {
+            file.register_waker(task);
+        }
+    }
+    fn as_epoll(self: Arc<Self>) -> Option<Arc<Epoll>> {
+        Some(self)
+    }
+}
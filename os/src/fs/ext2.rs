@@ -0,0 +1,363 @@
+//! Implementation of [`Ext2Fs`], read-only ext2 volumes on a block device
+//!
+//! ```text
+//! | boot | superblock | group descriptors | ... | group 0 inode table | ...
+//! ```
+//!
+//! Files are mapped through the direct, indirect, double and triple
+//! indirect blocks of their inode; a zero block number is a hole. Symbolic
+//! links are followed by the VFS, and short ones are read from the inode
+//! itself. Volumes with incompatible features such as extents are rejected.
+use super::block_cache::read_bytes;
+use super::{FileSystem, Inode, InodeType, Metadata};
+use crate::drivers::block::BlockDevice;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Byte position of the superblock
+const SUPERBLOCK_POS: usize = 1024;
+const EXT2_MAGIC: u16 = 0xef53;
+/// Inode number of the root directory
+const ROOT_INO: u32 = 2;
+/// Directory entries record the file type
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Flexible block groups only move metadata around
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+/// `i_mode` file types
+const S_IFMT: u16 = 0xf000;
+const S_IFCHR: u16 = 0x2000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFREG: u16 = 0x8000;
+const S_IFLNK: u16 = 0xa000;
+/// Direct blocks in `i_block`, followed by the indirect ones
+const DIRECT_BLOCKS: usize = 12;
+/// Bytes of `i_block`, which hold the target of a fast symlink
+const I_BLOCK_LEN: usize = 60;
+
+/// Read a little-endian `u16` at `offset`
+fn le16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Read a little-endian `u32` at `offset`
+fn le32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+/// Geometry of a mounted volume
+struct Volume {
+    block_device: Arc<dyn BlockDevice>,
+    block_size: usize,
+    inodes_per_group: u32,
+    inode_size: usize,
+    inodes_count: u32,
+    /// whether directory entries record the file type
+    filetype: bool,
+    /// first block of the inode table of each group
+    inode_tables: Vec<u32>,
+}
+
+impl Volume {
+    fn read_bytes(&self, pos: usize, buf: &mut [u8]) {
+        read_bytes(&self.block_device, pos, buf)
+    }
+    /// Block number at `index` of an indirect block
+    fn indirect(&self, block: u32, index: usize) -> u32 {
+        let mut buf = [0u8; 4];
+        self.read_bytes(block as usize * self.block_size + index * 4, &mut buf);
+        u32::from_le_bytes(buf)
+    }
+    /// Read the on-disk inode `ino`
+    fn disk_inode(&self, ino: u32) -> Option<DiskInode> {
+        if ino == 0 || ino > self.inodes_count {
+            return None;
+        }
+        let group = ((ino - 1) / self.inodes_per_group) as usize;
+        let index = ((ino - 1) % self.inodes_per_group) as usize;
+        let table = *self.inode_tables.get(group)? as usize;
+        let mut raw = [0u8; 128];
+        self.read_bytes(table * self.block_size + index * self.inode_size, &mut raw);
+        let mut block = [0u32; 15];
+        for (i, b) in block.iter_mut().enumerate() {
+            *b = le32(&raw, 40 + i * 4);
+        }
+        let mode = le16(&raw, 0);
+        let mut size = le32(&raw, 4) as u64;
+        if mode & S_IFMT == S_IFREG {
+            // `i_dir_acl` holds the high half of the size of a large file
+            size |= (le32(&raw, 108) as u64) << 32;
+        }
+        Some(DiskInode {
+            mode,
+            // the high halves of the ids are in `osd2`, as Linux lays it out
+            uid: le16(&raw, 2) as u32 | (le16(&raw, 120) as u32) << 16,
+            gid: le16(&raw, 24) as u32 | (le16(&raw, 122) as u32) << 16,
+            size: size as usize,
+            sectors: le32(&raw, 28),
+            file_acl: le32(&raw, 104),
+            block,
+        })
+    }
+}
+
+/// The fields of an on-disk inode the file system uses
+struct DiskInode {
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    size: usize,
+    /// `i_blocks`, counted in 512 byte sectors
+    sectors: u32,
+    file_acl: u32,
+    block: [u32; 15],
+}
+
+/// A read-only ext2 file system
+pub struct Ext2Fs {
+    volume: Arc<Volume>,
+}
+
+impl Ext2Fs {
+    /// Mount the ext2 volume on `block_device`, `None` if there isn't one
+    pub fn probe(block_device: Arc<dyn BlockDevice>) -> Option<Arc<Self>> {
+        let mut sb = [0u8; 1024];
+        read_bytes(&block_device, SUPERBLOCK_POS, &mut sb);
+        if le16(&sb, 56) != EXT2_MAGIC {
+            return None;
+        }
+        let inodes_count = le32(&sb, 0);
+        let blocks_count = le32(&sb, 4);
+        let first_data_block = le32(&sb, 20);
+        let log_block_size = le32(&sb, 24);
+        let blocks_per_group = le32(&sb, 32);
+        let inodes_per_group = le32(&sb, 40);
+        let (inode_size, incompat) = match le32(&sb, 76) {
+            0 => (128, 0),
+            _ => (le16(&sb, 88) as usize, le32(&sb, 96)),
+        };
+        if log_block_size > 6
+            || blocks_count <= first_data_block
+            || blocks_per_group == 0
+            || inodes_per_group == 0
+            || inode_size < 128
+            || incompat & !(INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG) != 0
+        {
+            println!(
+                "[kernel] ext2: unsupported volume, features {:#x}",
+                incompat
+            );
+            return None;
+        }
+        let block_size = 1024 << log_block_size;
+        let groups = (blocks_count - first_data_block + blocks_per_group - 1) / blocks_per_group;
+        // the descriptors start in the block after the superblock
+        let gdt = (first_data_block as usize + 1) * block_size;
+        let inode_tables = (0..groups as usize)
+            .map(|i| {
+                let mut desc = [0u8; 32];
+                read_bytes(&block_device, gdt + i * 32, &mut desc);
+                le32(&desc, 8)
+            })
+            .collect();
+        let volume = Volume {
+            block_device,
+            block_size,
+            inodes_per_group,
+            inode_size,
+            inodes_count,
+            filetype: incompat & INCOMPAT_FILETYPE != 0,
+            inode_tables,
+        };
+        if volume.disk_inode(ROOT_INO)?.mode & S_IFMT != S_IFDIR {
+            return None;
+        }
+        Some(Arc::new(Self {
+            volume: Arc::new(volume),
+        }))
+    }
+}
+
+impl FileSystem for Ext2Fs {
+    fn fs_type(&self) -> &'static str {
+        "ext2"
+    }
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Ext2Inode::open(&self.volume, ROOT_INO).unwrap()
+    }
+}
+
+/// A file, directory or symbolic link on an ext2 volume
+pub struct Ext2Inode {
+    volume: Arc<Volume>,
+    ino: u32,
+    disk_inode: DiskInode,
+}
+
+impl Ext2Inode {
+    fn open(volume: &Arc<Volume>, ino: u32) -> Option<Arc<dyn Inode>> {
+        let disk_inode = volume.disk_inode(ino)?;
+        Some(Arc::new(Self {
+            volume: volume.clone(),
+            ino,
+            disk_inode,
+        }))
+    }
+    /// Disk block holding block `index` of the file, 0 for a hole
+    fn block_id(&self, index: usize) -> u32 {
+        let per_block = self.volume.block_size / 4;
+        let mut index = index;
+        if index < DIRECT_BLOCKS {
+            return self.disk_inode.block[index];
+        }
+        index -= DIRECT_BLOCKS;
+        // single, double and triple indirect blocks in turn
+        let mut span = per_block;
+        for level in 1..=3 {
+            if index < span {
+                let mut block = self.disk_inode.block[DIRECT_BLOCKS + level - 1];
+                for depth in (0..level).rev() {
+                    if block == 0 {
+                        break;
+                    }
+                    let stride = per_block.pow(depth as u32);
+                    block = self.volume.indirect(block, index / stride % per_block);
+                }
+                return block;
+            }
+            index -= span;
+            span *= per_block;
+        }
+        0
+    }
+    /// Read the file data at `offset`, without looking at the file type
+    fn read_data(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let size = self.disk_inode.size;
+        if offset >= size {
+            return 0;
+        }
+        let block_size = self.volume.block_size;
+        let end = size.min(offset + buf.len());
+        let mut pos = offset;
+        while pos < end {
+            let in_block = pos % block_size;
+            let len = (block_size - in_block).min(end - pos);
+            let dst = &mut buf[pos - offset..pos - offset + len];
+            match self.block_id(pos / block_size) {
+                0 => dst.fill(0),
+                block => self
+                    .volume
+                    .read_bytes(block as usize * block_size + in_block, dst),
+            }
+            pos += len;
+        }
+        end - offset
+    }
+    /// `(name, inode number)` of each entry, without `.` and `..`
+    fn entries(&self) -> Vec<(String, u32)> {
+        let mut data = vec![0u8; self.disk_inode.size];
+        let len = self.read_data(0, &mut data);
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos + 8 <= len {
+            let ino = le32(&data, pos);
+            let rec_len = le16(&data, pos + 4) as usize;
+            let name_len = if self.volume.filetype {
+                data[pos + 6] as usize
+            } else {
+                le16(&data, pos + 6) as usize
+            };
+            if rec_len < 8 || pos + 8 + name_len > len {
+                break;
+            }
+            let name = String::from_utf8_lossy(&data[pos + 8..pos + 8 + name_len]);
+            if ino != 0 && name != "." && name != ".." {
+                entries.push((name.into_owned(), ino));
+            }
+            pos += rec_len;
+        }
+        entries
+    }
+}
+
+impl Inode for Ext2Inode {
+    fn kind(&self) -> InodeType {
+        match self.disk_inode.mode & S_IFMT {
+            S_IFDIR => InodeType::Directory,
+            S_IFLNK => InodeType::Symlink,
+            S_IFCHR => InodeType::CharDevice,
+            _ => InodeType::File,
+        }
+    }
+    fn id(&self) -> Option<(usize, usize)> {
+        Some((Arc::as_ptr(&self.volume) as usize, self.ino as usize))
+    }
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            mode: (self.disk_inode.mode & !S_IFMT) as u32,
+            uid: self.disk_inode.uid,
+            gid: self.disk_inode.gid,
+        }
+    }
+    fn size(&self) -> usize {
+        self.disk_inode.size
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if self.disk_inode.mode & S_IFMT != S_IFREG {
+            return 0;
+        }
+        self.read_data(offset, buf)
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn clear(&self) {}
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if self.kind() != InodeType::Directory {
+            return None;
+        }
+        let (_, ino) = self.entries().into_iter().find(|(n, _)| n == name)?;
+        Ext2Inode::open(&self.volume, ino)
+    }
+    fn create(&self, _name: &str, _kind: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    fn ls(&self) -> Vec<String> {
+        if self.kind() != InodeType::Directory {
+            return Vec::new();
+        }
+        self.entries().into_iter().map(|(name, _)| name).collect()
+    }
+    fn read_link(&self) -> Option<String> {
+        if self.kind() != InodeType::Symlink {
+            return None;
+        }
+        let size = self.disk_inode.size;
+        // a fast symlink has no data blocks, only an extended attribute
+        // block may be counted in `i_blocks`
+        let acl_sectors = match self.disk_inode.file_acl {
+            0 => 0,
+            _ => (self.volume.block_size / 512) as u32,
+        };
+        let mut target = vec![0u8; size];
+        if self.disk_inode.sectors == acl_sectors && size <= I_BLOCK_LEN {
+            let mut raw = [0u8; I_BLOCK_LEN];
+            for (i, b) in self.disk_inode.block.iter().enumerate() {
+                raw[i * 4..i * 4 + 4].copy_from_slice(&b.to_le_bytes());
+            }
+            target.copy_from_slice(&raw[..size]);
+        } else if self.read_data(0, &mut target) != size {
+            return None;
+        }
+        String::from_utf8(target).ok()
+    }
+}
@@ -0,0 +1,997 @@
+//! Implementation of [`Fat32Fs`], FAT32 volumes on a block device
+//!
+//! ```text
+//! | reserved (BPB, FSInfo, journal) | FAT #1 | FAT #2 ... | clusters 2, 3, ... |
+//! ```
+//!
+//! Every FAT copy is kept up to date, and the free cluster count and hint in
+//! FSInfo are maintained. Long file names are read and written, and names
+//! are looked up case-insensitively. Only 512 byte sectors are supported.
+//!
+//! A volume made with enough reserved sectors keeps a [`Journal`] in them,
+//! from sector [`JOURNAL_START`] on. Each operation changing the FAT,
+//! FSInfo or directory entries is then one transaction, so a crash leaves
+//! either all of its changes or none.
+//!
+//! An inode is named by the disk position of its short directory entry, so
+//! the size and first cluster are always read from the disk. FAT has no
+//! owners or permission bits: everything belongs to root and anyone may
+//! read, write and run it, as if mounted with `umask=0`.
+use super::block_cache::{block_cache_sync_all, get_block_cache, read_bytes, write_bytes};
+use super::journal::Journal;
+use super::{FileSystem, Inode, InodeType, Metadata};
+use crate::drivers::block::{BlockDevice, BLOCK_SZ};
+use crate::sync::UPSafeCell;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Size of a directory entry
+const DIRENT_SZ: usize = 32;
+/// Free entries and the end of a directory
+const DIRENT_FREE: u8 = 0xe5;
+const DIRENT_END: u8 = 0x00;
+/// Entry attributes
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0f;
+/// `NTRes` bits of names stored in lowercase
+const NTRES_LOWER_BASE: u8 = 0x08;
+const NTRES_LOWER_EXT: u8 = 0x10;
+/// Last long name entry of a set
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// UCS-2 characters held by a long name entry
+const LFN_CHARS: usize = 13;
+/// Offsets of those characters in the entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// FAT entries are 28 bits wide
+const FAT_MASK: u32 = 0x0fff_ffff;
+/// 2000-01-01, there is no RTC to stamp entries with
+const FAT_DATE: u16 = (20 << 9) | (1 << 5) | 1;
+/// First reserved sector of the journal, past the boot sector backup
+const JOURNAL_START: usize = 16;
+/// Blocks besides the FATs a transaction changes: FSInfo, the sectors of
+/// an entry set, the dot entries of a new directory and its parent's entry
+const TX_META_BLOCKS: usize = 6;
+/// Clusters a `create` allocates: two to grow the directory, one for a
+/// new directory
+const CREATE_CLUSTERS: usize = 3;
+
+/// Read a little-endian `u16` at `offset`
+fn le16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Read a little-endian `u32` at `offset`
+fn le32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+/// Free cluster bookkeeping, mirrored to FSInfo
+struct FreeInfo {
+    free_count: u32,
+    next_free: u32,
+}
+
+/// Geometry and allocation state of a mounted volume
+struct Volume {
+    block_device: Arc<dyn BlockDevice>,
+    sectors_per_cluster: usize,
+    /// first sector of FAT #1
+    fat_start: usize,
+    /// sectors per FAT
+    fat_sectors: usize,
+    num_fats: usize,
+    /// first sector of cluster 2
+    data_start: usize,
+    root_cluster: u32,
+    /// number of data clusters
+    cluster_count: u32,
+    /// sector of FSInfo, if it is valid
+    fsinfo_sector: Option<usize>,
+    free: UPSafeCell<FreeInfo>,
+    /// metadata journal, if there's room for one
+    journal: Option<Journal>,
+}
+
+/// A transaction on a volume, committed when dropped
+struct Transaction<'a>(&'a Volume);
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        match &self.0.journal {
+            Some(journal) => {
+                // the free counts followed the changes it dropped
+                if !journal.commit() {
+                    let free_count = self.0.count_free();
+                    self.0.free.exclusive_access().free_count = free_count;
+                }
+            }
+            None => block_cache_sync_all(),
+        }
+    }
+}
+
+impl Volume {
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster * BLOCK_SZ
+    }
+    /// Disk byte position of a cluster
+    fn cluster_pos(&self, cluster: u32) -> usize {
+        (self.data_start + (cluster as usize - 2) * self.sectors_per_cluster) * BLOCK_SZ
+    }
+    /// Clusters a transaction may allocate or free and still fit in the
+    /// log, each changing a FAT sector in every copy
+    fn tx_clusters(&self) -> usize {
+        match &self.journal {
+            Some(journal) => (journal.capacity().saturating_sub(TX_META_BLOCKS) / self.num_fats)
+                .saturating_sub(1),
+            None => usize::MAX,
+        }
+    }
+    /// Start a transaction, or join the running one
+    fn begin(&self) -> Transaction<'_> {
+        if let Some(journal) = &self.journal {
+            journal.begin();
+        }
+        Transaction(self)
+    }
+    fn read_bytes(&self, pos: usize, buf: &mut [u8]) {
+        match &self.journal {
+            Some(journal) => journal.read_bytes(pos, buf),
+            None => read_bytes(&self.block_device, pos, buf),
+        }
+    }
+    /// Write file data, which isn't journaled
+    fn write_bytes(&self, pos: usize, buf: &[u8]) {
+        match &self.journal {
+            Some(journal) => journal.write_data(pos, buf),
+            None => write_bytes(&self.block_device, pos, buf),
+        }
+    }
+    /// Write metadata, as part of the running transaction. `ENOSPC` if it
+    /// outgrows the log, which drops the whole transaction.
+    fn write_meta(&self, pos: usize, buf: &[u8]) -> Result<(), isize> {
+        match &self.journal {
+            Some(journal) => journal.write_meta(pos, buf),
+            None => {
+                write_bytes(&self.block_device, pos, buf);
+                Ok(())
+            }
+        }
+    }
+    /// Value of the FAT entry of a cluster
+    fn fat_get(&self, cluster: u32) -> u32 {
+        let mut buf = [0u8; 4];
+        self.read_bytes(self.fat_start * BLOCK_SZ + cluster as usize * 4, &mut buf);
+        u32::from_le_bytes(buf) & FAT_MASK
+    }
+    /// Set the FAT entry of a cluster in every FAT copy
+    fn fat_set(&self, cluster: u32, value: u32) -> Result<(), isize> {
+        for i in 0..self.num_fats {
+            let pos = (self.fat_start + i * self.fat_sectors) * BLOCK_SZ + cluster as usize * 4;
+            let mut buf = [0u8; 4];
+            self.read_bytes(pos, &mut buf);
+            // the high 4 bits are reserved and kept as they are
+            let entry = (u32::from_le_bytes(buf) & !FAT_MASK) | (value & FAT_MASK);
+            self.write_meta(pos, &entry.to_le_bytes())?;
+        }
+        Ok(())
+    }
+    /// Number of free clusters, counted in the FAT
+    fn count_free(&self) -> u32 {
+        (2..self.cluster_count + 2)
+            .filter(|c| self.fat_get(*c) == 0)
+            .count() as u32
+    }
+    /// Clusters of the chain starting at `first`
+    fn chain(&self, first: u32) -> Vec<u32> {
+        let mut clusters = Vec::new();
+        let mut cluster = first;
+        // end of chain marks and bad clusters fall outside the data clusters
+        while (2..self.cluster_count + 2).contains(&cluster)
+            && clusters.len() <= self.cluster_count as usize
+        {
+            clusters.push(cluster);
+            cluster = self.fat_get(cluster);
+        }
+        clusters
+    }
+    /// Allocate a zeroed cluster, appending it to the chain ending at `last`,
+    /// `None` if the volume is full
+    fn alloc_cluster(&self, last: Option<u32>) -> Result<Option<u32>, isize> {
+        let start = {
+            let free = self.free.exclusive_access();
+            if free.free_count == 0 {
+                return Ok(None);
+            }
+            free.next_free
+        };
+        let end = self.cluster_count + 2;
+        let start = if (2..end).contains(&start) { start } else { 2 };
+        let cluster = match (start..end).chain(2..start).find(|c| self.fat_get(*c) == 0) {
+            Some(cluster) => cluster,
+            None => return Ok(None),
+        };
+        // mark the end of the chain
+        self.fat_set(cluster, FAT_MASK)?;
+        if let Some(last) = last {
+            self.fat_set(last, cluster)?;
+        }
+        let zero = [0u8; BLOCK_SZ];
+        for i in 0..self.sectors_per_cluster {
+            self.write_bytes(self.cluster_pos(cluster) + i * BLOCK_SZ, &zero);
+        }
+        {
+            let mut free = self.free.exclusive_access();
+            free.free_count -= 1;
+            free.next_free = cluster + 1;
+        }
+        self.sync_fsinfo()?;
+        Ok(Some(cluster))
+    }
+    /// Free `clusters`, the tail of a chain
+    fn free_clusters(&self, clusters: &[u32]) -> Result<(), isize> {
+        for cluster in clusters.iter() {
+            self.fat_set(*cluster, 0)?;
+        }
+        self.free.exclusive_access().free_count += clusters.len() as u32;
+        self.sync_fsinfo()
+    }
+    /// Write the free cluster count and hint back to FSInfo
+    fn sync_fsinfo(&self) -> Result<(), isize> {
+        if let Some(sector) = self.fsinfo_sector {
+            let (free_count, next_free) = {
+                let free = self.free.exclusive_access();
+                (free.free_count, free.next_free)
+            };
+            self.write_meta(sector * BLOCK_SZ + 488, &free_count.to_le_bytes())?;
+            self.write_meta(sector * BLOCK_SZ + 492, &next_free.to_le_bytes())?;
+        }
+        Ok(())
+    }
+    /// Disk byte positions of the 32 byte slots of a directory
+    fn dir_slots(&self, first: u32) -> Vec<usize> {
+        let per_cluster = self.cluster_size() / DIRENT_SZ;
+        self.chain(first)
+            .into_iter()
+            .flat_map(|cluster| {
+                let base = self.cluster_pos(cluster);
+                (0..per_cluster).map(move |i| base + i * DIRENT_SZ)
+            })
+            .collect()
+    }
+    /// Entries of the directory starting at `first`, without `.` and `..`
+    fn dir_entries(&self, first: u32) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        // long name pieces seen since the last short entry
+        let mut long_name: Vec<(u8, [u16; LFN_CHARS])> = Vec::new();
+        let mut long_slots: Vec<usize> = Vec::new();
+        let mut checksum = 0u8;
+        for pos in self.dir_slots(first) {
+            let mut raw = [0u8; DIRENT_SZ];
+            self.read_bytes(pos, &mut raw);
+            if raw[0] == DIRENT_END {
+                break;
+            }
+            if raw[0] == DIRENT_FREE {
+                long_name.clear();
+                long_slots.clear();
+                continue;
+            }
+            if raw[11] & 0x3f == ATTR_LONG_NAME {
+                if raw[0] & LAST_LONG_ENTRY != 0 {
+                    long_name.clear();
+                    long_slots.clear();
+                    checksum = raw[13];
+                }
+                let mut chars = [0u16; LFN_CHARS];
+                for (i, offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                    chars[i] = le16(&raw, *offset);
+                }
+                long_name.push((raw[0] & 0x1f, chars));
+                long_slots.push(pos);
+                continue;
+            }
+            let short = ShortEntry::from_bytes(&raw);
+            let pieces = core::mem::take(&mut long_name);
+            let slots = core::mem::take(&mut long_slots);
+            if short.attr & ATTR_VOLUME_ID != 0 || short.name[0] == b'.' {
+                continue;
+            }
+            // the pieces come last first and must form a full set
+            let complete = !pieces.is_empty()
+                && checksum == short.checksum()
+                && pieces
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .all(|(i, (seq, _))| *seq as usize == i + 1);
+            let (name, long_slots) = if complete {
+                let units = pieces
+                    .iter()
+                    .rev()
+                    .flat_map(|(_, chars)| chars.iter().copied())
+                    .take_while(|unit| *unit != 0);
+                let name = core::char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+                    .collect();
+                (name, slots)
+            } else {
+                (short.display_name(), Vec::new())
+            };
+            entries.push(DirEntry {
+                name,
+                pos,
+                long_slots,
+                short,
+            });
+        }
+        entries
+    }
+}
+
+/// The fields of a short directory entry the file system uses
+#[derive(Clone, Copy)]
+struct ShortEntry {
+    name: [u8; 11],
+    attr: u8,
+    ntres: u8,
+    first_cluster: u32,
+    size: u32,
+}
+
+impl ShortEntry {
+    fn from_bytes(raw: &[u8; DIRENT_SZ]) -> Self {
+        let mut name = [0u8; 11];
+        name.copy_from_slice(&raw[..11]);
+        Self {
+            name,
+            attr: raw[11],
+            ntres: raw[12],
+            first_cluster: (le16(raw, 20) as u32) << 16 | le16(raw, 26) as u32,
+            size: le32(raw, 28),
+        }
+    }
+    fn to_bytes(self) -> [u8; DIRENT_SZ] {
+        let mut raw = [0u8; DIRENT_SZ];
+        raw[..11].copy_from_slice(&self.name);
+        raw[11] = self.attr;
+        raw[12] = self.ntres;
+        for offset in [16, 18, 24] {
+            raw[offset..offset + 2].copy_from_slice(&FAT_DATE.to_le_bytes());
+        }
+        raw[20..22].copy_from_slice(&((self.first_cluster >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(self.first_cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&self.size.to_le_bytes());
+        raw
+    }
+    /// Checksum of the short name, stored in its long name entries
+    fn checksum(&self) -> u8 {
+        self.name
+            .iter()
+            .fold(0u8, |sum, c| (sum >> 1 | sum << 7).wrapping_add(*c))
+    }
+    /// `NAME.EXT`, lowercased as `NTRes` asks
+    fn display_name(&self) -> String {
+        let mut base = self.name[..8].to_vec();
+        if base[0] == 0x05 {
+            base[0] = DIRENT_FREE;
+        }
+        let mut ext = self.name[8..].to_vec();
+        if self.ntres & NTRES_LOWER_BASE != 0 {
+            base.make_ascii_lowercase();
+        }
+        if self.ntres & NTRES_LOWER_EXT != 0 {
+            ext.make_ascii_lowercase();
+        }
+        let mut name: String = base.iter().map(|c| *c as char).collect();
+        let ext: String = ext.iter().map(|c| *c as char).collect();
+        name.truncate(name.trim_end().len());
+        if !ext.trim_end().is_empty() {
+            name.push('.');
+            name.push_str(ext.trim_end());
+        }
+        name
+    }
+}
+
+/// A directory entry with its name resolved
+struct DirEntry {
+    name: String,
+    /// disk byte position of the short entry
+    pos: usize,
+    /// disk byte positions of the long name entries
+    long_slots: Vec<usize>,
+    short: ShortEntry,
+}
+
+/// Whether `c` may appear in a short name
+fn is_short_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"$%'-_@~`!(){}^#&".contains(&c)
+}
+
+/// The short name and `NTRes` of a name that fits in 8.3 as it is
+fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    if !base.bytes().chain(ext.bytes()).all(is_short_char) {
+        return None;
+    }
+    // a part in mixed case needs a long name to keep its case
+    let case_flag = |part: &str, flag: u8| {
+        let lower = part.bytes().any(|c| c.is_ascii_lowercase());
+        let upper = part.bytes().any(|c| c.is_ascii_uppercase());
+        match (lower, upper) {
+            (true, true) => None,
+            (true, false) => Some(flag),
+            _ => Some(0),
+        }
+    };
+    let ntres = case_flag(base, NTRES_LOWER_BASE)? | case_flag(ext, NTRES_LOWER_EXT)?;
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.to_ascii_uppercase().as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.to_ascii_uppercase().as_bytes());
+    Some((short, ntres))
+}
+
+/// A `BASE~N.EXT` short name for a long name, unique among `taken`
+fn numbered_short_name(name: &str, taken: &[[u8; 11]]) -> Option<[u8; 11]> {
+    let squash = |part: &str| -> Vec<u8> {
+        part.bytes()
+            .filter(|c| *c != b' ' && *c != b'.')
+            .map(|c| {
+                if is_short_char(c) {
+                    c.to_ascii_uppercase()
+                } else {
+                    b'_'
+                }
+            })
+            .collect()
+    };
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (squash(&name[..dot]), squash(&name[dot + 1..])),
+        _ => (squash(name), Vec::new()),
+    };
+    let base = if base.is_empty() { vec![b'_'] } else { base };
+    for n in 1..1000000usize {
+        let tail = alloc::format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+        let mut short = [b' '; 11];
+        short[..keep].copy_from_slice(&base[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        let ext_len = ext.len().min(3);
+        short[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+        if !taken.contains(&short) {
+            return Some(short);
+        }
+    }
+    None
+}
+
+/// Whether `name` can be stored as a long name
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name.encode_utf16().count() <= 255
+        && !name
+            .chars()
+            .any(|c| (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c))
+}
+
+/// A FAT32 file system
+pub struct Fat32Fs {
+    volume: Arc<Volume>,
+}
+
+impl Fat32Fs {
+    /// Mount the FAT32 volume on `block_device`, `None` if there isn't one
+    pub fn probe(block_device: Arc<dyn BlockDevice>) -> Option<Arc<Self>> {
+        let mut bpb = [0u8; BLOCK_SZ];
+        get_block_cache(0, block_device.clone())
+            .exclusive_access()
+            .read(|data| bpb.copy_from_slice(data));
+        let bytes_per_sector = le16(&bpb, 11) as usize;
+        let sectors_per_cluster = bpb[13] as usize;
+        let reserved = le16(&bpb, 14) as usize;
+        let num_fats = bpb[16] as usize;
+        // FAT12/16 have a fixed root directory and a 16 bit FAT size
+        let root_entries = le16(&bpb, 17);
+        let fat_size16 = le16(&bpb, 22);
+        let total_sectors = match le16(&bpb, 19) {
+            0 => le32(&bpb, 32) as usize,
+            n => n as usize,
+        };
+        let fat_sectors = le32(&bpb, 36) as usize;
+        let root_cluster = le32(&bpb, 44);
+        let fsinfo = le16(&bpb, 48) as usize;
+        let backup_boot = le16(&bpb, 50) as usize;
+        if le16(&bpb, 510) != 0xaa55
+            || bytes_per_sector != BLOCK_SZ
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || num_fats == 0
+            || root_entries != 0
+            || fat_size16 != 0
+            || fat_sectors == 0
+        {
+            return None;
+        }
+        let data_start = reserved + num_fats * fat_sectors;
+        if total_sectors <= data_start {
+            return None;
+        }
+        let cluster_count = ((total_sectors - data_start) / sectors_per_cluster)
+            .min(fat_sectors * BLOCK_SZ / 4 - 2) as u32;
+        if !(2..cluster_count + 2).contains(&root_cluster) {
+            return None;
+        }
+        // the boot sector backup takes 3 sectors
+        let journal = if fsinfo < JOURNAL_START && backup_boot + 3 <= JOURNAL_START {
+            Journal::open(block_device.clone(), JOURNAL_START, reserved)
+        } else {
+            None
+        };
+        let mut volume = Volume {
+            block_device,
+            sectors_per_cluster,
+            fat_start: reserved,
+            fat_sectors,
+            num_fats,
+            data_start,
+            root_cluster,
+            cluster_count,
+            fsinfo_sector: None,
+            free: unsafe {
+                UPSafeCell::new(FreeInfo {
+                    free_count: u32::MAX,
+                    next_free: 2,
+                })
+            },
+            journal,
+        };
+        // a log that can't hold the largest transaction is no use
+        if volume.journal.is_some() && volume.tx_clusters() < CREATE_CLUSTERS {
+            println!("[kernel] fat32: journal too small, mounted without it");
+            volume.journal = None;
+        }
+        if fsinfo != 0 && fsinfo < reserved {
+            let mut info = [0u8; BLOCK_SZ];
+            volume.read_bytes(fsinfo * BLOCK_SZ, &mut info);
+            if le32(&info, 0) == 0x4161_5252 && le32(&info, 484) == 0x6141_7272 {
+                volume.fsinfo_sector = Some(fsinfo);
+                let mut free = volume.free.exclusive_access();
+                free.free_count = le32(&info, 488);
+                free.next_free = le32(&info, 492);
+            }
+        }
+        // the count in FSInfo is only a hint, count it when it can't be right
+        let free_count = volume.free.exclusive_access().free_count;
+        if free_count > cluster_count {
+            let _tx = volume.begin();
+            let free_count = volume.count_free();
+            volume.free.exclusive_access().free_count = free_count;
+            // it's only a hint, left stale if it can't be written
+            let _ = volume.sync_fsinfo();
+        }
+        Some(Arc::new(Self {
+            volume: Arc::new(volume),
+        }))
+    }
+}
+
+impl FileSystem for Fat32Fs {
+    fn fs_type(&self) -> &'static str {
+        "fat32"
+    }
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(Fat32Inode {
+            volume: self.volume.clone(),
+            kind: InodeType::Directory,
+            pos: None,
+        })
+    }
+}
+
+/// A file or directory on a FAT32 volume
+pub struct Fat32Inode {
+    volume: Arc<Volume>,
+    kind: InodeType,
+    /// disk byte position of the short entry, `None` for the root directory
+    pos: Option<usize>,
+}
+
+impl Fat32Inode {
+    fn short_entry(&self) -> Option<ShortEntry> {
+        let pos = self.pos?;
+        let mut raw = [0u8; DIRENT_SZ];
+        self.volume.read_bytes(pos, &mut raw);
+        Some(ShortEntry::from_bytes(&raw))
+    }
+    fn set_short_entry(&self, entry: ShortEntry) -> Result<(), isize> {
+        if let Some(pos) = self.pos {
+            // only the fields the file system changes are written
+            let raw = entry.to_bytes();
+            self.volume.write_meta(pos + 20, &raw[20..22])?;
+            self.volume.write_meta(pos + 26, &raw[26..32])?;
+        }
+        Ok(())
+    }
+    fn first_cluster(&self) -> u32 {
+        match self.short_entry() {
+            Some(entry) => entry.first_cluster,
+            None => self.volume.root_cluster,
+        }
+    }
+    /// Make the chain hold at least `len` bytes, returning its clusters,
+    /// fewer if the volume fills up
+    fn grow(&self, len: usize) -> Result<Vec<u32>, isize> {
+        let mut entry = self.short_entry();
+        let mut clusters = self.volume.chain(self.first_cluster());
+        let needed = (len + self.volume.cluster_size() - 1) / self.volume.cluster_size();
+        while clusters.len() < needed {
+            let cluster = match self.volume.alloc_cluster(clusters.last().copied())? {
+                Some(cluster) => cluster,
+                None => break,
+            };
+            if clusters.is_empty() {
+                if let Some(entry) = entry.as_mut() {
+                    entry.first_cluster = cluster;
+                    self.set_short_entry(*entry)?;
+                }
+            }
+            clusters.push(cluster);
+        }
+        Ok(clusters)
+    }
+    /// Cut the file down to `len` bytes, freeing clusters from the tail of
+    /// the chain a transaction at a time so that each fits in the log
+    fn truncate(&self, len: usize) -> Result<(), isize> {
+        let mut entry = match self.short_entry() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        let cluster_size = self.volume.cluster_size();
+        let keep = (len + cluster_size - 1) / cluster_size;
+        let mut clusters = self.volume.chain(entry.first_cluster);
+        while clusters.len() > keep {
+            let _tx = self.volume.begin();
+            let cut = clusters
+                .len()
+                .saturating_sub(self.volume.tx_clusters())
+                .max(keep);
+            match cut {
+                0 => entry.first_cluster = 0,
+                _ => self.volume.fat_set(clusters[cut - 1], FAT_MASK)?,
+            }
+            self.volume.free_clusters(&clusters[cut..])?;
+            clusters.truncate(cut);
+            entry.size = entry.size.min((cut * cluster_size) as u32);
+            self.set_short_entry(entry)?;
+        }
+        if entry.size as usize > len {
+            let _tx = self.volume.begin();
+            entry.size = len as u32;
+            self.set_short_entry(entry)?;
+        }
+        Ok(())
+    }
+    /// Copy between `buf` and the chain at `offset`, writing if `write`
+    fn transfer(&self, clusters: &[u32], offset: usize, buf: &mut [u8], write: bool) -> usize {
+        let cluster_size = self.volume.cluster_size();
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let cluster = match clusters.get(pos / cluster_size) {
+                Some(cluster) => *cluster,
+                None => break,
+            };
+            let in_cluster = pos % cluster_size;
+            let len = (cluster_size - in_cluster).min(buf.len() - done);
+            let disk_pos = self.volume.cluster_pos(cluster) + in_cluster;
+            if write {
+                self.volume.write_bytes(disk_pos, &buf[done..done + len]);
+            } else {
+                self.volume.read_bytes(disk_pos, &mut buf[done..done + len]);
+            }
+            done += len;
+        }
+        done
+    }
+    fn entries(&self) -> Vec<DirEntry> {
+        self.volume.dir_entries(self.first_cluster())
+    }
+    fn find_entry(&self, name: &str) -> Option<DirEntry> {
+        self.entries().into_iter().find(|entry| {
+            entry.name.eq_ignore_ascii_case(name)
+                || entry.short.display_name().eq_ignore_ascii_case(name)
+        })
+    }
+    fn inode_at(&self, entry: &DirEntry) -> Arc<dyn Inode> {
+        let kind = if entry.short.attr & ATTR_DIRECTORY != 0 {
+            InodeType::Directory
+        } else {
+            InodeType::File
+        };
+        Arc::new(Self {
+            volume: self.volume.clone(),
+            kind,
+            pos: Some(entry.pos),
+        })
+    }
+    /// Find `count` consecutive free slots, growing the directory if needed
+    fn free_slots(&self, count: usize) -> Option<Vec<usize>> {
+        loop {
+            let slots = self.volume.dir_slots(self.first_cluster());
+            let mut run = 0;
+            for (i, pos) in slots.iter().enumerate() {
+                let mut first = [0u8; 1];
+                self.volume.read_bytes(*pos, &mut first);
+                match first[0] {
+                    // everything after the end marker is free too
+                    DIRENT_END if slots.len() - i >= count - run => {
+                        return Some(slots[i - run..i - run + count].to_vec());
+                    }
+                    DIRENT_END | DIRENT_FREE => run += 1,
+                    _ => run = 0,
+                }
+                if run == count {
+                    return Some(slots[i + 1 - count..=i].to_vec());
+                }
+            }
+            let len = (slots.len() + count) * DIRENT_SZ;
+            if self.grow(len).ok()?.len() * self.volume.cluster_size() < len {
+                return None;
+            }
+        }
+    }
+}
+
+impl Inode for Fat32Inode {
+    fn kind(&self) -> InodeType {
+        self.kind
+    }
+    fn id(&self) -> Option<(usize, usize)> {
+        Some((Arc::as_ptr(&self.volume) as usize, self.pos.unwrap_or(0)))
+    }
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            mode: 0o777,
+            uid: 0,
+            gid: 0,
+        }
+    }
+    fn size(&self) -> usize {
+        match self.kind {
+            InodeType::File => self.short_entry().map_or(0, |entry| entry.size as usize),
+            _ => 0,
+        }
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let size = self.size();
+        if self.kind != InodeType::File || offset >= size {
+            return 0;
+        }
+        let len = buf.len().min(size - offset);
+        let clusters = self.volume.chain(self.first_cluster());
+        self.transfer(&clusters, offset, &mut buf[..len], false)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        if self.kind != InodeType::File {
+            return 0;
+        }
+        let mut entry = match self.short_entry() {
+            Some(entry) => entry,
+            None => return 0,
+        };
+        let cluster_size = self.volume.cluster_size();
+        let end = (offset + buf.len()).min(u32::MAX as usize);
+        if offset > end {
+            return 0;
+        }
+        let mut pos = offset.min(entry.size as usize);
+        // the file grows a transaction at a time so that each fits in the
+        // log, its size always matching its chain
+        while pos < end {
+            let _tx = self.volume.begin();
+            let step_start = pos;
+            let held = self.volume.chain(self.first_cluster()).len();
+            let step_end = end.min(
+                held.saturating_add(self.volume.tx_clusters())
+                    .saturating_mul(cluster_size),
+            );
+            // a step that outgrows the log is dropped, the write cut short
+            let clusters = match self.grow(step_end) {
+                Ok(clusters) => clusters,
+                Err(_) => break,
+            };
+            let step_end = step_end.min(clusters.len() * cluster_size);
+            if step_end <= pos {
+                break;
+            }
+            // the bytes between the old end and `offset` read as zero
+            if pos < offset {
+                let mut zero = vec![0u8; offset.min(step_end) - pos];
+                pos += self.transfer(&clusters, pos, &mut zero, true);
+            }
+            if pos < step_end {
+                let mut data = buf[pos - offset..step_end - offset].to_vec();
+                pos += self.transfer(&clusters, pos, &mut data, true);
+            }
+            if pos > entry.size as usize {
+                entry.first_cluster = self.first_cluster();
+                entry.size = pos as u32;
+                if self.set_short_entry(entry).is_err() {
+                    pos = step_start;
+                    break;
+                }
+            }
+        }
+        pos.saturating_sub(offset)
+    }
+    fn clear(&self) {
+        if self.kind != InodeType::File {
+            return;
+        }
+        // a step that can't be logged leaves the file longer
+        let _ = self.truncate(0);
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if self.kind != InodeType::Directory {
+            return None;
+        }
+        self.find_entry(name).map(|entry| self.inode_at(&entry))
+    }
+    fn create(&self, name: &str, kind: InodeType) -> Option<Arc<dyn Inode>> {
+        if self.kind != InodeType::Directory
+            || !matches!(kind, InodeType::File | InodeType::Directory)
+            || !valid_name(name)
+            || self.find_entry(name).is_some()
+        {
+            return None;
+        }
+        let (short_name, ntres, long) = match exact_short_name(name) {
+            Some((short_name, ntres)) => (short_name, ntres, Vec::new()),
+            None => {
+                let taken: Vec<[u8; 11]> = self.entries().iter().map(|e| e.short.name).collect();
+                let short_name = numbered_short_name(name, &taken)?;
+                (short_name, 0, name.encode_utf16().collect::<Vec<u16>>())
+            }
+        };
+        let long_count = (long.len() + LFN_CHARS - 1) / LFN_CHARS;
+        let _tx = self.volume.begin();
+        // the cluster of a new directory comes first, so that the parent
+        // isn't grown for an entry there's no cluster for
+        let cluster = match kind {
+            InodeType::Directory => Some(self.volume.alloc_cluster(None).ok()??),
+            _ => None,
+        };
+        let slots = match self.free_slots(long_count + 1) {
+            Some(slots) => slots,
+            None => {
+                if let Some(cluster) = cluster {
+                    // if this can't be logged the whole transaction is dropped
+                    let _ = self.volume.free_clusters(&[cluster]);
+                }
+                return None;
+            }
+        };
+        let mut short = ShortEntry {
+            name: short_name,
+            attr: if kind == InodeType::Directory {
+                ATTR_DIRECTORY
+            } else {
+                ATTR_ARCHIVE
+            },
+            ntres,
+            first_cluster: 0,
+            size: 0,
+        };
+        if let Some(cluster) = cluster {
+            short.first_cluster = cluster;
+            let mut dot = short;
+            dot.name = *b".          ";
+            dot.ntres = 0;
+            // `..` of a directory in the root points at cluster 0
+            let mut dotdot = dot;
+            dotdot.name = *b"..         ";
+            dotdot.first_cluster = match self.pos {
+                Some(_) => self.first_cluster(),
+                None => 0,
+            };
+            let base = self.volume.cluster_pos(cluster);
+            self.volume.write_meta(base, &dot.to_bytes()).ok()?;
+            self.volume
+                .write_meta(base + DIRENT_SZ, &dotdot.to_bytes())
+                .ok()?;
+        }
+        // long name entries go before the short one, last piece first
+        let checksum = short.checksum();
+        for (i, pos) in slots[..long_count].iter().enumerate() {
+            let seq = long_count - i;
+            let mut raw = [0u8; DIRENT_SZ];
+            raw[0] = seq as u8 | if i == 0 { LAST_LONG_ENTRY } else { 0 };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+            for (j, offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                let idx = (seq - 1) * LFN_CHARS + j;
+                // terminated by a NUL, then padded with 0xFFFF
+                let unit = match idx.cmp(&long.len()) {
+                    core::cmp::Ordering::Less => long[idx],
+                    core::cmp::Ordering::Equal => 0,
+                    core::cmp::Ordering::Greater => 0xffff,
+                };
+                raw[*offset..*offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            self.volume.write_meta(*pos, &raw).ok()?;
+        }
+        let pos = slots[long_count];
+        self.volume.write_meta(pos, &short.to_bytes()).ok()?;
+        Some(Arc::new(Self {
+            volume: self.volume.clone(),
+            kind,
+            pos: Some(pos),
+        }))
+    }
+    fn unlink(&self, name: &str) -> bool {
+        if self.kind != InodeType::Directory {
+            return false;
+        }
+        let entry = match self.find_entry(name) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let is_dir = entry.short.attr & ATTR_DIRECTORY != 0;
+        if is_dir
+            && !self
+                .volume
+                .dir_entries(entry.short.first_cluster)
+                .is_empty()
+        {
+            return false;
+        }
+        // all but the first cluster go first, the entry with the rest
+        let inode = Self {
+            volume: self.volume.clone(),
+            kind: InodeType::File,
+            pos: Some(entry.pos),
+        };
+        if inode.truncate(self.volume.cluster_size()).is_err() {
+            return false;
+        }
+        let _tx = self.volume.begin();
+        let first_cluster = inode.first_cluster();
+        if first_cluster != 0
+            && self
+                .volume
+                .free_clusters(&self.volume.chain(first_cluster))
+                .is_err()
+        {
+            return false;
+        }
+        entry
+            .long_slots
+            .iter()
+            .chain(core::iter::once(&entry.pos))
+            .all(|pos| self.volume.write_meta(*pos, &[DIRENT_FREE]).is_ok())
+    }
+    fn ls(&self) -> Vec<String> {
+        if self.kind != InodeType::Directory {
+            return Vec::new();
+        }
+        self.entries().into_iter().map(|entry| entry.name).collect()
+    }
+}
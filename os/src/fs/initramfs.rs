@@ -0,0 +1,122 @@
+//! Unpacking a cpio `newc` archive into a directory at boot
+//!
+//! ```text
+//! | "070701" | 13 fields of 8 hex digits | name\0 | pad | data | pad | ...
+//! ```
+//!
+//! Headers and names are padded to 4 bytes, and so is the data. Directories,
+//! regular files and symbolic links are unpacked with their permission bits
+//! and owners, other entries are skipped. Hard links are unpacked as separate files, and only the last of
+//! them gets the data, as it is stored in the archive.
+use super::{Inode, InodeType, Metadata};
+use alloc::sync::Arc;
+
+/// Size of an entry header
+const HEADER_SIZE: usize = 110;
+/// Magic of `newc` headers, without and with checksums
+const NEWC_MAGIC: &[u8] = b"070701";
+const CRC_MAGIC: &[u8] = b"070702";
+/// Name of the entry that ends the archive
+const TRAILER: &str = "TRAILER!!!";
+/// File types in `c_mode`
+const S_IFMT: usize = 0o170000;
+const S_IFDIR: usize = 0o040000;
+const S_IFREG: usize = 0o100000;
+const S_IFLNK: usize = 0o120000;
+
+/// Round `n` up to a multiple of 4
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Field `index` of a header, 8 hex digits after the magic
+fn field(header: &[u8], index: usize) -> Option<usize> {
+    let start = 6 + index * 8;
+    let digits = core::str::from_utf8(&header[start..start + 8]).ok()?;
+    usize::from_str_radix(digits, 16).ok()
+}
+
+/// Find or create `name` of type `kind` in `dir`
+fn find_or_create(dir: &Arc<dyn Inode>, name: &str, kind: InodeType) -> Option<Arc<dyn Inode>> {
+    match dir.find(name) {
+        Some(inode) if inode.kind() == kind => Some(inode),
+        Some(_) => None,
+        None => dir.create(name, kind),
+    }
+}
+
+/// Create the entry at `path` under `root`, with the parent directories
+fn unpack_entry(
+    root: &Arc<dyn Inode>,
+    path: &str,
+    kind: InodeType,
+    metadata: Metadata,
+    data: &[u8],
+) -> Option<()> {
+    let mut names = path
+        .split('/')
+        .filter(|name| !name.is_empty() && *name != ".");
+    let name = names.next_back()?;
+    let mut dir = root.clone();
+    for parent in names {
+        dir = find_or_create(&dir, parent, InodeType::Directory)?;
+    }
+    let inode = find_or_create(&dir, name, kind)?;
+    inode.set_metadata(metadata);
+    if kind != InodeType::Directory {
+        inode.clear();
+        if inode.write_at(0, data) != data.len() {
+            return None;
+        }
+    }
+    Some(())
+}
+
+/// Unpack `archive` into the directory `root`, return the number of entries
+/// unpacked or `None` if the archive is malformed
+pub fn unpack(archive: &[u8], root: &Arc<dyn Inode>) -> Option<usize> {
+    let mut pos = 0;
+    let mut count = 0;
+    loop {
+        let header = archive.get(pos..pos + HEADER_SIZE)?;
+        if &header[..6] != NEWC_MAGIC && &header[..6] != CRC_MAGIC {
+            return None;
+        }
+        let mode = field(header, 1)?;
+        let metadata = Metadata {
+            mode: (mode & !S_IFMT) as u32,
+            uid: field(header, 2)? as u32,
+            gid: field(header, 3)? as u32,
+        };
+        let file_size = field(header, 6)?;
+        let name_size = field(header, 11)?;
+        let name_start = pos + HEADER_SIZE;
+        // the name size counts the terminating NUL
+        let name = archive.get(name_start..name_start + name_size.checked_sub(1)?)?;
+        let name = core::str::from_utf8(name).ok()?;
+        let data_start = align4(name_start + name_size);
+        let data = archive.get(data_start..data_start + file_size)?;
+        pos = align4(data_start + file_size);
+        if name == TRAILER {
+            return Some(count);
+        }
+        let kind = match mode & S_IFMT {
+            S_IFDIR => InodeType::Directory,
+            S_IFREG => InodeType::File,
+            S_IFLNK => InodeType::Symlink,
+            _ => {
+                println!("[kernel] initramfs: skip {}", name);
+                continue;
+            }
+        };
+        // `.` is the root itself
+        if name.split('/').all(|name| name.is_empty() || name == ".") {
+            continue;
+        }
+        if unpack_entry(root, name, kind, metadata, data).is_none() {
+            println!("[kernel] initramfs: failed to unpack {}", name);
+            continue;
+        }
+        count += 1;
+    }
+}
@@ -0,0 +1,237 @@
+//! `OSInode`: a VFS inode opened by a process
+use super::{lookup, make_node, Access, Credentials, File, Inode, InodeType, PageCache};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EACCES, EISDIR, ENOENT, ENXIO};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+
+/// A wrapper around an inode to keep the open mode and offset of a file
+pub struct OSInode {
+    readable: bool,
+    writable: bool,
+    /// whether writes go to the end of the file
+    append: bool,
+    /// the data of a regular file, shared with its other users
+    cache: Option<Arc<PageCache>>,
+    inner: UPSafeCell<OSInodeInner>,
+}
+
+/// The OS inode inner in 'UPSafeCell'
+pub struct OSInodeInner {
+    offset: usize,
+    inode: Arc<dyn Inode>,
+}
+
+impl OSInode {
+    /// Construct an OS inode from an inode
+    pub fn new(readable: bool, writable: bool, inode: Arc<dyn Inode>) -> Self {
+        Self {
+            readable,
+            writable,
+            append: false,
+            cache: PageCache::of(&inode),
+            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
+        }
+    }
+    /// Read all data from the current offset
+    #[allow(unused)]
+    pub fn read_all(&self) -> Vec<u8> {
+        let mut inner = self.inner.exclusive_access();
+        let mut buffer = [0u8; 512];
+        let mut v: Vec<u8> = Vec::new();
+        loop {
+            let len = self.read_data(&inner.inode, inner.offset, &mut buffer);
+            if len == 0 {
+                break;
+            }
+            inner.offset += len;
+            v.extend_from_slice(&buffer[..len]);
+        }
+        v
+    }
+    /// Read the file at `offset`, through the page cache if it has one
+    fn read_data(&self, inode: &Arc<dyn Inode>, offset: usize, buf: &mut [u8]) -> usize {
+        match &self.cache {
+            Some(cache) => cache.read(offset, buf),
+            None => inode.read_at(offset, buf),
+        }
+    }
+    /// Write the file at `offset`, through the page cache if it has one
+    fn write_data(&self, inode: &Arc<dyn Inode>, offset: usize, buf: &[u8]) -> usize {
+        match &self.cache {
+            Some(cache) => cache.write(offset, buf),
+            None => inode.write_at(offset, buf),
+        }
+    }
+}
+
+bitflags! {
+    /// Flags of `sys_open`
+    pub struct OpenFlags: u32 {
+        /// Read only
+        const RDONLY = 0;
+        /// Write only
+        const WRONLY = 1 << 0;
+        /// Read and write
+        const RDWR = 1 << 1;
+        /// Create the file if it doesn't exist
+        const CREATE = 1 << 9;
+        /// Truncate the file to zero length
+        const TRUNC = 1 << 10;
+        /// Write at the end of the file
+        const APPEND = 1 << 11;
+        /// Close the fd on `exec`
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+impl OpenFlags {
+    /// Do not check validity for simplicity
+    /// Return (readable, writable)
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.difference(Self::CLOEXEC).is_empty() {
+            (true, false)
+        } else if self.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, true)
+        }
+    }
+}
+
+/// Open the file at `path` with `flags`, accessing it as `cred`
+pub fn open_file(path: &str, flags: OpenFlags, cred: Credentials) -> Result<Arc<dyn File>, isize> {
+    let (readable, writable) = flags.read_write();
+    let inode = match lookup(path, cred) {
+        Ok(inode) => {
+            let mut access = Access::empty();
+            access.set(Access::READ, readable);
+            access.set(Access::WRITE, writable || flags.contains(OpenFlags::TRUNC));
+            if !inode.metadata().permits(cred, access) {
+                return Err(EACCES);
+            }
+            if flags.contains(OpenFlags::TRUNC) {
+                inode.clear();
+                if let Some(cache) = PageCache::of(&inode) {
+                    cache.truncate();
+                }
+            }
+            inode
+        }
+        Err(ENOENT) if flags.contains(OpenFlags::CREATE) => make_node(path, InodeType::File, cred)?,
+        Err(errno) => return Err(errno),
+    };
+    if let Some(device) = inode.device() {
+        return Ok(device);
+    }
+    if let Some(fifo) = inode.fifo() {
+        return fifo
+            .open(readable, writable)
+            .map(|pipe| pipe as Arc<dyn File>);
+    }
+    if inode.kind() == InodeType::Socket {
+        // sockets are reached with connect, not open
+        return Err(ENXIO);
+    }
+    if writable && inode.kind() == InodeType::Directory {
+        return Err(EISDIR);
+    }
+    let mut os_inode = OSInode::new(readable, writable, inode);
+    os_inode.append = flags.contains(OpenFlags::APPEND);
+    Ok(Arc::new(os_inode))
+}
+
+/// Open the regular file at `path` to load it as a program run by `cred`,
+/// which needs execute permission but not read permission
+pub fn open_exec(path: &str, cred: Credentials) -> Result<Arc<dyn File>, isize> {
+    let inode = lookup(path, cred)?;
+    if !inode.metadata().permits(cred, Access::EXEC) {
+        return Err(EACCES);
+    }
+    // only regular files are loaded, whatever their mode
+    if inode.kind() != InodeType::File {
+        return Err(EACCES);
+    }
+    Ok(Arc::new(OSInode::new(true, false, inode)))
+}
+
+impl File for OSInode {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if inner.inode.kind() == InodeType::Directory {
+            // a directory reads as the names in it, one per line
+            let mut listing = String::new();
+            for name in inner.inode.ls() {
+                listing.push_str(&name);
+                listing.push('\n');
+            }
+            let listing = listing.as_bytes();
+            let mut total_read_size = 0usize;
+            for slice in buf.buffers.iter_mut() {
+                let start = listing.len().min(inner.offset);
+                let read_size = slice.len().min(listing.len() - start);
+                slice[..read_size].copy_from_slice(&listing[start..start + read_size]);
+                inner.offset += read_size;
+                total_read_size += read_size;
+            }
+            return total_read_size as isize;
+        }
+        let mut total_read_size = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            let read_size = self.read_data(&inner.inode, inner.offset, slice);
+            if read_size == 0 {
+                break;
+            }
+            inner.offset += read_size;
+            total_read_size += read_size;
+        }
+        total_read_size as isize
+    }
+    fn write(&self, buf: UserBuffer) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if self.append {
+            inner.offset = inner.inode.size();
+        }
+        let mut total_write_size = 0usize;
+        for slice in buf.buffers.iter() {
+            let write_size = self.write_data(&inner.inode, inner.offset, slice);
+            inner.offset += write_size;
+            total_write_size += write_size;
+            if write_size < slice.len() {
+                break;
+            }
+        }
+        total_write_size as isize
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Option<usize> {
+        let inner = self.inner.exclusive_access();
+        if inner.inode.kind() == InodeType::Directory {
+            return None;
+        }
+        let mut total_read_size = 0usize;
+        while total_read_size < buf.len() {
+            let read_size = self.read_data(
+                &inner.inode,
+                offset + total_read_size,
+                &mut buf[total_read_size..],
+            );
+            if read_size == 0 {
+                break;
+            }
+            total_read_size += read_size;
+        }
+        Some(total_read_size)
+    }
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.cache.clone()
+    }
+}
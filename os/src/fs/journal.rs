@@ -0,0 +1,358 @@
+//! Implementation of [`Journal`], a write-ahead log of metadata blocks
+//!
+//! ```text
+//! | superblock | descriptor (ids ...) | block images ... | commit | unused |
+//! ```
+//!
+//! Metadata blocks changed by a transaction are kept in memory until it
+//! commits. Data blocks written meanwhile are synced first, then the new
+//! images of the metadata blocks are logged with a commit block holding a
+//! checksum, and only then copied to their home locations. The log holds
+//! at most one transaction: once it's home the superblock moves on to the
+//! next sequence number, which leaves the old log stale.
+//!
+//! At mount a log whose commit block matches its descriptor and checksum
+//! is copied home again; anything else is a transaction cut short by a
+//! crash and is dropped, which leaves the metadata as it was before it.
+//!
+//! A kernel built with `JOURNAL_CRASH_AFTER=N` powers off right after
+//! logging its `N`th transaction since boot, to test the recovery.
+use super::block_cache::{block_cache_sync_all, get_block_cache};
+use crate::drivers::block::{BlockDevice, BLOCK_SZ};
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::ENOSPC;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Magic numbers of the superblock, descriptor and commit blocks
+const JOURNAL_MAGIC: u32 = 0x4c4e_524a;
+const DESCRIPTOR_MAGIC: u32 = 0x4353_4544;
+const COMMIT_MAGIC: u32 = 0x544d_4f43;
+/// Bytes of the descriptor before the block ids
+const DESCRIPTOR_HEADER: usize = 12;
+/// A smaller log isn't worth it
+const MIN_LOG_BLOCKS: usize = 64;
+
+/// Transaction logged since boot to power off right after, 0 never does
+fn crash_after() -> u32 {
+    option_env!("JOURNAL_CRASH_AFTER")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Read a little-endian `u32` at `offset`
+fn le32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn put32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Blocks taken by the descriptor of a transaction of `count` blocks
+fn descriptor_blocks(count: usize) -> usize {
+    (DESCRIPTOR_HEADER + count * 4 + BLOCK_SZ - 1) / BLOCK_SZ
+}
+
+/// Log blocks taken by a transaction of `count` blocks
+fn log_len(count: usize) -> usize {
+    descriptor_blocks(count) + count + 1
+}
+
+/// FNV-1a over the sequence number, the block ids and the block images
+fn checksum<'a>(sequence: u32, blocks: impl Iterator<Item = (usize, &'a [u8])>) -> u32 {
+    let mut hash = 0x811c_9dc5u32;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash = (hash ^ *byte as u32).wrapping_mul(0x0100_0193);
+        }
+    };
+    feed(&sequence.to_le_bytes());
+    for (block_id, data) in blocks {
+        feed(&(block_id as u32).to_le_bytes());
+        feed(data);
+    }
+    hash
+}
+
+/// Bookkeeping of the running transaction
+struct JournalState {
+    /// sequence number of the next transaction logged
+    sequence: u32,
+    /// power off after logging this many transactions, 0 never does
+    crash_after: u32,
+    /// transactions logged since mount
+    committed: u32,
+    /// nesting depth of the open transactions
+    depth: usize,
+    /// new images of the metadata blocks changed, by block id
+    pending: BTreeMap<usize, Box<[u8; BLOCK_SZ]>>,
+    /// the running transaction outgrew the log and will be dropped
+    failed: bool,
+}
+
+/// A metadata journal in a run of blocks set aside on a device
+pub struct Journal {
+    block_device: Arc<dyn BlockDevice>,
+    /// block of the superblock, the log follows it
+    start: usize,
+    /// blocks in the log
+    blocks: usize,
+    state: UPSafeCell<JournalState>,
+}
+
+impl Journal {
+    /// Open the journal in blocks `start..end` of `block_device`, replaying
+    /// a committed transaction or formatting it if it's new. `None` if
+    /// there is no room for a journal.
+    pub fn open(block_device: Arc<dyn BlockDevice>, start: usize, end: usize) -> Option<Self> {
+        if end < start + 1 + MIN_LOG_BLOCKS {
+            return None;
+        }
+        let mut superblock = [0u8; BLOCK_SZ];
+        block_device.read_block(start, &mut superblock);
+        let formatted = le32(&superblock, 0) == JOURNAL_MAGIC;
+        let (blocks, sequence) = if formatted {
+            let blocks = (le32(&superblock, 4) as usize).clamp(MIN_LOG_BLOCKS, end - start - 1);
+            (blocks, le32(&superblock, 8))
+        } else {
+            (end - start - 1, 1)
+        };
+        let journal = Self {
+            block_device,
+            start,
+            blocks,
+            state: unsafe {
+                UPSafeCell::new(JournalState {
+                    sequence,
+                    crash_after: crash_after(),
+                    committed: 0,
+                    depth: 0,
+                    pending: BTreeMap::new(),
+                    failed: false,
+                })
+            },
+        };
+        if !formatted {
+            println!("[kernel] journal: {} log blocks formatted", blocks);
+        } else if journal.replay() {
+            println!("[kernel] journal: committed transaction replayed");
+        }
+        // whatever is in the log now is stale
+        journal.state.exclusive_access().sequence += 1;
+        journal.write_superblock();
+        Some(journal)
+    }
+    fn write_superblock(&self) {
+        let state = self.state.exclusive_access();
+        let mut superblock = [0u8; BLOCK_SZ];
+        put32(&mut superblock, 0, JOURNAL_MAGIC);
+        put32(&mut superblock, 4, self.blocks as u32);
+        put32(&mut superblock, 8, state.sequence);
+        self.block_device.write_block(self.start, &superblock);
+    }
+    /// Copy the logged transaction home if it was committed, return
+    /// whether it was
+    fn replay(&self) -> bool {
+        let sequence = self.state.exclusive_access().sequence;
+        let mut block = [0u8; BLOCK_SZ];
+        self.block_device.read_block(self.start + 1, &mut block);
+        let count = le32(&block, 8) as usize;
+        if le32(&block, 0) != DESCRIPTOR_MAGIC
+            || le32(&block, 4) != sequence
+            || log_len(count) > self.blocks
+        {
+            return false;
+        }
+        let mut descriptor = vec![0u8; descriptor_blocks(count) * BLOCK_SZ];
+        for (i, chunk) in descriptor.chunks_mut(BLOCK_SZ).enumerate() {
+            self.block_device.read_block(self.start + 1 + i, chunk);
+        }
+        let images: Vec<(usize, Box<[u8; BLOCK_SZ]>)> = (0..count)
+            .map(|i| {
+                let mut data = Box::new([0u8; BLOCK_SZ]);
+                let log_block = self.start + 1 + descriptor_blocks(count) + i;
+                self.block_device.read_block(log_block, data.as_mut());
+                let block_id = le32(&descriptor, DESCRIPTOR_HEADER + i * 4) as usize;
+                (block_id, data)
+            })
+            .collect();
+        self.block_device
+            .read_block(self.start + log_len(count), &mut block);
+        let sum = checksum(
+            sequence,
+            images.iter().map(|(id, data)| (*id, data.as_slice())),
+        );
+        if le32(&block, 0) != COMMIT_MAGIC
+            || le32(&block, 4) != sequence
+            || le32(&block, 8) as usize != count
+            || le32(&block, 12) != sum
+        {
+            return false;
+        }
+        self.checkpoint(images.iter().map(|(id, data)| (*id, data.as_slice())));
+        true
+    }
+    /// Copy block images to their home locations
+    fn checkpoint<'a>(&self, images: impl Iterator<Item = (usize, &'a [u8])>) {
+        for (block_id, data) in images {
+            get_block_cache(block_id, self.block_device.clone())
+                .exclusive_access()
+                .modify(|block| block.copy_from_slice(data));
+        }
+        block_cache_sync_all();
+    }
+    /// Start a transaction, or join the one running
+    pub fn begin(&self) {
+        self.state.exclusive_access().depth += 1;
+    }
+    /// End a transaction, committing it if it's the outermost one. `false`
+    /// if it outgrew the log and was dropped instead, leaving the metadata
+    /// as it was before it.
+    pub fn commit(&self) -> bool {
+        let mut state = self.state.exclusive_access();
+        state.depth -= 1;
+        if state.depth > 0 {
+            return true;
+        }
+        if state.failed {
+            state.failed = false;
+            state.pending.clear();
+            return false;
+        }
+        drop(state);
+        self.flush();
+        true
+    }
+    /// Log the changed metadata blocks, then copy them home
+    fn flush(&self) {
+        // ordered: the data a transaction points at is on disk before it
+        block_cache_sync_all();
+        let mut state = self.state.exclusive_access();
+        if state.pending.is_empty() {
+            return;
+        }
+        let pending = core::mem::take(&mut state.pending);
+        let sequence = state.sequence;
+        let count = pending.len();
+        let mut descriptor = vec![0u8; descriptor_blocks(count) * BLOCK_SZ];
+        put32(&mut descriptor, 0, DESCRIPTOR_MAGIC);
+        put32(&mut descriptor, 4, sequence);
+        put32(&mut descriptor, 8, count as u32);
+        for (i, block_id) in pending.keys().enumerate() {
+            put32(&mut descriptor, DESCRIPTOR_HEADER + i * 4, *block_id as u32);
+        }
+        let mut log_block = self.start + 1;
+        for chunk in descriptor.chunks(BLOCK_SZ) {
+            self.block_device.write_block(log_block, chunk);
+            log_block += 1;
+        }
+        for data in pending.values() {
+            self.block_device.write_block(log_block, data.as_slice());
+            log_block += 1;
+        }
+        let mut commit = [0u8; BLOCK_SZ];
+        put32(&mut commit, 0, COMMIT_MAGIC);
+        put32(&mut commit, 4, sequence);
+        put32(&mut commit, 8, count as u32);
+        let sum = checksum(
+            sequence,
+            pending.iter().map(|(id, data)| (*id, data.as_slice())),
+        );
+        put32(&mut commit, 12, sum);
+        self.block_device.write_block(log_block, &commit);
+        // fault injection: lose power between the commit and the checkpoint
+        state.committed += 1;
+        if state.committed == state.crash_after {
+            println!("[kernel] journal: crashing after transaction {}", sequence);
+            crate::sbi::shutdown(false);
+        }
+        drop(state);
+        self.checkpoint(pending.iter().map(|(id, data)| (*id, data.as_slice())));
+        self.state.exclusive_access().sequence += 1;
+        self.write_superblock();
+    }
+    /// Read bytes at a byte position, seeing the running transaction
+    pub fn read_bytes(&self, mut pos: usize, buf: &mut [u8]) {
+        let state = self.state.exclusive_access();
+        let mut done = 0;
+        while done < buf.len() {
+            let offset = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - offset).min(buf.len() - done);
+            let dst = &mut buf[done..done + len];
+            match state.pending.get(&(pos / BLOCK_SZ)) {
+                Some(data) => dst.copy_from_slice(&data[offset..offset + len]),
+                None => get_block_cache(pos / BLOCK_SZ, self.block_device.clone())
+                    .exclusive_access()
+                    .read(|data| dst.copy_from_slice(&data[offset..offset + len])),
+            }
+            done += len;
+            pos += len;
+        }
+    }
+    /// Most blocks a transaction can change and still fit in the log
+    pub fn capacity(&self) -> usize {
+        (0..self.blocks)
+            .rev()
+            .find(|count| log_len(*count) <= self.blocks)
+            .unwrap_or(0)
+    }
+    /// Write metadata bytes at a byte position as part of the running
+    /// transaction. `ENOSPC` if it would outgrow [`Journal::capacity`], and
+    /// for every write after, the transaction then being dropped at commit.
+    pub fn write_meta(&self, mut pos: usize, buf: &[u8]) -> Result<(), isize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let offset = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - offset).min(buf.len() - done);
+            let block_id = pos / BLOCK_SZ;
+            let mut state = self.state.exclusive_access();
+            // committing part of it would break its atomicity
+            if state.failed
+                || (!state.pending.contains_key(&block_id)
+                    && log_len(state.pending.len() + 1) > self.blocks)
+            {
+                state.failed = true;
+                return Err(ENOSPC);
+            }
+            let data = state.pending.entry(block_id).or_insert_with(|| {
+                let mut data = Box::new([0u8; BLOCK_SZ]);
+                get_block_cache(block_id, self.block_device.clone())
+                    .exclusive_access()
+                    .read(|block| data.copy_from_slice(block));
+                data
+            });
+            data[offset..offset + len].copy_from_slice(&buf[done..done + len]);
+            done += len;
+            pos += len;
+        }
+        Ok(())
+    }
+    /// Write data bytes at a byte position straight to the block cache
+    pub fn write_data(&self, mut pos: usize, buf: &[u8]) {
+        let mut state = self.state.exclusive_access();
+        let mut done = 0;
+        while done < buf.len() {
+            let offset = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - offset).min(buf.len() - done);
+            let src = &buf[done..done + len];
+            // a block the transaction changed is written home from its image
+            if let Some(data) = state.pending.get_mut(&(pos / BLOCK_SZ)) {
+                data[offset..offset + len].copy_from_slice(src);
+            }
+            get_block_cache(pos / BLOCK_SZ, self.block_device.clone())
+                .exclusive_access()
+                .modify(|data| data[offset..offset + len].copy_from_slice(src));
+            done += len;
+            pos += len;
+        }
+    }
+}
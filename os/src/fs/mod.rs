@@ -0,0 +1,170 @@
+//! File system in os
+//!
+//! The kernel talks to every file system through the VFS traits in [`vfs`]:
+//! a [`FileSystem`] provides a root [`Inode`], and inodes implement the
+//! usual directory and data operations. File systems are attached to the
+//! directory tree with [`mount`], and paths are resolved across mount points
+//! by [`lookup`]. Reading a directory yields the names in it, one per line.
+//! Inodes carry permission bits and owners in their [`Metadata`], checked
+//! against the [`Credentials`] of a task when it opens, runs or removes
+//! files.
+//!
+//! Processes never see inodes directly. They hold [`File`]s in their fd
+//! table, such as an [`OSInode`] opened from a path or a device from
+//! `/dev`, or an end of a [`Pipe`]. The applications linked into the kernel
+//! are files in `/bin`. Files report what they are ready for with
+//! [`File::poll`], which [`Epoll`] and `ppoll` wait on.
+//!
+//! Disk file systems such as [`Fat32Fs`] and [`Ext2Fs`] reach the block device through the
+//! block cache. The data of regular files is read through a [`PageCache`]
+//! per file, whose frames are also what file mappings map. FAT32 metadata
+//! changes go through a write-ahead journal when the volume has room
+//! for one.
+//!
+//! When the kernel boots with an initramfs, a cpio archive handed over by
+//! the boot loader or linked into the kernel, the root is a tmpfs the
+//! archive is unpacked into and the disk is mounted at `/mnt`.
+mod appfs;
+mod block_cache;
+mod devfs;
+mod epoll;
+mod ext2;
+mod fat32;
+mod initramfs;
+mod inode;
+mod journal;
+mod page_cache;
+mod pipe;
+mod procfs;
+mod tmpfs;
+mod vfs;
+
+use crate::drivers::block::BlockDevice;
+use crate::drivers::BLOCK_DEVICE;
+use crate::mm::UserBuffer;
+use crate::net::UnixSocket;
+use crate::task::TaskControlBlock;
+use alloc::sync::Arc;
+use bitflags::*;
+
+bitflags! {
+    /// Events of `poll` and `epoll`, with the same values for both
+    pub struct PollEvents: u32 {
+        /// There is data to read
+        const IN = 1 << 0;
+        /// Writing won't wait
+        const OUT = 1 << 2;
+        /// Error, e.g. the read end of a pipe is closed
+        const ERR = 1 << 3;
+        /// Hang up, the other end is closed
+        const HUP = 1 << 4;
+        /// The fd isn't open, only reported by `poll`
+        const NVAL = 1 << 5;
+    }
+}
+
+/// File trait
+pub trait File: Send + Sync {
+    /// If readable
+    fn readable(&self) -> bool;
+    /// If writable
+    fn writable(&self) -> bool;
+    /// Read file to `UserBuffer`, return the bytes read or a negated errno
+    fn read(&self, buf: UserBuffer) -> isize;
+    /// Write `UserBuffer` to file, return the bytes written or a negated errno
+    fn write(&self, buf: UserBuffer) -> isize;
+    /// Read into a kernel buffer at `offset`, leaving the file offset alone.
+    /// `None` if the file has no offsets, like a device.
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Option<usize> {
+        None
+    }
+    /// Which of `events` the file is ready for, plus `ERR` and `HUP` if
+    /// they apply. Files that never wait are always ready.
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        events & (PollEvents::IN | PollEvents::OUT)
+    }
+    /// Wake `task` when the file may have become ready, to poll it again
+    fn register_waker(&self, _task: &Arc<TaskControlBlock>) {}
+    /// The socket behind the file, if it is one
+    fn as_socket(self: Arc<Self>) -> Option<Arc<UnixSocket>> {
+        None
+    }
+    /// The file as an epoll instance, if it is one
+    fn as_epoll(self: Arc<Self>) -> Option<Arc<Epoll>> {
+        None
+    }
+    /// The file as the console, if it is
+    fn as_console(self: Arc<Self>) -> Option<Arc<Console>> {
+        None
+    }
+    /// The pages of a file that can be mapped
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        None
+    }
+}
+
+pub use appfs::{AppFs, AppInode};
+pub use block_cache::block_cache_sync_all;
+pub use devfs::{Console, DevFs, DevInode, Null, Random, Zero, CONSOLE};
+pub use epoll::{Epoll, EpollCtl, EpollEvent};
+pub use ext2::{Ext2Fs, Ext2Inode};
+pub use fat32::{Fat32Fs, Fat32Inode};
+pub use inode::{open_exec, open_file, OSInode, OpenFlags};
+pub use page_cache::PageCache;
+pub use pipe::{make_pipe, Fifo, Pipe};
+pub use procfs::{ProcFs, ProcInode};
+pub use tmpfs::{TmpFs, TmpInode};
+pub use vfs::{
+    default_mode, lookup, lookup_parent, make_dir, make_node, mount, unlink, Access, Credentials,
+    FileSystem, Inode, InodeType, Metadata, S_ISVTX,
+};
+
+/// Mount the file system on `block_device`, trying each disk format in turn
+fn probe_disk(block_device: Arc<dyn BlockDevice>) -> Option<Arc<dyn FileSystem>> {
+    Fat32Fs::probe(block_device.clone())
+        .map(|fs| fs as Arc<dyn FileSystem>)
+        .or_else(|| Ext2Fs::probe(block_device).map(|fs| fs as Arc<dyn FileSystem>))
+}
+
+/// A tmpfs holding the content of the cpio archive `archive`
+fn unpack_initramfs(archive: &[u8]) -> Arc<dyn FileSystem> {
+    let fs = TmpFs::new();
+    match initramfs::unpack(archive, &fs.root_inode()) {
+        Some(count) => {
+            println!("[kernel] initramfs: {} entries unpacked", count);
+        }
+        None => {
+            println!("[kernel] initramfs: malformed archive, unpacked in part");
+        }
+    }
+    fs
+}
+
+/// Mount the root file system and the default mount points. The root is
+/// the unpacked `initramfs` if there is one, and the disk is then mounted
+/// at `/mnt`.
+pub fn init(initramfs: Option<&[u8]>) {
+    let mut disk = BLOCK_DEVICE.clone().and_then(probe_disk);
+    // Without a disk holding a known file system, the root lives in memory.
+    let root = match initramfs {
+        Some(archive) => unpack_initramfs(archive),
+        None => disk
+            .take()
+            .unwrap_or_else(|| TmpFs::new() as Arc<dyn FileSystem>),
+    };
+    mount("/", root);
+    if let Some(disk) = disk {
+        mount("/mnt", disk);
+    }
+    let tmp = TmpFs::new();
+    // anyone may create files in /tmp, and remove only their own
+    tmp.root_inode().set_metadata(Metadata {
+        mode: 0o777 | S_ISVTX,
+        uid: 0,
+        gid: 0,
+    });
+    mount("/tmp", tmp as Arc<dyn FileSystem>);
+    mount("/proc", ProcFs::new() as Arc<dyn FileSystem>);
+    mount("/dev", DevFs::new() as Arc<dyn FileSystem>);
+    mount("/bin", AppFs::new() as Arc<dyn FileSystem>);
+}
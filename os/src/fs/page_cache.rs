@@ -0,0 +1,145 @@
+//! Implementation of [`PageCache`], the pages of a file kept in memory
+//!
+//! Every regular file with an [`Inode::id`] has one cache, shared by all
+//! the files opened on it and all the mappings of it, so `read` and a
+//! shared mapping see the same frames. Writes go through to the inode and
+//! update the cached pages on the way; pages stored to through a mapping
+//! are written back by the mapping. A cache lives as long as an open file
+//! or a mapping uses it.
+use super::{Inode, InodeType};
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc, FrameTracker};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use lazy_static::*;
+
+lazy_static! {
+    /// Caches in use, by the identity of their file
+    static ref PAGE_CACHES: UPSafeCell<BTreeMap<(usize, usize), Weak<PageCache>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// The cached pages of a regular file
+pub struct PageCache {
+    inode: Arc<dyn Inode>,
+    /// frames by page index in the file
+    pages: UPSafeCell<BTreeMap<usize, Arc<FrameTracker>>>,
+}
+
+impl PageCache {
+    /// The cache of the regular file `inode`, `None` for other inodes and
+    /// files without an identity
+    pub fn of(inode: &Arc<dyn Inode>) -> Option<Arc<Self>> {
+        if inode.kind() != InodeType::File {
+            return None;
+        }
+        let id = inode.id()?;
+        let mut caches = PAGE_CACHES.exclusive_access();
+        if let Some(cache) = caches.get(&id).and_then(Weak::upgrade) {
+            return Some(cache);
+        }
+        caches.retain(|_, cache| cache.strong_count() > 0);
+        let cache = Arc::new(Self {
+            inode: inode.clone(),
+            pages: unsafe { UPSafeCell::new(BTreeMap::new()) },
+        });
+        caches.insert(id, Arc::downgrade(&cache));
+        Some(cache)
+    }
+    /// Stop handing out the cache of `inode`, whose name is gone. Users of
+    /// the cache keep it.
+    pub fn forget(inode: &Arc<dyn Inode>) {
+        if let Some(id) = inode.id() {
+            PAGE_CACHES.exclusive_access().remove(&id);
+        }
+    }
+    /// The frame holding page `index` of the file, read in if it isn't
+    /// cached. The part past the end of the file is zeroed. Return `None`
+    /// if there's no frame to read it into.
+    pub fn page(&self, index: usize) -> Option<Arc<FrameTracker>> {
+        let mut pages = self.pages.exclusive_access();
+        if let Some(frame) = pages.get(&index) {
+            return Some(frame.clone());
+        }
+        let frame = Arc::new(frame_alloc()?);
+        let page = frame.ppn.get_bytes_array();
+        let mut pos = 0;
+        while pos < PAGE_SIZE {
+            let len = self
+                .inode
+                .read_at(index * PAGE_SIZE + pos, &mut page[pos..]);
+            if len == 0 {
+                break;
+            }
+            pos += len;
+        }
+        pages.insert(index, frame.clone());
+        Some(frame)
+    }
+    /// Read the file at `offset` into `buf`, return the number of bytes read
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let end = self.inode.size().min(offset + buf.len());
+        let mut pos = offset;
+        while pos < end {
+            let in_page = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - in_page).min(end - pos);
+            let dst = &mut buf[pos - offset..pos - offset + len];
+            match self.page(pos / PAGE_SIZE) {
+                Some(frame) => {
+                    dst.copy_from_slice(&frame.ppn.get_bytes_array()[in_page..in_page + len])
+                }
+                // out of frames, read around the cache
+                None => {
+                    let read = self.inode.read_at(pos, dst);
+                    if read < len {
+                        return pos - offset + read;
+                    }
+                }
+            }
+            pos += len;
+        }
+        end.saturating_sub(offset)
+    }
+    /// Write `buf` to the file at `offset`, return the number of bytes
+    /// written
+    pub fn write(&self, offset: usize, buf: &[u8]) -> usize {
+        let written = self.inode.write_at(offset, buf);
+        // uncached pages are read in with the new data when they're needed
+        let pages = self.pages.exclusive_access();
+        let end = offset + written;
+        let mut pos = offset;
+        while pos < end {
+            let in_page = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - in_page).min(end - pos);
+            if let Some(frame) = pages.get(&(pos / PAGE_SIZE)) {
+                let page = frame.ppn.get_bytes_array();
+                page[in_page..in_page + len]
+                    .copy_from_slice(&buf[pos - offset..pos - offset + len]);
+            }
+            pos += len;
+        }
+        written
+    }
+    /// Write page `index` back to the file, up to the end of the file
+    pub fn write_back(&self, index: usize) {
+        let start = index * PAGE_SIZE;
+        let size = self.inode.size();
+        if start >= size {
+            return;
+        }
+        let frame = self.pages.exclusive_access().get(&index).cloned();
+        if let Some(frame) = frame {
+            let len = PAGE_SIZE.min(size - start);
+            self.inode
+                .write_at(start, &frame.ppn.get_bytes_array()[..len]);
+        }
+    }
+    /// Zero the cached pages after the file was truncated. Mappings keep
+    /// their frames and see zeros.
+    pub fn truncate(&self) {
+        for frame in self.pages.exclusive_access().values() {
+            frame.ppn.get_bytes_array().fill(0);
+        }
+    }
+}
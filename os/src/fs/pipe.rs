@@ -0,0 +1,283 @@
+//! Pipes, a ring buffer shared by read ends and write ends
+//!
+//! Each end is a [`Pipe`] held by the fd tables it was inherited into, and
+//! the buffer counts the ends still open: once every read end is dropped,
+//! writes fail with `EPIPE`, and once every write end is dropped, reads hit
+//! end of file. A reader blocks while the buffer is empty and a writer while
+//! it's full, until a signal cuts the wait short with `EINTR`. Blocked tasks
+//! and tasks polling either end are woken whenever data moves or an end is
+//! opened or closed.
+//!
+//! An anonymous pipe comes from [`make_pipe`]. A named one is a [`Fifo`]
+//! node in the file system, and each open of it adds an end.
+use super::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{UPSafeCell, WaitQueue};
+use crate::syscall::errno::{EINTR, EPIPE};
+use crate::task::{
+    block_current_and_run_next, current_interrupted, current_task, TaskControlBlock,
+};
+use alloc::sync::Arc;
+use core::cell::RefMut;
+
+/// Capacity of the ring buffer of a pipe
+const PIPE_BUF_SIZE: usize = 4096;
+
+/// One end of a pipe
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    buffer: Arc<UPSafeCell<PipeRingBuffer>>,
+}
+
+impl Pipe {
+    /// Open an end of the pipe with buffer `buffer`
+    fn new(readable: bool, writable: bool, buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+        let mut ring = buffer.exclusive_access();
+        if readable {
+            ring.readers += 1;
+            ring.read_opens += 1;
+        }
+        if writable {
+            ring.writers += 1;
+            ring.write_opens += 1;
+        }
+        // a FIFO end may wait for this one
+        ring.waiters.wake_all();
+        drop(ring);
+        Self {
+            readable,
+            writable,
+            buffer,
+        }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let mut ring = self.buffer.exclusive_access();
+        if self.readable {
+            ring.readers -= 1;
+        }
+        if self.writable {
+            ring.writers -= 1;
+        }
+        ring.waiters.wake_all();
+    }
+}
+
+/// The data in a pipe, from `head` on for `len` bytes with wrap-around
+pub struct PipeRingBuffer {
+    arr: [u8; PIPE_BUF_SIZE],
+    head: usize,
+    len: usize,
+    /// ends open for reading and for writing
+    readers: usize,
+    writers: usize,
+    /// how many times each kind of end was ever opened
+    read_opens: usize,
+    write_opens: usize,
+    /// tasks polling the pipe, or blocked opening, reading or writing it
+    waiters: WaitQueue,
+}
+
+impl PipeRingBuffer {
+    fn new() -> Self {
+        Self {
+            arr: [0; PIPE_BUF_SIZE],
+            head: 0,
+            len: 0,
+            readers: 0,
+            writers: 0,
+            read_opens: 0,
+            write_opens: 0,
+            waiters: WaitQueue::default(),
+        }
+    }
+    fn read_byte(&mut self) -> u8 {
+        let c = self.arr[self.head];
+        self.head = (self.head + 1) % PIPE_BUF_SIZE;
+        self.len -= 1;
+        c
+    }
+    fn write_byte(&mut self, c: u8) {
+        self.arr[(self.head + self.len) % PIPE_BUF_SIZE] = c;
+        self.len += 1;
+    }
+    fn available_read(&self) -> usize {
+        self.len
+    }
+    fn available_write(&self) -> usize {
+        PIPE_BUF_SIZE - self.len
+    }
+    fn all_read_ends_closed(&self) -> bool {
+        self.readers == 0
+    }
+    fn all_write_ends_closed(&self) -> bool {
+        self.writers == 0
+    }
+}
+
+/// Block the current task until the waiters of `ring` are woken. Return
+/// `Err(EINTR)` if a signal came or was already pending.
+fn wait_on(mut ring: RefMut<PipeRingBuffer>) -> Result<(), isize> {
+    if current_interrupted() {
+        return Err(EINTR);
+    }
+    ring.waiters.register(&current_task().unwrap());
+    drop(ring);
+    block_current_and_run_next();
+    if current_interrupted() {
+        return Err(EINTR);
+    }
+    Ok(())
+}
+
+/// Return (read_end, write_end) of a new pipe
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let buffer = Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) });
+    let read_end = Arc::new(Pipe::new(true, false, buffer.clone()));
+    let write_end = Arc::new(Pipe::new(false, true, buffer));
+    (read_end, write_end)
+}
+
+/// The buffer behind a named pipe, shared by everyone who opens it
+pub struct Fifo {
+    buffer: Arc<UPSafeCell<PipeRingBuffer>>,
+}
+
+impl Default for Fifo {
+    /// A FIFO nobody has opened yet
+    fn default() -> Self {
+        Self {
+            buffer: Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) }),
+        }
+    }
+}
+
+impl Fifo {
+    /// Open an end of the FIFO. Opening only one of the ends waits until
+    /// the other end is opened as well, or a signal interrupts it.
+    pub fn open(&self, readable: bool, writable: bool) -> Result<Arc<Pipe>, isize> {
+        let mut ring = self.buffer.exclusive_access();
+        if ring.readers == 0 && ring.writers == 0 {
+            // data nobody read before the last end was closed is gone
+            ring.head = 0;
+            ring.len = 0;
+        }
+        // a peer that opens and closes again before we look counts too
+        let (read_opens, write_opens) = (ring.read_opens, ring.write_opens);
+        drop(ring);
+        let pipe = Arc::new(Pipe::new(readable, writable, self.buffer.clone()));
+        if readable && writable {
+            return Ok(pipe);
+        }
+        loop {
+            let ring = self.buffer.exclusive_access();
+            let peer_opened = if readable {
+                ring.writers > 0 || ring.write_opens > write_opens
+            } else {
+                ring.readers > 0 || ring.read_opens > read_opens
+            };
+            if peer_opened {
+                return Ok(pipe);
+            }
+            wait_on(ring)?;
+        }
+    }
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, buf: UserBuffer) -> isize {
+        assert!(self.readable());
+        let want = buf.len();
+        if want == 0 {
+            return 0;
+        }
+        let mut buf_iter = buf.into_iter();
+        let mut ring = self.buffer.exclusive_access();
+        // wait for some data, or for the last writer to go away
+        while ring.available_read() == 0 {
+            if ring.all_write_ends_closed() {
+                return 0;
+            }
+            if let Err(errno) = wait_on(ring) {
+                return -errno;
+            }
+            ring = self.buffer.exclusive_access();
+        }
+        let len = want.min(ring.available_read());
+        for _ in 0..len {
+            let byte_ref = buf_iter.next().unwrap();
+            unsafe {
+                *byte_ref = ring.read_byte();
+            }
+        }
+        ring.waiters.wake_all();
+        len as isize
+    }
+    fn write(&self, buf: UserBuffer) -> isize {
+        assert!(self.writable());
+        let want = buf.len();
+        let mut buf_iter = buf.into_iter();
+        let mut written = 0;
+        while written < want {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.all_read_ends_closed() {
+                // report what made it into the pipe before the readers left
+                return if written > 0 {
+                    written as isize
+                } else {
+                    -EPIPE
+                };
+            }
+            let len = (want - written).min(ring.available_write());
+            if len == 0 {
+                if let Err(errno) = wait_on(ring) {
+                    return if written > 0 {
+                        written as isize
+                    } else {
+                        -errno
+                    };
+                }
+                continue;
+            }
+            for _ in 0..len {
+                let byte_ref = buf_iter.next().unwrap();
+                ring.write_byte(unsafe { *byte_ref });
+            }
+            ring.waiters.wake_all();
+            written += len;
+        }
+        written as isize
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let ring = self.buffer.exclusive_access();
+        let mut ready = PollEvents::empty();
+        if self.readable {
+            if ring.available_read() > 0 {
+                ready |= PollEvents::IN;
+            }
+            if ring.all_write_ends_closed() {
+                ready |= PollEvents::HUP;
+            }
+        }
+        if self.writable {
+            if ring.all_read_ends_closed() {
+                ready |= PollEvents::ERR;
+            } else if ring.available_write() > 0 {
+                ready |= PollEvents::OUT;
+            }
+        }
+        ready & (events | PollEvents::ERR | PollEvents::HUP)
+    }
+    fn register_waker(&self, task: &Arc<TaskControlBlock>) {
+        self.buffer.exclusive_access().waiters.register(task);
+    }
+}
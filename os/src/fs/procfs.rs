@@ -0,0 +1,264 @@
+//! Implementation of [`ProcFs`], exposing kernel state as text files
+//!
+//! ```text
+//! /proc/meminfo            frame usage
+//! /proc/<pid>/status       state, parent, threads, exit code and switches
+//! /proc/<pid>/stat         the same and more on one line, with CPU times
+//! /proc/<pid>/maps         user memory areas and their permissions
+//! /proc/<pid>/cmdline      command line the process runs
+//! /proc/self               the process reading it
+//! ```
+//!
+//! Nothing is stored: every read renders the file from the live kernel
+//! structures again.
+use super::{FileSystem, Inode, InodeType};
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_stats, MapPermission};
+use crate::task::{
+    current_task, cycles_to_ticks, pid2process, process_pids, ProcessControlBlock,
+    ProcessControlBlockInner, TaskStatus,
+};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The process information pseudo file system
+pub struct ProcFs;
+
+impl ProcFs {
+    /// Create a procfs instance
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self)
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn fs_type(&self) -> &'static str {
+        "procfs"
+    }
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(ProcInode::Root)
+    }
+}
+
+/// Files and directories of a procfs
+pub enum ProcInode {
+    /// `/proc`
+    Root,
+    /// `/proc/meminfo`
+    Meminfo,
+    /// `/proc/<pid>`
+    Process(usize),
+    /// `/proc/<pid>/status`
+    Status(usize),
+    /// `/proc/<pid>/stat`
+    Stat(usize),
+    /// `/proc/<pid>/maps`
+    Maps(usize),
+    /// `/proc/<pid>/cmdline`
+    Cmdline(usize),
+}
+
+/// Files in every `/proc/<pid>`
+const PROCESS_FILES: &[&str] = &["cmdline", "maps", "stat", "status"];
+
+impl ProcInode {
+    /// Render the content of a file, `None` if the process is gone
+    fn render(&self) -> Option<String> {
+        match *self {
+            Self::Root | Self::Process(_) => Some(String::new()),
+            Self::Meminfo => {
+                let (total, free) = frame_stats();
+                Some(format!(
+                    "MemTotal: {:>8} kB\nMemFree:  {:>8} kB\nMemUsed:  {:>8} kB\n",
+                    total * PAGE_SIZE / 1024,
+                    free * PAGE_SIZE / 1024,
+                    (total - free) * PAGE_SIZE / 1024,
+                ))
+            }
+            Self::Status(pid) => pid2process(pid).map(|process| render_status(&process)),
+            Self::Stat(pid) => pid2process(pid).map(|process| render_stat(&process)),
+            Self::Maps(pid) => pid2process(pid).map(|process| render_maps(&process)),
+            Self::Cmdline(pid) => {
+                pid2process(pid).map(|process| process.inner_exclusive_access().cmdline.clone())
+            }
+        }
+    }
+}
+
+/// The state of the busiest thread, or zombie once the process exited or
+/// stopped while it's stopped
+fn state(inner: &ProcessControlBlockInner) -> &'static str {
+    let statuses: Vec<_> = inner
+        .live_tasks()
+        .map(|task| task.inner_exclusive_access().task_status)
+        .collect();
+    if inner.is_zombie() {
+        "Z (zombie)"
+    } else if inner.stopped {
+        "T (stopped)"
+    } else if statuses.contains(&TaskStatus::Running) {
+        "R (running)"
+    } else if statuses.contains(&TaskStatus::Ready) {
+        "R (ready)"
+    } else {
+        "S (sleeping)"
+    }
+}
+
+/// pid of the parent, 0 if there is none
+fn ppid(inner: &ProcessControlBlockInner) -> usize {
+    inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid())
+}
+
+fn render_status(process: &Arc<ProcessControlBlock>) -> String {
+    let inner = process.inner_exclusive_access();
+    let usage = inner.usage();
+    format!(
+        "Pid:\t{}\nState:\t{}\nPPid:\t{}\nThreads:\t{}\nExitCode:\t{}\n\
+         voluntary_ctxt_switches:\t{}\nnonvoluntary_ctxt_switches:\t{}\n",
+        process.getpid(),
+        state(&inner),
+        ppid(&inner),
+        inner.live_tasks().count(),
+        inner.exit_code,
+        usage.nvcsw,
+        usage.nivcsw,
+    )
+}
+
+/// The first 20 fields of Linux's, from pid to the number of threads, the
+/// ones not kept are 0. Times are in clock ticks.
+fn render_stat(process: &Arc<ProcessControlBlock>) -> String {
+    let inner = process.inner_exclusive_access();
+    let (usage, children) = (inner.usage(), inner.children_usage);
+    let comm = inner.cmdline.split(' ').next().unwrap_or("");
+    let comm = comm.rsplit('/').next().unwrap_or(comm);
+    format!(
+        "{} ({}) {} {} {} {} 0 -1 0 {} {} 0 0 {} {} {} {} 20 0 {}\n",
+        process.getpid(),
+        comm,
+        &state(&inner)[..1],
+        ppid(&inner),
+        inner.pgid,
+        inner.sid,
+        usage.minflt,
+        children.minflt,
+        cycles_to_ticks(usage.utime),
+        cycles_to_ticks(usage.stime),
+        cycles_to_ticks(children.utime),
+        cycles_to_ticks(children.stime),
+        inner.live_tasks().count(),
+    )
+}
+
+fn render_maps(process: &Arc<ProcessControlBlock>) -> String {
+    let inner = process.inner_exclusive_access();
+    let mut maps = String::new();
+    for (start, end, perm) in inner.memory_set.area_info() {
+        let start: usize = start.into();
+        let end: usize = end.into();
+        maps.push_str(&format!(
+            "{:016x}-{:016x} {}{}{}{}\n",
+            start,
+            end,
+            if perm.contains(MapPermission::R) {
+                'r'
+            } else {
+                '-'
+            },
+            if perm.contains(MapPermission::W) {
+                'w'
+            } else {
+                '-'
+            },
+            if perm.contains(MapPermission::X) {
+                'x'
+            } else {
+                '-'
+            },
+            if perm.contains(MapPermission::U) {
+                'u'
+            } else {
+                '-'
+            },
+        ));
+    }
+    maps
+}
+
+impl Inode for ProcInode {
+    fn kind(&self) -> InodeType {
+        match self {
+            Self::Root | Self::Process(_) => InodeType::Directory,
+            _ => InodeType::File,
+        }
+    }
+    fn size(&self) -> usize {
+        self.render().map_or(0, |content| content.len())
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let content = match self.render() {
+            Some(content) => content,
+            None => return 0,
+        };
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return 0;
+        }
+        let len = buf.len().min(content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        len
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn clear(&self) {}
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        match *self {
+            Self::Root => {
+                let pid = match name {
+                    "meminfo" => return Some(Arc::new(Self::Meminfo)),
+                    "self" => current_task()?.process.upgrade()?.getpid(),
+                    _ => name.parse::<usize>().ok()?,
+                };
+                pid2process(pid)?;
+                Some(Arc::new(Self::Process(pid)))
+            }
+            Self::Process(pid) => {
+                let inode = match name {
+                    "status" => Self::Status(pid),
+                    "stat" => Self::Stat(pid),
+                    "maps" => Self::Maps(pid),
+                    "cmdline" => Self::Cmdline(pid),
+                    _ => return None,
+                };
+                Some(Arc::new(inode))
+            }
+            _ => None,
+        }
+    }
+    fn create(&self, _name: &str, _kind: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    fn ls(&self) -> Vec<String> {
+        match self {
+            Self::Root => {
+                let mut v = vec!["meminfo".to_string(), "self".to_string()];
+                v.extend(process_pids().iter().map(|pid| pid.to_string()));
+                v
+            }
+            Self::Process(_) => PROCESS_FILES.iter().map(|name| name.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Implementation of [`TmpFs`], a file system living in memory
+//!
+//! File data is kept page by page in frames taken from the frame allocator,
+//! so a tmpfs is gone together with its content once it's unmounted or the
+//! machine is reset.
+use super::{default_mode, Fifo, FileSystem, Inode, InodeType, Metadata};
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc, FrameTracker};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// A RAM-backed file system
+pub struct TmpFs {
+    root: Arc<TmpInode>,
+}
+
+impl TmpFs {
+    /// Create an empty tmpfs
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            root: Arc::new(TmpInode::new(InodeType::Directory)),
+        })
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn fs_type(&self) -> &'static str {
+        "tmpfs"
+    }
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+/// A file, directory, symbolic link or special node in a tmpfs
+pub struct TmpInode {
+    kind: InodeType,
+    /// the pipe buffer of a named pipe
+    fifo: Option<Arc<Fifo>>,
+    inner: UPSafeCell<TmpInodeInner>,
+}
+
+/// Mutable part of a [`TmpInode`]
+pub struct TmpInodeInner {
+    size: usize,
+    metadata: Metadata,
+    /// data pages of a regular file
+    frames: Vec<FrameTracker>,
+    /// entries of a directory
+    children: BTreeMap<String, Arc<TmpInode>>,
+}
+
+impl TmpInode {
+    /// Create an empty inode of type `kind`
+    pub fn new(kind: InodeType) -> Self {
+        Self {
+            kind,
+            fifo: (kind == InodeType::Fifo).then(|| Arc::new(Fifo::default())),
+            inner: unsafe {
+                UPSafeCell::new(TmpInodeInner {
+                    size: 0,
+                    metadata: Metadata {
+                        mode: default_mode(kind),
+                        uid: 0,
+                        gid: 0,
+                    },
+                    frames: Vec::new(),
+                    children: BTreeMap::new(),
+                })
+            },
+        }
+    }
+    fn inner_exclusive_access(&self) -> RefMut<'_, TmpInodeInner> {
+        self.inner.exclusive_access()
+    }
+}
+
+impl Inode for TmpInode {
+    fn kind(&self) -> InodeType {
+        self.kind
+    }
+    fn id(&self) -> Option<(usize, usize)> {
+        // there is a single object per file, alive as long as it's linked
+        Some((self as *const Self as usize, 0))
+    }
+    fn size(&self) -> usize {
+        self.inner_exclusive_access().size
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let inner = self.inner_exclusive_access();
+        let end = inner.size.min(offset + buf.len());
+        let mut pos = offset;
+        while pos < end {
+            let page_end = (pos / PAGE_SIZE + 1) * PAGE_SIZE;
+            let len = page_end.min(end) - pos;
+            let page = inner.frames[pos / PAGE_SIZE].ppn.get_bytes_array();
+            let in_page = pos % PAGE_SIZE;
+            buf[pos - offset..pos - offset + len].copy_from_slice(&page[in_page..in_page + len]);
+            pos += len;
+        }
+        end.saturating_sub(offset)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        // the data of a symlink is its target
+        if !matches!(self.kind, InodeType::File | InodeType::Symlink) {
+            return 0;
+        }
+        let mut inner = self.inner_exclusive_access();
+        let end = offset + buf.len();
+        let mut pos = offset;
+        while pos < end {
+            while inner.frames.len() <= pos / PAGE_SIZE {
+                match frame_alloc() {
+                    Some(frame) => inner.frames.push(frame),
+                    // out of memory, keep what has been written
+                    None => break,
+                }
+            }
+            if inner.frames.len() <= pos / PAGE_SIZE {
+                break;
+            }
+            let page_end = (pos / PAGE_SIZE + 1) * PAGE_SIZE;
+            let len = page_end.min(end) - pos;
+            let page = inner.frames[pos / PAGE_SIZE].ppn.get_bytes_array();
+            let in_page = pos % PAGE_SIZE;
+            page[in_page..in_page + len].copy_from_slice(&buf[pos - offset..pos - offset + len]);
+            pos += len;
+        }
+        inner.size = inner.size.max(pos);
+        pos - offset
+    }
+    fn clear(&self) {
+        let mut inner = self.inner_exclusive_access();
+        inner.size = 0;
+        inner.frames.clear();
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.inner_exclusive_access()
+            .children
+            .get(name)
+            .map(|inode| inode.clone() as Arc<dyn Inode>)
+    }
+    fn create(&self, name: &str, kind: InodeType) -> Option<Arc<dyn Inode>> {
+        if self.kind != InodeType::Directory {
+            return None;
+        }
+        let mut inner = self.inner_exclusive_access();
+        if inner.children.contains_key(name) {
+            return None;
+        }
+        let inode = Arc::new(TmpInode::new(kind));
+        inner.children.insert(name.to_string(), inode.clone());
+        Some(inode)
+    }
+    fn unlink(&self, name: &str) -> bool {
+        let mut inner = self.inner_exclusive_access();
+        match inner.children.get(name) {
+            Some(inode) if inode.inner_exclusive_access().children.is_empty() => {
+                // frames are released once the last opened file is closed
+                inner.children.remove(name);
+                true
+            }
+            _ => false,
+        }
+    }
+    fn ls(&self) -> Vec<String> {
+        self.inner_exclusive_access()
+            .children
+            .keys()
+            .cloned()
+            .collect()
+    }
+    fn read_link(&self) -> Option<String> {
+        if self.kind != InodeType::Symlink {
+            return None;
+        }
+        let mut target = vec![0u8; self.size()];
+        self.read_at(0, &mut target);
+        String::from_utf8(target).ok()
+    }
+    fn fifo(&self) -> Option<Arc<Fifo>> {
+        self.fifo.clone()
+    }
+    fn metadata(&self) -> Metadata {
+        self.inner_exclusive_access().metadata
+    }
+    fn set_metadata(&self, metadata: Metadata) -> bool {
+        self.inner_exclusive_access().metadata = metadata;
+        true
+    }
+}
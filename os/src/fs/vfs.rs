@@ -0,0 +1,341 @@
+//! Virtual file system layer
+//!
+//! Every file system implements [`Inode`] for its files and directories and
+//! [`FileSystem`] to hand out its root. Mounted file systems are kept in
+//! `MOUNTS`, indexed by the absolute path they are mounted at.
+use super::{Fifo, File, PageCache};
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EACCES, EBUSY, EEXIST, ELOOP, ENOENT, ENOTEMPTY, EPERM};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+use lazy_static::*;
+
+/// Type of an inode
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InodeType {
+    /// Regular file
+    File,
+    /// Directory
+    Directory,
+    /// Character device node
+    CharDevice,
+    /// Symbolic link, followed by path lookup
+    Symlink,
+    /// Named pipe
+    Fifo,
+    /// Unix domain socket bound to a path
+    Socket,
+}
+
+/// Sticky bit of a directory: only the owner of an entry, or of the
+/// directory, may remove it
+pub const S_ISVTX: u32 = 0o1000;
+
+/// Permission bits of a new inode of type `kind`, also the default of file
+/// systems that don't store them
+pub fn default_mode(kind: InodeType) -> u32 {
+    match kind {
+        InodeType::File | InodeType::Fifo => 0o644,
+        InodeType::Directory | InodeType::Socket => 0o755,
+        InodeType::CharDevice => 0o666,
+        InodeType::Symlink => 0o777,
+    }
+}
+
+/// Permission bits and owners of an inode
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Permission bits, `0o7777` at most
+    pub mode: u32,
+    /// Owning user
+    pub uid: u32,
+    /// Owning group
+    pub gid: u32,
+}
+
+bitflags! {
+    /// Accesses checked against the permission bits
+    pub struct Access: u32 {
+        /// Read a file or list a directory
+        const READ = 0o4;
+        /// Write a file or change the entries of a directory
+        const WRITE = 0o2;
+        /// Execute a file or search a directory
+        const EXEC = 0o1;
+    }
+}
+
+/// The user and group a task accesses files as, its effective ids
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Credentials {
+    /// Effective user id
+    pub uid: u32,
+    /// Group id
+    pub gid: u32,
+}
+
+impl Credentials {
+    /// The superuser, for the kernel's own accesses
+    pub const ROOT: Self = Self { uid: 0, gid: 0 };
+}
+
+impl Metadata {
+    /// Whether `cred` may access the inode as `access` asks. Root may do
+    /// anything but execute a file nobody may execute.
+    pub fn permits(&self, cred: Credentials, access: Access) -> bool {
+        if cred.uid == 0 {
+            return !access.contains(Access::EXEC) || self.mode & 0o111 != 0;
+        }
+        let shift = if cred.uid == self.uid {
+            6
+        } else if cred.gid == self.gid {
+            3
+        } else {
+            0
+        };
+        (self.mode >> shift) & access.bits() == access.bits()
+    }
+}
+
+/// Inode operations a file system provides to the VFS
+pub trait Inode: Send + Sync {
+    /// Type of the inode
+    fn kind(&self) -> InodeType;
+    /// Size of the file in bytes
+    fn size(&self) -> usize;
+    /// Read data at `offset` into `buf`, return the number of bytes read
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    /// Write `buf` at `offset`, return the number of bytes written
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    /// Drop all data of a regular file
+    fn clear(&self);
+    /// Find `name` in a directory
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>>;
+    /// Create `name` in a directory, fail if it already exists
+    fn create(&self, name: &str, kind: InodeType) -> Option<Arc<dyn Inode>>;
+    /// Remove `name` from a directory, a directory must be empty
+    fn unlink(&self, name: &str) -> bool;
+    /// List the names in a directory
+    fn ls(&self) -> Vec<String>;
+    /// The device behind a device node, opened in place of the inode
+    fn device(&self) -> Option<Arc<dyn File>> {
+        None
+    }
+    /// Target of a symbolic link
+    fn read_link(&self) -> Option<String> {
+        None
+    }
+    /// The pipe buffer of a named pipe, opened in place of the inode
+    fn fifo(&self) -> Option<Arc<Fifo>> {
+        None
+    }
+    /// Identity of the file behind the inode, the same for every inode
+    /// object of it: a key of its file system and a number unique there.
+    /// Files without one aren't kept in a page cache.
+    fn id(&self) -> Option<(usize, usize)> {
+        None
+    }
+    /// Permission bits and owners, root and [`default_mode`] if the file
+    /// system doesn't keep them
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            mode: default_mode(self.kind()),
+            uid: 0,
+            gid: 0,
+        }
+    }
+    /// Change the permission bits and owners, `false` if the file system
+    /// can't keep them
+    fn set_metadata(&self, _metadata: Metadata) -> bool {
+        false
+    }
+}
+
+/// A file system that can be mounted into the directory tree
+pub trait FileSystem: Send + Sync {
+    /// Name of the file system type, e.g. `tmpfs`
+    fn fs_type(&self) -> &'static str;
+    /// Root directory of the file system
+    fn root_inode(&self) -> Arc<dyn Inode>;
+}
+
+lazy_static! {
+    /// Mounted file systems indexed by their absolute mount point
+    static ref MOUNTS: UPSafeCell<BTreeMap<String, Arc<dyn FileSystem>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Split `path` into its components, resolving `.` and `..` lexically.
+/// Relative paths are taken from the root directory.
+fn components(path: &str) -> Vec<&str> {
+    let mut v: Vec<&str> = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                v.pop();
+            }
+            _ => v.push(name),
+        }
+    }
+    v
+}
+
+/// Absolute path made of `names`
+fn join(names: &[&str]) -> String {
+    if names.is_empty() {
+        return "/".to_string();
+    }
+    let mut path = String::new();
+    for name in names {
+        path.push('/');
+        path.push_str(name);
+    }
+    path
+}
+
+/// Mount `fs` at `path`. The mount point is created in the parent file
+/// system if it is missing; `/` has to be mounted first.
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) -> bool {
+    let path = join(&components(path));
+    if path != "/" && lookup(&path, Credentials::ROOT).is_err() {
+        if lookup_parent(&path, Credentials::ROOT).is_err() {
+            return false;
+        }
+        // a read-only parent can't hold the mount point, the mount is then
+        // still reachable by path but not listed in its parent
+        let _ = make_dir(&path, Credentials::ROOT);
+    }
+    println!("[kernel] mount {} at {}", fs.fs_type(), path);
+    MOUNTS.exclusive_access().insert(path, fs);
+    true
+}
+
+/// Symbolic links followed by one lookup before giving up
+const MAX_SYMLINKS: usize = 8;
+
+/// Find the inode at `path` for `cred`, crossing mount points and
+/// following symbolic links on the way. Every directory walked through must
+/// be searchable, or else it's `EACCES`.
+pub fn lookup(path: &str, cred: Credentials) -> Result<Arc<dyn Inode>, isize> {
+    let mut path = path.to_string();
+    for _ in 0..=MAX_SYMLINKS {
+        match walk(&path, cred)? {
+            Ok(inode) => return Ok(inode),
+            Err(next) => path = next,
+        }
+    }
+    Err(ELOOP)
+}
+
+/// Walk `path` for `cred` up to the first symbolic link. Return the inode
+/// at `path`, or the path to walk again with the link replaced by its
+/// target.
+fn walk(path: &str, cred: Credentials) -> Result<Result<Arc<dyn Inode>, String>, isize> {
+    let names = components(path);
+    let mounts = MOUNTS.exclusive_access();
+    let mut inode = mounts.get("/").ok_or(ENOENT)?.root_inode();
+    for i in 0..names.len() {
+        if inode.kind() != InodeType::Directory {
+            return Err(ENOENT);
+        }
+        if !inode.metadata().permits(cred, Access::EXEC) {
+            return Err(EACCES);
+        }
+        inode = match mounts.get(&join(&names[..=i])) {
+            Some(fs) => fs.root_inode(),
+            None => inode.find(names[i]).ok_or(ENOENT)?,
+        };
+        if inode.kind() == InodeType::Symlink {
+            let target = inode.read_link().ok_or(ENOENT)?;
+            // a relative target starts from the directory holding the link
+            let mut next = if target.starts_with('/') {
+                target
+            } else {
+                join(&names[..i]) + "/" + &target
+            };
+            for name in &names[i + 1..] {
+                next.push('/');
+                next.push_str(name);
+            }
+            return Ok(Err(next));
+        }
+    }
+    Ok(Ok(inode))
+}
+
+/// Find the directory holding `path` for `cred`, and the last component of
+/// `path`
+pub fn lookup_parent(path: &str, cred: Credentials) -> Result<(Arc<dyn Inode>, String), isize> {
+    let mut names = components(path);
+    let name = names.pop().ok_or(ENOENT)?.to_string();
+    let parent = lookup(&join(&names), cred)?;
+    if parent.kind() != InodeType::Directory {
+        return Err(ENOENT);
+    }
+    Ok((parent, name))
+}
+
+/// Create a directory at `path`
+pub fn make_dir(path: &str, cred: Credentials) -> Result<(), isize> {
+    make_node(path, InodeType::Directory, cred).map(|_| ())
+}
+
+/// Create an inode of type `kind` at `path`, owned by `cred`. The parent
+/// directory must be writable and searchable.
+pub fn make_node(path: &str, kind: InodeType, cred: Credentials) -> Result<Arc<dyn Inode>, isize> {
+    let (parent, name) = lookup_parent(path, cred)?;
+    if !parent
+        .metadata()
+        .permits(cred, Access::WRITE | Access::EXEC)
+    {
+        return Err(EACCES);
+    }
+    if parent.find(&name).is_some() {
+        return Err(EEXIST);
+    }
+    // e.g. the file system is read-only
+    let inode = parent.create(&name, kind).ok_or(EPERM)?;
+    inode.set_metadata(Metadata {
+        mode: default_mode(kind),
+        uid: cred.uid,
+        gid: cred.gid,
+    });
+    Ok(inode)
+}
+
+/// Remove the file or empty directory at `path`. The parent directory must
+/// be writable and searchable, and if it's sticky `cred` must own it or the
+/// file.
+pub fn unlink(path: &str, cred: Credentials) -> Result<(), isize> {
+    let names = components(path);
+    if MOUNTS.exclusive_access().contains_key(&join(&names)) {
+        return Err(EBUSY);
+    }
+    let (parent, name) = lookup_parent(path, cred)?;
+    let inode = parent.find(&name).ok_or(ENOENT)?;
+    let dir = parent.metadata();
+    if !dir.permits(cred, Access::WRITE | Access::EXEC) {
+        return Err(EACCES);
+    }
+    if dir.mode & S_ISVTX != 0
+        && cred.uid != 0
+        && cred.uid != dir.uid
+        && cred.uid != inode.metadata().uid
+    {
+        return Err(EPERM);
+    }
+    if inode.kind() == InodeType::Directory && !inode.ls().is_empty() {
+        return Err(ENOTEMPTY);
+    }
+    // e.g. a read-only file system
+    if !parent.unlink(&name) {
+        return Err(EPERM);
+    }
+    // a file created later in its place may get the same identity
+    PageCache::forget(&inode);
+    Ok(())
+}
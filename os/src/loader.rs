@@ -52,7 +52,6 @@ lazy_static! {
     };
 }
 
-#[allow(unused)]
 ///get app data from name
 pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
     let num_app = get_num_app();
@@ -60,6 +59,22 @@ pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
         .find(|&i| APP_NAMES[i] == name)
         .map(get_app_data)
 }
+///get the initramfs archive linked into the kernel
+pub fn linked_initramfs() -> Option<&'static [u8]> {
+    extern "C" {
+        fn _initramfs_start();
+        fn _initramfs_end();
+    }
+    let (start, end) = (_initramfs_start as usize, _initramfs_end as usize);
+    if start == end {
+        return None;
+    }
+    Some(unsafe { core::slice::from_raw_parts(start as *const u8, end - start) })
+}
+///names of all apps
+pub fn app_names() -> &'static [&'static str] {
+    APP_NAMES.as_slice()
+}
 ///list all apps
 pub fn list_apps() {
     println!("/**** APPS ****");
@@ -7,6 +7,9 @@
 //! - [`task`]: Task management
 //! - [`syscall`]: System call handling and implementation
 //! - [`mm`]: Address map using SV39
+//! - [`fs`]: Separate user from file system with some structures
+//! - [`drivers`]: Block devices the disk file systems live on
+//! - [`net`]: Unix domain sockets
 //! - [`sync`]:Wrap a static data structure inside it so that we are able to access it without any `unsafe`.
 //!
 //! The operating system also starts in this module. Kernel code starts
@@ -35,9 +38,14 @@ mod board;
 #[macro_use]
 mod console;
 mod config;
+pub mod drivers;
+mod fdt;
+pub mod fs;
 mod lang_items;
 mod loader;
 pub mod mm;
+pub mod net;
+mod random;
 mod sbi;
 pub mod sync;
 pub mod syscall;
@@ -63,11 +71,21 @@ fn clear_bss() {
 
 #[no_mangle]
 /// the rust entry-point of os
-pub fn rust_main() -> ! {
+pub fn rust_main(_hartid: usize, dtb: usize) -> ! {
     clear_bss();
     println!("[kernel] Hello, world!");
+    // the initrd stays where the boot loader put it until it's unpacked
+    let initrd = fdt::initrd_range(dtb).filter(|(_, end)| *end <= config::MEMORY_END);
+    if let Some((start, end)) = initrd {
+        mm::reserve_frames(start.into(), end.into());
+    }
     mm::init();
     mm::remap_test();
+    let initramfs = initrd
+        .map(|(start, end)| unsafe { core::slice::from_raw_parts(start as *const u8, end - start) })
+        .or_else(loader::linked_initramfs);
+    fs::init(initramfs);
+    mm::release_reserved_frames();
     task::add_initproc();
     println!("after initproc!");
     trap::init();
@@ -0,0 +1,132 @@
+//! Parsing the ELF header and program headers of an executable
+//!
+//! Only the parts needed to load a statically linked RV64 executable are
+//! read, through [`File::read_at`], so the file never has to be in memory
+//! as a whole.
+use crate::fs::File;
+
+/// Size of the ELF64 file header
+const EHDR_SIZE: usize = 64;
+/// Size of an ELF64 program header
+const PHDR_SIZE: usize = 56;
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 0xf3;
+/// Loadable segment
+pub const PT_LOAD: u32 = 1;
+/// Segment permissions
+pub const PF_X: u32 = 1;
+/// Segment permissions
+pub const PF_W: u32 = 2;
+/// Segment permissions
+pub const PF_R: u32 = 4;
+
+/// Why an executable can't be loaded
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ElfError {
+    /// The file ends before a header or segment does
+    Truncated,
+    /// The file doesn't start with the ELF magic
+    BadMagic,
+    /// Not a little-endian 64-bit RISC-V executable
+    Unsupported,
+    /// A segment overlaps another or leaves the user address space
+    BadSegment,
+    /// The program takes more than the address space limit
+    TooLarge,
+}
+
+/// Read exactly `buf.len()` bytes at `offset`
+pub fn read_exact(file: &dyn File, offset: usize, buf: &mut [u8]) -> Result<(), ElfError> {
+    match file.read_at(offset, buf) {
+        Some(len) if len == buf.len() => Ok(()),
+        _ => Err(ElfError::Truncated),
+    }
+}
+
+fn le16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn le32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn le64(buf: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+/// The fields of the ELF header the loader uses
+pub struct ElfHeader {
+    /// Entry point
+    pub entry: usize,
+    /// File offset of the program header table
+    pub phoff: usize,
+    /// Size of a program header
+    pub phentsize: usize,
+    /// Number of program headers
+    pub phnum: usize,
+}
+
+impl ElfHeader {
+    /// Read and check the ELF header of `file`
+    pub fn read(file: &dyn File) -> Result<Self, ElfError> {
+        let mut raw = [0u8; EHDR_SIZE];
+        read_exact(file, 0, &mut raw)?;
+        if raw[..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if raw[4] != ELFCLASS64
+            || raw[5] != ELFDATA2LSB
+            || le16(&raw, 16) != ET_EXEC
+            || le16(&raw, 18) != EM_RISCV
+            || (le16(&raw, 54) as usize) < PHDR_SIZE
+        {
+            return Err(ElfError::Unsupported);
+        }
+        Ok(Self {
+            entry: le64(&raw, 24),
+            phoff: le64(&raw, 32),
+            phentsize: le16(&raw, 54) as usize,
+            phnum: le16(&raw, 56) as usize,
+        })
+    }
+    /// Read program header `index`
+    pub fn program_header(&self, file: &dyn File, index: usize) -> Result<ProgramHeader, ElfError> {
+        let mut raw = [0u8; PHDR_SIZE];
+        read_exact(file, self.phoff + index * self.phentsize, &mut raw)?;
+        let ph = ProgramHeader {
+            p_type: le32(&raw, 0),
+            flags: le32(&raw, 4),
+            offset: le64(&raw, 8),
+            vaddr: le64(&raw, 16),
+            file_size: le64(&raw, 32),
+            mem_size: le64(&raw, 40),
+        };
+        if ph.file_size > ph.mem_size
+            || ph.vaddr.checked_add(ph.mem_size).is_none()
+            || ph.offset.checked_add(ph.file_size).is_none()
+        {
+            return Err(ElfError::BadSegment);
+        }
+        Ok(ph)
+    }
+}
+
+/// The fields of a program header the loader uses
+pub struct ProgramHeader {
+    /// Segment type
+    pub p_type: u32,
+    /// `PF_R`, `PF_W` and `PF_X`
+    pub flags: u32,
+    /// File offset of the segment data
+    pub offset: usize,
+    /// Virtual address of the segment
+    pub vaddr: usize,
+    /// Bytes of the segment in the file
+    pub file_size: usize,
+    /// Bytes of the segment in memory, the rest is zeroed
+    pub mem_size: usize,
+}
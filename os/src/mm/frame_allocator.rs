@@ -46,14 +46,46 @@ trait FrameAllocator {
 pub struct StackFrameAllocator {
     current: usize,
     end: usize,
+    total: usize,
     recycled: Vec<usize>,
+    /// frames `current` skips over, in use before the allocator was set up
+    reserved: (usize, usize),
 }
 
 impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
         self.current = l.0;
         self.end = r.0;
-        println!("last {} Physical Frames.", self.end - self.current);
+        self.total = r.0 - l.0;
+        let (start, end) = self.reserved;
+        self.reserved = (start.clamp(l.0, r.0), end.clamp(l.0, r.0));
+        println!("last {} Physical Frames.", self.free());
+    }
+    /// keep the frames in `[l, r)` from being allocated until they are released
+    pub fn reserve(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.reserved = (l.0, r.0);
+    }
+    /// hand the reserved frames out like any other
+    pub fn release_reserved(&mut self) {
+        let (start, end) = core::mem::take(&mut self.reserved);
+        if self.current > start {
+            // already skipped over
+            self.recycled.extend(start..end);
+        }
+    }
+    /// number of frames managed by the allocator
+    pub fn total(&self) -> usize {
+        self.total
+    }
+    /// number of frames not allocated yet
+    pub fn free(&self) -> usize {
+        let (start, end) = self.reserved;
+        let skipped = if self.current <= start {
+            end - start
+        } else {
+            0
+        };
+        self.end - self.current - skipped + self.recycled.len()
     }
 }
 impl FrameAllocator for StackFrameAllocator {
@@ -61,15 +93,21 @@ impl FrameAllocator for StackFrameAllocator {
         Self {
             current: 0,
             end: 0,
+            total: 0,
             recycled: Vec::new(),
+            reserved: (0, 0),
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
         if let Some(ppn) = self.recycled.pop() {
             Some(ppn.into())
-        } else if self.current == self.end {
-            None
         } else {
+            if self.current == self.reserved.0 {
+                self.current = self.reserved.1;
+            }
+            if self.current == self.end {
+                return None;
+            }
             self.current += 1;
             Some((self.current - 1).into())
         }
@@ -102,6 +140,17 @@ pub fn init_frame_allocator() {
         PhysAddr::from(MEMORY_END).floor(),
     );
 }
+/// keep the frames of `[start, end)` out of the frame allocator, must be
+/// called before `init_frame_allocator`
+pub fn reserve_frames(start: PhysAddr, end: PhysAddr) {
+    FRAME_ALLOCATOR
+        .exclusive_access()
+        .reserve(start.floor(), end.ceil());
+}
+/// return the frames kept by `reserve_frames` to the frame allocator
+pub fn release_reserved_frames() {
+    FRAME_ALLOCATOR.exclusive_access().release_reserved();
+}
 /// allocate a frame
 pub fn frame_alloc() -> Option<FrameTracker> {
     FRAME_ALLOCATOR
@@ -109,6 +158,11 @@ pub fn frame_alloc() -> Option<FrameTracker> {
         .alloc()
         .map(FrameTracker::new)
 }
+/// get (total, free) number of frames
+pub fn frame_stats() -> (usize, usize) {
+    let allocator = FRAME_ALLOCATOR.exclusive_access();
+    (allocator.total(), allocator.free())
+}
 /// deallocate a frame
 fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
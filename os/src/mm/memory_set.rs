@@ -1,11 +1,14 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
-use super::{frame_alloc, FrameTracker};
+use super::elf::{read_exact, ElfError, ElfHeader, PF_R, PF_W, PF_X, PT_LOAD};
+use super::{frame_alloc, frame_stats, FrameTracker};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use crate::config::{MEMORY_END, MMAP_BASE, MMIO, PAGE_SIZE, TRAMPOLINE, USER_SPACE_END};
+use crate::fs::{File, PageCache};
 use crate::sync::UPSafeCell;
-use alloc::collections::BTreeMap;
+use crate::syscall::errno::EFAULT;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::arch::asm;
@@ -30,10 +33,16 @@ lazy_static! {
     pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
         Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
 }
+/// the `satp` token of the kernel space
+pub fn kernel_token() -> usize {
+    KERNEL_SPACE.exclusive_access().token()
+}
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// Bytes the areas may take together, `RLIMIT_AS`
+    limit: usize,
 }
 
 impl MemorySet {
@@ -42,23 +51,68 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            limit: usize::MAX,
         }
     }
     ///Get pagetable `root_ppn`
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
-    /// Assume that no conflicts.
+    /// Bytes mapped by the areas
+    pub fn size(&self) -> usize {
+        let pages: usize = self
+            .areas
+            .iter()
+            .map(|area| area.vpn_range.get_end().0 - area.vpn_range.get_start().0)
+            .sum();
+        pages * PAGE_SIZE
+    }
+    /// Limit the bytes the areas may take together. Areas mapped already
+    /// stay, even past the limit.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+    /// Whether mapping pages `[start, end)`, over the user pages there, keeps
+    /// the areas within the limit
+    pub fn fits(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        let overlap: usize = self
+            .areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| {
+                let area_start = area.vpn_range.get_start().max(start);
+                let area_end = area.vpn_range.get_end().min(end);
+                area_end.0.saturating_sub(area_start.0)
+            })
+            .sum();
+        let pages = self.size() / PAGE_SIZE - overlap + (end.0 - start.0);
+        pages * PAGE_SIZE <= self.limit
+    }
+    /// Whether there are free frames for `pages` more pages and the page
+    /// tables mapping them, as mapping can't fail halfway
+    pub fn frames_for(pages: usize) -> bool {
+        let (_, free) = frame_stats();
+        // a leaf table per 512 pages, one more at each end and the ones above
+        pages + pages / 512 + 4 <= free
+    }
+    /// Whether no area has a page in `[start, end)`
+    pub fn is_free(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas
+            .iter()
+            .all(|area| area.vpn_range.get_end() <= start || area.vpn_range.get_start() >= end)
+    }
+    /// Assume that no conflicts. Return `false`, mapping nothing, if the
+    /// area doesn't fit within the limit or there aren't frames for it.
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> bool {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
     }
     ///Remove `MapArea` that starts with `start_vpn`
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
@@ -72,12 +126,151 @@ impl MemorySet {
             self.areas.remove(idx);
         }
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// Map pages `[start_vpn, end_vpn)` to the file of `cache` from page
+    /// `first_page` on. Stores reach the file if `shared`, or else go to
+    /// private copies. Assume that no conflicts. Return `false`, mapping
+    /// nothing, if the area doesn't fit within the limit or there aren't
+    /// frames for it.
+    pub fn insert_file_area(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        permission: MapPermission,
+        cache: Arc<PageCache>,
+        first_page: usize,
+        shared: bool,
+    ) -> bool {
+        let mut map_area = MapArea::new(
+            start_vpn.into(),
+            end_vpn.into(),
+            MapType::Framed,
+            permission,
+        );
+        map_area.file = Some(FileMapping {
+            cache,
+            first_page,
+            shared,
+            written: BTreeSet::new(),
+        });
+        self.push(map_area, None)
+    }
+    /// Start of `pages` free user pages: at `hint` if they're free there,
+    /// or else the lowest ones from `MMAP_BASE` on
+    pub fn free_range(&self, hint: VirtPageNum, pages: usize) -> Option<VirtPageNum> {
+        let end = VirtAddr::from(USER_SPACE_END).floor();
+        let is_free = |start: VirtPageNum| {
+            start.0 + pages <= end.0 && self.is_free(start, VirtPageNum(start.0 + pages))
+        };
+        if hint.0 != 0 && is_free(hint) {
+            return Some(hint);
+        }
+        let base = VirtAddr::from(MMAP_BASE).floor();
+        let mut candidates: Vec<VirtPageNum> = self
+            .areas
+            .iter()
+            .map(|area| area.vpn_range.get_end())
+            .filter(|vpn| *vpn > base)
+            .collect();
+        candidates.push(base);
+        candidates.sort();
+        candidates.into_iter().find(|start| is_free(*start))
+    }
+    /// Unmap the user pages in `[start, end)`, splitting the areas that
+    /// stick out of it. Dirty pages of shared file mappings are written back.
+    pub fn unmap_range(&mut self, start: VirtPageNum, end: VirtPageNum) {
+        let mut kept = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            let (area_start, area_end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if !area.map_perm.contains(MapPermission::U) || area_end <= start || area_start >= end {
+                kept.push(area);
+                continue;
+            }
+            let mut middle = if area_start < start {
+                let middle = area.split_off(start);
+                kept.push(area);
+                middle
+            } else {
+                area
+            };
+            if middle.vpn_range.get_end() > end {
+                kept.push(middle.split_off(end));
+            }
+            middle.unmap(&mut self.page_table);
+        }
+        self.areas = kept;
+    }
+    /// Write the dirty pages of shared file mappings in `[start, end)` back.
+    /// Return `false` if a page in there isn't mapped.
+    pub fn sync_range(&mut self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        let mapped = VPNRange::new(start, end).into_iter().all(|vpn| {
+            self.areas
+                .iter()
+                .any(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+        });
+        if !mapped {
+            return false;
+        }
+        for area in self.areas.iter_mut() {
+            area.sync(&mut self.page_table, start, end);
+        }
+        true
+    }
+    /// Handle a store to `vpn` that faulted. Return whether the store may
+    /// be tried again, which is when `vpn` is a write protected page of a
+    /// writable file mapping.
+    pub fn store_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let page_table = &mut self.page_table;
+        self.areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+            .map_or(false, |area| area.store_fault(page_table, vpn))
+    }
+    /// Take the faults of a store to `len` bytes at `va`, before the kernel
+    /// writes there on behalf of the task through the frames. Return
+    /// `Err(EFAULT)` if the task couldn't store there itself.
+    pub fn fault_in_writable(&mut self, va: usize, len: usize) -> Result<(), isize> {
+        if len == 0 {
+            return Ok(());
+        }
+        let end = va.checked_add(len).ok_or(EFAULT)?;
+        let range = VPNRange::new(VirtAddr::from(va).floor(), VirtAddr::from(end).ceil());
+        for vpn in range {
+            let writable = self.areas.iter().any(|area| {
+                area.vpn_range.get_start() <= vpn
+                    && vpn < area.vpn_range.get_end()
+                    && area.map_perm.contains(MapPermission::U | MapPermission::W)
+            });
+            if !writable {
+                return Err(EFAULT);
+            }
+            if self
+                .translate(vpn)
+                .map_or(false, |pte| pte.is_valid() && !pte.writable())
+                && !self.store_fault(vpn)
+            {
+                return Err(EFAULT);
+            }
+        }
+        Ok(())
+    }
+    /// Map `map_area`, with `data` copied in, unless it doesn't fit within
+    /// the limit or there aren't frames for it. Return whether it did.
+    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> bool {
+        let (start, end) = (map_area.vpn_range.get_start(), map_area.vpn_range.get_end());
+        if !self.fits(start, end) {
+            return false;
+        }
+        if map_area.map_type == MapType::Framed && !Self::frames_for(end.0 - start.0) {
+            return false;
+        }
+        if !map_area.map(&mut self.page_table) {
+            return false;
+        }
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        true
     }
     /// Mention that trampoline is not collected by areas.
     fn map_trampoline(&mut self) {
@@ -164,93 +357,93 @@ impl MemorySet {
         }
         memory_set
     }
-    /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// Include sections in elf and trampoline, also returns the base of the
+    /// user stacks and entry point. Threads map their user stack and
+    /// TrapContext themselves. The areas are to stay within `limit` bytes.
+    pub fn from_elf(elf: &dyn File, limit: usize) -> Result<(Self, usize, usize), ElfError> {
         let mut memory_set = Self::new_bare();
+        memory_set.limit = limit;
         // map trampoline
         memory_set.map_trampoline();
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
+        let elf_header = ElfHeader::read(elf)?;
         let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+        for i in 0..elf_header.phnum {
+            let ph = elf_header.program_header(elf, i)?;
+            if ph.p_type != PT_LOAD {
+                continue;
+            }
+            let end = ph
+                .vaddr
+                .checked_add(ph.mem_size)
+                .ok_or(ElfError::BadSegment)?;
+            let start_va: VirtAddr = ph.vaddr.into();
+            let end_va: VirtAddr = end.into();
+            // segments come in ascending order and may not share a page
+            if start_va.floor() < max_end_vpn || end > USER_SPACE_END {
+                return Err(ElfError::BadSegment);
+            }
+            let mut map_perm = MapPermission::U;
+            if ph.flags & PF_R != 0 {
+                map_perm |= MapPermission::R;
+            }
+            if ph.flags & PF_W != 0 {
+                map_perm |= MapPermission::W;
+            }
+            if ph.flags & PF_X != 0 {
+                map_perm |= MapPermission::X;
+            }
+            let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+            max_end_vpn = map_area.vpn_range.get_end();
+            if !memory_set.push(map_area, None) {
+                return Err(ElfError::TooLarge);
+            }
+            // copy the file part page by page, the frames start zeroed
+            let mut va = ph.vaddr;
+            let file_end = ph.vaddr + ph.file_size;
+            while va < file_end {
+                let page_offset = VirtAddr::from(va).page_offset();
+                let len = (PAGE_SIZE - page_offset).min(file_end - va);
+                let ppn = memory_set
+                    .translate(VirtAddr::from(va).floor())
+                    .unwrap()
+                    .ppn();
+                let dst = &mut ppn.get_bytes_array()[page_offset..page_offset + len];
+                read_exact(elf, ph.offset + (va - ph.vaddr), dst)?;
+                va += len;
             }
         }
-        // map user stack with U flags
+        // user stacks go above the elf, past a guard page
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
-            MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
-            None,
-        );
-        // map TrapContext
-        memory_set.push(
-            MapArea::new(
-                TRAP_CONTEXT.into(),
-                TRAMPOLINE.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+        let ustack_base: usize = usize::from(max_end_va) + PAGE_SIZE;
+        Ok((memory_set, ustack_base, elf_header.entry))
     }
-    ///Clone a same `MemorySet`
-    pub fn from_existed_user(user_space: &Self) -> Self {
+    /// Clone a same `MemorySet`, `None` if there aren't frames for it
+    pub fn from_existed_user(user_space: &Self) -> Option<Self> {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
             let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
+            if !memory_set.push(new_area, None) {
+                return None;
+            }
             // copy data from another space
             for vpn in area.vpn_range {
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                // a page cache frame is shared by both
+                if src_ppn == dst_ppn {
+                    continue;
+                }
                 dst_ppn
                     .get_bytes_array()
                     .copy_from_slice(src_ppn.get_bytes_array());
             }
         }
-        memory_set
+        memory_set.limit = user_space.limit;
+        Some(memory_set)
     }
     ///Refresh TLB with `sfence.vma`
     pub fn activate(&self) {
@@ -264,6 +457,16 @@ impl MemorySet {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
+    ///Get start, end and permission of every `MapArea`
+    pub fn area_info(&self) -> impl Iterator<Item = (VirtAddr, VirtAddr, MapPermission)> + '_ {
+        self.areas.iter().map(|area| {
+            (
+                area.vpn_range.get_start().into(),
+                area.vpn_range.get_end().into(),
+                area.map_perm,
+            )
+        })
+    }
     ///Remove all `MapArea`
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
@@ -273,9 +476,24 @@ impl MemorySet {
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// the file behind a file mapping
+    file: Option<FileMapping>,
+}
+
+/// The file mapped by a [`MapArea`]. Its pages are the frames of the page
+/// cache, mapped write protected until the first store to them.
+struct FileMapping {
+    cache: Arc<PageCache>,
+    /// page index in the file of the first page of the area
+    first_page: usize,
+    /// whether stores reach the file, or go to private copies
+    shared: bool,
+    /// pages stored to: dirty pages of a shared mapping, not yet written
+    /// back, or the copied pages of a private one
+    written: BTreeSet<VirtPageNum>,
 }
 
 impl MapArea {
@@ -292,30 +510,56 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            file: None,
         }
     }
+    /// An area like `another` with no pages mapped yet. A private file
+    /// mapping keeps its copied pages copied, the caller copies them over.
     pub fn from_another(another: &Self) -> Self {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            file: another.file.as_ref().map(|file| FileMapping {
+                cache: file.cache.clone(),
+                first_page: file.first_page,
+                shared: file.shared,
+                written: if file.shared {
+                    BTreeSet::new()
+                } else {
+                    file.written.clone()
+                },
+            }),
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    /// Map `vpn`. Return `false`, mapping nothing, if there's no frame for it.
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         let ppn: PhysPageNum;
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = match &self.file {
+                    Some(file) if file.shared || !file.written.contains(&vpn) => {
+                        pte_flags.remove(PTEFlags::W);
+                        let index = file.first_page + (vpn.0 - self.vpn_range.get_start().0);
+                        file.cache.page(index)
+                    }
+                    _ => frame_alloc().map(Arc::new),
+                };
+                let frame = match frame {
+                    Some(frame) => frame,
+                    None => return false,
+                };
                 ppn = frame.ppn;
                 self.data_frames.insert(vpn, frame);
             }
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
+        true
     }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         if self.map_type == MapType::Framed {
@@ -323,16 +567,94 @@ impl MapArea {
         }
         page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// Map all the pages, or none if there aren't frames for them all
+    pub fn map(&mut self, page_table: &mut PageTable) -> bool {
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            if !self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return false;
+            }
         }
+        true
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
     }
+    /// Split the area at `vpn`, keeping the pages below it and returning
+    /// an area of the others
+    fn split_off(&mut self, vpn: VirtPageNum) -> Self {
+        let (start, end) = (self.vpn_range.get_start(), self.vpn_range.get_end());
+        self.vpn_range = VPNRange::new(start, vpn);
+        Self {
+            vpn_range: VPNRange::new(vpn, end),
+            data_frames: self.data_frames.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            file: self.file.as_mut().map(|file| FileMapping {
+                cache: file.cache.clone(),
+                first_page: file.first_page + (vpn.0 - start.0),
+                shared: file.shared,
+                written: file.written.split_off(&vpn),
+            }),
+        }
+    }
+    /// Map the frame of `vpn` again, writable or not
+    fn remap(&self, page_table: &mut PageTable, vpn: VirtPageNum, writable: bool) {
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if !writable {
+            pte_flags.remove(PTEFlags::W);
+        }
+        page_table.unmap(vpn);
+        page_table.map(vpn, self.data_frames[&vpn].ppn, pte_flags);
+    }
+    /// Handle a store to the write protected page `vpn` of a writable file
+    /// mapping: mark it dirty if the mapping is shared, or else copy it.
+    /// Return `false` if the store isn't allowed, or there's no frame for
+    /// the copy.
+    fn store_fault(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let file = match self.file.as_mut() {
+            Some(file) if self.map_perm.contains(MapPermission::W) => file,
+            _ => return false,
+        };
+        // a page stored to before is writable already
+        if file.written.contains(&vpn) {
+            return false;
+        }
+        if !file.shared {
+            let frame = match frame_alloc() {
+                Some(frame) => frame,
+                None => return false,
+            };
+            let page = self.data_frames[&vpn].ppn.get_bytes_array();
+            frame.ppn.get_bytes_array().copy_from_slice(page);
+            self.data_frames.insert(vpn, Arc::new(frame));
+        }
+        file.written.insert(vpn);
+        self.remap(page_table, vpn, true);
+        true
+    }
+    /// Write the dirty pages in `[start, end)` of a shared file mapping
+    /// back, and protect them again to see the next store
+    fn sync(&mut self, page_table: &mut PageTable, start: VirtPageNum, end: VirtPageNum) {
+        let area_start = self.vpn_range.get_start();
+        let file = match self.file.as_mut() {
+            Some(file) if file.shared => file,
+            _ => return,
+        };
+        let dirty: Vec<VirtPageNum> = file.written.range(start..end).copied().collect();
+        for vpn in dirty.iter() {
+            file.written.remove(vpn);
+            file.cache
+                .write_back(file.first_page + (vpn.0 - area_start.0));
+        }
+        for vpn in dirty {
+            self.remap(page_table, vpn, false);
+        }
+    }
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
@@ -357,6 +679,18 @@ impl MapArea {
     }
 }
 
+impl Drop for MapArea {
+    /// Write the dirty pages of a shared file mapping back, as it's unmapped
+    fn drop(&mut self) {
+        let start = self.vpn_range.get_start();
+        if let Some(file) = self.file.as_ref().filter(|file| file.shared) {
+            for vpn in file.written.iter() {
+                file.cache.write_back(file.first_page + (vpn.0 - start.0));
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 /// map type for memory set: identical or framed
 pub enum MapType {
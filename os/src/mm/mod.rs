@@ -6,6 +6,7 @@
 //!
 //! Every task or process has a memory_set to control its virtual memory.
 mod address;
+mod elf;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
@@ -13,11 +14,17 @@ mod page_table;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
+pub use elf::ElfError;
+pub use frame_allocator::{
+    frame_alloc, frame_stats, release_reserved_frames, reserve_frames, FrameTracker,
+};
 pub use memory_set::remap_test;
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTableEntry};
-use page_table::{PTEFlags, PageTable};
+pub use memory_set::{kernel_token, MapPermission, MemorySet, KERNEL_SPACE};
+use page_table::PTEFlags;
+pub use page_table::{
+    copy_from_user, copy_to_user, translated_byte_buffer, translated_read, translated_refmut,
+    translated_str, translated_write, PageTable, PageTableEntry, UserBuffer, UserBufferIterator,
+};
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
     heap_allocator::init_heap();
@@ -4,6 +4,8 @@ use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
+use core::mem::{size_of, MaybeUninit};
+use core::slice;
 
 bitflags! {
     pub struct PTEFlags: u8 {
@@ -53,6 +55,10 @@ impl PageTableEntry {
     pub fn readable(&self) -> bool {
         (self.flags() & PTEFlags::R) != PTEFlags::empty()
     }
+    ///Check PTE accessible in U mode
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
     ///Check PTE writable
     pub fn writable(&self) -> bool {
         (self.flags() & PTEFlags::W) != PTEFlags::empty()
@@ -63,6 +69,7 @@ impl PageTableEntry {
     }
 }
 
+/// page table structure
 pub struct PageTable {
     root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
@@ -70,6 +77,7 @@ pub struct PageTable {
 
 /// Assume that it won't oom when creating/mapping.
 impl PageTable {
+    /// Create a new page table
     pub fn new() -> Self {
         let frame = frame_alloc().unwrap();
         PageTable {
@@ -120,21 +128,25 @@ impl PageTable {
         }
         result
     }
+    /// Map a virtual page to a physical page
     #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
         let pte = self.find_pte_create(vpn).unwrap();
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
+    /// Remove the mapping of a virtual page
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
+    /// Get the page table entry of a virtual page
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).map(|pte| *pte)
     }
+    /// Translate a virtual address to a physical address
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
         self.find_pte(va.clone().floor()).map(|pte| {
             //println!("translate_va:va = {:?}", va);
@@ -145,6 +157,7 @@ impl PageTable {
             (aligned_pa_usize + offset).into()
         })
     }
+    /// Get the `satp` value of the page table
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
@@ -201,3 +214,89 @@ pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
         .unwrap()
         .get_mut()
 }
+/// copy bytes from user space at `ptr` into `buf`, across pages
+pub fn copy_from_user(token: usize, ptr: *const u8, buf: &mut [u8]) {
+    let mut pos = 0;
+    for src in translated_byte_buffer(token, ptr, buf.len()) {
+        buf[pos..pos + src.len()].copy_from_slice(src);
+        pos += src.len();
+    }
+}
+/// copy `data` to user space at `ptr`, across pages
+pub fn copy_to_user(token: usize, ptr: *mut u8, data: &[u8]) {
+    let mut pos = 0;
+    for dst in translated_byte_buffer(token, ptr, data.len()) {
+        let len = dst.len();
+        dst.copy_from_slice(&data[pos..pos + len]);
+        pos += len;
+    }
+}
+/// read a plain value from user space, which may cross a page boundary
+pub fn translated_read<T: Copy>(token: usize, ptr: *const T) -> T {
+    let mut value = MaybeUninit::<T>::uninit();
+    let buf = unsafe { slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+    copy_from_user(token, ptr as *const u8, buf);
+    unsafe { value.assume_init() }
+}
+/// write a plain value to user space, which may cross a page boundary
+pub fn translated_write<T: Copy>(token: usize, ptr: *mut T, value: T) {
+    let data = unsafe { slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+    copy_to_user(token, ptr as *mut u8, data);
+}
+
+/// An abstraction over a buffer passed from user space to kernel space
+pub struct UserBuffer {
+    /// A list of buffers
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    /// Constuct UserBuffer
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        Self { buffers }
+    }
+    /// Get the length of the buffer
+    pub fn len(&self) -> usize {
+        let mut total: usize = 0;
+        for b in self.buffers.iter() {
+            total += b.len();
+        }
+        total
+    }
+}
+
+impl IntoIterator for UserBuffer {
+    type Item = *mut u8;
+    type IntoIter = UserBufferIterator;
+    fn into_iter(self) -> Self::IntoIter {
+        UserBufferIterator {
+            buffers: self.buffers,
+            current_buffer: 0,
+            current_idx: 0,
+        }
+    }
+}
+/// Iterator over every byte of a [`UserBuffer`]
+pub struct UserBufferIterator {
+    buffers: Vec<&'static mut [u8]>,
+    current_buffer: usize,
+    current_idx: usize,
+}
+
+impl Iterator for UserBufferIterator {
+    type Item = *mut u8;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_buffer >= self.buffers.len() {
+            None
+        } else {
+            let r = &mut self.buffers[self.current_buffer][self.current_idx] as *mut _;
+            if self.current_idx + 1 == self.buffers[self.current_buffer].len() {
+                self.current_idx = 0;
+                self.current_buffer += 1;
+            } else {
+                self.current_idx += 1;
+            }
+            Some(r)
+        }
+    }
+}
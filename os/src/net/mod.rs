@@ -0,0 +1,9 @@
+//! Sockets
+//!
+//! Only the Unix domain exists: [`UnixSocket`]s are reached through paths
+//! in the file system and live in the fd table like any other [`File`].
+//!
+//! [`File`]: crate::fs::File
+mod unix;
+
+pub use unix::{SocketType, UnixSocket};
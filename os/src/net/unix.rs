@@ -0,0 +1,512 @@
+//! Unix domain sockets, in stream and datagram flavours
+//!
+//! A socket is bound by creating a socket node at a path, and `BOUND`
+//! remembers which socket each node stands for. Connecting to a listening
+//! stream socket queues a new, already connected socket for `accept`; the
+//! two ends then talk through a `Stream` in each direction, which carries
+//! files along with the bytes. Datagram sockets queue whole messages at the
+//! receiver, with the address of the sender.
+//!
+//! Everything waits by yielding to other tasks, like pipes do, until a signal
+//! interrupts it, and wakes the tasks polling the socket when data moves. A socket that is passed over
+//! its own connection keeps itself alive until it's received.
+use crate::fs::{lookup, make_node, Credentials, File, Inode, InodeType, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{UPSafeCell, WaitQueue};
+use crate::syscall::errno::{
+    EADDRINUSE, ECONNREFUSED, EINVAL, EISCONN, EMSGSIZE, ENOTCONN, EOPNOTSUPP, EPIPE, EPROTOTYPE,
+};
+use crate::task::{suspend_interruptible, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Bytes a stream connection holds in each direction
+const STREAM_BUF_SIZE: usize = 4096;
+/// Datagrams a socket holds before senders have to wait
+const DGRAM_QUEUE_LEN: usize = 16;
+/// Size of the largest datagram
+const DGRAM_MAX_SIZE: usize = 4096;
+/// Largest backlog of a listening socket
+const MAX_BACKLOG: usize = 128;
+
+/// Socket nodes and the sockets bound to them
+type BoundList = Vec<(Weak<dyn Inode>, Weak<UnixSocket>)>;
+
+lazy_static! {
+    /// Bound sockets and the socket nodes they are bound to
+    static ref BOUND: UPSafeCell<BoundList> =
+        unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+/// The socket bound to the socket node at `path`, looked up for `cred`
+fn find_bound(path: &str, cred: Credentials) -> Result<Arc<UnixSocket>, isize> {
+    let inode = lookup(path, cred)?;
+    if inode.kind() != InodeType::Socket {
+        return Err(ECONNREFUSED);
+    }
+    let node = Arc::as_ptr(&inode) as *const u8;
+    BOUND
+        .exclusive_access()
+        .iter()
+        .find(|(bound, _)| bound.as_ptr() as *const u8 == node && bound.strong_count() > 0)
+        .and_then(|(_, socket)| socket.upgrade())
+        .ok_or(ECONNREFUSED)
+}
+
+/// What [`UnixSocket::recv`] got: the number of bytes, the files passed
+/// along and the address of the sender
+pub type Received = (usize, Vec<Arc<dyn File>>, Option<String>);
+
+/// Kind of a socket
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SocketType {
+    /// Connected byte stream, `SOCK_STREAM`
+    Stream,
+    /// Messages to any bound socket, `SOCK_DGRAM`
+    Datagram,
+}
+
+/// Bytes sent in one go, with the files passed along with them
+struct Segment {
+    data: VecDeque<u8>,
+    files: Vec<Arc<dyn File>>,
+}
+
+/// One direction of a stream connection
+#[derive(Default)]
+struct Stream {
+    segments: VecDeque<Segment>,
+    /// bytes in all segments
+    len: usize,
+    reader_closed: bool,
+    writer_closed: bool,
+    /// tasks polling either end
+    waiters: WaitQueue,
+}
+
+/// A message queued at a datagram socket
+struct Datagram {
+    data: Vec<u8>,
+    /// address of the sender, if it's bound
+    from: Option<String>,
+}
+
+/// Connection state of a stream socket
+enum SocketState {
+    Unconnected,
+    Listening {
+        backlog: usize,
+        /// connected sockets waiting for `accept`
+        pending: VecDeque<Arc<UnixSocket>>,
+    },
+    Connected {
+        /// from the peer
+        rx: Arc<UPSafeCell<Stream>>,
+        /// to the peer
+        tx: Arc<UPSafeCell<Stream>>,
+        /// address of the peer, if it's bound
+        peer: Option<String>,
+    },
+}
+
+/// A Unix domain socket, the [`File`] behind a socket fd
+pub struct UnixSocket {
+    kind: SocketType,
+    inner: UPSafeCell<SocketInner>,
+}
+
+/// Mutable part of a [`UnixSocket`]
+struct SocketInner {
+    /// path of the socket node it's bound to
+    path: Option<String>,
+    state: SocketState,
+    /// received datagrams
+    datagrams: VecDeque<Datagram>,
+    /// where `send` on a connected datagram socket goes
+    peer: Option<(Weak<UnixSocket>, String)>,
+    /// tasks polling for connections or datagrams
+    waiters: WaitQueue,
+}
+
+impl UnixSocket {
+    /// Create an unbound socket of type `kind`
+    pub fn new(kind: SocketType) -> Arc<Self> {
+        Self::with_state(kind, None, SocketState::Unconnected)
+    }
+    fn with_state(kind: SocketType, path: Option<String>, state: SocketState) -> Arc<Self> {
+        Arc::new(Self {
+            kind,
+            inner: unsafe {
+                UPSafeCell::new(SocketInner {
+                    path,
+                    state,
+                    datagrams: VecDeque::new(),
+                    peer: None,
+                    waiters: WaitQueue::default(),
+                })
+            },
+        })
+    }
+    /// Bind the socket to a new socket node at `path`, owned by `cred`
+    pub fn bind(self: &Arc<Self>, path: &str, cred: Credentials) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.path.is_some() {
+            return Err(EINVAL);
+        }
+        if lookup(path, cred).is_ok() {
+            return Err(EADDRINUSE);
+        }
+        // e.g. FAT32 can't hold socket nodes
+        let node = make_node(path, InodeType::Socket, cred)?;
+        let mut bound = BOUND.exclusive_access();
+        bound.retain(|(node, socket)| node.strong_count() > 0 && socket.strong_count() > 0);
+        bound.push((Arc::downgrade(&node), Arc::downgrade(self)));
+        inner.path = Some(path.to_string());
+        Ok(())
+    }
+    /// Accept connections, at most `backlog` of them waiting at a time
+    pub fn listen(&self, backlog: usize) -> Result<(), isize> {
+        if self.kind != SocketType::Stream {
+            return Err(EOPNOTSUPP);
+        }
+        let mut inner = self.inner.exclusive_access();
+        if inner.path.is_none() {
+            return Err(EINVAL);
+        }
+        let backlog = backlog.clamp(1, MAX_BACKLOG);
+        match &mut inner.state {
+            SocketState::Unconnected => {}
+            SocketState::Listening { backlog: old, .. } => {
+                *old = backlog;
+                return Ok(());
+            }
+            SocketState::Connected { .. } => return Err(EINVAL),
+        }
+        inner.state = SocketState::Listening {
+            backlog,
+            pending: VecDeque::new(),
+        };
+        Ok(())
+    }
+    /// Wait for a connection, return the socket connected to the peer and
+    /// the address of the peer
+    pub fn accept(&self) -> Result<(Arc<UnixSocket>, Option<String>), isize> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            match &mut inner.state {
+                SocketState::Listening { pending, .. } => {
+                    if let Some(socket) = pending.pop_front() {
+                        let peer = match &socket.inner.exclusive_access().state {
+                            SocketState::Connected { peer, .. } => peer.clone(),
+                            _ => None,
+                        };
+                        return Ok((socket, peer));
+                    }
+                }
+                _ => return Err(EINVAL),
+            }
+            drop(inner);
+            suspend_interruptible()?;
+        }
+    }
+    /// Connect to the socket bound at `path`. A stream socket waits for
+    /// room in the backlog of the listener; a datagram socket only records
+    /// where its messages go.
+    pub fn connect(&self, path: &str, cred: Credentials) -> Result<(), isize> {
+        let target = find_bound(path, cred)?;
+        if target.kind != self.kind {
+            return Err(EPROTOTYPE);
+        }
+        let mut inner = self.inner.exclusive_access();
+        if self.kind == SocketType::Datagram {
+            inner.peer = Some((Arc::downgrade(&target), path.to_string()));
+            return Ok(());
+        }
+        match inner.state {
+            SocketState::Unconnected => {}
+            SocketState::Listening { .. } => return Err(EINVAL),
+            SocketState::Connected { .. } => return Err(EISCONN),
+        }
+        let from = inner.path.clone();
+        drop(inner);
+        let to_server: Arc<UPSafeCell<Stream>> =
+            Arc::new(unsafe { UPSafeCell::new(Stream::default()) });
+        let to_client: Arc<UPSafeCell<Stream>> =
+            Arc::new(unsafe { UPSafeCell::new(Stream::default()) });
+        loop {
+            let mut listener = target.inner.exclusive_access();
+            let listener_path = listener.path.clone();
+            match &mut listener.state {
+                SocketState::Listening { backlog, pending } => {
+                    if pending.len() < *backlog {
+                        let state = SocketState::Connected {
+                            rx: to_server.clone(),
+                            tx: to_client.clone(),
+                            peer: from,
+                        };
+                        pending.push_back(Self::with_state(self.kind, listener_path, state));
+                        listener.waiters.wake_all();
+                        break;
+                    }
+                }
+                _ => return Err(ECONNREFUSED),
+            }
+            drop(listener);
+            suspend_interruptible()?;
+        }
+        let mut inner = self.inner.exclusive_access();
+        if !matches!(inner.state, SocketState::Unconnected) {
+            // connected by another process sharing the socket meanwhile
+            return Err(EISCONN);
+        }
+        inner.state = SocketState::Connected {
+            rx: to_client,
+            tx: to_server,
+            peer: Some(path.to_string()),
+        };
+        Ok(())
+    }
+    /// Send `buf` with `files` to the peer, or to the datagram socket at
+    /// `to`, looked up for `cred`. Return the number of bytes sent.
+    pub fn send(
+        &self,
+        buf: UserBuffer,
+        files: Vec<Arc<dyn File>>,
+        to: Option<&str>,
+        cred: Credentials,
+    ) -> Result<usize, isize> {
+        match self.kind {
+            SocketType::Stream if to.is_some() => Err(EISCONN),
+            SocketType::Stream => self.send_stream(buf, files),
+            SocketType::Datagram if !files.is_empty() => Err(EOPNOTSUPP),
+            SocketType::Datagram => self.send_datagram(buf, to, cred),
+        }
+    }
+    fn send_stream(&self, buf: UserBuffer, files: Vec<Arc<dyn File>>) -> Result<usize, isize> {
+        let tx = match &self.inner.exclusive_access().state {
+            SocketState::Connected { tx, .. } => tx.clone(),
+            _ => return Err(ENOTCONN),
+        };
+        let want = buf.len();
+        let mut bytes = buf.into_iter();
+        // the files go with the first byte
+        let mut files = Some(files);
+        let mut written = 0;
+        while written < want {
+            let mut stream = tx.exclusive_access();
+            if stream.reader_closed {
+                // report what made it through before the peer left
+                return if written > 0 { Ok(written) } else { Err(EPIPE) };
+            }
+            let len = (want - written).min(STREAM_BUF_SIZE - stream.len);
+            if len == 0 {
+                drop(stream);
+                if let Err(errno) = suspend_interruptible() {
+                    return if written > 0 { Ok(written) } else { Err(errno) };
+                }
+                continue;
+            }
+            let data = (0..len)
+                .map(|_| unsafe { *bytes.next().unwrap() })
+                .collect();
+            stream.segments.push_back(Segment {
+                data,
+                files: files.take().unwrap_or_default(),
+            });
+            stream.len += len;
+            stream.waiters.wake_all();
+            written += len;
+        }
+        Ok(written)
+    }
+    fn send_datagram(
+        &self,
+        buf: UserBuffer,
+        to: Option<&str>,
+        cred: Credentials,
+    ) -> Result<usize, isize> {
+        let target = match to {
+            Some(path) => find_bound(path, cred)?,
+            None => match &self.inner.exclusive_access().peer {
+                Some((peer, _)) => peer.upgrade().ok_or(ECONNREFUSED)?,
+                None => return Err(ENOTCONN),
+            },
+        };
+        if target.kind != SocketType::Datagram {
+            return Err(EPROTOTYPE);
+        }
+        if buf.len() > DGRAM_MAX_SIZE {
+            return Err(EMSGSIZE);
+        }
+        let data: Vec<u8> = buf.into_iter().map(|byte| unsafe { *byte }).collect();
+        let from = self.inner.exclusive_access().path.clone();
+        loop {
+            let mut receiver = target.inner.exclusive_access();
+            if receiver.datagrams.len() < DGRAM_QUEUE_LEN {
+                let len = data.len();
+                receiver.datagrams.push_back(Datagram { data, from });
+                receiver.waiters.wake_all();
+                return Ok(len);
+            }
+            drop(receiver);
+            suspend_interruptible()?;
+        }
+    }
+    /// Wait for data and read it into `buf`
+    ///
+    /// Files are only received with the first byte they were sent with, so
+    /// a read stops short of the next bytes that carry files.
+    pub fn recv(&self, buf: UserBuffer) -> Result<Received, isize> {
+        match self.kind {
+            SocketType::Stream => self.recv_stream(buf),
+            SocketType::Datagram => self.recv_datagram(buf),
+        }
+    }
+    fn recv_stream(&self, buf: UserBuffer) -> Result<Received, isize> {
+        let (rx, peer) = match &self.inner.exclusive_access().state {
+            SocketState::Connected { rx, peer, .. } => (rx.clone(), peer.clone()),
+            _ => return Err(ENOTCONN),
+        };
+        let want = buf.len();
+        if want == 0 {
+            return Ok((0, Vec::new(), peer));
+        }
+        let mut stream = rx.exclusive_access();
+        // wait for some data, or for the peer to go away
+        while stream.len == 0 {
+            if stream.writer_closed {
+                return Ok((0, Vec::new(), peer));
+            }
+            drop(stream);
+            suspend_interruptible()?;
+            stream = rx.exclusive_access();
+        }
+        let mut bytes = buf.into_iter();
+        let files = core::mem::take(&mut stream.segments[0].files);
+        let mut read = 0;
+        while read < want {
+            let segment = match stream.segments.front_mut() {
+                Some(segment) if read == 0 || segment.files.is_empty() => segment,
+                _ => break,
+            };
+            while read < want {
+                match segment.data.pop_front() {
+                    Some(byte) => unsafe { *bytes.next().unwrap() = byte },
+                    None => break,
+                }
+                read += 1;
+            }
+            if segment.data.is_empty() {
+                stream.segments.pop_front();
+            }
+        }
+        stream.len -= read;
+        stream.waiters.wake_all();
+        Ok((read, files, peer))
+    }
+    fn recv_datagram(&self, buf: UserBuffer) -> Result<Received, isize> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if let Some(datagram) = inner.datagrams.pop_front() {
+                // the part of the message that doesn't fit is lost
+                let mut len = 0;
+                for (dst, src) in buf.into_iter().zip(datagram.data.iter()) {
+                    unsafe { *dst = *src };
+                    len += 1;
+                }
+                return Ok((len, Vec::new(), datagram.from));
+            }
+            drop(inner);
+            suspend_interruptible()?;
+        }
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        if let SocketState::Connected { rx, tx, .. } = &self.inner.exclusive_access().state {
+            let mut stream = rx.exclusive_access();
+            stream.reader_closed = true;
+            stream.len = 0;
+            let in_flight = core::mem::take(&mut stream.segments);
+            stream.waiters.wake_all();
+            drop(stream);
+            let mut stream = tx.exclusive_access();
+            stream.writer_closed = true;
+            stream.waiters.wake_all();
+            drop(stream);
+            // files in flight to this socket are closed, which may drop
+            // sockets connected to this one
+            drop(in_flight);
+        }
+    }
+}
+
+impl File for UnixSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: UserBuffer) -> isize {
+        match self.recv(buf) {
+            Ok((len, _, _)) => len as isize,
+            Err(errno) => -errno,
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> isize {
+        // there is no path to look up
+        match self.send(buf, Vec::new(), None, Credentials::ROOT) {
+            Ok(len) => len as isize,
+            Err(errno) => -errno,
+        }
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut ready = PollEvents::empty();
+        match &inner.state {
+            _ if self.kind == SocketType::Datagram => {
+                ready |= PollEvents::OUT;
+                if !inner.datagrams.is_empty() {
+                    ready |= PollEvents::IN;
+                }
+            }
+            SocketState::Unconnected => ready |= PollEvents::OUT | PollEvents::HUP,
+            SocketState::Listening { pending, .. } => {
+                if !pending.is_empty() {
+                    ready |= PollEvents::IN;
+                }
+            }
+            SocketState::Connected { rx, tx, .. } => {
+                let rx = rx.exclusive_access();
+                if rx.len > 0 || rx.writer_closed {
+                    ready |= PollEvents::IN;
+                }
+                if rx.writer_closed {
+                    ready |= PollEvents::HUP;
+                }
+                let tx = tx.exclusive_access();
+                // writing to a closed peer fails right away
+                if tx.reader_closed || tx.len < STREAM_BUF_SIZE {
+                    ready |= PollEvents::OUT;
+                }
+            }
+        }
+        ready & (events | PollEvents::ERR | PollEvents::HUP)
+    }
+    fn register_waker(&self, task: &Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        inner.waiters.register(task);
+        if let SocketState::Connected { rx, tx, .. } = &inner.state {
+            rx.exclusive_access().waiters.register(task);
+            tx.exclusive_access().waiters.register(task);
+        }
+    }
+    fn as_socket(self: Arc<Self>) -> Option<Arc<UnixSocket>> {
+        Some(self)
+    }
+}
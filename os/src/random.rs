@@ -0,0 +1,62 @@
+//! Kernel pseudo random number generator
+//!
+//! A xorshift64* generator seeded from the `time` CSR the first time it's
+//! used. It's fast and good enough for `/dev/random`, but not meant for
+//! anything cryptographic.
+use crate::sync::UPSafeCell;
+use crate::timer::get_time;
+use lazy_static::*;
+
+/// xorshift64* state
+pub struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    /// Create a generator from `seed`
+    pub fn new(seed: u64) -> Self {
+        let mut prng = Self {
+            state: 0x9e37_79b9_7f4a_7c15,
+        };
+        prng.feed(seed);
+        prng
+    }
+    /// Get the next random number
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+    /// Stir `value` into the state
+    pub fn feed(&mut self, value: u64) {
+        self.state ^= value.rotate_left(17);
+        // the state must never be zero
+        if self.state == 0 {
+            self.state = 0x9e37_79b9_7f4a_7c15;
+        }
+    }
+}
+
+lazy_static! {
+    /// The global generator
+    static ref PRNG: UPSafeCell<Prng> = unsafe { UPSafeCell::new(Prng::new(get_time() as u64)) };
+}
+
+/// Get a random number
+pub fn rand_u64() -> u64 {
+    let mut prng = PRNG.exclusive_access();
+    // mix in the timer so that bytes read at different times differ more
+    prng.feed(get_time() as u64);
+    prng.next_u64()
+}
+
+/// Fill `buf` with random bytes
+pub fn fill_random(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        let bytes = rand_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
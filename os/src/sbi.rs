@@ -28,3 +28,10 @@ pub fn shutdown(failure: bool) -> ! {
     }
     unreachable!()
 }
+
+/// use sbi call to reboot the machine
+pub fn reboot() -> ! {
+    use sbi_rt::{system_reset, ColdReboot, NoReason};
+    system_reset(ColdReboot, NoReason);
+    unreachable!()
+}
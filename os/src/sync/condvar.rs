@@ -0,0 +1,38 @@
+//! Condition variables of user threads, see [`Condvar`]
+use super::{Mutex, UPSafeCell, WaitQueue};
+use crate::syscall::errno::EINTR;
+use crate::task::{block_current_and_run_next, current_killed, current_task};
+use alloc::sync::Arc;
+
+/// Threads waiting, with a mutex released, until another thread signals a
+/// change of what the mutex guards
+pub struct Condvar {
+    waiters: UPSafeCell<WaitQueue>,
+}
+
+impl Condvar {
+    /// Create a condition variable nobody waits on
+    pub fn new() -> Self {
+        Self {
+            waiters: unsafe { UPSafeCell::new(WaitQueue::default()) },
+        }
+    }
+    /// Wake the thread waiting longest
+    pub fn signal(&self) {
+        self.waiters.exclusive_access().wake_one();
+    }
+    /// Release `mutex` and block until signalled, then take `mutex` again.
+    /// A signal to the thread may end the wait early as well, so the caller
+    /// has to check again what it waits for.
+    pub fn wait(&self, mutex: Arc<dyn Mutex>) -> Result<(), isize> {
+        let task = current_task().unwrap();
+        self.waiters.exclusive_access().register(&task);
+        mutex.unlock();
+        block_current_and_run_next();
+        self.waiters.exclusive_access().remove(&task);
+        if current_killed() {
+            return Err(EINTR);
+        }
+        mutex.lock()
+    }
+}
@@ -0,0 +1,134 @@
+//! Banker's algorithm over the mutexes and semaphores of a process, see
+//! [`DeadlockDetector`]
+use crate::syscall::errno::EDEADLK;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// A sync object of a process, by its id
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    /// A mutex, which has one unit
+    Mutex(usize),
+    /// A semaphore, which has as many units as its count
+    Semaphore(usize),
+}
+
+/// Units of each resource
+type Units = BTreeMap<Resource, usize>;
+
+/// Add one unit of `resource` to `units`
+fn add_unit(units: &mut Units, resource: Resource) {
+    *units.entry(resource).or_default() += 1;
+}
+
+/// Take one unit of `resource` from `units` if there is one
+fn take_unit(units: &mut Units, resource: Resource) {
+    if let Some(count) = units.get_mut(&resource) {
+        *count -= 1;
+        if *count == 0 {
+            units.remove(&resource);
+        }
+    }
+}
+
+/// What a thread holds and waits for
+#[derive(Default)]
+struct Claims {
+    allocation: Units,
+    need: Units,
+}
+
+/// Bookkeeping of who holds and who waits for the units of each resource.
+///
+/// The state is safe if the threads can all finish in some order, each
+/// getting what it waits for from the units that are free or held by threads
+/// finished before it. A semaphore only `up`ped by threads that never took
+/// from it, to signal them, is beyond what this can tell: waiting on it looks
+/// like a deadlock.
+#[derive(Default)]
+pub struct DeadlockDetector {
+    /// Whether requests that leave the state unsafe are refused
+    pub enabled: bool,
+    available: Units,
+    /// Claims of each thread, by tid
+    threads: BTreeMap<usize, Claims>,
+}
+
+impl DeadlockDetector {
+    /// Create a detector, disabled, for a process without sync objects
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Make `count` units of the new `resource` available
+    pub fn add_resource(&mut self, resource: Resource, count: usize) {
+        if count > 0 {
+            self.available.insert(resource, count);
+        }
+    }
+    /// Note that thread `tid` waits for a unit of `resource`. Return
+    /// `Err(EDEADLK)` instead if enabled and that makes the state unsafe.
+    pub fn request(&mut self, tid: usize, resource: Resource) -> Result<(), isize> {
+        add_unit(&mut self.threads.entry(tid).or_default().need, resource);
+        if self.enabled && !self.is_safe() {
+            self.cancel(tid, resource);
+            return Err(EDEADLK);
+        }
+        Ok(())
+    }
+    /// Thread `tid` got a unit of `resource`
+    pub fn acquire(&mut self, tid: usize, resource: Resource) {
+        let claims = self.threads.entry(tid).or_default();
+        take_unit(&mut claims.need, resource);
+        add_unit(&mut claims.allocation, resource);
+        take_unit(&mut self.available, resource);
+    }
+    /// Thread `tid` gave up waiting for a unit of `resource`
+    pub fn cancel(&mut self, tid: usize, resource: Resource) {
+        if let Some(claims) = self.threads.get_mut(&tid) {
+            take_unit(&mut claims.need, resource);
+        }
+    }
+    /// Thread `tid` put a unit of `resource` back, one it holds or, for a
+    /// semaphore, a new one
+    pub fn release(&mut self, tid: usize, resource: Resource) {
+        if let Some(claims) = self.threads.get_mut(&tid) {
+            take_unit(&mut claims.allocation, resource);
+        }
+        add_unit(&mut self.available, resource);
+    }
+    /// Whether thread `tid` holds a unit of `resource`
+    pub fn holds(&self, tid: usize, resource: Resource) -> bool {
+        self.threads
+            .get(&tid)
+            .map_or(false, |claims| claims.allocation.contains_key(&resource))
+    }
+    /// Forget thread `tid`, which exited. What it held stays taken.
+    pub fn remove_thread(&mut self, tid: usize) {
+        self.threads.remove(&tid);
+    }
+    /// Whether the threads can all get what they wait for in some order
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut unfinished: Vec<&Claims> = self.threads.values().collect();
+        loop {
+            let count = unfinished.len();
+            unfinished.retain(|claims| {
+                let can_finish = claims.need.iter().all(|(resource, need)| {
+                    work.get(resource).map_or(false, |units| units >= need)
+                });
+                if can_finish {
+                    for (resource, units) in claims.allocation.iter() {
+                        *work.entry(*resource).or_default() += units;
+                    }
+                }
+                !can_finish
+            });
+            if unfinished.is_empty() {
+                return true;
+            }
+            if unfinished.len() == count {
+                return false;
+            }
+        }
+    }
+}
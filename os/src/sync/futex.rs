@@ -0,0 +1,72 @@
+//! Futex wait queues, keyed by the physical address of the futex word
+//!
+//! Keying by physical address lets processes sharing a page wait on the same
+//! word wherever each of them maps it. The value check against user memory
+//! is up to the caller, nothing runs between it and the wait.
+use super::{UPSafeCell, WaitQueue};
+use crate::syscall::errno::{EINTR, ETIMEDOUT};
+use crate::task::{block_current_and_run_next, current_interrupted, current_task};
+use crate::timer::{add_timer, get_time_ms};
+use alloc::collections::BTreeMap;
+use lazy_static::*;
+
+lazy_static! {
+    /// Threads waiting on each futex, queues are dropped once empty
+    static ref FUTEX_QUEUES: UPSafeCell<BTreeMap<usize, WaitQueue>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Block the current thread on the futex at physical address `key` until
+/// [`futex_wake`] wakes it. Return `Err(EINTR)` if a signal comes first, or
+/// `Err(ETIMEDOUT)` once `deadline` in ms passes.
+pub fn futex_wait(key: usize, deadline: Option<usize>) -> Result<(), isize> {
+    let task = current_task().unwrap();
+    loop {
+        if current_interrupted() {
+            return Err(EINTR);
+        }
+        if deadline.map_or(false, |deadline| get_time_ms() >= deadline) {
+            return Err(ETIMEDOUT);
+        }
+        FUTEX_QUEUES
+            .exclusive_access()
+            .entry(key)
+            .or_default()
+            .register(&task);
+        if let Some(deadline) = deadline {
+            add_timer(deadline, task.clone());
+        }
+        block_current_and_run_next();
+        // still queued unless futex_wake woke the thread
+        let mut queues = FUTEX_QUEUES.exclusive_access();
+        let queued = match queues.get_mut(&key) {
+            Some(queue) => {
+                let queued = queue.remove(&task);
+                if queue.is_empty() {
+                    queues.remove(&key);
+                }
+                queued
+            }
+            None => false,
+        };
+        if !queued {
+            return Ok(());
+        }
+    }
+}
+
+/// Wake up to `count` threads waiting on the futex at physical address
+/// `key`, first come first. Return how many were woken.
+pub fn futex_wake(key: usize, count: usize) -> usize {
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    let mut woken = 0;
+    if let Some(queue) = queues.get_mut(&key) {
+        while woken < count && queue.wake_one() {
+            woken += 1;
+        }
+        if queue.is_empty() {
+            queues.remove(&key);
+        }
+    }
+    woken
+}
@@ -1,4 +1,16 @@
 //! Synchronization and interior mutability primitives
+mod condvar;
+mod deadlock;
+mod futex;
+mod mutex;
+mod semaphore;
 mod up;
+mod wait_queue;
 
+pub use condvar::Condvar;
+pub use deadlock::{DeadlockDetector, Resource};
+pub use futex::{futex_wait, futex_wake};
+pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use semaphore::Semaphore;
 pub use up::UPSafeCell;
+pub use wait_queue::WaitQueue;
@@ -0,0 +1,99 @@
+//! Mutexes of user threads, see [`Mutex`]
+use super::{UPSafeCell, WaitQueue};
+use crate::syscall::errno::EINTR;
+use crate::task::suspend_current_and_run_next;
+use crate::task::{block_current_and_run_next, current_killed, current_task};
+
+/// A lock the threads of a process take turns holding. Waiting only ends
+/// early, with `Err(EINTR)`, for a thread that is killed, signals wait
+/// until the lock is taken.
+pub trait Mutex: Sync + Send {
+    /// Take the lock, waiting while another thread holds it
+    fn lock(&self) -> Result<(), isize>;
+    /// Release the lock
+    fn unlock(&self);
+}
+
+/// A mutex whose waiters keep yielding until the lock is free
+pub struct MutexSpin {
+    locked: UPSafeCell<bool>,
+}
+
+impl MutexSpin {
+    /// Create an unlocked mutex
+    pub fn new() -> Self {
+        Self {
+            locked: unsafe { UPSafeCell::new(false) },
+        }
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) -> Result<(), isize> {
+        loop {
+            let mut locked = self.locked.exclusive_access();
+            if !*locked {
+                *locked = true;
+                return Ok(());
+            }
+            drop(locked);
+            suspend_current_and_run_next();
+            if current_killed() {
+                return Err(EINTR);
+            }
+        }
+    }
+    fn unlock(&self) {
+        *self.locked.exclusive_access() = false;
+    }
+}
+
+/// A mutex whose waiters block until it's unlocked
+pub struct MutexBlocking {
+    inner: UPSafeCell<MutexBlockingInner>,
+}
+
+struct MutexBlockingInner {
+    locked: bool,
+    waiters: WaitQueue,
+}
+
+impl MutexBlocking {
+    /// Create an unlocked mutex
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(MutexBlockingInner {
+                    locked: false,
+                    waiters: WaitQueue::default(),
+                })
+            },
+        }
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) -> Result<(), isize> {
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if !inner.locked {
+                inner.locked = true;
+                inner.waiters.remove(&task);
+                return Ok(());
+            }
+            inner.waiters.register(&task);
+            drop(inner);
+            block_current_and_run_next();
+            if current_killed() {
+                self.inner.exclusive_access().waiters.remove(&task);
+                return Err(EINTR);
+            }
+        }
+    }
+    fn unlock(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.locked = false;
+        inner.waiters.wake_one();
+    }
+}
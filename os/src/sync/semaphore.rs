@@ -0,0 +1,54 @@
+//! Counting semaphores of user threads, see [`Semaphore`]
+use super::{UPSafeCell, WaitQueue};
+use crate::syscall::errno::EINTR;
+use crate::task::{block_current_and_run_next, current_killed, current_task};
+
+/// A count of available resources, whose takers block while there are none
+pub struct Semaphore {
+    inner: UPSafeCell<SemaphoreInner>,
+}
+
+struct SemaphoreInner {
+    count: usize,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    /// Create a semaphore with `count` resources available
+    pub fn new(count: usize) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(SemaphoreInner {
+                    count,
+                    waiters: WaitQueue::default(),
+                })
+            },
+        }
+    }
+    /// Give back a resource, waking the first waiter
+    pub fn up(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.count += 1;
+        inner.waiters.wake_one();
+    }
+    /// Take a resource, blocking until one is available. Only a killed
+    /// thread gives up, with `Err(EINTR)`.
+    pub fn down(&self) -> Result<(), isize> {
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.count > 0 {
+                inner.count -= 1;
+                inner.waiters.remove(&task);
+                return Ok(());
+            }
+            inner.waiters.register(&task);
+            drop(inner);
+            block_current_and_run_next();
+            if current_killed() {
+                self.inner.exclusive_access().waiters.remove(&task);
+                return Err(EINTR);
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Tasks blocked until something they wait for changes
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+/// Tasks to wake when the state of an object changes, all at once like the
+/// readers and writers of a pipe, or one at a time in the order they came
+/// like the waiters of a mutex. A task is only woken once per registration,
+/// and checks the object again when it runs.
+#[derive(Default)]
+pub struct WaitQueue {
+    tasks: Vec<Weak<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    /// Wake `task` on the next [`WaitQueue::wake_all`]
+    pub fn register(&mut self, task: &Arc<TaskControlBlock>) {
+        let task = Arc::downgrade(task);
+        if !self.tasks.iter().any(|waiter| waiter.ptr_eq(&task)) {
+            self.tasks.push(task);
+        }
+    }
+    /// Forget `task`, which got what it waited for without being woken.
+    /// Return whether it was registered.
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let task = Arc::downgrade(task);
+        let len = self.tasks.len();
+        self.tasks.retain(|waiter| !waiter.ptr_eq(&task));
+        self.tasks.len() < len
+    }
+    /// Wake the task registered first that is still around. Return whether
+    /// there was one.
+    pub fn wake_one(&mut self) -> bool {
+        while !self.tasks.is_empty() {
+            if let Some(task) = self.tasks.remove(0).upgrade() {
+                wakeup_task(task);
+                return true;
+            }
+        }
+        false
+    }
+    /// Whether no task is registered
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+    /// Wake every registered task that is still around
+    pub fn wake_all(&mut self) {
+        for task in self.tasks.drain(..) {
+            if let Some(task) = task.upgrade() {
+                wakeup_task(task);
+            }
+        }
+    }
+}
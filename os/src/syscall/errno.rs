@@ -0,0 +1,74 @@
+//! Error numbers returned by syscalls, negated, as in Linux
+//!
+//! Most syscalls still fail with -1; these are used where the cause matters
+//! to the caller.
+
+/// Operation not permitted
+pub const EPERM: isize = 1;
+/// No such file or directory
+pub const ENOENT: isize = 2;
+/// No such process
+pub const ESRCH: isize = 3;
+/// Interrupted by a signal
+pub const EINTR: isize = 4;
+/// I/O error, e.g. reading the console from the background while `SIGTTIN`
+/// can't stop the reader
+pub const EIO: isize = 5;
+/// No such device or address, e.g. opening a socket node
+pub const ENXIO: isize = 6;
+/// Exec format error
+pub const ENOEXEC: isize = 8;
+/// Bad file descriptor
+pub const EBADF: isize = 9;
+/// Try again, e.g. a futex no longer holds the value waited for
+pub const EAGAIN: isize = 11;
+/// Out of memory, or out of address space
+pub const ENOMEM: isize = 12;
+/// Permission denied
+pub const EACCES: isize = 13;
+/// Bad address
+pub const EFAULT: isize = 14;
+/// Device or resource busy, e.g. a mount point
+pub const EBUSY: isize = 16;
+/// File exists
+pub const EEXIST: isize = 17;
+/// The file can't be mapped
+pub const ENODEV: isize = 19;
+/// Is a directory
+pub const EISDIR: isize = 21;
+/// Invalid argument
+pub const EINVAL: isize = 22;
+/// Too many open files, past `RLIMIT_NOFILE`
+pub const EMFILE: isize = 24;
+/// Not a terminal, or a terminal request it doesn't know
+pub const ENOTTY: isize = 25;
+/// No space left on the device, or in its journal
+pub const ENOSPC: isize = 28;
+/// Broken pipe
+pub const EPIPE: isize = 32;
+/// Resource deadlock would occur
+pub const EDEADLK: isize = 35;
+/// Directory not empty
+pub const ENOTEMPTY: isize = 39;
+/// Too many levels of symbolic links, or a loop of epoll instances
+pub const ELOOP: isize = 40;
+/// Socket operation on non-socket
+pub const ENOTSOCK: isize = 88;
+/// Message too long
+pub const EMSGSIZE: isize = 90;
+/// Protocol wrong type for socket
+pub const EPROTOTYPE: isize = 91;
+/// Operation not supported
+pub const EOPNOTSUPP: isize = 95;
+/// Address family not supported
+pub const EAFNOSUPPORT: isize = 97;
+/// Address already in use
+pub const EADDRINUSE: isize = 98;
+/// Socket is already connected
+pub const EISCONN: isize = 106;
+/// Socket is not connected
+pub const ENOTCONN: isize = 107;
+/// Timed out
+pub const ETIMEDOUT: isize = 110;
+/// Connection refused
+pub const ECONNREFUSED: isize = 111;
@@ -1,49 +1,323 @@
 //! File and filesystem-related syscalls
-use crate::mm::translated_byte_buffer;
-use crate::sbi::console_getchar;
-use crate::task::{current_user_token, suspend_current_and_run_next};
+use super::errno::{EBADF, EEXIST, EINVAL, EMFILE, ENOTTY, EPERM, EPIPE};
+use crate::fs::{
+    lookup, make_dir, make_node, make_pipe, open_file, unlink, InodeType, Metadata, OpenFlags,
+};
+use crate::mm::{
+    translated_byte_buffer, translated_read, translated_refmut, translated_str, UserBuffer,
+};
+use crate::task::{
+    current_credentials, current_process, current_user_token, fault_in_writable, group_processes,
+    send_signal, LimitedResource, SignalFlags,
+};
+use core::mem::size_of;
 
-const FD_STDIN: usize = 0;
-const FD_STDOUT: usize = 1;
+/// File type bits of a mode, and the type of a named pipe
+const S_IFMT: u32 = 0o170000;
+const S_IFIFO: u32 = 0o010000;
+
+/// `ioctl` requests getting and setting the foreground process group
+const TIOCGPGRP: usize = 0x540f;
+const TIOCSPGRP: usize = 0x5410;
+
+/// An id `chown` leaves as it is
+const KEEP_ID: u32 = u32::MAX;
+
+/// Send SIGPIPE to the current process for writing to a pipe or socket
+/// nobody reads. The write still fails with -EPIPE if it ignores or handles
+/// the signal.
+pub(super) fn raise_sigpipe() {
+    send_signal(&current_process(), SignalFlags::SIGPIPE);
+}
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.writable() {
+            return -1;
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+        let file = file.clone();
+        // release current PCB manually to avoid multi-borrow
+        drop(inner);
+        drop(process);
+        let ret = file.write(UserBuffer::new(translated_byte_buffer(token, buf, len)));
+        if ret == -EPIPE {
+            raise_sigpipe();
         }
+        ret
+    } else {
+        -1
     }
 }
 
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDIN => {
-            assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-            let mut c: usize;
-            loop {
-                c = console_getchar();
-                if c == 0 {
-                    suspend_current_and_run_next();
-                    continue;
-                } else {
-                    break;
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        let file = file.clone();
+        if !file.readable() {
+            return -1;
+        }
+        // release current PCB manually to avoid multi-borrow
+        drop(inner);
+        if let Err(errno) = fault_in_writable(buf as usize, len) {
+            return -errno;
+        }
+        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len)))
+    } else {
+        -1
+    }
+}
+
+pub fn sys_open(path: *const u8, flags: u32) -> isize {
+    let process = current_process();
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let flags = OpenFlags::from_bits_truncate(flags);
+    let cred = process.inner_exclusive_access().credentials();
+    match open_file(path.as_str(), flags, cred) {
+        Ok(inode) => {
+            let mut inner = process.inner_exclusive_access();
+            let fd = match inner.alloc_fd() {
+                Some(fd) => fd,
+                None => {
+                    // the file is closed once the PCB is released
+                    drop(inner);
+                    return -EMFILE;
                 }
+            };
+            inner.fd_table[fd] = Some(inode);
+            if flags.contains(OpenFlags::CLOEXEC) {
+                inner.cloexec.insert(fd);
             }
-            let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
-            unsafe {
-                buffers[0].as_mut_ptr().write_volatile(ch);
+            fd as isize
+        }
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if inner.fd_table[fd].is_none() {
+        return -1;
+    }
+    let file = inner.fd_table[fd].take();
+    inner.cloexec.remove(&fd);
+    // release current PCB before the file goes, which may wake others
+    drop(inner);
+    drop(file);
+    0
+}
+
+pub fn sys_dup(fd: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    let new_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+
+/// Terminal requests, only the foreground process group of the console
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    let console = match file.as_console() {
+        Some(console) => console,
+        None => return -ENOTTY,
+    };
+    let token = current_user_token();
+    match request {
+        TIOCGPGRP => {
+            if let Err(errno) = fault_in_writable(arg, size_of::<i32>()) {
+                return -errno;
             }
-            1
+            *translated_refmut(token, arg as *mut i32) = console.foreground() as i32;
         }
-        _ => {
-            panic!("Unsupported fd in sys_read!");
+        TIOCSPGRP => {
+            let pgid = match usize::try_from(translated_read(token, arg as *const i32)) {
+                Ok(pgid) => pgid,
+                Err(_) => return -EINVAL,
+            };
+            // the group has to be in the caller's session
+            let sid = current_process().inner_exclusive_access().sid;
+            if !group_processes(pgid)
+                .iter()
+                .any(|member| member.inner_exclusive_access().sid == sid)
+            {
+                return -EPERM;
+            }
+            console.set_foreground(pgid);
         }
+        _ => return -ENOTTY,
+    }
+    0
+}
+
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    if flags & !OpenFlags::CLOEXEC.bits() != 0 || old_fd == new_fd {
+        return -EINVAL;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(old_fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    if new_fd >= inner.rlimits.cur(LimitedResource::Nofile) {
+        return -EBADF;
+    }
+    if inner.fd_table.len() <= new_fd {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    // the file open at `new_fd` is closed, once the PCB is released
+    let closed = inner.fd_table[new_fd].replace(file);
+    if flags & OpenFlags::CLOEXEC.bits() != 0 {
+        inner.cloexec.insert(new_fd);
+    } else {
+        inner.cloexec.remove(&new_fd);
+    }
+    drop(inner);
+    drop(closed);
+    new_fd as isize
+}
+
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    let process = current_process();
+    let token = current_user_token();
+    let mut inner = process.inner_exclusive_access();
+    if let Err(errno) = inner
+        .memory_set
+        .fault_in_writable(pipe as usize, 2 * size_of::<usize>())
+    {
+        return -errno;
+    }
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table[read_fd] = Some(pipe_read);
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            let pipe_read = inner.fd_table[read_fd].take();
+            // the ends are closed once the PCB is released
+            drop(inner);
+            drop(pipe_read);
+            return -EMFILE;
+        }
+    };
+    inner.fd_table[write_fd] = Some(pipe_write);
+    *translated_refmut(token, pipe) = read_fd;
+    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
+    0
+}
+
+pub fn sys_mkdir(path: *const u8) -> isize {
+    let path = translated_str(current_user_token(), path);
+    match make_dir(path.as_str(), current_credentials()) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_mknod(path: *const u8, mode: u32) -> isize {
+    if mode & S_IFMT != S_IFIFO {
+        return -EINVAL;
+    }
+    let path = translated_str(current_user_token(), path);
+    if lookup(path.as_str(), current_credentials()).is_ok() {
+        return -EEXIST;
+    }
+    match make_node(path.as_str(), InodeType::Fifo, current_credentials()) {
+        Ok(node) => {
+            let metadata = node.metadata();
+            node.set_metadata(Metadata {
+                mode: mode & 0o7777,
+                ..metadata
+            });
+            0
+        }
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_unlink(path: *const u8) -> isize {
+    let path = translated_str(current_user_token(), path);
+    match unlink(path.as_str(), current_credentials()) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// Only the owner of a file, or root, may change its mode
+pub fn sys_chmod(path: *const u8, mode: u32) -> isize {
+    let path = translated_str(current_user_token(), path);
+    let cred = current_credentials();
+    let inode = match lookup(path.as_str(), cred) {
+        Ok(inode) => inode,
+        Err(errno) => return -errno,
+    };
+    let metadata = inode.metadata();
+    if cred.uid != 0 && cred.uid != metadata.uid {
+        return -EPERM;
+    }
+    let metadata = Metadata {
+        mode: mode & 0o7777,
+        ..metadata
+    };
+    if inode.set_metadata(metadata) {
+        0
+    } else {
+        -EPERM
+    }
+}
+
+/// Only root may change the owners of a file. An id of -1 is left as it is.
+pub fn sys_chown(path: *const u8, uid: u32, gid: u32) -> isize {
+    let path = translated_str(current_user_token(), path);
+    let cred = current_credentials();
+    let inode = match lookup(path.as_str(), cred) {
+        Ok(inode) => inode,
+        Err(errno) => return -errno,
+    };
+    if cred.uid != 0 {
+        return -EPERM;
+    }
+    let metadata = inode.metadata();
+    let metadata = Metadata {
+        uid: if uid == KEEP_ID { metadata.uid } else { uid },
+        gid: if gid == KEEP_ID { metadata.gid } else { gid },
+        ..metadata
+    };
+    if inode.set_metadata(metadata) {
+        0
+    } else {
+        -EPERM
     }
 }
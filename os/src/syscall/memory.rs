@@ -0,0 +1,158 @@
+//! Memory mapping syscalls
+//!
+//! A file mapping maps the frames of the file's page cache, so it sees
+//! what `write` puts into the file right away. Stores through a shared
+//! mapping are written back on `msync`, `munmap` and exit; stores through
+//! a private one go to copies of the pages taken at the first store.
+use super::errno::{EACCES, EBADF, EINVAL, ENODEV, ENOMEM};
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use crate::fs::block_cache_sync_all;
+use crate::mm::{MapPermission, MemorySet, VirtPageNum};
+use crate::task::current_process;
+
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+const MS_ASYNC: usize = 1;
+const MS_INVALIDATE: usize = 2;
+const MS_SYNC: usize = 4;
+
+/// The pages of `len` bytes at `addr`, `None` unless `addr` is page
+/// aligned and they're in the user half of the address space
+fn page_range(addr: usize, len: usize) -> Option<(VirtPageNum, VirtPageNum)> {
+    if addr % PAGE_SIZE != 0 || addr > USER_SPACE_END || len > USER_SPACE_END - addr {
+        return None;
+    }
+    let end = (addr + len + PAGE_SIZE - 1) / PAGE_SIZE;
+    Some((VirtPageNum(addr / PAGE_SIZE), VirtPageNum(end)))
+}
+
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    if len == 0 || len > USER_SPACE_END || offset % PAGE_SIZE != 0 {
+        return -EINVAL;
+    }
+    // a page that can't be touched at all has no leaf page table entry
+    if prot == 0 || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return -EINVAL;
+    }
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return -EINVAL,
+    };
+    let mut map_perm = MapPermission::U;
+    // writable pages have to be readable too
+    if prot & (PROT_READ | PROT_WRITE) != 0 {
+        map_perm |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        map_perm |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        map_perm |= MapPermission::X;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let cache = if flags & MAP_ANONYMOUS != 0 {
+        // anonymous pages are copied on fork, there is nothing to share
+        if shared {
+            return -EINVAL;
+        }
+        None
+    } else {
+        let file = match inner.fd_table.get(fd).cloned().flatten() {
+            Some(file) => file,
+            None => return -EBADF,
+        };
+        if !file.readable() || (shared && prot & PROT_WRITE != 0 && !file.writable()) {
+            return -EACCES;
+        }
+        match file.page_cache() {
+            Some(cache) => Some(cache),
+            None => return -ENODEV,
+        }
+    };
+    let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    let fixed = flags & MAP_FIXED != 0;
+    let start = if fixed {
+        match page_range(addr, len) {
+            Some((start, _)) => start,
+            None => return -EINVAL,
+        }
+    } else {
+        let hint = VirtPageNum(addr / PAGE_SIZE);
+        match inner.memory_set.free_range(hint, pages) {
+            Some(start) => start,
+            None => return -ENOMEM,
+        }
+    };
+    let end = VirtPageNum(start.0 + pages);
+    // past `RLIMIT_AS` nothing changes, not even what `MAP_FIXED` replaces,
+    // and nor without the frames
+    if !inner.memory_set.fits(start, end) || !MemorySet::frames_for(pages) {
+        return -ENOMEM;
+    }
+    if fixed {
+        inner.memory_set.unmap_range(start, end);
+    }
+    let mapped = match cache {
+        Some(cache) => {
+            let first_page = offset / PAGE_SIZE;
+            inner
+                .memory_set
+                .insert_file_area(start, end, map_perm, cache, first_page, shared)
+        }
+        None => inner
+            .memory_set
+            .insert_framed_area(start.into(), end.into(), map_perm),
+    };
+    if !mapped {
+        return -ENOMEM;
+    }
+    (start.0 * PAGE_SIZE) as isize
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    let (start, end) = match page_range(addr, len) {
+        Some(range) if len > 0 => range,
+        _ => return -EINVAL,
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.memory_set.unmap_range(start, end);
+    0
+}
+
+pub fn sys_msync(addr: usize, len: usize, flags: usize) -> isize {
+    if flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || flags & (MS_ASYNC | MS_SYNC) == MS_ASYNC | MS_SYNC
+    {
+        return -EINVAL;
+    }
+    let (start, end) = match page_range(addr, len) {
+        Some(range) => range,
+        None => return -EINVAL,
+    };
+    // mappings share the page cache, there is nothing to invalidate
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner.memory_set.sync_range(start, end) {
+        return -ENOMEM;
+    }
+    drop(inner);
+    if flags & MS_SYNC != 0 {
+        block_cache_sync_all();
+    }
+    0
+}
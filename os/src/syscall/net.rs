@@ -0,0 +1,388 @@
+//! Socket syscalls, with Linux numbers and structures
+//!
+//! Only `AF_UNIX` is supported. Addresses are `sockaddr_un` holding a path,
+//! and files are passed with `SCM_RIGHTS` control messages of `sendmsg` and
+//! `recvmsg`.
+use super::errno::{EAFNOSUPPORT, EBADF, EINVAL, EMFILE, EMSGSIZE, ENOTSOCK, EPIPE};
+use super::fs::raise_sigpipe;
+use crate::fs::File;
+use crate::mm::{
+    copy_from_user, copy_to_user, translated_byte_buffer, translated_read, translated_write,
+    UserBuffer,
+};
+use crate::net::{SocketType, UnixSocket};
+use crate::task::{current_credentials, current_process, current_user_token, fault_in_writable};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const AF_UNIX: usize = 1;
+const SOCK_STREAM: usize = 1;
+const SOCK_DGRAM: usize = 2;
+const SOCK_CLOEXEC: usize = 0x80000;
+const SOL_SOCKET: i32 = 1;
+const SCM_RIGHTS: i32 = 1;
+const MSG_CTRUNC: i32 = 0x8;
+const MSG_NOSIGNAL: u32 = 0x4000;
+const MSG_CMSG_CLOEXEC: u32 = 0x4000_0000;
+/// Size of `sun_path` in a `sockaddr_un`
+const UNIX_PATH_MAX: usize = 108;
+/// Most iovecs in one message
+const UIO_MAXIOV: usize = 1024;
+/// Most files in one `SCM_RIGHTS` message
+const SCM_MAX_FD: usize = 253;
+
+/// `struct msghdr`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MsgHdr {
+    name: usize,
+    name_len: u32,
+    iov: usize,
+    iov_len: usize,
+    control: usize,
+    control_len: usize,
+    flags: i32,
+}
+
+/// `struct iovec`
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+/// `struct cmsghdr`, followed by the data of the control message
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct CmsgHdr {
+    len: usize,
+    level: i32,
+    kind: i32,
+}
+
+/// Size of a control message header, where its data starts
+const CMSG_HDR_LEN: usize = size_of::<CmsgHdr>();
+
+/// Length of a control message with `len` bytes of data, padded to the
+/// next header
+fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+/// Unwrap a result, or return its errno from the syscall
+macro_rules! try_errno {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(errno) => return -errno,
+        }
+    };
+}
+
+/// The socket open at `fd`
+fn socket_of(fd: usize) -> Result<Arc<UnixSocket>, isize> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone().as_socket().ok_or(ENOTSOCK),
+        _ => Err(EBADF),
+    }
+}
+
+/// Put `file` into the fd table of the current process, or close it and
+/// return `Err(EMFILE)` if there's no fd below `RLIMIT_NOFILE` left
+fn install_fd(file: Arc<dyn File>, cloexec: bool) -> Result<usize, isize> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            drop(inner);
+            drop(file);
+            return Err(EMFILE);
+        }
+    };
+    inner.fd_table[fd] = Some(file);
+    if cloexec {
+        inner.cloexec.insert(fd);
+    }
+    Ok(fd)
+}
+
+/// Read the path out of the `sockaddr_un` of `len` bytes at `addr`
+fn read_sockaddr(token: usize, addr: *const u8, len: usize) -> Result<String, isize> {
+    if addr.is_null() || len < size_of::<u16>() || len > size_of::<u16>() + UNIX_PATH_MAX {
+        return Err(EINVAL);
+    }
+    if translated_read(token, addr as *const u16) as usize != AF_UNIX {
+        return Err(EAFNOSUPPORT);
+    }
+    let mut path = [0u8; UNIX_PATH_MAX];
+    let path = &mut path[..len - size_of::<u16>()];
+    copy_from_user(token, unsafe { addr.add(size_of::<u16>()) }, path);
+    let end = path.iter().position(|c| *c == 0).unwrap_or(path.len());
+    // no abstract names, which start with a NUL
+    if end == 0 {
+        return Err(EINVAL);
+    }
+    core::str::from_utf8(&path[..end])
+        .map(String::from)
+        .map_err(|_| EINVAL)
+}
+
+/// Write `path` as a `sockaddr_un` to `addr`, truncated to the size in
+/// `*addr_len`, then set `*addr_len` to the full size
+fn write_sockaddr(
+    token: usize,
+    addr: *mut u8,
+    addr_len: *mut u32,
+    path: Option<&str>,
+) -> Result<(), isize> {
+    if addr.is_null() || addr_len.is_null() {
+        return Ok(());
+    }
+    let mut sockaddr = Vec::from((AF_UNIX as u16).to_ne_bytes());
+    if let Some(path) = path {
+        sockaddr.extend_from_slice(path.as_bytes());
+        sockaddr.push(0);
+    }
+    let room = translated_read(token, addr_len as *const u32) as usize;
+    fault_in_writable(addr as usize, room.min(sockaddr.len()))?;
+    fault_in_writable(addr_len as usize, size_of::<u32>())?;
+    copy_to_user(token, addr, &sockaddr[..room.min(sockaddr.len())]);
+    translated_write(token, addr_len, sockaddr.len() as u32);
+    Ok(())
+}
+
+/// Gather the buffers described by the iovecs at `iov`, to be written to
+/// by the kernel if `for_write`
+fn read_iovecs(
+    token: usize,
+    iov: usize,
+    iov_len: usize,
+    for_write: bool,
+) -> Result<UserBuffer, isize> {
+    if iov_len > UIO_MAXIOV {
+        return Err(EMSGSIZE);
+    }
+    let mut buffers = Vec::new();
+    for i in 0..iov_len {
+        let iovec: IoVec = translated_read(token, (iov + i * size_of::<IoVec>()) as *const IoVec);
+        if for_write {
+            fault_in_writable(iovec.base, iovec.len)?;
+        }
+        buffers.extend(translated_byte_buffer(
+            token,
+            iovec.base as *const u8,
+            iovec.len,
+        ));
+    }
+    Ok(UserBuffer::new(buffers))
+}
+
+/// The files named by the `SCM_RIGHTS` messages in a control buffer
+fn read_rights(token: usize, control: usize, len: usize) -> Result<Vec<Arc<dyn File>>, isize> {
+    let mut fds = Vec::new();
+    let mut offset = 0;
+    while offset + CMSG_HDR_LEN <= len {
+        let cmsg: CmsgHdr = translated_read(token, (control + offset) as *const CmsgHdr);
+        if cmsg.len < CMSG_HDR_LEN || offset + cmsg.len > len {
+            return Err(EINVAL);
+        }
+        if cmsg.level != SOL_SOCKET || cmsg.kind != SCM_RIGHTS {
+            return Err(EINVAL);
+        }
+        let data = control + offset + CMSG_HDR_LEN;
+        for i in 0..(cmsg.len - CMSG_HDR_LEN) / size_of::<i32>() {
+            let fd = (data + i * size_of::<i32>()) as *const i32;
+            fds.push(translated_read(token, fd));
+        }
+        offset += cmsg_align(cmsg.len);
+    }
+    if fds.len() > SCM_MAX_FD {
+        return Err(EINVAL);
+    }
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    fds.into_iter()
+        .map(|fd| match inner.fd_table.get(fd as usize) {
+            Some(Some(file)) if fd >= 0 => Ok(file.clone()),
+            _ => Err(EBADF),
+        })
+        .collect()
+}
+
+/// Send on `socket`, and raise SIGPIPE on a broken connection unless the
+/// flags say not to
+fn send(
+    socket: Arc<UnixSocket>,
+    buf: UserBuffer,
+    files: Vec<Arc<dyn File>>,
+    to: Option<&str>,
+    flags: u32,
+) -> Result<usize, isize> {
+    let result = socket.send(buf, files, to, current_credentials());
+    if result == Err(EPIPE) && flags & MSG_NOSIGNAL == 0 {
+        raise_sigpipe();
+    }
+    result
+}
+
+pub fn sys_socket(domain: usize, kind: usize, protocol: usize) -> isize {
+    if domain != AF_UNIX {
+        return -EAFNOSUPPORT;
+    }
+    let socket_type = match kind & !SOCK_CLOEXEC {
+        SOCK_STREAM => SocketType::Stream,
+        SOCK_DGRAM => SocketType::Datagram,
+        _ => return -EINVAL,
+    };
+    if protocol != 0 {
+        return -EINVAL;
+    }
+    try_errno!(install_fd(
+        UnixSocket::new(socket_type),
+        kind & SOCK_CLOEXEC != 0
+    )) as isize
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addr_len: usize) -> isize {
+    let socket = try_errno!(socket_of(fd));
+    let path = try_errno!(read_sockaddr(current_user_token(), addr, addr_len));
+    try_errno!(socket.bind(&path, current_credentials()));
+    0
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    try_errno!(try_errno!(socket_of(fd)).listen(backlog));
+    0
+}
+
+pub fn sys_accept(fd: usize, addr: *mut u8, addr_len: *mut u32) -> isize {
+    let (socket, peer) = try_errno!(try_errno!(socket_of(fd)).accept());
+    try_errno!(write_sockaddr(
+        current_user_token(),
+        addr,
+        addr_len,
+        peer.as_deref()
+    ));
+    try_errno!(install_fd(socket, false)) as isize
+}
+
+pub fn sys_connect(fd: usize, addr: *const u8, addr_len: usize) -> isize {
+    let socket = try_errno!(socket_of(fd));
+    let path = try_errno!(read_sockaddr(current_user_token(), addr, addr_len));
+    try_errno!(socket.connect(&path, current_credentials()));
+    0
+}
+
+pub fn sys_sendto(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    flags: u32,
+    addr: *const u8,
+    addr_len: usize,
+) -> isize {
+    let token = current_user_token();
+    let socket = try_errno!(socket_of(fd));
+    let to = if addr.is_null() {
+        None
+    } else {
+        Some(try_errno!(read_sockaddr(token, addr, addr_len)))
+    };
+    let buf = UserBuffer::new(translated_byte_buffer(token, buf, len));
+    try_errno!(send(socket, buf, Vec::new(), to.as_deref(), flags)) as isize
+}
+
+pub fn sys_recvfrom(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    _flags: u32,
+    addr: *mut u8,
+    addr_len: *mut u32,
+) -> isize {
+    let token = current_user_token();
+    let socket = try_errno!(socket_of(fd));
+    try_errno!(fault_in_writable(buf as usize, len));
+    let buf = UserBuffer::new(translated_byte_buffer(token, buf, len));
+    // files sent along with the data are closed
+    let (len, _, from) = try_errno!(socket.recv(buf));
+    try_errno!(write_sockaddr(token, addr, addr_len, from.as_deref()));
+    len as isize
+}
+
+pub fn sys_sendmsg(fd: usize, msg: *const MsgHdr, flags: u32) -> isize {
+    let token = current_user_token();
+    let socket = try_errno!(socket_of(fd));
+    let msg: MsgHdr = translated_read(token, msg);
+    let to = if msg.name == 0 {
+        None
+    } else {
+        let (name, name_len) = (msg.name as *const u8, msg.name_len as usize);
+        Some(try_errno!(read_sockaddr(token, name, name_len)))
+    };
+    let files = try_errno!(read_rights(token, msg.control, msg.control_len));
+    let buf = try_errno!(read_iovecs(token, msg.iov, msg.iov_len, false));
+    try_errno!(send(socket, buf, files, to.as_deref(), flags)) as isize
+}
+
+pub fn sys_recvmsg(fd: usize, msg_ptr: *mut MsgHdr, flags: u32) -> isize {
+    let token = current_user_token();
+    let socket = try_errno!(socket_of(fd));
+    let mut msg: MsgHdr = translated_read(token, msg_ptr);
+    try_errno!(fault_in_writable(msg_ptr as usize, size_of::<MsgHdr>()));
+    try_errno!(fault_in_writable(msg.control, msg.control_len));
+    let buf = try_errno!(read_iovecs(token, msg.iov, msg.iov_len, true));
+    let (len, mut files, from) = try_errno!(socket.recv(buf));
+    if msg.name != 0 {
+        let name_len = unsafe { core::ptr::addr_of_mut!((*msg_ptr).name_len) };
+        try_errno!(write_sockaddr(
+            token,
+            msg.name as *mut u8,
+            name_len,
+            from.as_deref()
+        ));
+        msg.name_len = translated_read(token, name_len);
+    }
+    msg.flags = 0;
+    // as many files as fit in the control buffer and the fd table, the rest
+    // are closed
+    let room = msg.control_len.saturating_sub(CMSG_HDR_LEN) / size_of::<i32>();
+    if files.len() > room {
+        files.truncate(room);
+        msg.flags |= MSG_CTRUNC;
+    }
+    let cloexec = flags & MSG_CMSG_CLOEXEC != 0;
+    let mut fds = Vec::new();
+    for file in files {
+        match install_fd(file, cloexec) {
+            Ok(fd) => fds.push(fd as i32),
+            Err(_) => {
+                msg.flags |= MSG_CTRUNC;
+                break;
+            }
+        }
+    }
+    if fds.is_empty() {
+        msg.control_len = 0;
+    } else {
+        let cmsg = CmsgHdr {
+            len: CMSG_HDR_LEN + fds.len() * size_of::<i32>(),
+            level: SOL_SOCKET,
+            kind: SCM_RIGHTS,
+        };
+        translated_write(token, msg.control as *mut CmsgHdr, cmsg);
+        for (i, fd) in fds.into_iter().enumerate() {
+            let data = msg.control + CMSG_HDR_LEN + i * size_of::<i32>();
+            translated_write(token, data as *mut i32, fd);
+        }
+        msg.control_len = cmsg_align(cmsg.len).min(msg.control_len);
+    }
+    translated_write(token, msg_ptr, msg);
+    len as isize
+}
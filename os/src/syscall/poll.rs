@@ -0,0 +1,206 @@
+//! Waiting for any of several fds to become ready
+//!
+//! A task that finds nothing ready registers itself with every file it
+//! watches, and with a timer for the timeout, then blocks until one of them
+//! wakes it and it looks again. A signal to take ends the wait with
+//! `EINTR`.
+use super::errno::{EBADF, EINTR, EINVAL, EMFILE};
+use crate::fs::{Epoll, EpollCtl, EpollEvent, File, PollEvents};
+use crate::mm::{translated_read, translated_write};
+use crate::task::{
+    block_current_and_run_next, current_interrupted, current_process, current_task,
+    current_user_token, fault_in_writable,
+};
+use crate::timer::{add_timer, get_time_ms};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const EPOLL_CLOEXEC: usize = 0x80000;
+
+/// `struct pollfd`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+/// `struct timespec`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct TimeSpec {
+    sec: usize,
+    nsec: usize,
+}
+
+/// The file open at `fd` in the current process
+fn file_of(fd: usize) -> Option<Arc<dyn File>> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    inner.fd_table.get(fd).cloned().flatten()
+}
+
+/// The deadline in ms of the relative timeout at `timeout`, `None` if it's
+/// null. Return `Err(EINVAL)` for a malformed timeout.
+pub(super) fn read_deadline(
+    token: usize,
+    timeout: *const TimeSpec,
+) -> Result<Option<usize>, isize> {
+    if timeout.is_null() {
+        return Ok(None);
+    }
+    let timeout: TimeSpec = translated_read(token, timeout);
+    if timeout.nsec >= 1_000_000_000 {
+        return Err(EINVAL);
+    }
+    Ok(Some(
+        get_time_ms() + timeout.sec * 1000 + timeout.nsec / 1_000_000,
+    ))
+}
+
+/// Whether `deadline` in ms has passed, `None` never passes
+fn expired(deadline: Option<usize>) -> bool {
+    deadline.map_or(false, |deadline| get_time_ms() >= deadline)
+}
+
+/// Block until one of `files` may have become ready, or until `deadline`.
+/// Return `Err(EINTR)` if a signal came or was already pending.
+fn wait_on(files: &[Arc<dyn File>], deadline: Option<usize>) -> Result<(), isize> {
+    if current_interrupted() {
+        return Err(EINTR);
+    }
+    let task = current_task().unwrap();
+    for file in files {
+        file.register_waker(&task);
+    }
+    if let Some(deadline) = deadline {
+        add_timer(deadline, task);
+    }
+    block_current_and_run_next();
+    if current_interrupted() {
+        return Err(EINTR);
+    }
+    Ok(())
+}
+
+pub fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout: *const TimeSpec, _mask: usize) -> isize {
+    let token = current_user_token();
+    let deadline = match read_deadline(token, timeout) {
+        Ok(deadline) => deadline,
+        Err(errno) => return -errno,
+    };
+    let fd_at = |i: usize| unsafe { fds.add(i) };
+    if let Err(errno) = fault_in_writable(fds as usize, nfds * size_of::<PollFd>()) {
+        return -errno;
+    }
+    loop {
+        let mut files = Vec::new();
+        let mut count = 0;
+        for i in 0..nfds {
+            let mut poll_fd: PollFd = translated_read(token, fd_at(i));
+            let events = PollEvents::from_bits_truncate(poll_fd.events as u16 as u32);
+            // negative fds are skipped
+            let revents = match usize::try_from(poll_fd.fd).ok() {
+                None => PollEvents::empty(),
+                Some(fd) => match file_of(fd) {
+                    None => PollEvents::NVAL,
+                    Some(file) => {
+                        let revents = file.poll(events);
+                        files.push(file);
+                        revents
+                    }
+                },
+            };
+            if !revents.is_empty() {
+                count += 1;
+            }
+            poll_fd.revents = revents.bits() as i16;
+            translated_write(token, fd_at(i), poll_fd);
+        }
+        if count > 0 || expired(deadline) {
+            return count;
+        }
+        if let Err(errno) = wait_on(&files, deadline) {
+            return -errno;
+        }
+    }
+}
+
+pub fn sys_epoll_create1(flags: usize) -> isize {
+    if flags & !EPOLL_CLOEXEC != 0 {
+        return -EINVAL;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table[fd] = Some(Epoll::new());
+    if flags & EPOLL_CLOEXEC != 0 {
+        inner.cloexec.insert(fd);
+    }
+    fd as isize
+}
+
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: *const EpollEvent) -> isize {
+    let (epoll, file) = match (file_of(epfd), file_of(fd)) {
+        (Some(epoll), Some(file)) => (epoll, file),
+        _ => return -EBADF,
+    };
+    let (epoll, op) = match (epoll.as_epoll(), EpollCtl::from_op(op)) {
+        (Some(epoll), Some(op)) => (epoll, op),
+        _ => return -EINVAL,
+    };
+    // the event is ignored by `EPOLL_CTL_DEL`
+    let event = if op == EpollCtl::Del {
+        EpollEvent { events: 0, data: 0 }
+    } else {
+        translated_read(current_user_token(), event)
+    };
+    match epoll.ctl(op, fd, file, event) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_epoll_pwait(
+    epfd: usize,
+    events: *mut EpollEvent,
+    max_events: usize,
+    timeout: isize,
+    _mask: usize,
+) -> isize {
+    let epoll = match file_of(epfd).map(|file| file.as_epoll()) {
+        Some(Some(epoll)) => epoll,
+        Some(None) => return -EINVAL,
+        None => return -EBADF,
+    };
+    if max_events as i32 <= 0 {
+        return -EINVAL;
+    }
+    // a negative timeout waits forever
+    let deadline = usize::try_from(timeout as i32)
+        .ok()
+        .map(|timeout| get_time_ms() + timeout);
+    // the events taken from the epoll have to reach the caller
+    if let Err(errno) = fault_in_writable(events as usize, max_events * size_of::<EpollEvent>()) {
+        return -errno;
+    }
+    let token = current_user_token();
+    loop {
+        let ready = epoll.ready(max_events);
+        if !ready.is_empty() || expired(deadline) {
+            for (i, event) in ready.iter().enumerate() {
+                let dst = (events as usize + i * size_of::<EpollEvent>()) as *mut EpollEvent;
+                translated_write(token, dst, *event);
+            }
+            return ready.len() as isize;
+        }
+        if let Err(errno) = wait_on(&[epoll.clone() as Arc<dyn File>], deadline) {
+            return -errno;
+        }
+    }
+}
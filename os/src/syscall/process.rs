@@ -1,11 +1,30 @@
-use crate::loader::get_app_data_by_name;
-use crate::mm::{translated_refmut, translated_str};
+use super::errno::{EAGAIN, EINVAL, ENOENT, ENOEXEC, ENOMEM, EPERM, ESRCH};
+use crate::fs::{block_cache_sync_all, open_exec};
+use crate::mm::{translated_refmut, translated_str, ElfError};
+use crate::sbi::{reboot, shutdown};
 use crate::task::{
-    add_task, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next,
+    current_process, current_task, current_user_token, end_other_threads,
+    exit_current_and_run_next, group_processes, pid2process, remove_from_pid2process,
+    suspend_current_and_run_next, user_process_count, JobChange, LimitedResource,
 };
 use crate::timer::get_time_ms;
-use alloc::sync::Arc;
+use alloc::format;
+use alloc::sync::{Arc, Weak};
+use core::mem::size_of;
+
+/// `waitpid` options
+const WNOHANG: usize = 1;
+const WUNTRACED: usize = 2;
+const WCONTINUED: usize = 8;
+
+/// `reboot` magic numbers, so that a stray call doesn't take the machine
+/// down
+const LINUX_REBOOT_MAGIC1: usize = 0xfee1_dead;
+const LINUX_REBOOT_MAGIC2: usize = 0x2812_1969;
+/// `reboot` commands
+const LINUX_REBOOT_CMD_RESTART: usize = 0x0123_4567;
+const LINUX_REBOOT_CMD_HALT: usize = 0xcdef_0123;
+const LINUX_REBOOT_CMD_POWER_OFF: usize = 0x4321_fedc;
 
 pub fn sys_exit(exit_code: i32) -> ! {
     exit_current_and_run_next(exit_code);
@@ -22,43 +41,238 @@ pub fn sys_get_time() -> isize {
 }
 
 pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid.0 as isize
+    current_process().getpid() as isize
+}
+
+/// The pid of the parent, initproc for an orphan, 0 for initproc itself
+pub fn sys_getppid() -> isize {
+    let process = current_process();
+    let parent = process
+        .inner_exclusive_access()
+        .parent
+        .as_ref()
+        .and_then(Weak::upgrade);
+    parent.map_or(0, |parent| parent.getpid() as isize)
 }
 
+pub fn sys_getuid() -> isize {
+    current_process().inner_exclusive_access().uid as isize
+}
+
+pub fn sys_geteuid() -> isize {
+    current_process().inner_exclusive_access().euid as isize
+}
+
+pub fn sys_getgid() -> isize {
+    current_process().inner_exclusive_access().gid as isize
+}
+
+/// Root sets both the real and effective user id, and so gives up root.
+/// Others may only set the effective id back to the real one.
+pub fn sys_setuid(uid: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.euid == 0 {
+        inner.uid = uid;
+    } else if uid != inner.uid {
+        return -EPERM;
+    }
+    inner.euid = uid;
+    0
+}
+
+/// Only root may change the group id
+pub fn sys_setgid(gid: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.euid != 0 && gid != inner.gid {
+        return -EPERM;
+    }
+    inner.gid = gid;
+    0
+}
+
+/// Move the process `pid` to process group `pgid`, 0 meaning the caller
+/// and a group led by the process. Only the caller and its children in its
+/// session can be moved, but not a session leader, to a new group or one
+/// that has processes in the session.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let process = current_process();
+    let sid = process.inner_exclusive_access().sid;
+    let target = match pid {
+        0 => process.clone(),
+        pid if pid == process.getpid() => process.clone(),
+        pid => {
+            let inner = process.inner_exclusive_access();
+            match inner.children.iter().find(|child| child.getpid() == pid) {
+                Some(child) => child.clone(),
+                None => return -ESRCH,
+            }
+        }
+    };
+    let pgid = match pgid {
+        0 => target.getpid(),
+        pgid if pgid as isize > 0 => pgid,
+        _ => return -EINVAL,
+    };
+    let target_sid = target.inner_exclusive_access().sid;
+    if target_sid != sid || target_sid == target.getpid() {
+        return -EPERM;
+    }
+    if pgid != target.getpid()
+        && !group_processes(pgid)
+            .iter()
+            .any(|member| member.inner_exclusive_access().sid == sid)
+    {
+        return -EPERM;
+    }
+    target.inner_exclusive_access().pgid = pgid;
+    0
+}
+
+/// The process group of the process `pid`, 0 meaning the caller
+pub fn sys_getpgid(pid: usize) -> isize {
+    let process = match pid {
+        0 => Some(current_process()),
+        pid => pid2process(pid),
+    };
+    match process {
+        Some(process) => process.inner_exclusive_access().pgid as isize,
+        None => -ESRCH,
+    }
+}
+
+/// The session of the process `pid`, 0 meaning the caller
+pub fn sys_getsid(pid: usize) -> isize {
+    let process = match pid {
+        0 => Some(current_process()),
+        pid => pid2process(pid),
+    };
+    match process {
+        Some(process) => process.inner_exclusive_access().sid as isize,
+        None => -ESRCH,
+    }
+}
+
+/// Start a new session, led by the caller alone in a new process group.
+/// Return the session id, or -EPERM if the caller leads a process group.
+pub fn sys_setsid() -> isize {
+    let process = current_process();
+    let pid = process.getpid();
+    let mut inner = process.inner_exclusive_access();
+    if inner.pgid == pid {
+        return -EPERM;
+    }
+    inner.pgid = pid;
+    inner.sid = pid;
+    pid as isize
+}
+
+/// Restart the machine, or halt or power it off, `arg` non-zero reporting
+/// a failure to the SBI, e.g. for QEMU's exit status. The block caches are
+/// written back first. Only root may, with the right magic numbers.
+pub fn sys_reboot(magic1: usize, magic2: usize, cmd: usize, arg: usize) -> isize {
+    if magic1 != LINUX_REBOOT_MAGIC1 || magic2 != LINUX_REBOOT_MAGIC2 {
+        return -EINVAL;
+    }
+    if !matches!(
+        cmd,
+        LINUX_REBOOT_CMD_RESTART | LINUX_REBOOT_CMD_HALT | LINUX_REBOOT_CMD_POWER_OFF
+    ) {
+        return -EINVAL;
+    }
+    if current_process().inner_exclusive_access().euid != 0 {
+        return -EPERM;
+    }
+    block_cache_sync_all();
+    if cmd == LINUX_REBOOT_CMD_RESTART {
+        println!("[kernel] Restarting ...");
+        reboot()
+    }
+    println!("[kernel] Power down ...");
+    shutdown(arg != 0)
+}
+
+/// The child only has a copy of the calling thread
+/// Return -EAGAIN if the real user has `RLIMIT_NPROC` processes already,
+/// unless the caller is root, and -ENOMEM if there aren't frames for the
+/// copy.
 pub fn sys_fork() -> isize {
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
-    let new_pid = new_task.pid.0;
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let (uid, euid) = (inner.uid, inner.euid);
+    let nproc = inner.rlimits.cur(LimitedResource::Nproc);
+    drop(inner);
+    if euid != 0 && user_process_count(uid) >= nproc {
+        return -EAGAIN;
+    }
+    let new_process = match process.fork(&current_task) {
+        Some(new_process) => new_process,
+        None => return -ENOMEM,
+    };
+    let new_pid = new_process.getpid();
+    let new_task = new_process
+        .inner_exclusive_access()
+        .get_task(current_task.gettid())
+        .unwrap();
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns 0
     trap_cx.x[10] = 0;
-    // add new task to scheduler
-    add_task(new_task);
     new_pid as isize
 }
 
+/// A bare name is looked up in `/bin` if it isn't found from the root.
+/// Return -ENOENT for a missing file, -EACCES for one that isn't a regular
+/// file the process may execute, -ENOEXEC for a bad executable and -ENOMEM
+/// for one that takes more than `RLIMIT_AS`. The other threads of the
+/// process exit first.
 pub fn sys_exec(path: *const u8) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(data) = get_app_data_by_name(path.as_str()) {
-        let task = current_task().unwrap();
-        task.exec(data);
-        0
-    } else {
-        -1
+    let task = current_task().unwrap();
+    let process = current_process();
+    let cred = process.inner_exclusive_access().credentials();
+    let mut path = translated_str(token, path);
+    let mut elf = open_exec(path.as_str(), cred);
+    if matches!(elf, Err(ENOENT)) && !path.contains('/') {
+        path = format!("/bin/{}", path);
+        elf = open_exec(path.as_str(), cred);
+    }
+    let elf = match elf {
+        Ok(elf) => elf,
+        Err(errno) => return -errno,
+    };
+    if let Err(errno) = end_other_threads() {
+        return -errno;
+    }
+    match process.exec(&task, path.as_str(), elf.as_ref()) {
+        Ok(()) => 0,
+        Err(ElfError::TooLarge) => -ENOMEM,
+        Err(err) => {
+            println!("[kernel] exec {}: {:?}", path, err);
+            -ENOEXEC
+        }
     }
 }
 
 /// If there is not a child process whose pid is same as given, return -1.
 /// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    let task = current_task().unwrap();
+/// With `WUNTRACED` a stop of a child is reported as well, and with
+/// `WCONTINUED` a continue, once each and with the status Linux gives them.
+/// Asking for either, an exit is then encoded as in Linux too, so that no
+/// exit code reads as a stop or continue; otherwise the status is the whole
+/// exit code. Waiting never blocks, so `WNOHANG` makes no difference.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
+    if options & !(WNOHANG | WUNTRACED | WCONTINUED) != 0 {
+        return -EINVAL;
+    }
+    let process = current_process();
     // find a child process
 
-    // ---- access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
+    // ---- access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
     if !inner
         .children
         .iter()
@@ -67,23 +281,61 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         return -1;
         // ---- release current PCB
     }
+    // before a child is reaped, so that it isn't lost
+    if let Err(errno) = inner
+        .memory_set
+        .fault_in_writable(exit_code_ptr as usize, size_of::<i32>())
+    {
+        return -errno;
+    }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
         // ++++ temporarily access child PCB lock exclusively
         p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
         // ++++ release child PCB
     });
-    if let Some((idx, _)) = pair {
+    let (found_pid, exit_code) = if let Some((idx, _)) = pair {
         let child = inner.children.remove(idx);
         // confirm that child will be deallocated after removing from children list
         assert_eq!(Arc::strong_count(&child), 1);
         let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
+        remove_from_pid2process(found_pid);
+        // ++++ temporarily access child PCB exclusively
+        let child_inner = child.inner_exclusive_access();
+        let exit_code = if options & (WUNTRACED | WCONTINUED) != 0 {
+            (child_inner.exit_code & 0xff) << 8
+        } else {
+            child_inner.exit_code
+        };
+        inner.children_usage += child_inner.usage();
+        inner.children_usage += child_inner.children_usage;
+        drop(child_inner);
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        (found_pid, exit_code)
     } else {
-        -2
-    }
+        let changed = inner
+            .children
+            .iter()
+            .filter(|p| pid == -1 || pid as usize == p.getpid())
+            .find_map(|p| {
+                let mut child_inner = p.inner_exclusive_access();
+                let status = match child_inner.job_change? {
+                    change @ JobChange::Stopped(_) if options & WUNTRACED != 0 => {
+                        change.wait_status()
+                    }
+                    change @ JobChange::Continued if options & WCONTINUED != 0 => {
+                        change.wait_status()
+                    }
+                    _ => return None,
+                };
+                child_inner.job_change = None;
+                Some((p.getpid(), status))
+            });
+        match changed {
+            Some(changed) => changed,
+            None => return -2,
+        }
+    };
+    *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+    found_pid as isize
     // ---- release current PCB lock automatically
 }
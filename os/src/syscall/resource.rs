@@ -0,0 +1,165 @@
+//! CPU time and other resources used by processes
+//!
+//! Threads account their own [`Usage`] as they trap and are switched; a
+//! process adds up its threads, and the processes it waited for. What a
+//! process may use is limited by its [`RLimits`](crate::task::RLimits).
+use super::errno::{EINVAL, EPERM};
+use crate::config::MAX_FD;
+use crate::mm::{translated_read, translated_write};
+use crate::task::{
+    current_process, current_task, current_user_token, cycles_to_ticks, cycles_to_us,
+    fault_in_writable, LimitedResource, RLimit, Usage,
+};
+use crate::timer::get_time;
+use core::mem::size_of;
+
+/// `getrusage` targets
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+const RUSAGE_THREAD: isize = 1;
+
+/// `struct tms`, times in clock ticks
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Tms {
+    utime: usize,
+    stime: usize,
+    cutime: usize,
+    cstime: usize,
+}
+
+/// `struct timeval`
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct TimeVal {
+    sec: usize,
+    usec: usize,
+}
+
+impl TimeVal {
+    fn from_cycles(cycles: usize) -> Self {
+        let us = cycles_to_us(cycles);
+        Self {
+            sec: us / 1_000_000,
+            usec: us % 1_000_000,
+        }
+    }
+}
+
+/// `struct rusage`, the fields not kept are 0
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct RUsage {
+    utime: TimeVal,
+    stime: TimeVal,
+    maxrss: usize,
+    ixrss: usize,
+    idrss: usize,
+    isrss: usize,
+    minflt: usize,
+    majflt: usize,
+    nswap: usize,
+    inblock: usize,
+    oublock: usize,
+    msgsnd: usize,
+    msgrcv: usize,
+    nsignals: usize,
+    nvcsw: usize,
+    nivcsw: usize,
+}
+
+impl From<Usage> for RUsage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            utime: TimeVal::from_cycles(usage.utime),
+            stime: TimeVal::from_cycles(usage.stime),
+            minflt: usage.minflt,
+            nvcsw: usage.nvcsw,
+            nivcsw: usage.nivcsw,
+            ..Default::default()
+        }
+    }
+}
+
+/// Write the CPU times of the caller and of its children waited for to
+/// `tms`, which may be null. Return the clock ticks since boot.
+pub fn sys_times(tms: *mut Tms) -> isize {
+    if !tms.is_null() {
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        let (usage, children) = (inner.usage(), inner.children_usage);
+        drop(inner);
+        let times = Tms {
+            utime: cycles_to_ticks(usage.utime),
+            stime: cycles_to_ticks(usage.stime),
+            cutime: cycles_to_ticks(children.utime),
+            cstime: cycles_to_ticks(children.stime),
+        };
+        if let Err(errno) = fault_in_writable(tms as usize, size_of::<Tms>()) {
+            return -errno;
+        }
+        translated_write(current_user_token(), tms, times);
+    }
+    cycles_to_ticks(get_time()) as isize
+}
+
+/// Write what the caller, its children waited for or the calling thread
+/// used, as told by `who`, to `rusage`
+pub fn sys_getrusage(who: isize, rusage: *mut RUsage) -> isize {
+    let usage = match who {
+        RUSAGE_SELF => current_process().inner_exclusive_access().usage(),
+        RUSAGE_CHILDREN => current_process().inner_exclusive_access().children_usage,
+        RUSAGE_THREAD => current_task().unwrap().inner_exclusive_access().usage,
+        _ => return -EINVAL,
+    };
+    if let Err(errno) = fault_in_writable(rusage as usize, size_of::<RUsage>()) {
+        return -errno;
+    }
+    translated_write(current_user_token(), rusage, RUsage::from(usage));
+    0
+}
+
+/// Write the limit of `resource` of the caller to `rlim`
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    let resource = match LimitedResource::from_number(resource) {
+        Some(resource) => resource,
+        None => return -EINVAL,
+    };
+    let limit = current_process()
+        .inner_exclusive_access()
+        .rlimits
+        .get(resource);
+    if let Err(errno) = fault_in_writable(rlim as usize, size_of::<RLimit>()) {
+        return -errno;
+    }
+    translated_write(current_user_token(), rlim, limit);
+    0
+}
+
+/// Set the limit of `resource` of the caller to `rlim`. Only root may raise
+/// the hard limit. A lower address space limit leaves what is mapped
+/// already, and a stack limit takes effect at the next `exec`.
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    let resource = match LimitedResource::from_number(resource) {
+        Some(resource) => resource,
+        None => return -EINVAL,
+    };
+    let limit: RLimit = translated_read(current_user_token(), rlim);
+    if limit.cur > limit.max {
+        return -EINVAL;
+    }
+    // the fd table doesn't grow past `MAX_FD`, not even for root
+    if resource == LimitedResource::Nofile && limit.max > MAX_FD {
+        return -EPERM;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if limit.max > inner.rlimits.get(resource).max && inner.euid != 0 {
+        return -EPERM;
+    }
+    inner.rlimits.set(resource, limit);
+    if resource == LimitedResource::As {
+        inner.memory_set.set_limit(limit.cur);
+    }
+    0
+}
@@ -0,0 +1,145 @@
+//! Signal syscalls, with Linux numbers
+//!
+//! Signal sets are 32 bits, signal `n` being bit `n`, and `struct sigaction`
+//! is [`SignalAction`]. A handler is entered with the signal number in `a0`
+//! and returns to the restorer of its action, which calls `sigreturn`.
+use super::errno::{EINVAL, EPERM, ESRCH};
+use crate::mm::{translated_read, translated_write};
+use crate::task::{
+    current_credentials, current_process, current_task, current_user_token, fault_in_writable,
+    group_processes, pid2process, process_pids, send_signal, SignalAction, SignalFlags, INITPROC,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const SIG_BLOCK: usize = 0;
+const SIG_UNBLOCK: usize = 1;
+const SIG_SETMASK: usize = 2;
+
+/// Signals that can't be caught, ignored or blocked
+fn unblockable() -> SignalFlags {
+    SignalFlags::SIGKILL | SignalFlags::SIGSTOP
+}
+
+/// Send signal `signum` to the process `pid`, to process group `-pid` if
+/// it's negative, to the caller's group if it's 0, or to every process but
+/// initproc and the caller if it's -1. Signal 0 only checks there are
+/// processes that may be signalled. Only root, or a process of the same
+/// user, may signal a process.
+pub fn sys_kill(pid: isize, signum: usize) -> isize {
+    let signal = match signum {
+        0 => None,
+        _ => match SignalFlags::from_signum(signum) {
+            Some(signal) => Some(signal),
+            None => return -EINVAL,
+        },
+    };
+    let process = current_process();
+    let pgid = process.inner_exclusive_access().pgid;
+    let targets: Vec<_> = match pid {
+        -1 => process_pids()
+            .into_iter()
+            .filter_map(pid2process)
+            .filter(|target| !Arc::ptr_eq(target, &process) && !Arc::ptr_eq(target, &INITPROC))
+            .collect(),
+        0 => group_processes(pgid),
+        pid if pid < 0 => group_processes(pid.unsigned_abs()),
+        pid => pid2process(pid as usize).into_iter().collect(),
+    };
+    if targets.is_empty() {
+        return -ESRCH;
+    }
+    let sender = current_credentials();
+    let targets: Vec<_> = targets
+        .into_iter()
+        .filter(|target| sender.uid == 0 || sender.uid == target.inner_exclusive_access().uid)
+        .collect();
+    if targets.is_empty() {
+        return -EPERM;
+    }
+    if let Some(signal) = signal {
+        for target in targets {
+            send_signal(&target, signal);
+        }
+    }
+    0
+}
+
+/// Set the action of signal `signum` to `*action` unless it's null, and
+/// store the old one in `*old_action` unless it's null
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) if !unblockable().contains(signal) => signal,
+        _ => return -EINVAL,
+    };
+    let token = current_user_token();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !old_action.is_null() {
+        if let Err(errno) = inner
+            .memory_set
+            .fault_in_writable(old_action as usize, size_of::<SignalAction>())
+        {
+            return -errno;
+        }
+        translated_write(token, old_action, inner.signal_actions[signum]);
+    }
+    if !action.is_null() {
+        let mut action: SignalAction = translated_read(token, action);
+        action.mask = SignalFlags::from_bits_truncate(action.mask.bits()) - unblockable();
+        inner.signal_actions[signum] = action;
+        // a pending signal ignored from now on is dropped
+        if inner.ignores(signal) {
+            inner.signals.remove(signal);
+        }
+    }
+    0
+}
+
+/// Change the signals the calling thread blocks by `how` with `*set` unless
+/// it's null, and store the old set in `*old_set` unless it's null
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    let token = current_user_token();
+    if !old_set.is_null() {
+        if let Err(errno) = fault_in_writable(old_set as usize, size_of::<u32>()) {
+            return -errno;
+        }
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let old = inner.signal_mask;
+    if !set.is_null() {
+        let set = SignalFlags::from_bits_truncate(translated_read(token, set)) - unblockable();
+        inner.signal_mask = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old - set,
+            SIG_SETMASK => set,
+            _ => return -EINVAL,
+        };
+    }
+    drop(inner);
+    if !old_set.is_null() {
+        translated_write(token, old_set, old.bits());
+    }
+    0
+}
+
+/// Return from a signal handler to what it interrupted, with the signal
+/// mask from before
+pub fn sys_sigreturn() -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let frame = match inner.signal_frame.take() {
+        Some(frame) => frame,
+        None => return -EINVAL,
+    };
+    inner.signal_mask = frame.mask;
+    *inner.get_trap_cx() = frame.trap_cx;
+    // the syscall return value goes to a0, which has to be kept as well
+    frame.trap_cx.x[10] as isize
+}
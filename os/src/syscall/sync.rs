@@ -0,0 +1,275 @@
+//! Mutex, semaphore and condition variable syscalls, numbered like rCore's,
+//! and `futex`
+//!
+//! The objects belong to the calling process and are named by their index
+//! in its lists. Waiting never returns early to user mode: a killed thread
+//! gives up with -EINTR but doesn't get back. Once deadlock detection is
+//! enabled, taking a mutex or semaphore fails with -EDEADLK where waiting
+//! could go on forever.
+use super::errno::{EAGAIN, EFAULT, EINVAL, EPERM};
+use super::poll::{read_deadline, TimeSpec};
+use crate::mm::{PageTable, VirtAddr};
+use crate::sync::{
+    futex_wait, futex_wake, Condvar, Mutex, MutexBlocking, MutexSpin, Resource, Semaphore,
+};
+use crate::task::{current_process, current_task, current_user_token, fault_in_writable};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+/// Only tells the futex isn't shared with other processes, which makes no
+/// difference here
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// Put `object` into the first free slot of `list` and return its index
+fn insert_object<T: ?Sized>(list: &mut Vec<Option<Arc<T>>>, object: Arc<T>) -> usize {
+    match list.iter().position(Option::is_none) {
+        Some(id) => {
+            list[id] = Some(object);
+            id
+        }
+        None => {
+            list.push(Some(object));
+            list.len() - 1
+        }
+    }
+}
+
+/// The object at index `id` of `list`
+fn get_object<T: ?Sized>(list: &[Option<Arc<T>>], id: usize) -> Option<Arc<T>> {
+    list.get(id).cloned().flatten()
+}
+
+/// Take a unit of `resource` with `take`, which blocks until there is one,
+/// keeping the deadlock detector of the process up to date
+fn take_resource(resource: Resource, take: impl FnOnce() -> Result<(), isize>) -> isize {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    let request = process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .request(tid, resource);
+    if let Err(errno) = request {
+        return -errno;
+    }
+    let result = take();
+    let detector = &mut process.inner_exclusive_access().deadlock_detector;
+    match result {
+        Ok(()) => {
+            detector.acquire(tid, resource);
+            0
+        }
+        Err(errno) => {
+            detector.cancel(tid, resource);
+            -errno
+        }
+    }
+}
+
+/// Put back a unit of `resource`, in the deadlock detector of the process
+fn put_resource(resource: Resource) {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .release(tid, resource);
+}
+
+/// Whether the current thread holds mutex `mutex_id`
+fn holds_mutex(mutex_id: usize) -> bool {
+    let tid = current_task().unwrap().gettid();
+    let process = current_process();
+    let holds = process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .holds(tid, Resource::Mutex(mutex_id));
+    holds
+}
+
+/// Refuse to take a mutex or semaphore where waiting for it could go on
+/// forever if `enabled` is 1, or stop that if it's 0
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    if enabled > 1 {
+        return -EINVAL;
+    }
+    let process = current_process();
+    process.inner_exclusive_access().deadlock_detector.enabled = enabled == 1;
+    0
+}
+
+/// Create a mutex whose waiters block, or yield while they wait unless
+/// `blocking`. Return its id.
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    let mutex: Arc<dyn Mutex> = if blocking {
+        Arc::new(MutexBlocking::new())
+    } else {
+        Arc::new(MutexSpin::new())
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let id = insert_object(&mut inner.mutex_list, mutex);
+    inner.deadlock_detector.add_resource(Resource::Mutex(id), 1);
+    id as isize
+}
+
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    let process = current_process();
+    let mutex = get_object(&process.inner_exclusive_access().mutex_list, mutex_id);
+    drop(process);
+    match mutex {
+        Some(mutex) => take_resource(Resource::Mutex(mutex_id), || mutex.lock()),
+        None => -EINVAL,
+    }
+}
+
+/// Return -EPERM if the caller doesn't hold the mutex
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    let process = current_process();
+    let mutex = get_object(&process.inner_exclusive_access().mutex_list, mutex_id);
+    drop(process);
+    match mutex {
+        Some(_) if !holds_mutex(mutex_id) => -EPERM,
+        Some(mutex) => {
+            mutex.unlock();
+            put_resource(Resource::Mutex(mutex_id));
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+/// Create a semaphore with `res_count` resources. Return its id.
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let id = insert_object(
+        &mut inner.semaphore_list,
+        Arc::new(Semaphore::new(res_count)),
+    );
+    inner
+        .deadlock_detector
+        .add_resource(Resource::Semaphore(id), res_count);
+    id as isize
+}
+
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    let process = current_process();
+    let semaphore = get_object(&process.inner_exclusive_access().semaphore_list, sem_id);
+    drop(process);
+    match semaphore {
+        Some(semaphore) => {
+            semaphore.up();
+            put_resource(Resource::Semaphore(sem_id));
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    let process = current_process();
+    let semaphore = get_object(&process.inner_exclusive_access().semaphore_list, sem_id);
+    drop(process);
+    match semaphore {
+        Some(semaphore) => take_resource(Resource::Semaphore(sem_id), || semaphore.down()),
+        None => -EINVAL,
+    }
+}
+
+/// Create a condition variable. Return its id.
+pub fn sys_condvar_create() -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    insert_object(&mut inner.condvar_list, Arc::new(Condvar::new())) as isize
+}
+
+pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+    let process = current_process();
+    let condvar = get_object(&process.inner_exclusive_access().condvar_list, condvar_id);
+    drop(process);
+    match condvar {
+        Some(condvar) => {
+            condvar.signal();
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+/// Wait on condition variable `condvar_id` with mutex `mutex_id` released,
+/// which the caller has to hold or else get -EPERM
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let condvar = get_object(&inner.condvar_list, condvar_id);
+    let mutex = get_object(&inner.mutex_list, mutex_id);
+    drop(inner);
+    drop(process);
+    let (condvar, mutex) = match (condvar, mutex) {
+        (Some(condvar), Some(mutex)) => (condvar, mutex),
+        _ => return -EINVAL,
+    };
+    if !holds_mutex(mutex_id) {
+        return -EPERM;
+    }
+    // the mutex is free while waiting, and taken again without a check
+    put_resource(Resource::Mutex(mutex_id));
+    match condvar.wait(mutex) {
+        Ok(()) => {
+            let tid = current_task().unwrap().gettid();
+            let process = current_process();
+            process
+                .inner_exclusive_access()
+                .deadlock_detector
+                .acquire(tid, Resource::Mutex(mutex_id));
+            0
+        }
+        Err(errno) => -errno,
+    }
+}
+
+/// Wait on the futex word at `uaddr` while it holds `val`, for up to the
+/// relative `timeout` unless it's null, or wake up to `val` waiters of it.
+/// Futexes are told apart by the physical address of the word, so a word in
+/// shared memory is one futex for every process mapping it. Return -EFAULT
+/// unless the task may store to the word.
+pub fn sys_futex(uaddr: usize, op: usize, val: u32, timeout: *const TimeSpec) -> isize {
+    if uaddr % size_of::<u32>() != 0 {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    // a private page is copied by the first store, the futex is the copy
+    if let Err(errno) = fault_in_writable(uaddr, size_of::<u32>()) {
+        return -errno;
+    }
+    let page_table = PageTable::from_token(token);
+    let va = VirtAddr::from(uaddr);
+    // e.g. a trap context, which is mapped but not to the task
+    if !page_table.translate(va.floor()).map_or(false, |pte| {
+        pte.is_valid() && pte.is_user() && pte.readable()
+    }) {
+        return -EFAULT;
+    }
+    let pa = page_table.translate_va(va).unwrap();
+    let key: usize = pa.into();
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            let deadline = match read_deadline(token, timeout) {
+                Ok(deadline) => deadline,
+                Err(errno) => return -errno,
+            };
+            if *pa.get_mut::<u32>() != val {
+                return -EAGAIN;
+            }
+            match futex_wait(key, deadline) {
+                Ok(()) => 0,
+                Err(errno) => -errno,
+            }
+        }
+        FUTEX_WAKE => futex_wake(key, val as usize) as isize,
+        _ => -EINVAL,
+    }
+}
@@ -0,0 +1,80 @@
+//! Thread syscalls, numbered like rCore's except `gettid`
+use super::errno::ENOMEM;
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE};
+use crate::mm::{kernel_token, MemorySet};
+use crate::task::{add_task, current_process, current_task, TaskControlBlock, TaskUserRes};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::sync::Arc;
+
+/// Start a thread of the current process at `entry`, with `arg` in `a0`
+/// and a user stack of its own. Return its tid, or `-ENOMEM` if something
+/// is mapped where the stack goes, it doesn't fit in the address space
+/// limit or there aren't frames for it.
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let process = current_process();
+    let task_inner = task.inner_exclusive_access();
+    let task_res = task_inner.res.as_ref().unwrap();
+    let (ustack_base, ustack_size) = (task_res.ustack_base, task_res.ustack_size);
+    drop(task_inner);
+    // the kernel stack isn't in the address space, but takes frames too
+    if !MemorySet::frames_for((ustack_size + PAGE_SIZE + KERNEL_STACK_SIZE) / PAGE_SIZE) {
+        return -ENOMEM;
+    }
+    let res = match TaskUserRes::new(process.clone(), ustack_base, ustack_size, true) {
+        Some(res) => res,
+        None => return -ENOMEM,
+    };
+    let new_task = Arc::new(TaskControlBlock::new(&process, res));
+    let new_task_inner = new_task.inner_exclusive_access();
+    let new_task_res = new_task_inner.res.as_ref().unwrap();
+    let new_task_tid = new_task_res.tid;
+    let new_task_trap_cx = new_task_inner.get_trap_cx();
+    *new_task_trap_cx = TrapContext::app_init_context(
+        entry,
+        new_task_res.ustack_top(),
+        kernel_token(),
+        new_task.kstack.get_top(),
+        trap_handler as usize,
+    );
+    new_task_trap_cx.x[10] = arg;
+    drop(new_task_inner);
+    process
+        .inner_exclusive_access()
+        .insert_task(new_task_tid, new_task.clone());
+    add_task(new_task);
+    new_task_tid as isize
+}
+
+pub fn sys_gettid() -> isize {
+    current_task().unwrap().gettid() as isize
+}
+
+/// Reap the thread `tid` of the current process and return its exit code.
+/// Return -1 for the caller itself or a thread that doesn't exist, and -2
+/// for one that hasn't exited yet.
+pub fn sys_waittid(tid: usize) -> isize {
+    let task = current_task().unwrap();
+    if task.gettid() == tid {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let waited = match inner.get_task(tid) {
+        Some(waited) => waited,
+        None => return -1,
+    };
+    let exit_code = waited.inner_exclusive_access().exit_code;
+    match exit_code {
+        Some(exit_code) => {
+            let reaped = inner.tasks[tid].take();
+            // only now may another thread get the tid
+            inner.dealloc_tid(tid);
+            drop(inner);
+            // the kernel stack goes with the last reference
+            drop(reaped);
+            exit_code as isize
+        }
+        None => -2,
+    }
+}
@@ -0,0 +1,266 @@
+//!Allocation of pids, kernel stacks and thread ids, and [`TaskUserRes`]
+use super::ProcessControlBlock;
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE};
+use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::*;
+///Allocator of small ids, handing out recycled ones first
+#[derive(Clone)]
+pub struct RecycleAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    ///Create an empty `RecycleAllocator`
+    pub fn new() -> Self {
+        RecycleAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    ///Allocate an id
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    ///Recycle an id
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+    static ref KSTACK_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+}
+///Bind pid lifetime to `PidHandle`
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        //println!("drop pid {}", self.0);
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+///Allocate a pid from PID_ALLOCATOR
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
+}
+
+/// Return (bottom, top) of a kernel stack in kernel space.
+pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
+    let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - KERNEL_STACK_SIZE;
+    (bottom, top)
+}
+///Kernel stack of a thread
+pub struct KernelStack(pub usize);
+
+///Allocate and map a kernel stack
+pub fn kstack_alloc() -> KernelStack {
+    let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
+    let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
+    KERNEL_SPACE.exclusive_access().insert_framed_area(
+        kstack_bottom.into(),
+        kstack_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
+    KernelStack(kstack_id)
+}
+
+impl KernelStack {
+    #[allow(unused)]
+    ///Push a value on top of kernelstack
+    pub fn push_on_top<T>(&self, value: T) -> *mut T
+    where
+        T: Sized,
+    {
+        let kernel_stack_top = self.get_top();
+        let ptr_mut = (kernel_stack_top - core::mem::size_of::<T>()) as *mut T;
+        unsafe {
+            *ptr_mut = value;
+        }
+        ptr_mut
+    }
+    ///Get the value on the top of kernelstack
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.0);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
+        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+        KERNEL_SPACE
+            .exclusive_access()
+            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// What a thread owns in the address space of its process: its tid, and
+/// the user stack and trap context pages that come with it
+pub struct TaskUserRes {
+    /// Thread id, unique in the process
+    pub tid: usize,
+    /// Bottom of the user stack of thread 0, the others are above it
+    pub ustack_base: usize,
+    /// Size of the user stacks of the process, set by `RLIMIT_STACK` at
+    /// `exec`
+    pub ustack_size: usize,
+    /// The process the thread belongs to
+    pub process: Weak<ProcessControlBlock>,
+}
+
+/// Bottom of the trap context page of thread `tid`
+fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT_BASE - tid * PAGE_SIZE
+}
+
+/// Bottom of the user stack of thread `tid`, with a guard page below each
+fn ustack_bottom_from_tid(ustack_base: usize, ustack_size: usize, tid: usize) -> usize {
+    ustack_base + tid * (PAGE_SIZE + ustack_size)
+}
+
+/// Map the user stack and trap context of thread `tid`. Return `false`,
+/// mapping neither, if something is mapped there already or they don't fit
+/// in the address space limit.
+fn map_user_res(
+    memory_set: &mut MemorySet,
+    ustack_base: usize,
+    ustack_size: usize,
+    tid: usize,
+) -> bool {
+    let ustack_bottom = ustack_bottom_from_tid(ustack_base, ustack_size, tid);
+    let ustack_top = ustack_bottom + ustack_size;
+    let trap_cx_bottom = trap_cx_bottom_from_tid(tid);
+    let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
+    // an mmap may have taken the place of the stack
+    if !memory_set.is_free(
+        VirtAddr::from(ustack_bottom).floor(),
+        VirtAddr::from(ustack_top).ceil(),
+    ) || !memory_set.is_free(
+        VirtAddr::from(trap_cx_bottom).floor(),
+        VirtAddr::from(trap_cx_top).ceil(),
+    ) {
+        return false;
+    }
+    if !memory_set.insert_framed_area(
+        ustack_bottom.into(),
+        ustack_top.into(),
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    ) {
+        return false;
+    }
+    if !memory_set.insert_framed_area(
+        trap_cx_bottom.into(),
+        trap_cx_top.into(),
+        MapPermission::R | MapPermission::W,
+    ) {
+        let ustack_bottom_va: VirtAddr = ustack_bottom.into();
+        memory_set.remove_area_with_start_vpn(ustack_bottom_va.into());
+        return false;
+    }
+    true
+}
+
+impl TaskUserRes {
+    /// Allocate a tid in `process`, and map the user stack and trap context
+    /// of the thread if `alloc_user_res`. Return `None` if something is
+    /// mapped where they go or they don't fit in the address space limit.
+    pub fn new(
+        process: Arc<ProcessControlBlock>,
+        ustack_base: usize,
+        ustack_size: usize,
+        alloc_user_res: bool,
+    ) -> Option<Self> {
+        let mut process_inner = process.inner_exclusive_access();
+        let tid = process_inner.alloc_tid();
+        if alloc_user_res
+            && !map_user_res(&mut process_inner.memory_set, ustack_base, ustack_size, tid)
+        {
+            process_inner.dealloc_tid(tid);
+            return None;
+        }
+        drop(process_inner);
+        Some(Self {
+            tid,
+            ustack_base,
+            ustack_size,
+            process: Arc::downgrade(&process),
+        })
+    }
+    /// Map the user stack and trap context of the thread. Return `false`,
+    /// mapping neither, if something is mapped there already or they don't
+    /// fit in the address space limit.
+    pub fn alloc_user_res(&self) -> bool {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        map_user_res(
+            &mut process_inner.memory_set,
+            self.ustack_base,
+            self.ustack_size,
+            self.tid,
+        )
+    }
+    /// Unmap the user stack and trap context of the thread
+    fn dealloc_user_res(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        let ustack_bottom_va: VirtAddr =
+            ustack_bottom_from_tid(self.ustack_base, self.ustack_size, self.tid).into();
+        process_inner
+            .memory_set
+            .remove_area_with_start_vpn(ustack_bottom_va.into());
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
+        process_inner
+            .memory_set
+            .remove_area_with_start_vpn(trap_cx_bottom_va.into());
+    }
+    /// Where the trap context of the thread is in user space
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+    /// The frame holding the trap context of the thread
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        let process = self.process.upgrade().unwrap();
+        let process_inner = process.inner_exclusive_access();
+        let trap_cx_bottom_va: VirtAddr = self.trap_cx_user_va().into();
+        process_inner
+            .memory_set
+            .translate(trap_cx_bottom_va.into())
+            .unwrap()
+            .ppn()
+    }
+    /// Top of the user stack of the thread
+    pub fn ustack_top(&self) -> usize {
+        ustack_bottom_from_tid(self.ustack_base, self.ustack_size, self.tid) + self.ustack_size
+    }
+}
+
+impl Drop for TaskUserRes {
+    fn drop(&mut self) {
+        // the process may be gone, taking its address space with it. The
+        // tid stays taken until the thread is waited for.
+        if self.process.upgrade().is_some() {
+            self.dealloc_user_res();
+        }
+    }
+}
@@ -1,8 +1,9 @@
 //!Implementation of [`TaskManager`]
-use super::TaskControlBlock;
+use super::{ProcessControlBlock, TaskControlBlock};
 use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
-use alloc::sync::Arc;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use lazy_static::*;
 ///A array of `TaskControlBlock` that is thread-safe
 pub struct TaskManager {
@@ -30,6 +31,9 @@ impl TaskManager {
 lazy_static! {
     pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
         unsafe { UPSafeCell::new(TaskManager::new()) };
+    /// All processes not reaped yet, indexed by pid
+    pub static ref PID2PCB: UPSafeCell<BTreeMap<usize, Weak<ProcessControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
 }
 ///Interface offered to add task
 pub fn add_task(task: Arc<TaskControlBlock>) {
@@ -39,3 +43,39 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
+///Record a new process so that it can be found by pid
+pub fn insert_into_pid2process(pid: usize, process: &Arc<ProcessControlBlock>) {
+    PID2PCB
+        .exclusive_access()
+        .insert(pid, Arc::downgrade(process));
+}
+///Forget a process once it has been reaped
+pub fn remove_from_pid2process(pid: usize) {
+    PID2PCB.exclusive_access().remove(&pid);
+}
+///Get the process with the given pid
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PID2PCB.exclusive_access().get(&pid).and_then(Weak::upgrade)
+}
+///Get the pids of all processes, in ascending order
+pub fn process_pids() -> Vec<usize> {
+    PID2PCB.exclusive_access().keys().copied().collect()
+}
+///Count the processes of real user `uid`, reaped or not
+pub fn user_process_count(uid: u32) -> usize {
+    PID2PCB
+        .exclusive_access()
+        .values()
+        .filter_map(Weak::upgrade)
+        .filter(|process| process.inner_exclusive_access().uid == uid)
+        .count()
+}
+///Get the processes of process group `pgid`
+pub fn group_processes(pgid: usize) -> Vec<Arc<ProcessControlBlock>> {
+    PID2PCB
+        .exclusive_access()
+        .values()
+        .filter_map(Weak::upgrade)
+        .filter(|process| process.inner_exclusive_access().pgid == pgid)
+        .collect()
+}
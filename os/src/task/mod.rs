@@ -9,36 +9,68 @@
 //! A single global instance of [`Processor`] called `PROCESSOR` monitors running
 //! task(s) for each core.
 //!
-//! A single global instance of [`PidAllocator`] called `PID_ALLOCATOR` allocates
-//! pid for user apps.
+//! A task is a thread. The threads of a process share a
+//! [`ProcessControlBlock`] holding the address space, files and children,
+//! each thread has its own kernel stack, and user stack and trap context in
+//! the address space, see [`TaskUserRes`]. A process exits once its last
+//! thread has.
 //!
 //! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
 mod context;
+mod id;
 mod manager;
-mod pid;
+mod process;
 mod processor;
+mod rlimit;
+mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
+mod usage;
 
-use crate::loader::get_app_data_by_name;
-use crate::sbi::shutdown;
-use alloc::sync::Arc;
+use crate::fs::{open_exec, Credentials};
+use crate::mm::VirtAddr;
+use crate::syscall::errno::EINTR;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use lazy_static::*;
-pub use manager::{fetch_task, TaskManager};
+pub use manager::{
+    fetch_task, group_processes, insert_into_pid2process, pid2process, process_pids,
+    remove_from_pid2process, user_process_count, TaskManager,
+};
+pub use rlimit::{LimitedResource, RLimit, RLimits, RLIM_INFINITY};
+use signal::interrupted;
 use switch::__switch;
-use task::{TaskControlBlock, TaskStatus};
+pub use task::{TaskControlBlock, TaskControlBlockInner, TaskStatus};
+pub use usage::{cycles_to_ticks, cycles_to_us, Usage};
 
 pub use context::TaskContext;
+pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, RecycleAllocator, TaskUserRes};
 pub use manager::add_task;
-pub use pid::{pid_alloc, KernelStack, PidAllocator, PidHandle};
+pub use process::{ProcessControlBlock, ProcessControlBlockInner};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
-    Processor,
+    current_credentials, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
+    current_user_token, run_tasks, schedule, take_current_task, Processor,
+};
+pub use signal::{
+    current_fault, current_syscall_interrupted, current_takes, handle_signals, send_group_signal,
+    send_signal, JobChange, SignalAction, SignalFlags, SignalFrame, MAX_SIG, SIG_DFL, SIG_IGN,
 };
 /// Suspend the current 'Running' task and run the next task in task list.
 pub fn suspend_current_and_run_next() {
+    suspend_current(false);
+}
+
+/// Take the CPU from the current task at the end of its time slice, and run
+/// the next task in task list
+pub fn preempt_current_and_run_next() {
+    suspend_current(true);
+}
+
+/// Put the current task back into the ready queue and run the next one,
+/// counting a switch the task didn't ask for if `preempted`
+fn suspend_current(preempted: bool) {
     // There must be an application running.
     let task = take_current_task().unwrap();
 
@@ -47,6 +79,12 @@ pub fn suspend_current_and_run_next() {
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     // Change status to Ready
     task_inner.task_status = TaskStatus::Ready;
+    task_inner.charge_system_time();
+    if preempted {
+        task_inner.usage.nivcsw += 1;
+    } else {
+        task_inner.usage.nvcsw += 1;
+    }
     drop(task_inner);
     // ---- release current PCB
 
@@ -56,38 +94,251 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
-/// pid of usertests app in make run TEST=1
-pub const IDLE_PID: usize = 0;
+/// Yield while waiting for something, like [`suspend_current_and_run_next`].
+/// Return `Err(EINTR)` if the current task has a signal to take once it's
+/// back, and should give up waiting.
+pub fn suspend_interruptible() -> Result<(), isize> {
+    suspend_current_and_run_next();
+    if current_interrupted() {
+        return Err(EINTR);
+    }
+    Ok(())
+}
 
-/// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next(exit_code: i32) {
-    // take from Processor
+/// Block the current 'Running' task and run the next task in task list.
+/// It stays off the ready queue until [`wakeup_task`] is called on it, so
+/// the caller has to leave a way to find it, e.g. in a [`WaitQueue`] or a
+/// timer, and check again what it waits for once it's back.
+///
+/// [`WaitQueue`]: crate::sync::WaitQueue
+pub fn block_current_and_run_next() {
     let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    task_inner.charge_system_time();
+    task_inner.usage.nvcsw += 1;
+    drop(task_inner);
+    // the task lives on in whatever is going to wake it
+    drop(task);
+    schedule(task_cx_ptr);
+}
+
+/// Put a blocked task back into the ready queue, do nothing to a task
+/// that isn't blocked
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
+
+/// Handle a store page fault of the current task at `va`. Return whether
+/// the store may be tried again.
+pub fn current_store_fault(va: usize) -> bool {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner.memory_set.store_fault(VirtAddr::from(va).floor()) {
+        return false;
+    }
+    drop(inner);
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .usage
+        .minflt += 1;
+    true
+}
+
+/// The current task trapped into the kernel, the time since it left is
+/// user time
+pub fn current_enter_kernel() {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .charge_user_time();
+}
+
+/// The current task returns to user mode, the time since it entered the
+/// kernel, or was switched to, is system time
+pub fn current_leave_kernel() {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .charge_system_time();
+}
+
+/// Signal the current process if its CPU time reached `RLIMIT_CPU`:
+/// `SIGXCPU` at the soft limit, which then goes up by a second so that it
+/// comes again, and `SIGKILL` at the hard limit
+pub fn current_check_cpu_limit() {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let limit = inner.rlimits.get(LimitedResource::Cpu);
+    if limit.cur == RLIM_INFINITY {
+        return;
+    }
+    let usage = inner.usage();
+    let secs = cycles_to_us(usage.utime + usage.stime) / 1_000_000;
+    let signal = if secs >= limit.max {
+        SignalFlags::SIGKILL
+    } else if secs >= limit.cur {
+        let cur = (limit.cur + 1).min(limit.max);
+        inner
+            .rlimits
+            .set(LimitedResource::Cpu, RLimit { cur, ..limit });
+        SignalFlags::SIGXCPU
+    } else {
+        return;
+    };
+    drop(inner);
+    send_signal(&process, signal);
+}
+
+/// Whether the current task is killed or has a signal to take, so that a
+/// syscall waiting for something should return `-EINTR`
+pub fn current_interrupted() -> bool {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let task_inner = task.inner_exclusive_access();
+    interrupted(&process_inner, &task_inner)
+}
+
+/// Whether the current thread has to exit on its way back to user mode,
+/// because its process exits or execs
+pub fn current_killed() -> bool {
+    current_task().unwrap().inner_exclusive_access().killed
+}
 
-    let pid = task.getpid();
-    if pid == IDLE_PID {
-        println!(
-            "[kernel] Idle process exit with exit_code {} ...",
-            exit_code
-        );
-        if exit_code != 0 {
-            //crate::sbi::shutdown(255); //255 == -1 for err hint
-            shutdown(true)
-        } else {
-            //crate::sbi::shutdown(0); //0 for success hint
-            shutdown(false)
+/// Prepare `len` bytes at `ptr` in the current task for the kernel to
+/// write, copying or dirtying file mapped pages like a store from the task.
+/// Return `Err(EFAULT)` if the task may not store there.
+pub fn fault_in_writable(ptr: usize, len: usize) -> Result<(), isize> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.memory_set.fault_in_writable(ptr, len)
+}
+
+/// Kill the other threads of the current process, e.g. before it execs,
+/// and wait until they have exited. Return `Err(EINTR)` if the current
+/// thread is killed meanwhile.
+pub fn end_other_threads() -> Result<(), isize> {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    loop {
+        let others: Vec<_> = process
+            .inner_exclusive_access()
+            .live_tasks()
+            .filter(|other| !Arc::ptr_eq(other, &task))
+            .cloned()
+            .collect();
+        if others.is_empty() {
+            return Ok(());
+        }
+        for other in others {
+            other.inner_exclusive_access().killed = true;
+            wakeup_task(other);
         }
+        if task.inner_exclusive_access().killed {
+            return Err(EINTR);
+        }
+        suspend_current_and_run_next();
+    }
+}
+
+/// Exit the current thread, and the whole process if it's the main thread.
+pub fn exit_current_and_run_next(exit_code: i32) {
+    if current_task().unwrap().gettid() == 0 {
+        exit_current_process_and_run_next(exit_code);
+    } else {
+        exit_current_thread_and_run_next(exit_code);
+    }
+}
+
+/// Exit the current process with `exit_code`, unless it's already exiting:
+/// its other threads are killed, and exit on their way back to user mode.
+pub fn exit_current_process_and_run_next(exit_code: i32) {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner.exiting {
+        inner.exiting = true;
+        inner.exit_code = exit_code;
+    }
+    let tasks: Vec<_> = inner.live_tasks().cloned().collect();
+    drop(inner);
+    drop(process);
+    for task in tasks {
+        task.inner_exclusive_access().killed = true;
+        wakeup_task(task);
     }
+    exit_current_thread_and_run_next(exit_code);
+}
+
+/// Exit the current thread and run the next task in task list. The last
+/// thread to exit ends the process.
+fn exit_current_thread_and_run_next(exit_code: i32) {
+    // take from Processor
+    let task = take_current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
 
     // **** access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
     // Change status to Zombie
-    inner.task_status = TaskStatus::Zombie;
+    task_inner.task_status = TaskStatus::Zombie;
     // Record exit code
-    inner.exit_code = exit_code;
+    task_inner.exit_code = Some(exit_code);
+    task_inner.charge_system_time();
+    let usage = task_inner.usage;
+    // the user stack and trap context go, the kernel stack is still in use
+    // and stays with the TCB until it's waited for
+    let res = task_inner.res.take();
+    drop(task_inner);
+    // **** release current TCB
+    let tid = res.as_ref().unwrap().tid;
+    drop(res);
+    // drop task manually to maintain rc correctly
+    drop(task);
+
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.deadlock_detector.remove_thread(tid);
+    process_inner.exited_usage += usage;
+    let last = process_inner.live_tasks().next().is_none();
+    drop(process_inner);
+    if last {
+        exit_process(process, exit_code);
+    } else {
+        drop(process);
+    }
+    // we do not have to save task context
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+}
+
+/// End `process` once its last thread exited with `exit_code`, which is
+/// its exit code unless an exit of the whole process set one
+fn exit_process(process: Arc<ProcessControlBlock>, exit_code: i32) {
+    // **** access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
+    if !inner.exiting {
+        inner.exit_code = exit_code;
+    }
+    let exit_code = inner.exit_code;
+    let parent = inner.parent.as_ref().and_then(Weak::upgrade);
+
+    // initproc reaps the orphans, the machine goes down with a `reboot`
+    if Arc::ptr_eq(&process, &INITPROC) {
+        drop(inner);
+        panic!("initproc exited with exit_code {}", exit_code);
+    }
+
+    inner.is_zombie = true;
     // do not move to its parent but under initproc
 
-    // ++++++ access initproc TCB exclusively
+    // ++++++ access initproc PCB exclusively
     {
         let mut initproc_inner = INITPROC.inner_exclusive_access();
         for child in inner.children.iter() {
@@ -100,22 +351,32 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     inner.children.clear();
     // deallocate user space
     inner.memory_set.recycle_data_pages();
+    // close the files now, so that e.g. the readers of a pipe see end of
+    // file without waiting for the parent to reap this process
+    let files = core::mem::take(&mut inner.fd_table);
     drop(inner);
     // **** release current PCB
-    // drop task manually to maintain rc correctly
-    drop(task);
-    // we do not have to save task context
-    let mut _unused = TaskContext::zero_init();
-    schedule(&mut _unused as *mut _);
+    // closing may wake tasks waiting on the files, after the PCB is free
+    drop(files);
+    if let Some(parent) = parent {
+        send_signal(&parent, SignalFlags::SIGCHLD);
+    }
 }
 
+/// Paths of the first user program, an initramfs has it in `/sbin`
+const INITPROC_PATHS: [&str; 2] = ["/sbin/initproc", "/bin/initproc"];
+
 lazy_static! {
     ///Globle process that init user shell
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
-        get_app_data_by_name("initproc").unwrap()
-    ));
+    pub static ref INITPROC: Arc<ProcessControlBlock> = {
+        let (path, elf) = INITPROC_PATHS
+            .iter()
+            .find_map(|path| Some((*path, open_exec(path, Credentials::ROOT).ok()?)))
+            .expect("initproc not found");
+        ProcessControlBlock::new(path, elf.as_ref()).expect("bad initproc")
+    };
 }
-///Add init process to the manager
+///Add init process to the manager, creating it puts its main thread there
 pub fn add_initproc() {
-    add_task(INITPROC.clone());
+    lazy_static::initialize(&INITPROC);
 }
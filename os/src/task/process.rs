@@ -0,0 +1,408 @@
+//!Implementation of [`ProcessControlBlock`]
+use super::{add_task, insert_into_pid2process, pid_alloc};
+use super::{JobChange, SignalAction, SignalFlags, Usage, MAX_SIG, SIG_IGN};
+use super::{LimitedResource, PidHandle, RLimits, RecycleAllocator, TaskControlBlock, TaskUserRes};
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE};
+use crate::fs::{Credentials, File, CONSOLE};
+use crate::mm::{ElfError, MemorySet, KERNEL_SPACE};
+use crate::sync::{Condvar, DeadlockDetector, Mutex, Semaphore, UPSafeCell, WaitQueue};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// Process control block structure
+///
+/// What the threads of a process share: the address space, files, ids and
+/// signal actions
+pub struct ProcessControlBlock {
+    // immutable
+    /// Process identifier
+    pub pid: PidHandle,
+    // mutable
+    inner: UPSafeCell<ProcessControlBlockInner>,
+}
+
+/// The part of a process that changes, wrapped by UPSafeCell to provide
+/// mutual exclusion
+pub struct ProcessControlBlockInner {
+    /// Whether the process has exited but not been reaped yet
+    pub is_zombie: bool,
+    /// Whether the process is exiting, with `exit_code`, once its threads
+    /// have left
+    pub exiting: bool,
+    /// Application address space
+    pub memory_set: MemorySet,
+    /// Parent process of the current process.
+    /// Weak will not affect the reference count of the parent
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// A vector containing PCBs of all child processes of the current process
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// It is set when active exit or execution error occurs
+    pub exit_code: i32,
+    /// Opened files, indexed by fd
+    pub fd_table: Vec<Option<Arc<dyn File>>>,
+    /// fds closed on `exec`
+    pub cloexec: BTreeSet<usize>,
+    /// Command line the current program was started with
+    pub cmdline: String,
+    /// Real user id, who started the process
+    pub uid: u32,
+    /// Effective user id, who files are accessed as
+    pub euid: u32,
+    /// Group id
+    pub gid: u32,
+    /// Process group, signalled as a whole e.g. by Ctrl-C
+    pub pgid: usize,
+    /// Session, the process groups of a login
+    pub sid: usize,
+    /// Signals sent to the process and not taken yet
+    pub signals: SignalFlags,
+    /// Whether a stop signal stopped the process
+    pub stopped: bool,
+    /// Threads blocked until the process continues
+    pub stopped_threads: WaitQueue,
+    /// The last stop or continue, until the parent learns of it
+    pub job_change: Option<JobChange>,
+    /// Action of each signal, indexed by its number
+    pub signal_actions: [SignalAction; MAX_SIG + 1],
+    /// Threads of the process, indexed by tid. Exited threads stay until
+    /// they're waited for.
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// Allocator of tids
+    pub task_res_allocator: RecycleAllocator,
+    /// Mutexes, indexed by id
+    pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
+    /// Semaphores, indexed by id
+    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
+    /// Condition variables, indexed by id
+    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// Who holds and waits for the mutexes and semaphores
+    pub deadlock_detector: DeadlockDetector,
+    /// What the threads that exited used
+    pub exited_usage: Usage,
+    /// What the children waited for, and their waited for children, used
+    pub children_usage: Usage,
+    /// Resource limits, inherited by children
+    pub rlimits: RLimits,
+}
+
+impl ProcessControlBlockInner {
+    /// Get the address of app's page table
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// Whether the process has exited but not been reaped yet
+    pub fn is_zombie(&self) -> bool {
+        self.is_zombie
+    }
+    /// Get the lowest unused fd, or `None` if it's past `RLIMIT_NOFILE`
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        let fd = (0..self.fd_table.len())
+            .find(|fd| self.fd_table[*fd].is_none())
+            .unwrap_or(self.fd_table.len());
+        if fd >= self.rlimits.cur(LimitedResource::Nofile) {
+            return None;
+        }
+        if fd == self.fd_table.len() {
+            self.fd_table.push(None);
+        }
+        Some(fd)
+    }
+    /// Who the process accesses files as
+    pub fn credentials(&self) -> Credentials {
+        Credentials {
+            uid: self.euid,
+            gid: self.gid,
+        }
+    }
+    /// Allocate a tid
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+    /// Recycle a tid
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+    /// Get the thread `tid`
+    pub fn get_task(&self, tid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.tasks.get(tid).cloned().flatten()
+    }
+    /// Add thread `task` as `tid`
+    pub fn insert_task(&mut self, tid: usize, task: Arc<TaskControlBlock>) {
+        if self.tasks.len() <= tid {
+            self.tasks.resize(tid + 1, None);
+        }
+        self.tasks[tid] = Some(task);
+    }
+    /// What the threads of the process used, together
+    pub fn usage(&self) -> Usage {
+        let mut usage = self.exited_usage;
+        for task in self.live_tasks() {
+            usage += task.inner_exclusive_access().usage;
+        }
+        usage
+    }
+    /// Threads that haven't exited
+    pub fn live_tasks(&self) -> impl Iterator<Item = &Arc<TaskControlBlock>> + '_ {
+        self.tasks
+            .iter()
+            .flatten()
+            .filter(|task| task.inner_exclusive_access().exit_code.is_none())
+    }
+}
+
+impl ProcessControlBlock {
+    /// Get the mutable reference of the inner PCB
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// Create a new process from an elf file, `name` becomes its command
+    /// line. Its main thread is ready to run.
+    ///
+    /// At present, it is only used for the creation of initproc
+    pub fn new(name: &str, elf: &dyn File) -> Result<Arc<Self>, ElfError> {
+        let rlimits = RLimits::new();
+        // memory_set with elf program headers/trampoline
+        let (memory_set, ustack_base, entry_point) =
+            MemorySet::from_elf(elf, rlimits.cur(LimitedResource::As))?;
+        let pid_handle = pid_alloc();
+        let pgid = pid_handle.0;
+        let process = Arc::new(Self {
+            pid: pid_handle,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    exiting: false,
+                    memory_set,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: vec![
+                        // 0 -> stdin
+                        Some(CONSOLE.clone()),
+                        // 1 -> stdout
+                        Some(CONSOLE.clone()),
+                        // 2 -> stderr
+                        Some(CONSOLE.clone()),
+                    ],
+                    cloexec: BTreeSet::new(),
+                    cmdline: name.to_string(),
+                    // the first process runs as root
+                    uid: 0,
+                    euid: 0,
+                    gid: 0,
+                    // leads a group and a session of its own
+                    pgid,
+                    sid: pgid,
+                    signals: SignalFlags::empty(),
+                    stopped: false,
+                    stopped_threads: WaitQueue::default(),
+                    job_change: None,
+                    signal_actions: [SignalAction::default(); MAX_SIG + 1],
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                    mutex_list: Vec::new(),
+                    semaphore_list: Vec::new(),
+                    condvar_list: Vec::new(),
+                    deadlock_detector: DeadlockDetector::new(),
+                    exited_usage: Usage::default(),
+                    children_usage: Usage::default(),
+                    rlimits,
+                })
+            },
+        });
+        // create the main thread, with its user stack and trap context
+        let res = TaskUserRes::new(process.clone(), ustack_base, rlimits.stack_size(), true)
+            .ok_or(ElfError::TooLarge)?;
+        let task = Arc::new(TaskControlBlock::new(&process, res));
+        let task_inner = task.inner_exclusive_access();
+        let tid = task_inner.res.as_ref().unwrap().tid;
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        // prepare TrapContext in user space
+        *task_inner.get_trap_cx() = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.exclusive_access().token(),
+            task.kstack.get_top(),
+            trap_handler as usize,
+        );
+        drop(task_inner);
+        process
+            .inner_exclusive_access()
+            .insert_task(tid, task.clone());
+        insert_into_pid2process(process.getpid(), &process);
+        add_task(task);
+        Ok(process)
+    }
+    /// Load a new elf to replace the original application address space and
+    /// start execution in `task`, which has to be the only thread left and
+    /// becomes thread 0. The old address space is kept if the elf can't be
+    /// loaded, or it and the stack of thread 0 take more than `RLIMIT_AS`.
+    pub fn exec(
+        &self,
+        task: &Arc<TaskControlBlock>,
+        path: &str,
+        elf: &dyn File,
+    ) -> Result<(), ElfError> {
+        let rlimits = self.inner_exclusive_access().rlimits;
+        let limit = rlimits.cur(LimitedResource::As);
+        let ustack_size = rlimits.stack_size();
+        // memory_set with elf program headers/trampoline
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf, limit)?;
+        // and the stack and trap context of thread 0 have to fit as well
+        if memory_set.size() + ustack_size + PAGE_SIZE > limit
+            || !MemorySet::frames_for((ustack_size + PAGE_SIZE) / PAGE_SIZE)
+        {
+            return Err(ElfError::TooLarge);
+        }
+
+        // **** access inner exclusively
+        let mut inner = self.inner_exclusive_access();
+        // substitute memory_set
+        inner.memory_set = memory_set;
+        // the caller becomes thread 0 of the new program, the exited
+        // threads are gone with the old one
+        inner.task_res_allocator = RecycleAllocator::new();
+        let tid = inner.alloc_tid();
+        let exited = core::mem::replace(&mut inner.tasks, vec![Some(task.clone())]);
+        // so are the sync objects
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+        inner.deadlock_detector = DeadlockDetector::new();
+        inner.cmdline = path.to_string();
+        // the handlers are gone with the old program, ignored signals stay so
+        for action in inner.signal_actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+        let closed: Vec<_> = core::mem::take(&mut inner.cloexec)
+            .into_iter()
+            .map(|fd| inner.fd_table[fd].take())
+            .collect();
+        drop(inner);
+        // **** release inner
+
+        let mut task_inner = task.inner_exclusive_access();
+        let res = task_inner.res.as_mut().unwrap();
+        res.tid = tid;
+        res.ustack_base = ustack_base;
+        res.ustack_size = ustack_size;
+        assert!(res.alloc_user_res());
+        let ustack_top = res.ustack_top();
+        task_inner.trap_cx_ppn = task_inner.res.as_ref().unwrap().trap_cx_ppn();
+        task_inner.signal_frame = None;
+        // initialize trap_cx
+        *task_inner.get_trap_cx() = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.exclusive_access().token(),
+            task.kstack.get_top(),
+            trap_handler as usize,
+        );
+        drop(task_inner);
+        // closing may wake tasks waiting on the files, after the PCB is free
+        drop(closed);
+        drop(exited);
+        Ok(())
+    }
+    /// Fork from parent to child. The child has a single thread, a copy of
+    /// `task` with the same tid. Return `None` if there aren't frames for
+    /// the copy.
+    pub fn fork(self: &Arc<Self>, task: &Arc<TaskControlBlock>) -> Option<Arc<Self>> {
+        // ---- access parent PCB exclusively
+        let mut parent_inner = self.inner_exclusive_access();
+        // copy user space(include trap contexts and user stacks)
+        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set)?;
+        // and the kernel stack of the thread has to fit as well
+        if !MemorySet::frames_for(KERNEL_STACK_SIZE / PAGE_SIZE) {
+            return None;
+        }
+        let pid_handle = pid_alloc();
+        // copy fd table
+        let mut new_fd_table: Vec<Option<Arc<dyn File>>> = Vec::new();
+        for fd in parent_inner.fd_table.iter() {
+            if let Some(file) = fd {
+                new_fd_table.push(Some(file.clone()));
+            } else {
+                new_fd_table.push(None);
+            }
+        }
+        let child = Arc::new(Self {
+            pid: pid_handle,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    exiting: false,
+                    memory_set,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: new_fd_table,
+                    cloexec: parent_inner.cloexec.clone(),
+                    cmdline: parent_inner.cmdline.clone(),
+                    uid: parent_inner.uid,
+                    euid: parent_inner.euid,
+                    gid: parent_inner.gid,
+                    pgid: parent_inner.pgid,
+                    sid: parent_inner.sid,
+                    // pending signals are the parent's alone
+                    signals: SignalFlags::empty(),
+                    stopped: false,
+                    stopped_threads: WaitQueue::default(),
+                    job_change: None,
+                    signal_actions: parent_inner.signal_actions,
+                    tasks: Vec::new(),
+                    // the stacks of the other threads are copied as well,
+                    // their tids stay taken so that nothing is mapped over
+                    task_res_allocator: parent_inner.task_res_allocator.clone(),
+                    // sync objects aren't inherited
+                    mutex_list: Vec::new(),
+                    semaphore_list: Vec::new(),
+                    condvar_list: Vec::new(),
+                    deadlock_detector: DeadlockDetector::new(),
+                    exited_usage: Usage::default(),
+                    children_usage: Usage::default(),
+                    rlimits: parent_inner.rlimits,
+                })
+            },
+        });
+        // add child
+        parent_inner.children.push(child.clone());
+        drop(parent_inner);
+        // ---- release parent PCB
+
+        let task_inner = task.inner_exclusive_access();
+        let parent_res = task_inner.res.as_ref().unwrap();
+        let res = TaskUserRes {
+            tid: parent_res.tid,
+            ustack_base: parent_res.ustack_base,
+            ustack_size: parent_res.ustack_size,
+            process: Arc::downgrade(&child),
+        };
+        let tid = res.tid;
+        let child_task = Arc::new(TaskControlBlock::new(&child, res));
+        let mut child_task_inner = child_task.inner_exclusive_access();
+        child_task_inner.signal_mask = task_inner.signal_mask;
+        child_task_inner.signal_frame = task_inner.signal_frame;
+        drop(task_inner);
+        // modify kernel_sp in trap_cx
+        child_task_inner.get_trap_cx().kernel_sp = child_task.kstack.get_top();
+        drop(child_task_inner);
+        child
+            .inner_exclusive_access()
+            .insert_task(tid, child_task.clone());
+        insert_into_pid2process(child.getpid(), &child);
+        add_task(child_task);
+        Some(child)
+    }
+    /// Get pid of the process
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+}
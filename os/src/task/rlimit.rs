@@ -0,0 +1,104 @@
+//! Per-process resource limits, see [`RLimits`]
+use crate::config::{MAX_FD, MAX_USER_STACK_SIZE, PAGE_SIZE, USER_STACK_SIZE};
+
+/// No limit
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// `struct rlimit`, the limit enforced and the ceiling it may be raised
+/// to without root
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct RLimit {
+    /// Soft limit
+    pub cur: usize,
+    /// Hard limit
+    pub max: usize,
+}
+
+impl RLimit {
+    const INFINITY: Self = Self::new(RLIM_INFINITY, RLIM_INFINITY);
+    const fn new(cur: usize, max: usize) -> Self {
+        Self { cur, max }
+    }
+}
+
+/// The resources limited, by their number in Linux
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LimitedResource {
+    /// CPU time in seconds, `SIGXCPU` at the soft limit and `SIGKILL` at
+    /// the hard one
+    Cpu = 0,
+    /// Bytes of the user stack of each thread, taken at `exec`
+    Stack = 3,
+    /// Processes of the real user, checked by `fork`
+    Nproc = 6,
+    /// One more than the highest fd that may be opened
+    Nofile = 7,
+    /// Bytes of the address space
+    As = 9,
+}
+
+impl LimitedResource {
+    /// The resource numbered `resource`, if it's one of those limited
+    pub fn from_number(resource: usize) -> Option<Self> {
+        match resource {
+            0 => Some(Self::Cpu),
+            3 => Some(Self::Stack),
+            6 => Some(Self::Nproc),
+            7 => Some(Self::Nofile),
+            9 => Some(Self::As),
+            _ => None,
+        }
+    }
+    fn index(self) -> usize {
+        match self {
+            Self::Cpu => 0,
+            Self::Stack => 1,
+            Self::Nproc => 2,
+            Self::Nofile => 3,
+            Self::As => 4,
+        }
+    }
+}
+
+/// The limits of a process, inherited across `fork` and `exec`
+#[derive(Copy, Clone)]
+pub struct RLimits([RLimit; 5]);
+
+impl RLimits {
+    /// The limits of initproc: only the stack and the fds are limited
+    pub fn new() -> Self {
+        let mut limits = Self([RLimit::INFINITY; 5]);
+        limits.set(
+            LimitedResource::Stack,
+            RLimit::new(USER_STACK_SIZE, RLIM_INFINITY),
+        );
+        limits.set(LimitedResource::Nofile, RLimit::new(MAX_FD, MAX_FD));
+        limits
+    }
+    /// The limit of `resource`
+    pub fn get(&self, resource: LimitedResource) -> RLimit {
+        self.0[resource.index()]
+    }
+    /// Set the limit of `resource`
+    pub fn set(&mut self, resource: LimitedResource, limit: RLimit) {
+        self.0[resource.index()] = limit;
+    }
+    /// The soft limit of `resource`
+    pub fn cur(&self, resource: LimitedResource) -> usize {
+        self.get(resource).cur
+    }
+    /// Size of the user stacks mapped by `exec`: the soft limit rounded up
+    /// to whole pages, at least one, and no more than
+    /// `MAX_USER_STACK_SIZE` as the stacks are mapped whole
+    pub fn stack_size(&self) -> usize {
+        let size = self.cur(LimitedResource::Stack).min(MAX_USER_STACK_SIZE);
+        ((size + PAGE_SIZE - 1) / PAGE_SIZE).max(1) * PAGE_SIZE
+    }
+}
+
+impl Default for RLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,389 @@
+//! Signals sent to processes, by other processes or by the kernel
+//!
+//! A signal sent to a process stays pending until one of its threads is on
+//! its way back to user mode and doesn't block it. [`handle_signals`] then
+//! takes its action: ignore it, end the process, stop it, or run a user
+//! handler in that thread. A handler runs on the user stack of the
+//! interrupted code, whose trap context is kept in the TCB until the handler
+//! returns through `sigreturn`. Handlers don't nest: other handled signals
+//! wait until `sigreturn`.
+//!
+//! A stopped process has its threads block on their way back to user mode
+//! until `SIGCONT` or `SIGKILL` is sent to it. A syscall that gave way to the
+//! stop with `-EINTR` is restarted once the thread goes on, unless a handler
+//! runs first.
+use super::{
+    block_current_and_run_next, current_task, exit_current_process_and_run_next,
+    exit_current_thread_and_run_next, group_processes, wakeup_task, ProcessControlBlock,
+    ProcessControlBlockInner, TaskControlBlockInner, INITPROC,
+};
+use crate::trap::TrapContext;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use bitflags::*;
+
+/// Largest signal number
+pub const MAX_SIG: usize = 31;
+/// Handler taking the default action
+pub const SIG_DFL: usize = 0;
+/// Handler ignoring the signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// A set of signals, signal `n` is bit `n`
+    pub struct SignalFlags: u32 {
+        /// Hangup
+        const SIGHUP = 1 << 1;
+        /// Interrupt from the keyboard
+        const SIGINT = 1 << 2;
+        /// Quit from the keyboard
+        const SIGQUIT = 1 << 3;
+        /// Illegal instruction
+        const SIGILL = 1 << 4;
+        /// Breakpoint
+        const SIGTRAP = 1 << 5;
+        /// Abort
+        const SIGABRT = 1 << 6;
+        /// Bus error
+        const SIGBUS = 1 << 7;
+        /// Arithmetic error
+        const SIGFPE = 1 << 8;
+        /// Kill, can't be caught or blocked
+        const SIGKILL = 1 << 9;
+        /// User defined signal 1
+        const SIGUSR1 = 1 << 10;
+        /// Invalid memory access
+        const SIGSEGV = 1 << 11;
+        /// User defined signal 2
+        const SIGUSR2 = 1 << 12;
+        /// Write to a pipe or socket nobody reads
+        const SIGPIPE = 1 << 13;
+        /// Timer alarm
+        const SIGALRM = 1 << 14;
+        /// Termination request
+        const SIGTERM = 1 << 15;
+        /// Coprocessor stack fault
+        const SIGSTKFLT = 1 << 16;
+        /// A child stopped or exited
+        const SIGCHLD = 1 << 17;
+        /// Continue if stopped
+        const SIGCONT = 1 << 18;
+        /// Stop, can't be caught or blocked
+        const SIGSTOP = 1 << 19;
+        /// Stop from the keyboard
+        const SIGTSTP = 1 << 20;
+        /// Terminal input of a background task
+        const SIGTTIN = 1 << 21;
+        /// Terminal output of a background task
+        const SIGTTOU = 1 << 22;
+        /// Urgent data on a socket
+        const SIGURG = 1 << 23;
+        /// CPU time limit exceeded
+        const SIGXCPU = 1 << 24;
+        /// File size limit exceeded
+        const SIGXFSZ = 1 << 25;
+        /// Virtual timer alarm
+        const SIGVTALRM = 1 << 26;
+        /// Profiling timer alarm
+        const SIGPROF = 1 << 27;
+        /// Terminal window size changed
+        const SIGWINCH = 1 << 28;
+        /// I/O is possible
+        const SIGIO = 1 << 29;
+        /// Power failure
+        const SIGPWR = 1 << 30;
+        /// Bad system call
+        const SIGSYS = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// The set holding only signal `signum`, `None` if there is no such
+    /// signal
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            return None;
+        }
+        Self::from_bits(1 << signum)
+    }
+    /// Number of the lowest signal in the set
+    pub fn signum(&self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+    /// Each signal of the set on its own, lowest first
+    fn iter_signals(self) -> impl Iterator<Item = SignalFlags> {
+        (1..=MAX_SIG)
+            .filter_map(Self::from_signum)
+            .filter(move |signal| self.contains(*signal))
+    }
+    /// Signals whose default action is to do nothing
+    fn ignored_by_default() -> Self {
+        Self::SIGCHLD | Self::SIGCONT | Self::SIGURG | Self::SIGWINCH
+    }
+    /// Signals whose default action is to stop the process
+    fn stopping() -> Self {
+        Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU
+    }
+}
+
+/// `struct sigaction`: what to do with a signal
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalAction {
+    /// [`SIG_DFL`], [`SIG_IGN`] or the address of a handler
+    pub handler: usize,
+    /// Where the handler returns to, code calling `sigreturn`
+    pub restorer: usize,
+    /// Signals blocked while the handler runs, besides the one handled
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            restorer: 0,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// A stop or continue of a process, for its parent to learn with `waitpid`
+#[derive(Copy, Clone)]
+pub enum JobChange {
+    /// Stopped by the signal
+    Stopped(SignalFlags),
+    /// Continued by `SIGCONT`
+    Continued,
+}
+
+impl JobChange {
+    /// The status `waitpid` reports the change with, as Linux encodes it
+    pub fn wait_status(&self) -> i32 {
+        match self {
+            Self::Stopped(signal) => (signal.signum() as i32) << 8 | 0x7f,
+            Self::Continued => 0xffff,
+        }
+    }
+}
+
+/// What a handler interrupted, put back by `sigreturn`
+#[derive(Copy, Clone)]
+pub struct SignalFrame {
+    /// User registers when the handler was entered
+    pub trap_cx: TrapContext,
+    /// Blocked signals before the handler was entered
+    pub mask: SignalFlags,
+}
+
+impl ProcessControlBlockInner {
+    /// Whether `signal` would be ignored if it was delivered now
+    pub fn ignores(&self, signal: SignalFlags) -> bool {
+        match self.signal_actions[signal.signum()].handler {
+            SIG_DFL => SignalFlags::ignored_by_default().contains(signal),
+            SIG_IGN => true,
+            _ => false,
+        }
+    }
+}
+
+/// Pending signals of `process` that thread `task` can be delivered now
+fn deliverable_signals(
+    process: &ProcessControlBlockInner,
+    task: &TaskControlBlockInner,
+) -> SignalFlags {
+    let mut signals = process.signals - task.signal_mask;
+    if task.signal_frame.is_some() {
+        // a handler is running, other handlers have to wait
+        for signal in signals.iter_signals() {
+            if process.signal_actions[signal.signum()].handler > SIG_IGN {
+                signals.remove(signal);
+            }
+        }
+    }
+    signals
+}
+
+/// Whether thread `task` of `process` is killed or has a signal pending
+/// that a waiting syscall should give way to, returning `-EINTR`
+pub fn interrupted(process: &ProcessControlBlockInner, task: &TaskControlBlockInner) -> bool {
+    task.killed
+        || deliverable_signals(process, task)
+            .iter_signals()
+            .any(|signal| !process.ignores(signal))
+}
+
+/// Make `signal` pending in `process`, and wake its threads blocked waiting
+/// for something that can take the signal. `SIGCONT` and `SIGKILL` continue
+/// a stopped process, a stop signal and `SIGCONT` discard each other.
+/// Initproc only takes the signals it has a handler for.
+pub fn send_signal(process: &Arc<ProcessControlBlock>, signal: SignalFlags) {
+    let mut inner = process.inner_exclusive_access();
+    if Arc::ptr_eq(process, &INITPROC) && inner.signal_actions[signal.signum()].handler == SIG_DFL {
+        return;
+    }
+    if signal == SignalFlags::SIGCONT {
+        inner.signals.remove(SignalFlags::stopping());
+    } else if SignalFlags::stopping().contains(signal) {
+        inner.signals.remove(SignalFlags::SIGCONT);
+    }
+    inner.signals |= signal;
+    let mut stopped_threads = None;
+    let mut parent = None;
+    if inner.stopped && (signal == SignalFlags::SIGCONT || signal == SignalFlags::SIGKILL) {
+        inner.stopped = false;
+        stopped_threads = Some(core::mem::take(&mut inner.stopped_threads));
+        if signal == SignalFlags::SIGCONT {
+            inner.job_change = Some(JobChange::Continued);
+            parent = inner.parent.as_ref().and_then(Weak::upgrade);
+        }
+    }
+    let wake: Vec<_> = inner
+        .live_tasks()
+        .filter(|task| interrupted(&inner, &task.inner_exclusive_access()))
+        .cloned()
+        .collect();
+    drop(inner);
+    for task in wake {
+        wakeup_task(task);
+    }
+    if let Some(mut stopped_threads) = stopped_threads {
+        stopped_threads.wake_all();
+    }
+    if let Some(parent) = parent {
+        send_signal(&parent, SignalFlags::SIGCHLD);
+    }
+}
+
+/// Make `signal` pending in every process of process group `pgid`
+pub fn send_group_signal(pgid: usize, signal: SignalFlags) {
+    for process in group_processes(pgid) {
+        send_signal(&process, signal);
+    }
+}
+
+/// Send `signal` to the current process for a fault the current thread
+/// can't get past. If the signal would be blocked, ignored or wait for
+/// another handler, its action is reset to the default, ending the process.
+pub fn current_fault(signal: SignalFlags) {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    let handler = process_inner.signal_actions[signal.signum()].handler;
+    if task_inner.signal_mask.contains(signal)
+        || handler == SIG_IGN
+        || (handler != SIG_DFL && task_inner.signal_frame.is_some())
+    {
+        process_inner.signal_actions[signal.signum()] = SignalAction::default();
+        task_inner.signal_mask.remove(signal);
+    }
+    process_inner.signals |= signal;
+}
+
+/// Note that the syscall the current thread returns from gave way to a
+/// signal, to be restarted with `arg0` in `a0` if the thread only stops
+pub fn current_syscall_interrupted(arg0: usize) {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .interrupted_syscall = Some(arg0);
+}
+
+/// Take the action of the signals the current thread can be delivered,
+/// before it returns to user mode. A killed thread, or a signal ending the
+/// process, doesn't return.
+pub fn handle_signals() {
+    let task = current_task().unwrap();
+    let interrupted_syscall = task.inner_exclusive_access().interrupted_syscall.take();
+    let mut stopped = false;
+    loop {
+        let process = task.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        let mut task_inner = task.inner_exclusive_access();
+        if task_inner.killed {
+            let exit_code = process_inner.exit_code;
+            drop(task_inner);
+            drop(process_inner);
+            drop(process);
+            drop(task);
+            exit_current_thread_and_run_next(exit_code);
+            return;
+        }
+        if process_inner.stopped {
+            process_inner.stopped_threads.register(&task);
+            drop(task_inner);
+            drop(process_inner);
+            block_current_and_run_next();
+            process
+                .inner_exclusive_access()
+                .stopped_threads
+                .remove(&task);
+            stopped = true;
+            continue;
+        }
+        let mut next = None;
+        for signal in deliverable_signals(&process_inner, &task_inner).iter_signals() {
+            if process_inner.ignores(signal) {
+                process_inner.signals.remove(signal);
+            } else {
+                next = Some(signal);
+                break;
+            }
+        }
+        let signal = match next {
+            Some(signal) => signal,
+            None => break,
+        };
+        let action = process_inner.signal_actions[signal.signum()];
+        if action.handler == SIG_DFL && SignalFlags::stopping().contains(signal) {
+            // the other threads stop on their way back to user mode
+            process_inner.signals.remove(signal);
+            process_inner.stopped = true;
+            process_inner.job_change = Some(JobChange::Stopped(signal));
+            let parent = process_inner.parent.as_ref().and_then(Weak::upgrade);
+            drop(task_inner);
+            drop(process_inner);
+            if let Some(parent) = parent {
+                send_signal(&parent, SignalFlags::SIGCHLD);
+            }
+            continue;
+        }
+        if action.handler == SIG_DFL {
+            drop(task_inner);
+            drop(process_inner);
+            drop(process);
+            drop(task);
+            // ended by a signal, the exit code is its negated number
+            exit_current_process_and_run_next(-(signal.signum() as i32));
+            return;
+        }
+        process_inner.signals.remove(signal);
+        let trap_cx = task_inner.get_trap_cx();
+        task_inner.signal_frame = Some(SignalFrame {
+            trap_cx: *trap_cx,
+            mask: task_inner.signal_mask,
+        });
+        task_inner.signal_mask |= action.mask | signal;
+        trap_cx.sepc = action.handler;
+        trap_cx.x[1] = action.restorer;
+        trap_cx.x[10] = signal.signum();
+        return;
+    }
+    if let (true, Some(arg0)) = (stopped, interrupted_syscall) {
+        // back to the ecall
+        let trap_cx = task.inner_exclusive_access().get_trap_cx();
+        trap_cx.sepc -= 4;
+        trap_cx.x[10] = arg0;
+    }
+}
+
+/// Whether the current thread would take `signal` now, neither ignoring
+/// nor blocking it
+pub fn current_takes(signal: SignalFlags) -> bool {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let blocked = task.inner_exclusive_access().signal_mask.contains(signal);
+    !blocked && !process_inner.ignores(signal)
+}
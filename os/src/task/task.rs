@@ -1,171 +1,121 @@
 //!Implementation of [`TaskControlBlock`]
-use super::TaskContext;
-use super::{pid_alloc, KernelStack, PidHandle};
-use crate::config::TRAP_CONTEXT;
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext, TaskUserRes};
+use super::{SignalFlags, SignalFrame, Usage};
+use crate::mm::PhysPageNum;
 use crate::sync::UPSafeCell;
-use crate::trap::{trap_handler, TrapContext};
+use crate::timer::get_time;
+use crate::trap::TrapContext;
 use alloc::sync::{Arc, Weak};
-use alloc::vec::Vec;
 use core::cell::RefMut;
 
+/// Task control block structure, a thread of a process
+///
+/// Directly save the contents that will not change during running
 pub struct TaskControlBlock {
     // immutable
-    pub pid: PidHandle,
-    pub kernel_stack: KernelStack,
+    /// The process the thread belongs to
+    pub process: Weak<ProcessControlBlock>,
+    /// Kernel stack of the thread
+    pub kstack: KernelStack,
     // mutable
     inner: UPSafeCell<TaskControlBlockInner>,
 }
 
+/// Structure containing more thread content
+///
+/// Store the contents that will change during operation
+/// and are wrapped by UPSafeCell to provide mutual exclusion
 pub struct TaskControlBlockInner {
+    /// tid, user stack and trap context, gone once the thread exited
+    pub res: Option<TaskUserRes>,
+    /// The physical page number of the frame where the trap context is placed
     pub trap_cx_ppn: PhysPageNum,
-    pub base_size: usize,
+    /// Save task context
     pub task_cx: TaskContext,
+    /// Maintain the execution status of the current thread
     pub task_status: TaskStatus,
-    pub memory_set: MemorySet,
-    pub parent: Option<Weak<TaskControlBlock>>,
-    pub children: Vec<Arc<TaskControlBlock>>,
-    pub exit_code: i32,
+    /// Set when the thread exits
+    pub exit_code: Option<i32>,
+    /// Whether the thread has to exit on its way back to user mode, because
+    /// its process exits or execs
+    pub killed: bool,
+    /// Signals blocked from delivery
+    pub signal_mask: SignalFlags,
+    /// What the running signal handler interrupted
+    pub signal_frame: Option<SignalFrame>,
+    /// `a0` of the syscall that just gave way to a signal with `-EINTR`
+    pub interrupted_syscall: Option<usize>,
+    /// CPU time, switches and page faults of the thread so far
+    pub usage: Usage,
+    /// When the thread last entered or left user mode, or was switched to
+    pub usage_since: usize,
 }
 
 impl TaskControlBlockInner {
-    /*
-    pub fn get_task_cx_ptr2(&self) -> *const usize {
-        &self.task_cx_ptr as *const usize
-    }
-    */
+    /// Get the mutable reference of trap context
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
-    }
-    fn get_status(&self) -> TaskStatus {
-        self.task_status
+    /// Charge the time since [`Self::usage_since`] as user time
+    pub fn charge_user_time(&mut self) {
+        let now = get_time();
+        self.usage.utime += now - self.usage_since;
+        self.usage_since = now;
     }
-    pub fn is_zombie(&self) -> bool {
-        self.get_status() == TaskStatus::Zombie
+    /// Charge the time since [`Self::usage_since`] as system time
+    pub fn charge_system_time(&mut self) {
+        let now = get_time();
+        self.usage.stime += now - self.usage_since;
+        self.usage_since = now;
     }
 }
 
 impl TaskControlBlock {
+    /// Get the mutable reference of the inner TCB
     pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
-    pub fn new(elf_data: &[u8]) -> Self {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
-        // push a task context which goes to trap_return to the top of kernel stack
-        let task_control_block = Self {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                })
-            },
-        };
-        // prepare TrapContext in user space
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        task_control_block
-    }
-    pub fn exec(&self, elf_data: &[u8]) {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-
-        // **** access inner exclusively
-        let mut inner = self.inner_exclusive_access();
-        // substitute memory_set
-        inner.memory_set = memory_set;
-        // update trap_cx ppn
-        inner.trap_cx_ppn = trap_cx_ppn;
-        // initialize base_size
-        inner.base_size = user_sp;
-        // initialize trap_cx
-        let trap_cx = inner.get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-        // **** release inner automatically
-    }
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
-        // ---- access parent PCB exclusively
-        let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            kernel_stack,
+    /// Create a thread of `process` owning `res`, which starts in
+    /// `trap_return` once its trap context is set up
+    pub fn new(process: &Arc<ProcessControlBlock>, res: TaskUserRes) -> Self {
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let kstack = kstack_alloc();
+        let kstack_top = kstack.get_top();
+        Self {
+            process: Arc::downgrade(process),
+            kstack,
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
+                    res: Some(res),
                     trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_cx: TaskContext::goto_trap_return(kstack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
+                    exit_code: None,
+                    killed: false,
+                    signal_mask: SignalFlags::empty(),
+                    signal_frame: None,
+                    interrupted_syscall: None,
+                    usage: Usage::default(),
+                    usage_since: get_time(),
                 })
             },
-        });
-        // add child
-        parent_inner.children.push(task_control_block.clone());
-        // modify kernel_sp in trap_cx
-        // **** access children PCB exclusively
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        trap_cx.kernel_sp = kernel_stack_top;
-        // return
-        task_control_block
-        // ---- release parent PCB automatically
-        // **** release children PCB automatically
+        }
     }
-    pub fn getpid(&self) -> usize {
-        self.pid.0
+    /// Get tid of the thread
+    pub fn gettid(&self) -> usize {
+        self.inner_exclusive_access().res.as_ref().unwrap().tid
     }
 }
 
 #[derive(Copy, Clone, PartialEq)]
+/// task status: Ready, Running, Blocked, Zombie
 pub enum TaskStatus {
+    /// ready to run
     Ready,
+    /// running
     Running,
+    /// waiting to be woken by [`super::wakeup_task`]
+    Blocked,
+    /// exited
     Zombie,
 }
@@ -0,0 +1,45 @@
+//! Resource accounting of tasks, see [`Usage`]
+use crate::config::CLOCK_FREQ;
+use core::ops::AddAssign;
+
+/// Clock ticks per second that `times` and `/proc/<pid>/stat` count in,
+/// `sysconf(_SC_CLK_TCK)`
+const CLK_TCK: usize = 100;
+
+/// What a thread used, or the threads of processes together: CPU time in
+/// cycles of the `time` CSR, context switches and page faults
+#[derive(Copy, Clone, Default)]
+pub struct Usage {
+    /// Time spent in user mode
+    pub utime: usize,
+    /// Time spent in the kernel on behalf of the thread
+    pub stime: usize,
+    /// Times the thread gave way, blocking or yielding
+    pub nvcsw: usize,
+    /// Times the timer took the CPU away from the thread
+    pub nivcsw: usize,
+    /// Page faults that were handled, e.g. the first store to a page of a
+    /// file mapping
+    pub minflt: usize,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.utime += other.utime;
+        self.stime += other.stime;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+        self.minflt += other.minflt;
+    }
+}
+
+/// Convert cycles of the `time` CSR to microseconds
+pub fn cycles_to_us(cycles: usize) -> usize {
+    const USEC_PER_SEC: usize = 1_000_000;
+    cycles / CLOCK_FREQ * USEC_PER_SEC + cycles % CLOCK_FREQ * USEC_PER_SEC / CLOCK_FREQ
+}
+
+/// Convert cycles of the `time` CSR to clock ticks
+pub fn cycles_to_ticks(cycles: usize) -> usize {
+    cycles_to_us(cycles) / (1_000_000 / CLK_TCK)
+}
@@ -2,9 +2,17 @@
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::{Ordering, Reverse};
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
+/// Milliseconds between two timer interrupts
+pub const MSEC_PER_TICK: usize = MSEC_PER_SEC / TICKS_PER_SEC;
 const MSEC_PER_SEC: usize = 1000;
 ///get current time
 pub fn get_time() -> usize {
@@ -18,3 +26,58 @@ pub fn get_time_ms() -> usize {
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
+
+/// A task to wake at `expire_ms`
+struct Timer {
+    expire_ms: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.expire_ms.cmp(&other.expire_ms)
+    }
+}
+
+lazy_static! {
+    /// Pending timers, the earliest on top
+    static ref TIMERS: UPSafeCell<BinaryHeap<Reverse<Timer>>> =
+        unsafe { UPSafeCell::new(BinaryHeap::new()) };
+}
+
+/// Wake `task` at `expire_ms`, if it's blocked by then
+pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    TIMERS
+        .exclusive_access()
+        .push(Reverse(Timer { expire_ms, task }));
+}
+
+/// Wake the tasks whose timers have expired
+pub fn check_timers() {
+    let now = get_time_ms();
+    loop {
+        let mut timers = TIMERS.exclusive_access();
+        match timers.peek() {
+            Some(Reverse(timer)) if timer.expire_ms <= now => {
+                let Reverse(timer) = timers.pop().unwrap();
+                drop(timers);
+                wakeup_task(timer.task);
+            }
+            _ => break,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::{
+    enable_deadlock_detect, exit, mutex_blocking_create, mutex_lock, mutex_unlock,
+    semaphore_create, semaphore_down, semaphore_up, thread_create, waittid, yield_,
+};
+
+const EDEADLK: isize = -35;
+
+static HOLDS_SECOND: AtomicBool = AtomicBool::new(false);
+
+/// Take the second mutex, then wait for the first one, ids packed into `ids`
+extern "C" fn lock_second_first(ids: usize) -> ! {
+    let (first, second) = (ids >> 16, ids & 0xffff);
+    assert_eq!(mutex_lock(second), 0);
+    HOLDS_SECOND.store(true, Ordering::SeqCst);
+    assert_eq!(mutex_lock(first), 0);
+    assert_eq!(mutex_unlock(first), 0);
+    assert_eq!(mutex_unlock(second), 0);
+    exit(0)
+}
+
+/// Locking a mutex the thread holds already would never return
+fn relock() {
+    let mutex_id = mutex_blocking_create() as usize;
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(mutex_lock(mutex_id), EDEADLK);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    println!("relock ok");
+}
+
+/// Two threads taking two mutexes in opposite orders
+fn opposite_orders() {
+    let first = mutex_blocking_create() as usize;
+    let second = mutex_blocking_create() as usize;
+    assert_eq!(mutex_lock(first), 0);
+    let tid = thread_create(lock_second_first as usize, first << 16 | second);
+    assert!(tid > 0);
+    while !HOLDS_SECOND.load(Ordering::SeqCst) {
+        yield_();
+    }
+    // let the thread block on the first mutex
+    for _ in 0..10 {
+        yield_();
+    }
+    assert_eq!(mutex_lock(second), EDEADLK);
+    // giving up the first mutex lets the thread finish
+    assert_eq!(mutex_unlock(first), 0);
+    assert_eq!(waittid(tid as usize), 0);
+    assert_eq!(mutex_lock(second), 0);
+    assert_eq!(mutex_unlock(second), 0);
+    println!("opposite orders ok");
+}
+
+/// Taking more from a semaphore than it has, with nobody to give back
+fn semaphore() {
+    let sem_id = semaphore_create(2) as usize;
+    assert_eq!(semaphore_down(sem_id), 0);
+    assert_eq!(semaphore_down(sem_id), 0);
+    assert_eq!(semaphore_down(sem_id), EDEADLK);
+    assert_eq!(semaphore_up(sem_id), 0);
+    assert_eq!(semaphore_down(sem_id), 0);
+    assert_eq!(semaphore_up(sem_id), 0);
+    assert_eq!(semaphore_up(sem_id), 0);
+    println!("semaphore ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(enable_deadlock_detect(true), 0);
+    relock();
+    opposite_orders();
+    semaphore();
+    assert_eq!(enable_deadlock_detect(false), 0);
+    println!("deadlock_test passed!");
+    0
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::{close, open, read, write, OpenFlags};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let null = open("/dev/null\0", OpenFlags::RDWR);
+    assert!(null > 0);
+    let null = null as usize;
+    let mut buffer = [0xffu8; 64];
+    assert_eq!(write(null, &buffer), buffer.len() as isize);
+    assert_eq!(read(null, &mut buffer), 0);
+    close(null);
+
+    let zero = open("/dev/zero\0", OpenFlags::RDONLY);
+    assert!(zero > 0);
+    let zero = zero as usize;
+    assert_eq!(read(zero, &mut buffer), buffer.len() as isize);
+    assert!(buffer.iter().all(|b| *b == 0));
+    close(zero);
+
+    let random = open("/dev/random\0", OpenFlags::RDONLY);
+    assert!(random > 0);
+    let random = random as usize;
+    let mut another = [0u8; 64];
+    assert_eq!(read(random, &mut buffer), buffer.len() as isize);
+    assert_eq!(read(random, &mut another), another.len() as isize);
+    assert_ne!(buffer, another);
+    close(random);
+
+    let console = open("/dev/console\0", OpenFlags::WRONLY);
+    assert!(console > 0);
+    let msg = "devfs_test passed!\n";
+    assert_eq!(write(console as usize, msg.as_bytes()), msg.len() as isize);
+    close(console as usize);
+    0
+}
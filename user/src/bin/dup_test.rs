@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    close, dup, dup2, dup3, exec, exit, fork, get_time, open, pipe, read, unlink, waitpid, write,
+    OpenFlags,
+};
+
+const OUTPUT: &str = "/tmp/dup_test\0";
+const HELLO: &[u8] = b"Hello world from user mode program!\n";
+
+/// Read from `fd` until end of file
+fn read_to_end(fd: usize) -> Vec<u8> {
+    let mut content = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        content.extend_from_slice(&buf[..len as usize]);
+    }
+    content
+}
+
+/// Run `path` with `fd` as its stdout and wait for it
+fn run_with_stdout(path: &str, fd: usize) {
+    let pid = fork();
+    if pid == 0 {
+        dup2(fd, 1);
+        close(fd);
+        exec(path);
+        exit(-4);
+    }
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // a dup'd fd shares the file with the original one
+    let fd = dup(1);
+    assert!(fd > 2);
+    assert_eq!(write(fd as usize, b"dup_test: via dup\n"), 18);
+    close(fd as usize);
+    assert_eq!(dup(100), -9);
+    assert_eq!(dup2(100, 3), -9);
+    assert_eq!(dup2(1, 1), 1);
+    assert_eq!(dup3(1, 1, OpenFlags::empty()), -22);
+    assert_eq!(dup3(1, 5, OpenFlags::TRUNC), -22);
+
+    // `hello_world > file`, then `hello_world >> file`
+    let fd = open(
+        OUTPUT,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd >= 0);
+    run_with_stdout("hello_world\0", fd as usize);
+    close(fd as usize);
+    let fd = open(
+        OUTPUT,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::APPEND,
+    );
+    assert!(fd >= 0);
+    run_with_stdout("hello_world\0", fd as usize);
+    close(fd as usize);
+    let fd = open(OUTPUT, OpenFlags::RDONLY);
+    let content = read_to_end(fd as usize);
+    close(fd as usize);
+    let lines: Vec<&[u8]> = content.split_inclusive(|c| *c == b'\n').collect();
+    assert_eq!(lines.len(), 2);
+    for line in lines {
+        assert!(line.ends_with(HELLO));
+    }
+    unlink(OUTPUT);
+
+    // `hello_world | <this process>`
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    run_with_stdout("hello_world\0", pipe_fd[1]);
+    close(pipe_fd[1]);
+    assert!(read_to_end(pipe_fd[0]).ends_with(HELLO));
+    close(pipe_fd[0]);
+
+    // a close-on-exec write end doesn't keep the pipe open in `sleep`
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        assert_eq!(dup3(pipe_fd[1], 10, OpenFlags::CLOEXEC), 10);
+        close(pipe_fd[1]);
+        exec("sleep\0");
+        exit(-4);
+    }
+    close(pipe_fd[1]);
+    let start = get_time();
+    assert!(read_to_end(pipe_fd[0]).is_empty());
+    // `sleep` takes 500 ms
+    assert!(get_time() - start < 400);
+    close(pipe_fd[0]);
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+    println!("dup_test passed!");
+    0
+}
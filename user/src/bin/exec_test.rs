@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{chmod, close, exec, fork, open, wait, write, OpenFlags};
+
+/// -ENOENT
+const ENOENT: isize = -2;
+/// -ENOEXEC
+const ENOEXEC: isize = -8;
+/// -ENOMEM
+const ENOMEM: isize = -12;
+/// -EACCES
+const EACCES: isize = -13;
+
+/// Write an executable RISC-V ELF to `path` with one zeroed segment of
+/// `mem_size` bytes at `vaddr`
+fn write_elf(path: &str, vaddr: u64, mem_size: u64) {
+    let mut elf = [0u8; 64 + 56];
+    elf[..8].copy_from_slice(b"\x7fELF\x02\x01\x01\x00");
+    // executable, RISC-V, version 1, entry, program headers at 64
+    elf[16..18].copy_from_slice(&2u16.to_le_bytes());
+    elf[18..20].copy_from_slice(&243u16.to_le_bytes());
+    elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+    elf[24..32].copy_from_slice(&vaddr.to_le_bytes());
+    elf[32..40].copy_from_slice(&64u64.to_le_bytes());
+    elf[52..54].copy_from_slice(&64u16.to_le_bytes());
+    elf[54..56].copy_from_slice(&56u16.to_le_bytes());
+    elf[56..58].copy_from_slice(&1u16.to_le_bytes());
+    // a readable and writable load segment
+    let ph = &mut elf[64..];
+    ph[0..4].copy_from_slice(&1u32.to_le_bytes());
+    ph[4..8].copy_from_slice(&6u32.to_le_bytes());
+    ph[16..24].copy_from_slice(&vaddr.to_le_bytes());
+    ph[40..48].copy_from_slice(&mem_size.to_le_bytes());
+    let fd = open(
+        path,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &elf), elf.len() as isize);
+    close(fd as usize);
+    assert_eq!(chmod(path, 0o755), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(exec("/no/such/app\0"), ENOENT);
+    assert_eq!(exec("no_such_app\0"), ENOENT);
+    // not an ELF file
+    let fd = open("/tmp/not_elf\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, b"#!/bin/sh\necho not an elf\n");
+    close(fd as usize);
+    // not even root may run a file without execute permission
+    assert_eq!(exec("/tmp/not_elf\0"), EACCES);
+    assert_eq!(chmod("/tmp/not_elf\0", 0o755), 0);
+    assert_eq!(exec("/tmp/not_elf\0"), ENOEXEC);
+    // an ELF header and nothing else
+    let fd = open("/tmp/truncated\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, b"\x7fELF\x02\x01\x01");
+    close(fd as usize);
+    assert_eq!(chmod("/tmp/truncated\0", 0o755), 0);
+    assert_eq!(exec("/tmp/truncated\0"), ENOEXEC);
+    // a segment wrapping around the address space
+    write_elf("/tmp/bad_segment\0", 0xffff_ffff_ffff_f000, 0x2000);
+    assert_eq!(exec("/tmp/bad_segment\0"), ENOEXEC);
+    // a segment bigger than the memory there is
+    write_elf("/tmp/too_large\0", 0x1000, 1 << 36);
+    assert_eq!(exec("/tmp/too_large\0"), ENOMEM);
+    // only regular files are run
+    assert_eq!(exec("/dev/zero\0"), EACCES);
+    // the failed execs left this process intact, now run a real one
+    let pid = fork();
+    if pid == 0 {
+        exec("/bin/hello_world\0");
+        panic!("exec /bin/hello_world failed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(pid, wait(&mut exit_code));
+    assert_eq!(exit_code, 0);
+    println!("exec_test passed!");
+    0
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{close, open, read, unlink, OpenFlags, EXIT_SKIPPED};
+
+/// Line repeated in `big`, which spans the double indirect blocks of an
+/// image made with 1024 byte blocks
+const LINE: &[u8] = b"abcdefghijklmnopqrstuvwxy\n";
+const BIG_LEN: usize = 307200;
+
+/// Read the whole file at `path`, `None` if it can't be opened
+fn read_all(path: &str) -> Option<Vec<u8>> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut content = Vec::new();
+    let mut buf = [0u8; 1000];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        content.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd);
+    Some(content)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the files come from `make ext2-img` in os/
+    let hello = match read_all("/ext2-test/hello.txt\0") {
+        Some(hello) => hello,
+        None => {
+            println!("ext2_test: no ext2 image with test files, skipped");
+            return EXIT_SKIPPED;
+        }
+    };
+    assert_eq!(hello.as_slice(), b"hello, ext2\n");
+    let big = read_all("/ext2-test/big\0").unwrap();
+    assert_eq!(big.len(), BIG_LEN);
+    for (i, byte) in big.iter().enumerate() {
+        assert_eq!(*byte, LINE[i % LINE.len()]);
+    }
+    // a fast symlink, a relative one and one too long to fit in the inode
+    for link in [
+        "/ext2-test/link\0",
+        "/ext2-test/dir/up\0",
+        "/ext2-test/longlink\0",
+    ] {
+        assert_eq!(read_all(link).unwrap(), hello);
+    }
+    // the volume is read-only
+    assert!(open("/ext2-test/new\0", OpenFlags::CREATE | OpenFlags::WRONLY) < 0);
+    assert!(unlink("/ext2-test/hello.txt\0") < 0);
+    assert!(read_all("/ext2-test/hello.txt\0").is_some());
+    println!("ext2_test passed!");
+    0
+}
@@ -0,0 +1,104 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use user_lib::{close, mkdir, open, read, unlink, write, OpenFlags};
+
+/// Larger than a cluster of any FAT32 volume mkfs.vfat makes for small disks
+const DATA_LEN: usize = 20000;
+const ENOTEMPTY: isize = -39;
+
+/// Names in the directory at `path`
+fn list(path: &str) -> Vec<u8> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut listing = Vec::new();
+    let mut buf = [0u8; 128];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        listing.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd);
+    listing
+}
+
+fn contains_line(listing: &[u8], name: &str) -> bool {
+    listing
+        .split(|c| *c == b'\n')
+        .any(|line| line == name.as_bytes())
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // long names and data spanning several clusters
+    let data: Vec<u8> = (0..DATA_LEN).map(|i| (i % 251) as u8).collect();
+    let fd = open(
+        "/A Long File Name.txt\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &data), DATA_LEN as isize);
+    close(fd as usize);
+    let fd = open("/A Long File Name.txt\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut back = vec![0u8; DATA_LEN + 1];
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, &mut back[total..]);
+        if len <= 0 {
+            break;
+        }
+        total += len as usize;
+    }
+    close(fd as usize);
+    assert_eq!(total, DATA_LEN);
+    assert_eq!(&back[..DATA_LEN], data.as_slice());
+
+    // a lowercase 8.3 name keeps its case
+    let fd = open("/short.txt\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    let listing = list("/\0");
+    assert!(contains_line(&listing, "A Long File Name.txt"));
+    assert!(contains_line(&listing, "short.txt"));
+
+    // enough entries to grow a directory past its first cluster
+    assert_eq!(mkdir("/A Directory\0"), 0);
+    for i in 0..40 {
+        let path = format!("/A Directory/nested file number {}\0", i);
+        let fd = open(path.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd > 0);
+        close(fd as usize);
+    }
+    assert_eq!(
+        list("/A Directory\0")
+            .iter()
+            .filter(|c| **c == b'\n')
+            .count(),
+        40
+    );
+    assert_eq!(unlink("/A Directory\0"), ENOTEMPTY);
+    for i in 0..40 {
+        let path = format!("/A Directory/nested file number {}\0", i);
+        assert_eq!(unlink(path.as_str()), 0);
+    }
+    assert_eq!(unlink("/A Directory\0"), 0);
+    assert_eq!(unlink("/A Long File Name.txt\0"), 0);
+    assert_eq!(unlink("/short.txt\0"), 0);
+    let listing = list("/\0");
+    assert!(!contains_line(&listing, "A Long File Name.txt"));
+    assert!(!contains_line(&listing, "short.txt"));
+    println!("fat32_test passed!");
+    0
+}
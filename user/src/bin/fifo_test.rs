@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, mkfifo, open, read, unlink, waitpid, write, OpenFlags};
+
+const FIFO: &str = "/tmp/fifo_test\0";
+const STR: &str = "Hello, FIFO!";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkfifo(FIFO), 0);
+    assert_eq!(mkfifo(FIFO), -17);
+    assert_eq!(mkfifo("/no_such_dir/fifo\0"), -2);
+
+    // opening for writing waits for a reader, which then sees the data
+    // and end of file once the writer is gone
+    let pid = fork();
+    if pid == 0 {
+        let fd = open(FIFO, OpenFlags::WRONLY);
+        assert!(fd >= 0);
+        assert_eq!(write(fd as usize, STR.as_bytes()), STR.len() as isize);
+        close(fd as usize);
+        exit(0);
+    }
+    let fd = open(FIFO, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buffer = [0u8; 32];
+    let mut len = 0;
+    loop {
+        let read_len = read(fd as usize, &mut buffer[len..]);
+        assert!(read_len >= 0);
+        if read_len == 0 {
+            break;
+        }
+        len += read_len as usize;
+    }
+    assert_eq!(core::str::from_utf8(&buffer[..len]).unwrap(), STR);
+    close(fd as usize);
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+
+    // both ends in one open don't wait
+    let fd = open(FIFO, OpenFlags::RDWR);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, STR.as_bytes()), STR.len() as isize);
+    assert_eq!(read(fd as usize, &mut buffer), STR.len() as isize);
+    close(fd as usize);
+
+    assert_eq!(unlink(FIFO), 0);
+    println!("fifo_test passed!");
+    0
+}
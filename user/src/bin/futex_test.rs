@@ -0,0 +1,130 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::AtomicU32;
+use user_lib::{
+    close, exit, fork, futex_wait, futex_wake, get_time, mmap, munmap, open, thread_create, unlink,
+    waitpid, waittid, write, yield_, Condvar, Mutex, OpenFlags, MAP_SHARED, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const PATH: &str = "/tmp/futex_test\0";
+const EAGAIN: isize = -11;
+const EFAULT: isize = -14;
+/// The trap context of the main thread, mapped only to the kernel
+const TRAP_CONTEXT: usize = usize::MAX - 2 * PAGE_SIZE + 1;
+const ETIMEDOUT: isize = -110;
+const THREADS: usize = 4;
+const ROUNDS: usize = 50;
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+static READY: Mutex<bool> = Mutex::new(false);
+static CONDVAR: Condvar = Condvar::new();
+
+/// Increment the counter in steps that race unless the mutex is held
+extern "C" fn increment(_arg: usize) -> ! {
+    for _ in 0..ROUNDS {
+        let mut counter = COUNTER.lock();
+        let value = *counter;
+        yield_();
+        *counter = value + 1;
+    }
+    exit(0)
+}
+
+/// Set the flag the main thread waits for
+extern "C" fn set_ready(_arg: usize) -> ! {
+    *READY.lock() = true;
+    CONDVAR.notify_one();
+    exit(0)
+}
+
+/// The futex mutex keeps the increments from getting lost
+fn mutex() {
+    let mut tids = [0usize; THREADS];
+    for tid in tids.iter_mut() {
+        let created = thread_create(increment as usize, 0);
+        assert!(created > 0);
+        *tid = created as usize;
+    }
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), 0);
+    }
+    assert_eq!(*COUNTER.lock(), THREADS * ROUNDS);
+    let guard = COUNTER.lock();
+    assert!(COUNTER.try_lock().is_none());
+    drop(guard);
+    assert!(COUNTER.try_lock().is_some());
+    println!("mutex ok");
+}
+
+/// A waiter sleeps with the mutex released until it's notified
+fn condvar() {
+    let mut ready = READY.lock();
+    let tid = thread_create(set_ready as usize, 0);
+    assert!(tid > 0);
+    while !*ready {
+        ready = CONDVAR.wait(ready);
+    }
+    drop(ready);
+    assert_eq!(waittid(tid as usize), 0);
+    println!("condvar ok");
+}
+
+/// Waiting fails at once on a changed value, and ends when the time is up
+fn wait_errors() {
+    let word = AtomicU32::new(5);
+    assert_eq!(futex_wait(&word, 6, -1), EAGAIN);
+    assert_eq!(futex_wake(&word, 1), 0);
+    let start = get_time();
+    assert_eq!(futex_wait(&word, 5, 50), ETIMEDOUT);
+    assert!(get_time() - start >= 50);
+    let kernel_word = unsafe { &*(TRAP_CONTEXT as *const AtomicU32) };
+    assert_eq!(futex_wait(kernel_word, 0, 0), EFAULT);
+    assert_eq!(futex_wake(kernel_word, 1), EFAULT);
+    println!("wait errors ok");
+}
+
+/// A futex word in a shared mapping is the same futex in both processes
+fn shared() {
+    let fd = open(
+        PATH,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, &[0u8; PAGE_SIZE]), PAGE_SIZE as isize);
+    close(fd as usize);
+    let fd = open(PATH, OpenFlags::RDWR) as usize;
+    let addr = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    close(fd);
+    assert!(addr > 0);
+    let word = unsafe { &*(addr as *const AtomicU32) };
+    let pid = fork();
+    if pid == 0 {
+        // the word stays 0, only a wake can end the wait
+        exit((futex_wait(word, 0, -1) == 0) as i32);
+    }
+    // a wake in the parent finds the child once it sleeps
+    while futex_wake(word, 1) == 0 {
+        yield_();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 1);
+    munmap(addr as usize, PAGE_SIZE);
+    unlink(PATH);
+    println!("shared ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mutex();
+    condvar();
+    wait_errors();
+    shared();
+    println!("futex_test passed!");
+    0
+}
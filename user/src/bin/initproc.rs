@@ -4,25 +4,53 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, fork, wait, yield_};
+use user_lib::{exec, exit, fork, setgid, setuid, wait, yield_};
 
+/// Exit code of the shell process if it couldn't start the shell
+const START_FAILED: i32 = -4;
+
+/// Id from the build environment variable `value`, or `default`
+fn id(value: Option<&str>, default: u32) -> u32 {
+    value.and_then(|id| id.parse().ok()).unwrap_or(default)
+}
+
+/// Start the shell, return its pid
+fn spawn_shell() -> isize {
+    let pid = fork();
+    if pid == 0 {
+        // initproc runs as root, the shell as the user it was built for
+        let gid = id(option_env!("SHELL_GID"), 1000);
+        let uid = id(option_env!("SHELL_UID"), 1000);
+        // never fall back to a root shell
+        if setgid(gid) != 0 || setuid(uid) != 0 {
+            println!("[initproc] failed to run the shell as {}:{}", uid, gid);
+            exit(START_FAILED);
+        }
+        exec("user_shell\0");
+        println!("[initproc] failed to exec the shell");
+        exit(START_FAILED);
+    }
+    pid
+}
+
+/// Reap the shell and the orphans handed over by the kernel, forever,
+/// starting the shell again whenever it exits unless it couldn't start
 #[no_mangle]
 fn main() -> i32 {
-    if fork() == 0 {
-        exec("user_shell\0");
-    } else {
-        loop {
-            let mut exit_code: i32 = 0;
-            let pid = wait(&mut exit_code);
-            if pid == -1 {
-                yield_();
-                continue;
-            }
-            println!(
-                "[initproc] Released a zombie process, pid={}, exit_code={}",
-                pid, exit_code,
-            );
+    let mut shell = spawn_shell();
+    loop {
+        let mut exit_code: i32 = 0;
+        let pid = wait(&mut exit_code);
+        if pid == -1 {
+            yield_();
+            continue;
+        }
+        println!(
+            "[initproc] Released a zombie process, pid={}, exit_code={}",
+            pid, exit_code,
+        );
+        if pid == shell && exit_code != START_FAILED {
+            shell = spawn_shell();
         }
     }
-    0
 }
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{close, open, read, write, OpenFlags, EXIT_SKIPPED};
+
+/// Read the whole file at `path`, `None` if it can't be opened
+fn read_all(path: &str) -> Option<Vec<u8>> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut content = Vec::new();
+    let mut buf = [0u8; 1000];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        content.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd);
+    Some(content)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the files come from `make initramfs` in os/
+    let content = match read_all("/etc/initramfs-test\0") {
+        Some(content) => content,
+        None => {
+            println!("initramfs_test: not booted from an initramfs, skipped");
+            return EXIT_SKIPPED;
+        }
+    };
+    assert_eq!(content.as_slice(), b"hello, initramfs\n");
+    assert_eq!(read_all("/etc/initramfs-link\0").unwrap(), content);
+    // the first process came from the archive
+    assert!(read_all("/sbin/initproc\0")
+        .unwrap()
+        .starts_with(b"\x7fELF"));
+    // the unpacked root is writable
+    let fd = open(
+        "/etc/initramfs-new\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"new"), 3);
+    close(fd as usize);
+    assert_eq!(read_all("/etc/initramfs-new\0").unwrap().as_slice(), b"new");
+    println!("initramfs_test passed!");
+    0
+}
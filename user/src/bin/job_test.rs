@@ -0,0 +1,151 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::{
+    close, exit, exit_status, fork, getpgid, getpid, getsid, is_continued, kill, pipe, read,
+    setpgid, setsid, sigaction, stop_signal, waitpid, waitpid_options, write, yield_, SignalAction,
+    SIGCHLD, SIGCONT, SIGKILL, SIGSTOP, SIGTSTP, WCONTINUED, WUNTRACED,
+};
+
+const EPERM: isize = -1;
+
+static CHILD_CHANGED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_child(_signum: usize) {
+    CHILD_CHANGED.store(true, Ordering::SeqCst);
+}
+
+/// A child in a session of its own can't be moved back to a group of ours
+fn sessions() {
+    let sid = getsid(0);
+    assert!(sid >= 0);
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        assert_eq!(getsid(0), sid);
+        let pid = getpid();
+        assert_eq!(setsid(), pid);
+        assert_eq!(getsid(0), pid);
+        assert_eq!(getpgid(0), pid);
+        // now it leads a group
+        assert_eq!(setsid(), EPERM);
+        write(pipe_fd[1], b"s");
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+    close(pipe_fd[0]);
+    assert_eq!(getsid(pid as usize), pid);
+    assert_eq!(setpgid(pid as usize, 0), EPERM);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("sessions ok");
+}
+
+/// Stops and continues are reported to the parent when it asks for them
+fn stop_continue() {
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    let mut status = 0;
+    assert_eq!(kill(pid, SIGSTOP), 0);
+    assert_eq!(waitpid_options(pid, &mut status, WUNTRACED), pid);
+    assert_eq!(stop_signal(status), Some(SIGSTOP));
+    assert_eq!(kill(pid, SIGCONT), 0);
+    assert_eq!(waitpid_options(pid, &mut status, WCONTINUED), pid);
+    assert!(is_continued(status));
+    assert_eq!(kill(pid, SIGTSTP), 0);
+    assert_eq!(waitpid_options(pid, &mut status, WUNTRACED), pid);
+    assert_eq!(stop_signal(status), Some(SIGTSTP));
+    // a stopped process still dies of SIGKILL
+    assert_eq!(kill(pid, SIGKILL), 0);
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, -(SIGKILL as i32));
+    println!("stop and continue ok");
+}
+
+/// Exit codes that look like the status of a stop or continue are still
+/// reported as exits
+fn exit_codes() {
+    for code in [0x137f, 0xffff, -1] {
+        let pid = fork();
+        if pid == 0 {
+            exit(code);
+        }
+        let mut status = 0;
+        assert_eq!(
+            waitpid_options(pid, &mut status, WUNTRACED | WCONTINUED),
+            pid
+        );
+        assert_eq!(stop_signal(status), None);
+        assert!(!is_continued(status));
+        assert_eq!(exit_status(status), Some(code as i8 as i32));
+    }
+    println!("exit codes ok");
+}
+
+/// A syscall cut short by a stop goes on once the process continues
+fn restart() {
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[1]);
+        let mut buf = [0u8; 1];
+        exit(read(pipe_fd[0], &mut buf) as i32);
+    }
+    close(pipe_fd[0]);
+    // let the child block reading
+    for _ in 0..10 {
+        yield_();
+    }
+    let mut status = 0;
+    assert_eq!(kill(pid, SIGSTOP), 0);
+    assert_eq!(waitpid_options(pid, &mut status, WUNTRACED), pid);
+    assert_eq!(kill(pid, SIGCONT), 0);
+    write(pipe_fd[1], b"r");
+    close(pipe_fd[1]);
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 1);
+    println!("restart ok");
+}
+
+/// The parent is sent SIGCHLD when a child exits
+fn sigchld() {
+    sigaction(
+        SIGCHLD,
+        Some(&SignalAction::new(on_child as usize, 0)),
+        None,
+    );
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(CHILD_CHANGED.load(Ordering::SeqCst));
+    sigaction(SIGCHLD, Some(&SignalAction::default()), None);
+    println!("sigchld ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    sessions();
+    stop_continue();
+    exit_codes();
+    restart();
+    sigchld();
+    println!("job_test passed!");
+    0
+}
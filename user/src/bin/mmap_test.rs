@@ -0,0 +1,205 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec;
+use core::slice;
+use user_lib::{
+    close, exit, fork, mmap, msync, munmap, open, pipe, read, unlink, waitpid, write, OpenFlags,
+    MAP_ANONYMOUS, MAP_PRIVATE, MAP_SHARED, MS_SYNC, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const PATH: &str = "/tmp/mmap_test\0";
+const EBADF: isize = -9;
+const ENOMEM: isize = -12;
+const EACCES: isize = -13;
+const EFAULT: isize = -14;
+const ENODEV: isize = -19;
+const EINVAL: isize = -22;
+
+/// `len` bytes of memory at `addr`
+fn memory(addr: isize, len: usize) -> &'static mut [u8] {
+    assert!(addr > 0);
+    unsafe { slice::from_raw_parts_mut(addr as *mut u8, len) }
+}
+
+/// Create the test file with two pages, the first of `a`s, the second of `b`s
+fn create_file() {
+    let fd = open(
+        PATH,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    ) as usize;
+    for c in [b'a', b'b'] {
+        assert_eq!(write(fd, &vec![c; PAGE_SIZE]), PAGE_SIZE as isize);
+    }
+    close(fd);
+}
+
+/// The byte of the test file at `offset`, read with `read`
+fn file_byte(offset: usize) -> u8 {
+    let fd = open(PATH, OpenFlags::RDONLY) as usize;
+    let mut buf = vec![0u8; PAGE_SIZE];
+    let mut pos = 0;
+    while pos + PAGE_SIZE <= offset {
+        assert_eq!(read(fd, &mut buf), PAGE_SIZE as isize);
+        pos += PAGE_SIZE;
+    }
+    assert!(read(fd, &mut buf) > (offset - pos) as isize);
+    close(fd);
+    buf[offset - pos]
+}
+
+/// Stores through a shared mapping are seen by `read` at once, and by the
+/// file after the mapping is gone
+fn shared_mapping() {
+    create_file();
+    let fd = open(PATH, OpenFlags::RDWR) as usize;
+    let addr = mmap(0, 2 * PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    close(fd);
+    let mem = memory(addr, 2 * PAGE_SIZE);
+    assert_eq!(mem[0], b'a');
+    assert_eq!(mem[PAGE_SIZE], b'b');
+    mem[1] = b'x';
+    assert_eq!(file_byte(1), b'x');
+    // the kernel writes through the mapping too
+    let fd = open(PATH, OpenFlags::RDONLY) as usize;
+    assert_eq!(read(fd, &mut mem[PAGE_SIZE..PAGE_SIZE + 2]), 2);
+    close(fd);
+    assert_eq!(&mem[PAGE_SIZE..PAGE_SIZE + 2], b"ax");
+    assert_eq!(msync(addr as usize, 2 * PAGE_SIZE, MS_SYNC), 0);
+    assert_eq!(munmap(addr as usize, 2 * PAGE_SIZE), 0);
+    assert_eq!(file_byte(PAGE_SIZE + 1), b'x');
+    println!("shared mapping ok");
+}
+
+/// Stores through a private mapping go to a copy, `read` and other
+/// mappings don't see them
+fn private_mapping() {
+    create_file();
+    let fd = open(PATH, OpenFlags::RDWR) as usize;
+    let prot = PROT_READ | PROT_WRITE;
+    let private = mmap(0, 2 * PAGE_SIZE, prot, MAP_PRIVATE, fd, 0);
+    let shared = mmap(0, 2 * PAGE_SIZE, prot, MAP_SHARED, fd, 0);
+    let private_mem = memory(private, 2 * PAGE_SIZE);
+    let shared_mem = memory(shared, 2 * PAGE_SIZE);
+    private_mem[0] = b'p';
+    assert_eq!(shared_mem[0], b'a');
+    assert_eq!(file_byte(0), b'a');
+    // a copied page keeps what it had, one not stored to follows the file
+    shared_mem[1] = b's';
+    shared_mem[PAGE_SIZE] = b's';
+    assert_eq!(private_mem[1], b'a');
+    assert_eq!(private_mem[PAGE_SIZE], b's');
+    // the kernel writing into a private mapping doesn't reach the file
+    assert_eq!(read(fd, &mut private_mem[PAGE_SIZE + 8..PAGE_SIZE + 16]), 8);
+    assert_eq!(private_mem[PAGE_SIZE + 9], b's');
+    assert_eq!(shared_mem[PAGE_SIZE + 9], b'b');
+    close(fd);
+    munmap(private as usize, 2 * PAGE_SIZE);
+    munmap(shared as usize, 2 * PAGE_SIZE);
+    assert_eq!(file_byte(0), b'a');
+    assert_eq!(file_byte(1), b's');
+    assert_eq!(file_byte(PAGE_SIZE + 9), b'b');
+    println!("private mapping ok");
+}
+
+/// A forked child shares the pages of a shared mapping with its parent,
+/// and gets its own copy of a private one
+fn fork_mapping() {
+    create_file();
+    let fd = open(PATH, OpenFlags::RDWR) as usize;
+    let shared = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    let private = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    close(fd);
+    let (shared_mem, private_mem) = (memory(shared, PAGE_SIZE), memory(private, PAGE_SIZE));
+    private_mem[0] = b'p';
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(private_mem[0], b'p');
+        private_mem[0] = b'c';
+        shared_mem[2] = b'c';
+        exit(0);
+    }
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+    assert_eq!(shared_mem[2], b'c');
+    assert_eq!(private_mem[0], b'p');
+    // the child wrote its dirty page back as it exited, the parent has
+    // nothing to write back
+    munmap(shared as usize, PAGE_SIZE);
+    munmap(private as usize, PAGE_SIZE);
+    assert_eq!(file_byte(2), b'c');
+    println!("fork mapping ok");
+}
+
+/// Unmapping part of a mapping leaves the rest mapped
+fn partial_unmap() {
+    let addr = mmap(
+        0,
+        3 * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    let mem = memory(addr, 3 * PAGE_SIZE);
+    mem[0] = 1;
+    mem[2 * PAGE_SIZE] = 3;
+    assert_eq!(munmap(addr as usize + PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(mem[0] + mem[2 * PAGE_SIZE], 4);
+    assert_eq!(msync(addr as usize, 3 * PAGE_SIZE, 0), ENOMEM);
+    munmap(addr as usize, 3 * PAGE_SIZE);
+    println!("partial unmap ok");
+}
+
+fn errors() {
+    create_file();
+    let fd = open(PATH, OpenFlags::RDONLY) as usize;
+    assert_eq!(mmap(0, 0, PROT_READ, MAP_SHARED, fd, 0), EINVAL);
+    assert_eq!(mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, fd, 1), EINVAL);
+    assert_eq!(mmap(0, PAGE_SIZE, PROT_READ, 0, fd, 0), EINVAL);
+    let prot = PROT_READ | PROT_WRITE;
+    assert_eq!(mmap(0, PAGE_SIZE, prot, MAP_SHARED, fd, 0), EACCES);
+    // a private writable mapping of a read-only file is fine
+    let addr = mmap(0, PAGE_SIZE, prot, MAP_PRIVATE, fd, 0);
+    assert!(addr > 0);
+    munmap(addr as usize, PAGE_SIZE);
+    let read_only = mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, fd, 0);
+    close(fd);
+    assert_eq!(mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, fd, 0), EBADF);
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    // the kernel doesn't store where the task couldn't
+    assert_eq!(write(pipe_fd[1], b"x"), 1);
+    assert_eq!(read(pipe_fd[0], memory(read_only, 1)), EFAULT);
+    assert_eq!(memory(read_only, 1)[0], b'a');
+    munmap(read_only as usize, PAGE_SIZE);
+    assert_eq!(
+        mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, pipe_fd[0], 0),
+        ENODEV
+    );
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    // far more than there are frames for
+    let anonymous = MAP_PRIVATE | MAP_ANONYMOUS;
+    assert_eq!(mmap(0, 1 << 30, prot, anonymous, 0, 0), ENOMEM);
+    println!("errors ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    shared_mapping();
+    private_mapping();
+    fork_mapping();
+    partial_unmap();
+    errors();
+    unlink(PATH);
+    println!("mmap_test passed!");
+    0
+}
@@ -0,0 +1,171 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chmod, chown, close, exec, exit, fork, geteuid, getgid, getuid, mkdir, open, setgid, setuid,
+    unlink, waitpid, OpenFlags,
+};
+
+const FILE: &str = "/tmp/perm_test\0";
+const DIR: &str = "/tmp/perm_dir\0";
+const IN_DIR: &str = "/tmp/perm_dir/file\0";
+const USER_FILE: &str = "/tmp/perm_user\0";
+const UID: u32 = 1000;
+const GID: u32 = 100;
+const KEEP: u32 = u32::MAX;
+const EPERM: isize = -1;
+const EACCES: isize = -13;
+
+/// Run `f` in a child as `UID`:`GID` and check it passed
+fn as_user(f: fn()) {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setgid(GID), 0);
+        assert_eq!(setuid(UID), 0);
+        f();
+        exit(0);
+    }
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+/// Open `path` with `flags` and close it again, return the fd or error
+fn try_open(path: &str, flags: OpenFlags) -> isize {
+    let fd = open(path, flags);
+    if fd >= 0 {
+        close(fd as usize);
+    }
+    fd
+}
+
+/// A user can't take root back, and children keep the ids
+fn credentials() {
+    as_user(|| {
+        assert_eq!(getuid(), UID as isize);
+        assert_eq!(geteuid(), UID as isize);
+        assert_eq!(getgid(), GID as isize);
+        assert_eq!(setuid(0), EPERM);
+        assert_eq!(setgid(0), EPERM);
+        // setting the id it already has is fine
+        assert_eq!(setuid(UID), 0);
+        let pid = fork();
+        if pid == 0 {
+            assert_eq!(getuid(), UID as isize);
+            exit(0);
+        }
+        let mut exit_code = -1;
+        waitpid(pid as usize, &mut exit_code);
+        assert_eq!(exit_code, 0);
+    });
+    println!("credentials ok");
+}
+
+/// Owner, group and other bits each apply to their class
+fn open_permissions() {
+    assert!(try_open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY) >= 0);
+    assert_eq!(chmod(FILE, 0o600), 0);
+    as_user(|| {
+        assert_eq!(try_open(FILE, OpenFlags::RDONLY), EACCES);
+        assert_eq!(try_open(FILE, OpenFlags::WRONLY), EACCES);
+    });
+    assert_eq!(chmod(FILE, 0o644), 0);
+    as_user(|| {
+        assert!(try_open(FILE, OpenFlags::RDONLY) >= 0);
+        assert_eq!(try_open(FILE, OpenFlags::RDWR), EACCES);
+        assert_eq!(try_open(FILE, OpenFlags::RDONLY | OpenFlags::TRUNC), EACCES);
+    });
+    // the group bits apply to members of the owning group
+    assert_eq!(chown(FILE, 0, GID), 0);
+    assert_eq!(chmod(FILE, 0o604), 0);
+    as_user(|| assert_eq!(try_open(FILE, OpenFlags::RDONLY), EACCES));
+    // and the owner bits to the owner, whatever the others get
+    assert_eq!(chown(FILE, UID, KEEP), 0);
+    assert_eq!(chmod(FILE, 0o200), 0);
+    as_user(|| {
+        assert!(try_open(FILE, OpenFlags::WRONLY) >= 0);
+        assert_eq!(try_open(FILE, OpenFlags::RDONLY), EACCES);
+        // the owner may change the mode, but not give the file away
+        assert_eq!(chmod(FILE, 0o600), 0);
+        assert!(try_open(FILE, OpenFlags::RDWR) >= 0);
+        assert_eq!(chown(FILE, 0, KEEP), EPERM);
+    });
+    // root isn't held back by the bits
+    assert_eq!(chmod(FILE, 0), 0);
+    assert!(try_open(FILE, OpenFlags::RDWR) >= 0);
+    println!("open permissions ok");
+}
+
+/// Running a file takes execute permission, even for root
+fn exec_permissions() {
+    assert_eq!(chown(FILE, 0, 0), 0);
+    assert_eq!(chmod(FILE, 0o644), 0);
+    assert_eq!(exec(FILE), EACCES);
+    assert_eq!(chmod(FILE, 0o700), 0);
+    as_user(|| assert_eq!(exec(FILE), EACCES));
+    as_user(|| assert_eq!(exec("/tmp\0"), EACCES));
+    println!("exec permissions ok");
+}
+
+/// Creating and removing entries takes write permission on the directory,
+/// and in the sticky /tmp only owners may remove entries
+fn directory_permissions() {
+    assert_eq!(mkdir(DIR), 0);
+    assert!(try_open(IN_DIR, OpenFlags::CREATE | OpenFlags::WRONLY) >= 0);
+    as_user(|| {
+        assert_eq!(unlink(IN_DIR), EACCES);
+        assert_eq!(try_open("/tmp/perm_dir/new\0", OpenFlags::CREATE), EACCES);
+        assert_eq!(mkdir("/tmp/perm_dir/sub\0"), EACCES);
+        // /tmp is open to everyone
+        assert!(try_open(USER_FILE, OpenFlags::CREATE | OpenFlags::WRONLY) >= 0);
+        assert_eq!(unlink(FILE), EPERM);
+        assert_eq!(unlink(DIR), EPERM);
+    });
+    assert_eq!(chmod(DIR, 0o777), 0);
+    as_user(|| {
+        assert_eq!(unlink(IN_DIR), 0);
+        // the new file is the user's to remove
+        assert_eq!(chmod(USER_FILE, 0o600), 0);
+        assert_eq!(unlink(USER_FILE), 0);
+    });
+    assert_eq!(unlink(DIR), 0);
+    assert_eq!(unlink(FILE), 0);
+    println!("directory permissions ok");
+}
+
+/// Reaching a file takes search permission on every directory on the way
+fn search_permissions() {
+    assert_eq!(mkdir(DIR), 0);
+    assert!(try_open(IN_DIR, OpenFlags::CREATE | OpenFlags::WRONLY) >= 0);
+    assert_eq!(chmod(IN_DIR, 0o644), 0);
+    assert_eq!(chmod(DIR, 0o666), 0);
+    as_user(|| {
+        assert_eq!(try_open(IN_DIR, OpenFlags::RDONLY), EACCES);
+        assert_eq!(chmod(IN_DIR, 0o666), EACCES);
+        assert_eq!(exec(IN_DIR), EACCES);
+        assert_eq!(mkdir("/tmp/perm_dir/sub\0"), EACCES);
+    });
+    assert_eq!(chmod(DIR, 0o755), 0);
+    as_user(|| assert!(try_open(IN_DIR, OpenFlags::RDONLY) >= 0));
+    assert_eq!(unlink(IN_DIR), 0);
+    assert_eq!(unlink(DIR), 0);
+    println!("search permissions ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    if getuid() != 0 {
+        println!("perm_test has to run as root");
+        return -1;
+    }
+    credentials();
+    open_permissions();
+    exec_permissions();
+    directory_permissions();
+    search_permissions();
+    println!("perm_test passed!");
+    0
+}
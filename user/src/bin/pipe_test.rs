@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use user_lib::{close, exit, fork, pipe, read, waitpid, write};
+
+const STR: &str = "Hello, world!";
+/// Bigger than the ring buffer of a pipe, so the writer has to wait
+const LARGE_LEN: usize = 20000;
+
+/// The child reads what the parent writes, then sees end of file
+fn small_transfer() {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[1]);
+        let mut buffer = [0u8; 32];
+        let len = read(pipe_fd[0], &mut buffer) as usize;
+        assert_eq!(core::str::from_utf8(&buffer[..len]).unwrap(), STR);
+        assert_eq!(read(pipe_fd[0], &mut buffer), 0);
+        close(pipe_fd[0]);
+        exit(0);
+    }
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], STR.as_bytes()), STR.len() as isize);
+    close(pipe_fd[1]);
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+/// The child writes more than fits in the pipe, the parent reads all of it
+fn large_transfer() {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        let data: Vec<u8> = (0..LARGE_LEN).map(|i| (i % 251) as u8).collect();
+        assert_eq!(write(pipe_fd[1], &data), LARGE_LEN as isize);
+        // exiting closes the write end
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let mut buffer = vec![0u8; 1000];
+    let mut total = 0;
+    loop {
+        let len = read(pipe_fd[0], &mut buffer);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for byte in &buffer[..len as usize] {
+            assert_eq!(*byte, (total % 251) as u8);
+            total += 1;
+        }
+    }
+    assert_eq!(total, LARGE_LEN);
+    close(pipe_fd[0]);
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+/// Writing with no reader left kills the writer with SIGPIPE
+fn broken_pipe() {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    let pid = fork();
+    if pid == 0 {
+        write(pipe_fd[1], STR.as_bytes());
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, -13);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    small_transfer();
+    large_transfer();
+    broken_pipe();
+    println!("pipe_test passed!");
+    0
+}
@@ -0,0 +1,164 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, connect, epoll_create, epoll_ctl, epoll_wait, exit, fork, get_time,
+    listen, pipe, poll, read, sleep, socket, unlink, waitpid, write, EpollEvent, PollEvents,
+    PollFd, AF_UNIX, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD, SOCK_STREAM,
+};
+
+const SOCKET: &str = "/tmp/poll_test\0";
+
+/// Write a byte to `fd` in a child process after a while
+fn write_later(fd: usize) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        assert_eq!(write(fd, b"x"), 1);
+        exit(0);
+    }
+    pid
+}
+
+fn wait_for(pid: isize) {
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+fn test_poll() {
+    let mut p = [0usize; 2];
+    let mut q = [0usize; 2];
+    assert_eq!(pipe(&mut p), 0);
+    assert_eq!(pipe(&mut q), 0);
+
+    // nothing to read yet, then one byte
+    let mut fds = [PollFd::new(p[0], PollEvents::IN)];
+    assert_eq!(poll(&mut fds, 0), 0);
+    assert!(fds[0].revents().is_empty());
+    assert_eq!(write(p[1], b"x"), 1);
+    assert_eq!(poll(&mut fds, 0), 1);
+    assert_eq!(fds[0].revents(), PollEvents::IN);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(p[0], &mut buf), 1);
+    let mut fds = [PollFd::new(p[1], PollEvents::IN | PollEvents::OUT)];
+    assert_eq!(poll(&mut fds, 0), 1);
+    assert_eq!(fds[0].revents(), PollEvents::OUT);
+
+    // the timeout passes with nothing ready
+    let mut fds = [PollFd::new(p[0], PollEvents::IN)];
+    let start = get_time();
+    assert_eq!(poll(&mut fds, 100), 0);
+    assert!(get_time() - start >= 100);
+
+    // closed and negative fds
+    let mut fds = [
+        PollFd::new(100, PollEvents::IN),
+        PollFd::new(p[0], PollEvents::IN),
+    ];
+    fds[1].fd = -1;
+    assert_eq!(poll(&mut fds, 0), 1);
+    assert_eq!(fds[0].revents(), PollEvents::NVAL);
+    assert!(fds[1].revents().is_empty());
+
+    // blocks until the child writes to the second pipe
+    let pid = write_later(q[1]);
+    let mut fds = [
+        PollFd::new(p[0], PollEvents::IN),
+        PollFd::new(q[0], PollEvents::IN),
+    ];
+    assert_eq!(poll(&mut fds, -1), 1);
+    assert!(fds[0].revents().is_empty());
+    assert_eq!(fds[1].revents(), PollEvents::IN);
+    wait_for(pid);
+
+    // hang up once the write end is closed
+    close(p[1]);
+    let mut fds = [PollFd::new(p[0], PollEvents::IN)];
+    assert_eq!(poll(&mut fds, -1), 1);
+    assert_eq!(fds[0].revents(), PollEvents::HUP);
+    close(p[0]);
+    close(q[0]);
+    close(q[1]);
+}
+
+fn test_epoll() {
+    let epfd = epoll_create(0);
+    assert!(epfd >= 0);
+    let epfd = epfd as usize;
+    let mut a = [0usize; 2];
+    let mut b = [0usize; 2];
+    assert_eq!(pipe(&mut a), 0);
+    assert_eq!(pipe(&mut b), 0);
+    let event_a = EpollEvent::new(PollEvents::IN, 1);
+    let event_b = EpollEvent::new(PollEvents::IN, 2);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, a[0], &event_a), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, a[0], &event_a), -17);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, b[0], &event_b), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_MOD, b[1], &event_b), -2);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, 100, &event_b), -9);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, epfd, &event_b), -22);
+    assert_eq!(epoll_ctl(a[0], EPOLL_CTL_ADD, b[0], &event_b), -22);
+
+    let mut events = [EpollEvent::new(PollEvents::empty(), 0); 4];
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+    let pid = write_later(b[1]);
+    assert_eq!(epoll_wait(epfd, &mut events, -1), 1);
+    assert_eq!(events[0].data, 2);
+    assert_eq!(events[0].events(), PollEvents::IN);
+    wait_for(pid);
+    // level-triggered: still there until read
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 1);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, b[0], &event_b), 0);
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 0);
+
+    // an epoll fd is ready when something it watches is
+    let outer = epoll_create(0) as usize;
+    let event_inner = EpollEvent::new(PollEvents::IN, 3);
+    assert_eq!(epoll_ctl(outer, EPOLL_CTL_ADD, epfd, &event_inner), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, outer, &event_inner), -40);
+    assert_eq!(write(a[1], b"x"), 1);
+    let mut fds = [PollFd::new(outer, PollEvents::IN)];
+    assert_eq!(poll(&mut fds, 0), 1);
+    assert_eq!(epoll_wait(outer, &mut events, 0), 1);
+    assert_eq!(events[0].data, 3);
+    close(outer);
+
+    // a listening socket is ready once a client connects
+    let server = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(bind(server, SOCKET), 0);
+    assert_eq!(listen(server, 1), 0);
+    let event_server = EpollEvent::new(PollEvents::IN, 4);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, a[0], &event_a), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, server, &event_server), 0);
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        let fd = socket(AF_UNIX, SOCK_STREAM) as usize;
+        assert_eq!(connect(fd, SOCKET), 0);
+        exit(0);
+    }
+    assert_eq!(epoll_wait(epfd, &mut events, -1), 1);
+    assert_eq!(events[0].data, 4);
+    let conn = accept(server);
+    assert!(conn >= 0);
+    wait_for(pid);
+    close(conn as usize);
+    close(server);
+    unlink(SOCKET);
+
+    for fd in [a[0], a[1], b[0], b[1], epfd] {
+        close(fd);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    test_poll();
+    test_epoll();
+    println!("poll_test passed!");
+    0
+}
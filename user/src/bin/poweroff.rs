@@ -0,0 +1,15 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::poweroff;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // only returns if it failed, e.g. for not being root
+    let err = poweroff(false);
+    println!("poweroff: failed with {}", err);
+    1
+}
@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getpid, getppid, pipe, read, reboot, setuid, waitpid, write, yield_,
+    RB_POWER_OFF,
+};
+
+const EPERM: isize = -1;
+const EINVAL: isize = -22;
+/// initproc is the first process
+const INITPROC_PID: isize = 0;
+
+/// A child sees the caller as its parent
+fn parent() {
+    let pid = getpid();
+    let child = fork();
+    if child == 0 {
+        exit((getppid() == pid) as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 1);
+    println!("parent ok");
+}
+
+/// A grandchild outliving its parent goes to initproc, which reaps it
+fn orphan() {
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let child = fork();
+    if child == 0 {
+        let parent = getpid();
+        if fork() == 0 {
+            while getppid() == parent {
+                yield_();
+            }
+            write(pipe_fd[1], &getppid().to_le_bytes());
+            exit(0);
+        }
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+    let mut ppid = [0u8; 8];
+    assert_eq!(read(pipe_fd[0], &mut ppid), 8);
+    close(pipe_fd[0]);
+    assert_eq!(isize::from_le_bytes(ppid), INITPROC_PID);
+    println!("orphan ok");
+}
+
+/// Only root may take the machine down, and only with a known command
+fn reboot_checks() {
+    assert_eq!(reboot(0x1234), EINVAL);
+    let child = fork();
+    if child == 0 {
+        assert_eq!(setuid(1000), 0);
+        exit((reboot(RB_POWER_OFF) == EPERM) as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 1);
+    println!("reboot ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    parent();
+    orphan();
+    reboot_checks();
+    println!("ppid_test passed!");
+    0
+}
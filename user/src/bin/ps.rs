@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{close, open, read, OpenFlags};
+
+/// Read the whole file at `path`, `None` if it can't be opened
+fn read_to_string(path: &str) -> Option<String> {
+    let fd = open(format!("{}\0", path).as_str(), OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut content = String::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        content.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    close(fd);
+    Some(content)
+}
+
+/// Value of `key` in a `Key:\tvalue` formatted status file
+fn status_field<'a>(status: &'a str, key: &str) -> &'a str {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key))
+        .map_or("?", |value| value.trim())
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pids = match read_to_string("/proc") {
+        Some(pids) => pids,
+        None => {
+            println!("ps: /proc is not available");
+            return -1;
+        }
+    };
+    println!("  PID  PPID THR STATE       CMD");
+    for pid in pids.lines().filter(|name| name.parse::<usize>().is_ok()) {
+        // the process may be gone since the directory was listed
+        let status = match read_to_string(format!("/proc/{}/status", pid).as_str()) {
+            Some(status) => status,
+            None => continue,
+        };
+        let cmdline = read_to_string(format!("/proc/{}/cmdline", pid).as_str()).unwrap_or_default();
+        println!(
+            "{:>5} {:>5} {:>3} {:<11} {}",
+            pid,
+            status_field(&status, "PPid:"),
+            status_field(&status, "Threads:"),
+            status_field(&status, "State:"),
+            cmdline
+        );
+    }
+    if let Some(meminfo) = read_to_string("/proc/meminfo") {
+        print!("{}", meminfo);
+    }
+    0
+}
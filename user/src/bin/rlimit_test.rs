@@ -0,0 +1,158 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, dup3, exec, exit, fork, get_time, getrlimit, getuid, mmap, munmap, setrlimit,
+    setuid, thread_create, waitpid, OpenFlags, RLimit, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ,
+    PROT_WRITE, RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK, RLIM_INFINITY,
+    SIGXCPU,
+};
+
+const EPERM: isize = -1;
+const EBADF: isize = -9;
+const EAGAIN: isize = -11;
+const ENOMEM: isize = -12;
+const EINVAL: isize = -22;
+const EMFILE: isize = -24;
+const PAGE_SIZE: usize = 4096;
+const NOFILE: usize = 8;
+const USER: u32 = 1000;
+
+/// Run `f` in a child and return its exit code
+fn in_child(f: fn() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn limit(resource: usize) -> RLimit {
+    let mut rlim = RLimit::default();
+    assert_eq!(getrlimit(resource, &mut rlim), 0);
+    rlim
+}
+
+/// The limits start out as the kernel sets them, and bad ones are refused
+fn get_set() {
+    let nofile = limit(RLIMIT_NOFILE);
+    assert!(nofile.cur > NOFILE && nofile.cur <= nofile.max);
+    assert_eq!(limit(RLIMIT_CPU).cur, RLIM_INFINITY);
+    let stack = RLimit {
+        cur: 4 * PAGE_SIZE,
+        max: RLIM_INFINITY,
+    };
+    assert_eq!(setrlimit(RLIMIT_STACK, &stack), 0);
+    assert_eq!(limit(RLIMIT_STACK), stack);
+    let bad = RLimit { cur: 2, max: 1 };
+    assert_eq!(setrlimit(RLIMIT_NPROC, &bad), EINVAL);
+    assert_eq!(getrlimit(1, &mut RLimit::default()), EINVAL);
+    println!("get set ok");
+}
+
+/// fds stop at the soft limit, and a child inherits it. Only root raises
+/// the hard limit.
+fn nofile() -> i32 {
+    let rlim = RLimit {
+        cur: NOFILE,
+        max: NOFILE,
+    };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &rlim), 0);
+    let mut last = 0;
+    loop {
+        let fd = dup(0);
+        if fd < 0 {
+            assert_eq!(fd, EMFILE);
+            break;
+        }
+        last = fd;
+    }
+    assert_eq!(last, NOFILE as isize - 1);
+    assert_eq!(dup3(0, NOFILE, OpenFlags::empty()), EBADF);
+    assert_eq!(in_child(|| (limit(RLIMIT_NOFILE).max == NOFILE) as i32), 1);
+    close(last as usize);
+    assert_eq!(dup(0), last);
+    let raised = RLimit {
+        cur: NOFILE,
+        max: 2 * NOFILE,
+    };
+    // the shell may run as a user already
+    if getuid() != 0 {
+        assert_eq!(setrlimit(RLIMIT_NOFILE, &raised), EPERM);
+        return 0;
+    }
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &raised), 0);
+    assert_eq!(setuid(USER), 0);
+    let raised = RLimit {
+        cur: NOFILE,
+        max: 4 * NOFILE,
+    };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &raised), EPERM);
+    0
+}
+
+/// A user past its process limit can't fork
+fn nproc() -> i32 {
+    let rlim = RLimit { cur: 1, max: 1 };
+    assert_eq!(setrlimit(RLIMIT_NPROC, &rlim), 0);
+    assert_eq!(setuid(USER), 0);
+    assert_eq!(fork(), EAGAIN);
+    0
+}
+
+fn thread_entry(_arg: usize) {
+    exit(1);
+}
+
+/// Nothing new is mapped past the address space limit, and a program too
+/// large isn't run
+fn address_space() -> i32 {
+    let rlim = RLimit {
+        cur: PAGE_SIZE,
+        max: RLIM_INFINITY,
+    };
+    assert_eq!(setrlimit(RLIMIT_AS, &rlim), 0);
+    let prot = PROT_READ | PROT_WRITE;
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+    assert_eq!(mmap(0, PAGE_SIZE, prot, flags, 0, 0), ENOMEM);
+    assert_eq!(thread_create(thread_entry as usize, 0), ENOMEM);
+    assert_eq!(exec("hello_world\0"), ENOMEM);
+    let rlim = RLimit {
+        cur: RLIM_INFINITY,
+        max: RLIM_INFINITY,
+    };
+    assert_eq!(setrlimit(RLIMIT_AS, &rlim), 0);
+    let addr = mmap(0, PAGE_SIZE, prot, flags, 0, 0);
+    assert!(addr > 0);
+    assert_eq!(munmap(addr as usize, PAGE_SIZE), 0);
+    0
+}
+
+/// Spinning past the soft CPU limit brings SIGXCPU
+fn cpu() -> i32 {
+    let rlim = RLimit { cur: 1, max: 3 };
+    assert_eq!(setrlimit(RLIMIT_CPU, &rlim), 0);
+    let start = get_time();
+    while get_time() - start < 5000 {}
+    0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    get_set();
+    assert_eq!(in_child(nofile), 0);
+    println!("nofile ok");
+    assert_eq!(in_child(nproc), 0);
+    println!("nproc ok");
+    assert_eq!(in_child(address_space), 0);
+    println!("address space ok");
+    assert_eq!(in_child(cpu), -(SIGXCPU as i32));
+    println!("cpu ok");
+    println!("rlimit_test passed!");
+    0
+}
@@ -0,0 +1,212 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, exit, fork, getpgid, getpid, getuid, kill, pipe, read, setpgid, setuid, sigaction,
+    sigmask, sigprocmask, sleep, tcgetpgrp, tcsetpgrp, waitpid, write, yield_, SignalAction,
+    SIGKILL, SIGPIPE, SIGSEGV, SIGTERM, SIGUSR1, SIGUSR2, SIG_BLOCK, SIG_DFL, SIG_IGN, SIG_SETMASK,
+    SIG_UNBLOCK,
+};
+
+const EPERM: isize = -1;
+const ESRCH: isize = -3;
+const EINTR: isize = -4;
+const EINVAL: isize = -22;
+const ENOTTY: isize = -25;
+const EPIPE: isize = -32;
+
+static USR1_COUNT: AtomicUsize = AtomicUsize::new(0);
+static LAST_SIGNAL: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_usr1(signum: usize) {
+    USR1_COUNT.fetch_add(1, Ordering::SeqCst);
+    LAST_SIGNAL.store(signum, Ordering::SeqCst);
+}
+
+/// Sends SIGUSR1 to itself, which is blocked while this handler runs
+extern "C" fn on_usr2(_signum: usize) {
+    let before = USR1_COUNT.load(Ordering::SeqCst);
+    kill(getpid(), SIGUSR1);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), before);
+    LAST_SIGNAL.store(SIGUSR2, Ordering::SeqCst);
+}
+
+/// Wait for `pid` and return its exit code
+fn wait_exit(pid: isize) -> i32 {
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    exit_code
+}
+
+/// A handler runs before the task goes on, and the old action can be read
+fn handlers() {
+    let action = SignalAction::new(on_usr1 as usize, 0);
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(kill(getpid(), SIGUSR1), 0);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 1);
+    assert_eq!(LAST_SIGNAL.load(Ordering::SeqCst), SIGUSR1);
+    let mut old = SignalAction::default();
+    assert_eq!(sigaction(SIGUSR1, None, Some(&mut old)), 0);
+    assert_eq!(old.handler, on_usr1 as usize);
+    // SIGKILL can't be caught, and there is no signal 64
+    assert_eq!(sigaction(SIGKILL, Some(&action), None), EINVAL);
+    assert_eq!(kill(getpid(), 64), EINVAL);
+    assert_eq!(kill(99999, SIGUSR1), ESRCH);
+    println!("handlers ok");
+}
+
+/// A blocked signal waits until it's unblocked, also while a handler with
+/// it in its mask runs
+fn masks() {
+    assert_eq!(sigprocmask(SIG_BLOCK, sigmask(SIGUSR1)), 0);
+    assert_eq!(kill(getpid(), SIGUSR1), 0);
+    assert_eq!(kill(getpid(), SIGUSR1), 0);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 1);
+    // pending signals don't queue up
+    assert_eq!(
+        sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR1)),
+        sigmask(SIGUSR1) as isize
+    );
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 2);
+    let action = SignalAction::new(on_usr2 as usize, sigmask(SIGUSR1));
+    assert_eq!(sigaction(SIGUSR2, Some(&action), None), 0);
+    assert_eq!(kill(getpid(), SIGUSR2), 0);
+    // SIGUSR1 came after the handler returned, and the mask is back
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 3);
+    assert_eq!(LAST_SIGNAL.load(Ordering::SeqCst), SIGUSR1);
+    assert_eq!(sigprocmask(SIG_SETMASK, 0), 0);
+    println!("masks ok");
+}
+
+/// Default actions end the task, ignored signals don't
+fn default_actions() {
+    let pid = spin_child();
+    assert_eq!(kill(pid, SIGTERM), 0);
+    assert_eq!(wait_exit(pid), -(SIGTERM as i32));
+
+    let pid = fork();
+    if pid == 0 {
+        let null = core::ptr::null::<u8>();
+        unsafe { null.read_volatile() };
+        exit(0);
+    }
+    assert_eq!(wait_exit(pid), -(SIGSEGV as i32));
+
+    // with SIGPIPE ignored the write only fails
+    let ignore = SignalAction::new(SIG_IGN, 0);
+    assert_eq!(sigaction(SIGPIPE, Some(&ignore), None), 0);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], b"lost"), EPIPE);
+    close(pipe_fd[1]);
+    let default = SignalAction::new(SIG_DFL, 0);
+    assert_eq!(sigaction(SIGPIPE, Some(&default), None), 0);
+    println!("default actions ok");
+}
+
+/// Only root or the same user may signal a task
+fn permissions() {
+    if getuid() != 0 {
+        println!("permissions skipped, not root");
+        return;
+    }
+    let parent = getpid();
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        // signal 0 only checks, so the parent is safe either way
+        assert_eq!(kill(parent, 0), EPERM);
+        assert_eq!(kill(getpid(), 0), 0);
+        exit(0);
+    }
+    assert_eq!(wait_exit(pid), 0);
+    println!("permissions ok");
+}
+
+/// A signal ends a blocking read with EINTR once its handler ran
+fn interrupted_read() {
+    let mut data_fd = [0usize; 2];
+    let mut ready_fd = [0usize; 2];
+    assert_eq!(pipe(&mut data_fd), 0);
+    assert_eq!(pipe(&mut ready_fd), 0);
+    let count = USR1_COUNT.load(Ordering::SeqCst);
+    let pid = fork();
+    if pid == 0 {
+        close(ready_fd[0]);
+        write(ready_fd[1], b"r");
+        let mut buffer = [0u8; 4];
+        assert_eq!(read(data_fd[0], &mut buffer), EINTR);
+        assert_eq!(USR1_COUNT.load(Ordering::SeqCst), count + 1);
+        exit(0);
+    }
+    close(ready_fd[1]);
+    let mut buffer = [0u8; 1];
+    assert_eq!(read(ready_fd[0], &mut buffer), 1);
+    // give the child time to block in the read
+    sleep(10);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(wait_exit(pid), 0);
+    close(ready_fd[0]);
+    close(data_fd[0]);
+    close(data_fd[1]);
+    println!("interrupted read ok");
+}
+
+/// Fork a child that waits to be signalled
+fn spin_child() -> isize {
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    pid
+}
+
+/// A whole process group is signalled at once, and the console signals its
+/// foreground group
+fn process_groups() {
+    let leader = spin_child();
+    let member = spin_child();
+    assert_eq!(setpgid(leader as usize, 0), 0);
+    assert_eq!(setpgid(member as usize, leader as usize), 0);
+    assert_eq!(getpgid(member as usize), leader);
+    assert_ne!(getpgid(0), leader);
+    // groups can only be joined while they have tasks
+    assert_eq!(setpgid(0, 99999), EPERM);
+    assert_eq!(setpgid(99999, 0), ESRCH);
+    assert_eq!(kill(-leader, SIGTERM), 0);
+    assert_eq!(wait_exit(leader), -(SIGTERM as i32));
+    assert_eq!(wait_exit(member), -(SIGTERM as i32));
+    assert_eq!(kill(-leader, 0), ESRCH);
+
+    let foreground = tcgetpgrp(0);
+    assert!(foreground >= 0);
+    assert_eq!(tcsetpgrp(0, 99999), EPERM);
+    assert_eq!(tcsetpgrp(0, getpgid(0) as usize), 0);
+    assert_eq!(tcgetpgrp(0), getpgid(0));
+    assert_eq!(tcsetpgrp(0, foreground as usize), 0);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(tcgetpgrp(pipe_fd[0]), ENOTTY);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("process groups ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    handlers();
+    masks();
+    default_actions();
+    permissions();
+    interrupted_read();
+    process_groups();
+    println!("signal_test passed!");
+    0
+}
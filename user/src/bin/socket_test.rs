@@ -0,0 +1,141 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, connect, exit, fork, listen, pipe, read, recv, recv_fds, recvfrom, send,
+    send_fds, sendto, socket, unlink, waitpid, write, SockAddrUn, AF_UNIX, MSG_NOSIGNAL,
+    SOCK_DGRAM, SOCK_STREAM,
+};
+
+const SERVER: &str = "/tmp/socket_test\0";
+const DGRAM_A: &str = "/tmp/socket_test_a\0";
+const DGRAM_B: &str = "/tmp/socket_test_b\0";
+const PIPE_STR: &str = "sent through a passed pipe";
+
+fn wait_for(pid: isize) {
+    let mut exit_code = -1;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+/// Connect to the server in a child process and run `client` on the socket
+fn spawn_client(client: fn(usize)) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        let fd = socket(AF_UNIX, SOCK_STREAM);
+        assert!(fd >= 0);
+        assert_eq!(connect(fd as usize, SERVER), 0);
+        client(fd as usize);
+        close(fd as usize);
+        exit(0);
+    }
+    pid
+}
+
+/// A ping from the client, a pong from the server, end of file once the
+/// client is gone
+fn stream(server: usize) {
+    let pid = spawn_client(|fd| {
+        assert_eq!(send(fd, b"ping", 0), 4);
+        let mut buffer = [0u8; 16];
+        assert_eq!(recv(fd, &mut buffer), 4);
+        assert_eq!(&buffer[..4], b"pong");
+    });
+    let conn = accept(server);
+    assert!(conn >= 0);
+    let conn = conn as usize;
+    let mut buffer = [0u8; 16];
+    assert_eq!(recv(conn, &mut buffer), 4);
+    assert_eq!(&buffer[..4], b"ping");
+    assert_eq!(send(conn, b"pong", 0), 4);
+    assert_eq!(read(conn, &mut buffer), 0);
+    assert_eq!(send(conn, b"lost", MSG_NOSIGNAL), -32);
+    close(conn);
+    wait_for(pid);
+}
+
+/// The client passes the read end of a pipe, the server reads from it
+fn fd_passing(server: usize) {
+    let pid = spawn_client(|fd| {
+        let mut pipe_fd = [0usize; 2];
+        assert_eq!(pipe(&mut pipe_fd), 0);
+        assert_eq!(
+            write(pipe_fd[1], PIPE_STR.as_bytes()),
+            PIPE_STR.len() as isize
+        );
+        close(pipe_fd[1]);
+        assert_eq!(send_fds(fd, b"!", &pipe_fd[..1]), 1);
+        close(pipe_fd[0]);
+    });
+    let conn = accept(server) as usize;
+    let mut buffer = [0u8; 32];
+    let mut fds = [0usize; 4];
+    assert_eq!(recv_fds(conn, &mut buffer, &mut fds), (1, 1));
+    assert_eq!(buffer[0], b'!');
+    let len = read(fds[0], &mut buffer);
+    assert_eq!(&buffer[..len as usize], PIPE_STR.as_bytes());
+    assert_eq!(read(fds[0], &mut buffer), 0);
+    close(fds[0]);
+    close(conn);
+    wait_for(pid);
+}
+
+/// Datagrams arrive whole, with the address of the sender
+fn datagram() {
+    let a = socket(AF_UNIX, SOCK_DGRAM) as usize;
+    let b = socket(AF_UNIX, SOCK_DGRAM) as usize;
+    assert_eq!(bind(a, DGRAM_A), 0);
+    assert_eq!(bind(b, DGRAM_B), 0);
+    assert_eq!(sendto(b, b"hello", DGRAM_A), 5);
+    assert_eq!(sendto(b, b"world", DGRAM_A), 5);
+    let mut buffer = [0u8; 16];
+    let mut from = SockAddrUn::new("");
+    assert_eq!(recvfrom(a, &mut buffer, &mut from), 5);
+    assert_eq!(&buffer[..5], b"hello");
+    assert_eq!(from.path(), DGRAM_B.trim_end_matches('\0'));
+    assert_eq!(recv(a, &mut buffer[..3]), 3);
+    assert_eq!(&buffer[..3], b"wor");
+    // a stream socket can't connect to a datagram socket
+    let fd = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(connect(fd, DGRAM_A), -91);
+    close(fd);
+    close(a);
+    close(b);
+    unlink(DGRAM_A);
+    unlink(DGRAM_B);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let server = socket(AF_UNIX, SOCK_STREAM);
+    assert!(server >= 0);
+    let server = server as usize;
+    assert_eq!(bind(server, SERVER), 0);
+    assert_eq!(listen(server, 4), 0);
+    stream(server);
+    fd_passing(server);
+    datagram();
+
+    // errors
+    let fd = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(bind(fd, SERVER), -98);
+    assert_eq!(connect(fd, "/tmp/no_such_socket\0"), -2);
+    assert_eq!(connect(fd, "/tmp\0"), -111);
+    assert_eq!(recv(fd, &mut [0u8; 4]), -107);
+    close(fd);
+    assert_eq!(socket(2, SOCK_STREAM), -97);
+    assert_eq!(listen(1, 4), -88);
+    assert_eq!(listen(100, 4), -9);
+
+    // nobody listens once the server is closed
+    close(server);
+    let fd = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(connect(fd, SERVER), -111);
+    close(fd);
+    unlink(SERVER);
+    println!("socket_test passed!");
+    0
+}
@@ -0,0 +1,148 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait, exit, fork, mutex_blocking_create, mutex_create,
+    mutex_lock, mutex_unlock, semaphore_create, semaphore_down, semaphore_up, thread_create,
+    waitpid, waittid, yield_,
+};
+
+const EPERM: isize = -1;
+const EINVAL: isize = -22;
+const THREADS: usize = 4;
+const ROUNDS: usize = 50;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static READY: AtomicBool = AtomicBool::new(false);
+
+/// Increment the counter in steps that race unless mutex `mutex_id` is held
+extern "C" fn increment(mutex_id: usize) -> ! {
+    for _ in 0..ROUNDS {
+        assert_eq!(mutex_lock(mutex_id), 0);
+        let value = COUNTER.load(Ordering::SeqCst);
+        yield_();
+        COUNTER.store(value + 1, Ordering::SeqCst);
+        assert_eq!(mutex_unlock(mutex_id), 0);
+    }
+    exit(0)
+}
+
+/// Take a resource from semaphore `sem_id`, then note it
+extern "C" fn take(sem_id: usize) -> ! {
+    assert_eq!(semaphore_down(sem_id), 0);
+    COUNTER.fetch_add(1, Ordering::SeqCst);
+    exit(0)
+}
+
+/// Set the flag the main thread waits for, condvar and mutex ids packed
+/// into `ids`
+extern "C" fn set_ready(ids: usize) -> ! {
+    let (condvar_id, mutex_id) = (ids >> 16, ids & 0xffff);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    READY.store(true, Ordering::SeqCst);
+    assert_eq!(condvar_signal(condvar_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    exit(0)
+}
+
+/// Run `THREADS` threads of `entry` with `arg` and wait for them
+fn run_threads(entry: usize, arg: usize) {
+    let mut tids = [0usize; THREADS];
+    for tid in tids.iter_mut() {
+        let created = thread_create(entry, arg);
+        assert!(created > 0);
+        *tid = created as usize;
+    }
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), 0);
+    }
+}
+
+/// Both kinds of mutex keep the increments from getting lost
+fn mutexes() {
+    for mutex_id in [mutex_create(), mutex_blocking_create()] {
+        assert!(mutex_id >= 0);
+        COUNTER.store(0, Ordering::SeqCst);
+        run_threads(increment as usize, mutex_id as usize);
+        assert_eq!(COUNTER.load(Ordering::SeqCst), THREADS * ROUNDS);
+    }
+    assert_eq!(mutex_lock(99), EINVAL);
+    assert_eq!(mutex_unlock(99), EINVAL);
+    // only the holder may unlock
+    let mutex_id = mutex_blocking_create() as usize;
+    assert_eq!(mutex_unlock(mutex_id), EPERM);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), EPERM);
+    println!("mutexes ok");
+}
+
+/// Takers block until there is a resource for each of them
+fn semaphores() {
+    let sem_id = semaphore_create(1);
+    assert!(sem_id >= 0);
+    let sem_id = sem_id as usize;
+    COUNTER.store(0, Ordering::SeqCst);
+    let mut tids = [0usize; THREADS];
+    for tid in tids.iter_mut() {
+        *tid = thread_create(take as usize, sem_id) as usize;
+    }
+    for _ in 0..10 {
+        yield_();
+    }
+    assert_eq!(COUNTER.load(Ordering::SeqCst), 1);
+    for _ in 1..THREADS {
+        assert_eq!(semaphore_up(sem_id), 0);
+    }
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), 0);
+    }
+    assert_eq!(COUNTER.load(Ordering::SeqCst), THREADS);
+    assert_eq!(semaphore_down(99), EINVAL);
+    println!("semaphores ok");
+}
+
+/// A waiter sleeps with the mutex released until it's signalled
+fn condvars() {
+    let mutex_id = mutex_blocking_create() as usize;
+    let condvar_id = condvar_create();
+    assert!(condvar_id >= 0);
+    let condvar_id = condvar_id as usize;
+    assert_eq!(mutex_lock(mutex_id), 0);
+    let tid = thread_create(set_ready as usize, condvar_id << 16 | mutex_id);
+    while !READY.load(Ordering::SeqCst) {
+        assert_eq!(condvar_wait(condvar_id, mutex_id), 0);
+    }
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    assert_eq!(waittid(tid as usize), 0);
+    assert_eq!(condvar_wait(99, mutex_id), EINVAL);
+    assert_eq!(condvar_wait(condvar_id, mutex_id), EPERM);
+    println!("condvars ok");
+}
+
+/// A forked child doesn't inherit the sync objects
+fn fork_child() {
+    let mutex_id = mutex_blocking_create() as usize;
+    let pid = fork();
+    if pid == 0 {
+        exit((mutex_lock(mutex_id) == EINVAL) as i32);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 1);
+    println!("fork child ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mutexes();
+    semaphores();
+    condvars();
+    fork_child();
+    println!("sync_test passed!");
+    0
+}
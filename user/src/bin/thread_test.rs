@@ -0,0 +1,170 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, gettid, kill, mmap, munmap, thread_create, waitpid, waittid, yield_, MAP_ANONYMOUS,
+    MAP_PRIVATE, PROT_READ, PROT_WRITE, SIGTERM,
+};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 100;
+const PAGE_SIZE: usize = 4096;
+const ENOMEM: isize = -12;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static STACKS: [AtomicUsize; THREADS] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Count up, note where its stack is and exit with its tid plus 100
+extern "C" fn worker(index: usize) -> ! {
+    let local = 0usize;
+    STACKS[index].store(&local as *const _ as usize, Ordering::SeqCst);
+    for _ in 0..ROUNDS {
+        COUNTER.fetch_add(1, Ordering::SeqCst);
+        yield_();
+    }
+    exit(gettid() as i32 + 100)
+}
+
+/// Never exits on its own
+extern "C" fn spinner(_arg: usize) -> ! {
+    loop {
+        yield_();
+    }
+}
+
+/// Fork from a thread and exit with the tid the child finds
+extern "C" fn forker(_arg: usize) -> ! {
+    let tid = gettid();
+    let pid = fork();
+    if pid == 0 {
+        // the child only has this thread, with the same tid
+        exit(gettid() as i32);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code as isize, tid);
+    exit(0)
+}
+
+/// Exit with 9
+extern "C" fn quitter(_arg: usize) -> ! {
+    exit(9)
+}
+
+/// The tid of a thread that exited isn't taken by another one before it's
+/// waited for
+fn tid_kept_until_waited() {
+    let exited = thread_create(quitter as usize, 0);
+    assert!(exited > 0);
+    // let it exit
+    for _ in 0..10 {
+        yield_();
+    }
+    let tid = thread_create(quitter as usize, 0);
+    assert!(tid > 0);
+    assert_ne!(tid, exited);
+    assert_eq!(waittid(exited as usize), 9);
+    assert_eq!(waittid(tid as usize), 9);
+    println!("tid kept until waited ok");
+}
+
+/// A thread can't be created where its stack would go over a mapping
+fn stack_over_mapping() {
+    let pid = fork();
+    if pid == 0 {
+        // the stack of thread 1 is past the guard page above this one
+        let local = 0usize;
+        let top = (&local as *const _ as usize + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let addr = top + PAGE_SIZE;
+        let prot = PROT_READ | PROT_WRITE;
+        let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+        assert_eq!(mmap(addr, PAGE_SIZE, prot, flags, 0, 0), addr as isize);
+        assert_eq!(thread_create(quitter as usize, 0), ENOMEM);
+        assert_eq!(munmap(addr, PAGE_SIZE), 0);
+        let tid = thread_create(quitter as usize, 0);
+        assert!(tid > 0);
+        assert_eq!(waittid(tid as usize), 9);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("stack over mapping ok");
+}
+
+/// Threads share the address space, but each has its own tid and stack
+fn shared_memory() {
+    assert_eq!(gettid(), 0);
+    let mut tids = [0usize; THREADS];
+    for (index, tid) in tids.iter_mut().enumerate() {
+        let created = thread_create(worker as usize, index);
+        assert!(created > 0);
+        *tid = created as usize;
+    }
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), *tid as isize + 100);
+    }
+    assert_eq!(COUNTER.load(Ordering::SeqCst), THREADS * ROUNDS);
+    for (i, stack) in STACKS.iter().enumerate() {
+        for other in STACKS[..i].iter() {
+            assert_ne!(stack.load(Ordering::SeqCst), other.load(Ordering::SeqCst));
+        }
+    }
+    // reaped threads, and the caller itself, can't be waited for
+    assert_eq!(waittid(tids[0]), -1);
+    assert_eq!(waittid(0), -1);
+    println!("shared memory ok");
+}
+
+/// The process ends with its main thread, or with a signal, taking the
+/// other threads along
+fn process_exit() {
+    let pid = fork();
+    if pid == 0 {
+        assert!(thread_create(spinner as usize, 0) > 0);
+        exit(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+
+    let pid = fork();
+    if pid == 0 {
+        assert!(thread_create(spinner as usize, 0) > 0);
+        spinner(0);
+    }
+    // let both threads start
+    yield_();
+    assert_eq!(kill(pid, SIGTERM), 0);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -(SIGTERM as i32));
+    println!("process exit ok");
+}
+
+/// A child forked from a thread keeps its tid
+fn fork_from_thread() {
+    let tid = thread_create(forker as usize, 0);
+    assert!(tid > 0);
+    assert_eq!(waittid(tid as usize), 0);
+    println!("fork from thread ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    shared_memory();
+    process_exit();
+    fork_from_thread();
+    stack_over_mapping();
+    tid_kept_until_waited();
+    println!("thread_test passed!");
+    0
+}
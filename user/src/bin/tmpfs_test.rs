@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, open, read, unlink, write, OpenFlags};
+
+const ENOENT: isize = -2;
+const EEXIST: isize = -17;
+const ENOTEMPTY: isize = -39;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let test_str = "Hello, tmpfs!";
+    let filea = "/tmp/filea\0";
+    let fd = open(filea, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    // cross a page boundary to use more than one frame
+    for _ in 0..400 {
+        assert_eq!(write(fd, test_str.as_bytes()), test_str.len() as isize);
+    }
+    close(fd);
+
+    let fd = open(filea, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let mut total = 0;
+    loop {
+        let read_len = read(fd, &mut buffer[..test_str.len()]) as usize;
+        if read_len == 0 {
+            break;
+        }
+        assert_eq!(&buffer[..read_len], test_str.as_bytes());
+        total += read_len;
+    }
+    assert_eq!(total, 400 * test_str.len());
+    close(fd);
+
+    assert_eq!(mkdir("/tmp/dir\0"), 0);
+    assert_eq!(mkdir("/tmp/dir\0"), EEXIST);
+    let fd = open("/tmp/dir/fileb\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    // a directory must be empty before it's removed
+    assert_eq!(unlink("/tmp/dir\0"), ENOTEMPTY);
+    assert_eq!(unlink("/tmp/dir/fileb\0"), 0);
+    assert_eq!(unlink("/tmp/dir\0"), 0);
+    assert_eq!(unlink(filea), 0);
+    assert_eq!(open(filea, OpenFlags::RDONLY), ENOENT);
+    println!("tmpfs_test passed!");
+    0
+}
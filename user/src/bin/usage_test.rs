@@ -0,0 +1,125 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, get_time, getpid, getrusage, mmap, munmap, open, read, times, unlink,
+    waitpid, write, yield_, OpenFlags, RUsage, Tms, CLK_TCK, MAP_SHARED, PROT_READ, PROT_WRITE,
+    RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD,
+};
+
+const EINVAL: isize = -22;
+const PAGE_SIZE: usize = 4096;
+const PATH: &str = "/tmp/usage_test\0";
+const SPIN_MS: isize = 100;
+const YIELDS: usize = 10;
+
+/// Keep the CPU busy in user mode for `ms` milliseconds
+fn spin(ms: isize) {
+    let start = get_time();
+    while get_time() - start < ms {}
+}
+
+/// A busy child is charged user time and gets preempted by the timer, which
+/// the parent sees once it waited for the child
+fn busy_child() {
+    let mut before = RUsage::default();
+    assert_eq!(getrusage(RUSAGE_CHILDREN, &mut before), 0);
+    let pid = fork();
+    if pid == 0 {
+        spin(SPIN_MS);
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let mut after = RUsage::default();
+    assert_eq!(getrusage(RUSAGE_CHILDREN, &mut after), 0);
+    let user_us = after.utime.as_us() - before.utime.as_us();
+    assert!(user_us > 0 && user_us <= SPIN_MS as usize * 1000 + 20_000);
+    assert!(after.nivcsw > before.nivcsw);
+    let mut tms = Tms::default();
+    let ticks = times(&mut tms);
+    assert!(ticks > 0);
+    assert!(tms.cutime * 1_000_000 / CLK_TCK <= after.utime.as_us());
+    println!("busy child ok");
+}
+
+/// Yielding counts as giving way, for the thread and the process
+fn switches() {
+    let mut thread = RUsage::default();
+    let mut process = RUsage::default();
+    assert_eq!(getrusage(RUSAGE_THREAD, &mut thread), 0);
+    assert_eq!(getrusage(RUSAGE_SELF, &mut process), 0);
+    for _ in 0..YIELDS {
+        yield_();
+    }
+    let mut after = RUsage::default();
+    assert_eq!(getrusage(RUSAGE_THREAD, &mut after), 0);
+    assert!(after.nvcsw >= thread.nvcsw + YIELDS);
+    assert_eq!(getrusage(RUSAGE_SELF, &mut after), 0);
+    assert!(after.nvcsw >= process.nvcsw + YIELDS);
+    assert_eq!(getrusage(2, &mut after), EINVAL);
+    println!("switches ok");
+}
+
+/// The first store to a page of a file mapping is a page fault
+fn page_faults() {
+    let fd = open(
+        PATH,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, &[0u8; PAGE_SIZE]), PAGE_SIZE as isize);
+    close(fd as usize);
+    let fd = open(PATH, OpenFlags::RDWR) as usize;
+    let addr = mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    close(fd);
+    assert!(addr > 0);
+    let mut before = RUsage::default();
+    getrusage(RUSAGE_SELF, &mut before);
+    unsafe { (addr as *mut u8).write_volatile(1) };
+    let mut after = RUsage::default();
+    getrusage(RUSAGE_SELF, &mut after);
+    assert_eq!(after.minflt, before.minflt + 1);
+    assert_eq!(munmap(addr as usize, PAGE_SIZE), 0);
+    assert_eq!(unlink(PATH), 0);
+    println!("page faults ok");
+}
+
+/// `/proc/self/stat` starts with the pid and the program name, and has
+/// the CPU times as fields 14 to 17
+fn proc_stat() {
+    spin(SPIN_MS);
+    let fd = open("/proc/self/stat\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 256];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    assert!(len > 0);
+    let stat = String::from(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    assert!(stat.starts_with(format!("{} (usage_test) R ", getpid()).as_str()));
+    let fields: Vec<&str> = stat.split_whitespace().collect();
+    assert_eq!(fields.len(), 20);
+    let utime: usize = fields[13].parse().unwrap();
+    let cutime: usize = fields[15].parse().unwrap();
+    assert!(utime > 0);
+    assert!(cutime > 0);
+    println!("proc stat ok");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    busy_child();
+    switches();
+    page_faults();
+    proc_stat();
+    println!("usage_test passed!");
+    0
+}
@@ -13,37 +13,436 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
-use user_lib::console::getchar;
-use user_lib::{exec, fork, waitpid};
+use alloc::vec::Vec;
+use user_lib::{
+    close, dup2, exec, exit, exit_status, fork, get_time, getpid, getrusage, is_continued, kill,
+    open, pipe, read, setpgid, sigaction, stop_signal, tcsetpgrp, waitpid_options, OpenFlags,
+    RUsage, SignalAction, RUSAGE_CHILDREN, SIGCONT, SIGINT, SIGTSTP, SIG_IGN, WCONTINUED, WNOHANG,
+    WUNTRACED,
+};
+
+/// A word or an operator of a command line
+#[derive(PartialEq)]
+enum Token {
+    Word(String),
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `|`
+    Pipe,
+    /// `&`
+    Background,
+}
+
+/// Split `line` into words and operators, which needn't be surrounded by spaces
+fn tokenize(line: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '<' => Token::Input,
+            '>' if chars.peek() == Some(&'>') => {
+                chars.next();
+                Token::Append
+            }
+            '>' => Token::Output,
+            '|' => Token::Pipe,
+            '&' => Token::Background,
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    tokens.push(Token::Word(core::mem::take(&mut word)));
+                }
+                continue;
+            }
+            c => {
+                word.push(c);
+                continue;
+            }
+        };
+        if !word.is_empty() {
+            tokens.push(Token::Word(core::mem::take(&mut word)));
+        }
+        tokens.push(token);
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+    tokens
+}
+
+/// A program of a pipeline with its redirections, paths end with `\0`
+#[derive(Default)]
+struct Command {
+    path: String,
+    input: Option<String>,
+    /// the file and whether to append to it
+    output: Option<(String, bool)>,
+}
+
+impl Command {
+    /// Open the redirections onto stdin and stdout
+    fn redirect(&self) -> bool {
+        if let Some(input) = &self.input {
+            if !redirect_fd(input, OpenFlags::RDONLY, 0) {
+                return false;
+            }
+        }
+        if let Some((output, append)) = &self.output {
+            let mode = if *append {
+                OpenFlags::APPEND
+            } else {
+                OpenFlags::TRUNC
+            };
+            if !redirect_fd(output, OpenFlags::CREATE | OpenFlags::WRONLY | mode, 1) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Open `path` with `flags` as fd `target`
+fn redirect_fd(path: &str, flags: OpenFlags, target: usize) -> bool {
+    let fd = open(path, flags);
+    if fd < 0 {
+        println!("Shell: can't open {}", path.trim_end_matches('\0'));
+        return false;
+    }
+    dup2(fd as usize, target);
+    close(fd as usize);
+    true
+}
+
+/// Parse a command line into the commands of a pipeline, and whether it
+/// runs in the background
+fn parse(line: &str) -> Result<(Vec<Command>, bool), &'static str> {
+    let mut commands = Vec::new();
+    let mut command = Command::default();
+    let mut tokens = tokenize(line).into_iter();
+    loop {
+        match tokens.next() {
+            Some(Token::Word(word)) => {
+                if !command.path.is_empty() {
+                    // exec takes no arguments
+                    return Err("arguments are not supported");
+                }
+                command.path = word + "\0";
+            }
+            Some(redirection @ (Token::Input | Token::Output | Token::Append)) => {
+                let path = match tokens.next() {
+                    Some(Token::Word(path)) => path + "\0",
+                    _ => return Err("missing file name"),
+                };
+                match redirection {
+                    Token::Input => command.input = Some(path),
+                    _ => command.output = Some((path, redirection == Token::Append)),
+                }
+            }
+            token => {
+                if command.path.is_empty() {
+                    return Err("missing command");
+                }
+                commands.push(core::mem::take(&mut command));
+                match token {
+                    Some(Token::Pipe) => {}
+                    Some(_) if tokens.next().is_none() => return Ok((commands, true)),
+                    Some(_) => return Err("& has to end the line"),
+                    None => return Ok((commands, false)),
+                }
+            }
+        }
+    }
+}
+
+/// A pipeline run by the shell, in a process group of its own
+struct Job {
+    /// What `fg` and `bg` name the job by
+    id: usize,
+    pgid: usize,
+    /// Processes of the pipeline not reaped yet
+    pids: Vec<usize>,
+    stopped: bool,
+    line: String,
+}
+
+impl Job {
+    fn state(&self) -> &'static str {
+        if self.stopped {
+            "Stopped"
+        } else {
+            "Running"
+        }
+    }
+    /// Note what `status` reports of `pid`: a stop, a continue or an exit,
+    /// whose code is printed if `verbose`
+    fn note(&mut self, pid: usize, status: i32, verbose: bool) {
+        if stop_signal(status).is_some() {
+            self.stopped = true;
+        } else if is_continued(status) {
+            self.stopped = false;
+        } else if let Some(exit_code) = exit_status(status) {
+            self.pids.retain(|other| *other != pid);
+            if verbose {
+                println!("Shell: Process {} exited with code {}", pid, exit_code);
+            }
+        }
+    }
+    /// Reap the processes that are done and note stops and continues
+    fn poll(&mut self) {
+        for pid in self.pids.clone() {
+            let mut status = 0;
+            let options = WNOHANG | WUNTRACED | WCONTINUED;
+            if waitpid_options(pid as isize, &mut status, options) == pid as isize {
+                self.note(pid, status, false);
+            }
+        }
+    }
+    /// Wait until the job is done or stopped, it has the terminal meanwhile
+    fn wait(&mut self) {
+        while let Some(&pid) = self.pids.first() {
+            let mut status = 0;
+            if waitpid_options(pid as isize, &mut status, WUNTRACED) != pid as isize {
+                break;
+            }
+            self.note(pid, status, true);
+            if self.stopped {
+                break;
+            }
+        }
+        // take the terminal back
+        tcsetpgrp(0, getpid() as usize);
+    }
+}
+
+/// Run the commands of a pipeline, each reading what the previous one wrote.
+/// The pipeline gets a process group led by its first command, which has
+/// the terminal until they're all done or stopped unless it runs in the
+/// `background`. A pipeline that doesn't finish becomes one of `jobs`.
+fn run(commands: &[Command], line: &str, background: bool, jobs: &mut Vec<Job>) {
+    let start = get_time();
+    let mut before = RUsage::default();
+    getrusage(RUSAGE_CHILDREN, &mut before);
+    let mut pids = Vec::new();
+    // process group of the pipeline, 0 until the first command leads it
+    let mut pgid = 0;
+    // read end of the pipe from the previous command
+    let mut prev_read: Option<usize> = None;
+    for (i, command) in commands.iter().enumerate() {
+        let mut pipe_fd = [0usize; 2];
+        let has_next = i + 1 < commands.len();
+        if has_next && pipe(&mut pipe_fd) < 0 {
+            println!("Shell: can't create a pipe");
+            break;
+        }
+        let pid = fork();
+        if pid == 0 {
+            // child process
+            setpgid(0, pgid);
+            // ignored signals would stay so across exec
+            sigaction(SIGTSTP, Some(&SignalAction::default()), None);
+            if let Some(read_fd) = prev_read {
+                dup2(read_fd, 0);
+                close(read_fd);
+            }
+            if has_next {
+                close(pipe_fd[0]);
+                dup2(pipe_fd[1], 1);
+                close(pipe_fd[1]);
+            }
+            if !command.redirect() {
+                exit(-4);
+            }
+            if exec(command.path.as_str()) < 0 {
+                println!("Error when executing!");
+                exit(-4);
+            }
+            unreachable!();
+        }
+        // set here as well, so that it's done before the child gets to run
+        setpgid(pid as usize, pgid);
+        if pgid == 0 {
+            pgid = pid as usize;
+            if !background {
+                tcsetpgrp(0, pgid);
+            }
+        }
+        if let Some(read_fd) = prev_read.take() {
+            close(read_fd);
+        }
+        if has_next {
+            close(pipe_fd[1]);
+            prev_read = Some(pipe_fd[0]);
+        }
+        pids.push(pid as usize);
+    }
+    if pids.is_empty() {
+        return;
+    }
+    let mut job = Job {
+        id: jobs.last().map_or(1, |job| job.id + 1),
+        pgid,
+        pids,
+        stopped: false,
+        line: String::from(line),
+    };
+    if background {
+        println!("[{}] {}", job.id, job.pgid);
+        jobs.push(job);
+        return;
+    }
+    job.wait();
+    if job.stopped {
+        println!("");
+        println!("[{}]  Stopped\t{}", job.id, job.line);
+        jobs.push(job);
+    } else {
+        print_usage(&before, (get_time() - start) as usize);
+    }
+}
+
+/// Print the time a finished foreground job took, `real_ms` in all, and
+/// what its processes used, the children usage since `before`
+fn print_usage(before: &RUsage, real_ms: usize) {
+    let mut after = RUsage::default();
+    getrusage(RUSAGE_CHILDREN, &mut after);
+    let user_us = after.utime.as_us() - before.utime.as_us();
+    let sys_us = after.stime.as_us() - before.stime.as_us();
+    println!(
+        "Shell: real {}.{:03}s user {}.{:03}s sys {}.{:03}s, {} voluntary and {} involuntary switches, {} page faults",
+        real_ms / 1000,
+        real_ms % 1000,
+        user_us / 1_000_000,
+        user_us / 1000 % 1000,
+        sys_us / 1_000_000,
+        sys_us / 1000 % 1000,
+        after.nvcsw - before.nvcsw,
+        after.nivcsw - before.nivcsw,
+        after.minflt - before.minflt,
+    );
+}
+
+/// Tell which background jobs finished or stopped, and forget the finished
+fn report_jobs(jobs: &mut Vec<Job>) {
+    for job in jobs.iter_mut() {
+        let was_stopped = job.stopped;
+        job.poll();
+        if job.pids.is_empty() {
+            println!("[{}]  Done\t{}", job.id, job.line);
+        } else if job.stopped && !was_stopped {
+            println!("[{}]  Stopped\t{}", job.id, job.line);
+        }
+    }
+    jobs.retain(|job| !job.pids.is_empty());
+}
+
+/// Run `line` if it's one of the job control builtins `jobs`, `fg` and
+/// `bg`, which take the job as `%n` or `n`, the latest one if it's left out.
+/// Return whether it was.
+fn builtin(line: &str, jobs: &mut Vec<Job>) -> bool {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or("");
+    if !matches!(name, "jobs" | "fg" | "bg") {
+        return false;
+    }
+    if name == "jobs" {
+        for job in jobs.iter() {
+            println!("[{}]  {}\t{}", job.id, job.state(), job.line);
+        }
+        return true;
+    }
+    let index = match words.next() {
+        None => jobs.len().checked_sub(1),
+        Some(arg) => arg
+            .trim_start_matches('%')
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| jobs.iter().position(|job| job.id == id)),
+    };
+    let index = match index {
+        Some(index) => index,
+        None => {
+            println!("Shell: {}: no such job", name);
+            return true;
+        }
+    };
+    if name == "bg" {
+        let job = &mut jobs[index];
+        kill(-(job.pgid as isize), SIGCONT);
+        job.stopped = false;
+        println!("[{}] {} &", job.id, job.line);
+        return true;
+    }
+    let mut job = jobs.remove(index);
+    println!("{}", job.line);
+    tcsetpgrp(0, job.pgid);
+    kill(-(job.pgid as isize), SIGCONT);
+    job.stopped = false;
+    job.wait();
+    if job.stopped {
+        println!("");
+        println!("[{}]  Stopped\t{}", job.id, job.line);
+        jobs.insert(index, job);
+    }
+    true
+}
+
+/// Ctrl-C at the prompt only interrupts reading the line
+extern "C" fn on_interrupt(_signum: usize) {}
+
+/// The next char typed, `None` if Ctrl-C was typed instead
+fn getchar() -> Option<u8> {
+    let mut c = [0u8; 1];
+    match read(0, &mut c) {
+        1 => Some(c[0]),
+        _ => None,
+    }
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    // lead a process group of our own, which has the terminal at the prompt
+    setpgid(0, 0);
+    tcsetpgrp(0, getpid() as usize);
+    sigaction(
+        SIGINT,
+        Some(&SignalAction::new(on_interrupt as usize, 0)),
+        None,
+    );
+    // Ctrl-Z only stops the foreground job
+    sigaction(SIGTSTP, Some(&SignalAction::new(SIG_IGN, 0)), None);
+    let mut jobs: Vec<Job> = Vec::new();
     let mut line: String = String::new();
     print!(">> ");
     loop {
-        let c = getchar();
+        let c = match getchar() {
+            Some(c) => c,
+            None => {
+                // drop the line
+                println!("^C");
+                line.clear();
+                print!(">> ");
+                continue;
+            }
+        };
         match c {
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
-                    line.push('\0');
-                    let pid = fork();
-                    if pid == 0 {
-                        // child process
-                        if exec(line.as_str()) == -1 {
-                            println!("Error when executing!");
-                            return -4;
+                if !line.trim().is_empty() && !builtin(line.as_str(), &mut jobs) {
+                    match parse(line.as_str()) {
+                        Ok((commands, background)) => {
+                            let line = line.trim().trim_end_matches('&').trim_end();
+                            run(&commands, line, background, &mut jobs)
                         }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        Err(err) => println!("Shell: {}", err),
                     }
-                    line.clear();
                 }
+                report_jobs(&mut jobs);
+                line.clear();
                 print!(">> ");
             }
             BS | DL => {
@@ -9,25 +9,51 @@ extern crate user_lib;
 
 // item of TESTS : app_name(argv_0), argv_1, argv_2, argv_3, exit_code
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
+    ("deadlock_test\0", "\0", "\0", "\0", 0),
+    ("devfs_test\0", "\0", "\0", "\0", 0),
+    ("dup_test\0", "\0", "\0", "\0", 0),
+    ("exec_test\0", "\0", "\0", "\0", 0),
     ("exit\0", "\0", "\0", "\0", 0),
+    ("ext2_test\0", "\0", "\0", "\0", 0),
     ("fantastic_text\0", "\0", "\0", "\0", 0),
+    ("fat32_test\0", "\0", "\0", "\0", 0),
+    ("fifo_test\0", "\0", "\0", "\0", 0),
     ("forktest_simple\0", "\0", "\0", "\0", 0),
     ("forktest\0", "\0", "\0", "\0", 0),
     ("forktest2\0", "\0", "\0", "\0", 0),
     ("forktree\0", "\0", "\0", "\0", 0),
+    ("futex_test\0", "\0", "\0", "\0", 0),
     ("hello_world\0", "\0", "\0", "\0", 0),
+    ("initramfs_test\0", "\0", "\0", "\0", 0),
+    ("job_test\0", "\0", "\0", "\0", 0),
     ("matrix\0", "\0", "\0", "\0", 0),
+    ("mmap_test\0", "\0", "\0", "\0", 0),
+    ("perm_test\0", "\0", "\0", "\0", 0),
+    ("pipe_test\0", "\0", "\0", "\0", 0),
+    ("poll_test\0", "\0", "\0", "\0", 0),
+    ("ppid_test\0", "\0", "\0", "\0", 0),
+    ("ps\0", "\0", "\0", "\0", 0),
+    ("rlimit_test\0", "\0", "\0", "\0", 0),
+    ("signal_test\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sleep\0", "\0", "\0", "\0", 0),
+    ("socket_test\0", "\0", "\0", "\0", 0),
+    ("sync_test\0", "\0", "\0", "\0", 0),
+    ("thread_test\0", "\0", "\0", "\0", 0),
+    ("tmpfs_test\0", "\0", "\0", "\0", 0),
+    ("usage_test\0", "\0", "\0", "\0", 0),
     ("yield\0", "\0", "\0", "\0", 0),
 ];
 
-static FAIL_TESTS: &[(&str, &str, &str, &str, i32)] = &[("stack_overflow\0", "\0", "\0", "\0", -2)];
+static FAIL_TESTS: &[(&str, &str, &str, &str, i32)] =
+    &[("stack_overflow\0", "\0", "\0", "\0", -11)];
 
-use user_lib::{exec, fork, waitpid};
+use user_lib::{exec, fork, getpid, poweroff, waitpid, EXIT_SKIPPED};
 
-fn run_tests(tests: &[(&str, &str, &str, &str, i32)]) -> i32 {
+/// Run `tests`, return how many passed and how many were skipped
+fn run_tests(tests: &[(&str, &str, &str, &str, i32)]) -> (i32, i32) {
     let mut pass_num = 0;
+    let mut skip_num = 0;
     let mut arr: [*const u8; 4] = [
         core::ptr::null::<u8>(),
         core::ptr::null::<u8>(),
@@ -71,6 +97,9 @@ fn run_tests(tests: &[(&str, &str, &str, &str, i32)]) -> i32 {
             if exit_code == test.4 {
                 // summary apps with  exit_code
                 pass_num = pass_num + 1;
+            } else if exit_code == EXIT_SKIPPED {
+                skip_num += 1;
+                println!("\x1b[33mUsertests: Test {} skipped\x1b[0m", test.0);
             }
             println!(
                 "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
@@ -78,26 +107,40 @@ fn run_tests(tests: &[(&str, &str, &str, &str, i32)]) -> i32 {
             );
         }
     }
-    pass_num
+    (pass_num, skip_num)
 }
 
+/// Run the tests, as initproc with `make run TEST=1`, where they end with
+/// the machine powered off, exiting QEMU with a failure unless they passed
 #[no_mangle]
 pub fn main() -> i32 {
-    let succ_num = run_tests(SUCC_TESTS);
-    let err_num = run_tests(FAIL_TESTS);
-    if succ_num == SUCC_TESTS.len() as i32 && err_num == FAIL_TESTS.len() as i32 {
+    let exit_code = check();
+    if getpid() == 0 {
+        poweroff(exit_code != 0);
+    }
+    exit_code
+}
+
+/// Run the tests, 0 if they passed
+fn check() -> i32 {
+    let (succ_num, skip_num) = run_tests(SUCC_TESTS);
+    let (err_num, _) = run_tests(FAIL_TESTS);
+    // a skipped test isn't a failure, but it didn't pass either
+    if succ_num + skip_num == SUCC_TESTS.len() as i32 && err_num == FAIL_TESTS.len() as i32 {
         println!(
-            "{} of sueecssed apps, {} of failed apps run correctly. \nUsertests passed!",
-            SUCC_TESTS.len(),
+            "{} of sueecssed apps ({} skipped), {} of failed apps run correctly. \nUsertests passed!",
+            succ_num,
+            skip_num,
             FAIL_TESTS.len()
         );
         return 0;
     }
-    if succ_num != SUCC_TESTS.len() as i32 {
+    if succ_num + skip_num != SUCC_TESTS.len() as i32 {
         println!(
-            "all successed app_num is  {} , but only  passed {}",
+            "all successed app_num is  {} , but only  passed {} and skipped {}",
             SUCC_TESTS.len(),
-            succ_num
+            succ_num,
+            skip_num
         );
     }
     if err_num != FAIL_TESTS.len() as i32 {
@@ -6,9 +6,24 @@
 #[macro_use]
 pub mod console;
 mod lang_items;
+mod mman;
+mod net;
+mod poll;
+mod resource;
+mod signal;
+mod sync;
 mod syscall;
 
+#[macro_use]
+extern crate bitflags;
+
 use buddy_system_allocator::LockedHeap;
+pub use mman::*;
+pub use net::*;
+pub use poll::*;
+pub use resource::*;
+pub use signal::*;
+pub use sync::*;
 use syscall::*;
 
 const USER_HEAP_SIZE: usize = 16384;
@@ -39,12 +54,81 @@ fn main() -> i32 {
     panic!("Cannot find main!");
 }
 
+mod open_flags {
+    // `RDONLY` is a zero bit, only the absence of `WRONLY` and `RDWR`
+    #![allow(clippy::bad_bit_mask)]
+    use bitflags::*;
+
+    bitflags! {
+        pub struct OpenFlags: u32 {
+            const RDONLY = 0;
+            const WRONLY = 1 << 0;
+            const RDWR = 1 << 1;
+            const CREATE = 1 << 9;
+            const TRUNC = 1 << 10;
+            const APPEND = 1 << 11;
+            const CLOEXEC = 1 << 19;
+        }
+    }
+}
+pub use open_flags::OpenFlags;
+
+pub fn dup(fd: usize) -> isize {
+    sys_dup(fd)
+}
+// There is no dup2 syscall on RISC-V, it's dup3 unless both fds are the same
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    if old_fd == new_fd {
+        // only check that the fd is open
+        let fd = sys_dup(old_fd);
+        if fd < 0 {
+            return fd;
+        }
+        sys_close(fd as usize);
+        return new_fd as isize;
+    }
+    sys_dup3(old_fd, new_fd, 0)
+}
+pub fn dup3(old_fd: usize, new_fd: usize, flags: OpenFlags) -> isize {
+    sys_dup3(old_fd, new_fd, flags.bits())
+}
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdir(path)
+}
+/// Create a named pipe at `path`
+pub fn mkfifo(path: &str) -> isize {
+    const S_IFIFO: u32 = 0o010000;
+    sys_mknod(path, S_IFIFO | 0o666)
+}
+pub fn unlink(path: &str) -> isize {
+    sys_unlink(path)
+}
+/// Set the permission bits of `path`
+pub fn chmod(path: &str, mode: u32) -> isize {
+    sys_chmod(path, mode)
+}
+/// Set the owners of `path`, an id of `u32::MAX` is left as it is
+pub fn chown(path: &str, uid: u32, gid: u32) -> isize {
+    sys_chown(path, uid, gid)
+}
+pub fn open(path: &str, flags: OpenFlags) -> isize {
+    sys_open(path, flags.bits())
+}
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+pub fn pipe(pipe_fd: &mut [usize]) -> isize {
+    sys_pipe(pipe_fd)
+}
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+/// Exit code of a test that can't run where it was started, which usertests
+/// report as skipped rather than passed
+pub const EXIT_SKIPPED: i32 = 77;
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
@@ -57,6 +141,57 @@ pub fn get_time() -> isize {
 pub fn getpid() -> isize {
     sys_getpid()
 }
+/// The pid of the parent, initproc once the parent exited
+pub fn getppid() -> isize {
+    sys_getppid()
+}
+pub fn getuid() -> isize {
+    sys_getuid()
+}
+pub fn geteuid() -> isize {
+    sys_geteuid()
+}
+pub fn getgid() -> isize {
+    sys_getgid()
+}
+pub fn setuid(uid: u32) -> isize {
+    sys_setuid(uid)
+}
+pub fn setgid(gid: u32) -> isize {
+    sys_setgid(gid)
+}
+/// Move the task `pid` to process group `pgid`, 0 meaning the caller and
+/// a group led by the task
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+/// Start a new session and process group led by the caller, which mustn't
+/// lead a process group already
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+/// The foreground process group of the terminal open at `fd`
+pub fn tcgetpgrp(fd: usize) -> isize {
+    const TIOCGPGRP: usize = 0x540f;
+    let mut pgid = 0i32;
+    match sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut _ as usize) {
+        0 => pgid as isize,
+        errno => errno,
+    }
+}
+/// Make `pgid` the foreground process group of the terminal open at `fd`,
+/// the one Ctrl-C is sent to
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    const TIOCSPGRP: usize = 0x5410;
+    let pgid = pgid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgid as *const _ as usize)
+}
 pub fn fork() -> isize {
     sys_fork()
 }
@@ -65,7 +200,7 @@ pub fn exec(path: &str) -> isize {
 }
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
+        match sys_waitpid(-1, exit_code as *mut _, 0) {
             -2 => {
                 yield_();
             }
@@ -77,7 +212,7 @@ pub fn wait(exit_code: &mut i32) -> isize {
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
+        match sys_waitpid(pid as isize, exit_code as *mut _, 0) {
             -2 => {
                 yield_();
             }
@@ -86,6 +221,126 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
         }
     }
 }
+pub const WNOHANG: usize = 1;
+pub const WUNTRACED: usize = 2;
+pub const WCONTINUED: usize = 8;
+/// Like `waitpid` for `pid`, -1 meaning any child, but with `WUNTRACED` a
+/// stopped child is reported as well and with `WCONTINUED` a continued one,
+/// see [`stop_signal`] and [`is_continued`]. With either, an exit is encoded
+/// as well, see [`exit_status`]. With `WNOHANG` return 0 at once if no child
+/// changed.
+pub fn waitpid_options(pid: isize, status: &mut i32, options: usize) -> isize {
+    loop {
+        match sys_waitpid(pid, status as *mut _, options) {
+            -2 if options & WNOHANG != 0 => return 0,
+            -2 => {
+                yield_();
+            }
+            // -1 or a real pid
+            exit_pid => return exit_pid,
+        }
+    }
+}
+/// The signal that stopped the child, if `status` reports a stop
+pub fn stop_signal(status: i32) -> Option<usize> {
+    match (status & 0xff, status >> 8) {
+        (0x7f, signum) if (SIGSTOP..=SIGTTOU).contains(&(signum as usize)) => Some(signum as usize),
+        _ => None,
+    }
+}
+/// Whether `status` reports that the child continued
+pub fn is_continued(status: i32) -> bool {
+    status == 0xffff
+}
+/// The exit code of the child if `status`, from [`waitpid_options`] with
+/// `WUNTRACED` or `WCONTINUED`, reports an exit. Only the low 8 bits are
+/// kept, read as signed so that a death by a signal stays negative.
+pub fn exit_status(status: i32) -> Option<i32> {
+    match status & 0xff {
+        0 => Some((status >> 8) as i8 as i32),
+        _ => None,
+    }
+}
+const LINUX_REBOOT_MAGIC1: usize = 0xfee1_dead;
+const LINUX_REBOOT_MAGIC2: usize = 0x2812_1969;
+pub const RB_AUTOBOOT: usize = 0x0123_4567;
+pub const RB_HALT_SYSTEM: usize = 0xcdef_0123;
+pub const RB_POWER_OFF: usize = 0x4321_fedc;
+/// Restart the machine with `RB_AUTOBOOT`, or halt or power it off. Only
+/// root may, and it only returns on an error.
+pub fn reboot(cmd: usize) -> isize {
+    sys_reboot(LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2, cmd, 0)
+}
+/// Power the machine off, telling QEMU to exit with a failure if `failure`
+pub fn poweroff(failure: bool) -> isize {
+    sys_reboot(
+        LINUX_REBOOT_MAGIC1,
+        LINUX_REBOOT_MAGIC2,
+        RB_POWER_OFF,
+        failure as usize,
+    )
+}
+/// Start a thread running `entry(arg)`, which has to end with `exit`.
+/// Return its tid.
+pub fn thread_create(entry: usize, arg: usize) -> isize {
+    sys_thread_create(entry, arg)
+}
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+/// Wait for the thread `tid` of this process to exit and return its exit
+/// code, -1 if there is no such thread
+pub fn waittid(tid: usize) -> isize {
+    loop {
+        match sys_waittid(tid) {
+            -2 => {
+                yield_();
+            }
+            exit_code => return exit_code,
+        }
+    }
+}
+/// Create a mutex whose waiters yield until it's free. Return its id.
+pub fn mutex_create() -> isize {
+    sys_mutex_create(false)
+}
+/// Create a mutex whose waiters block until it's unlocked. Return its id.
+pub fn mutex_blocking_create() -> isize {
+    sys_mutex_create(true)
+}
+pub fn mutex_lock(mutex_id: usize) -> isize {
+    sys_mutex_lock(mutex_id)
+}
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
+}
+/// Create a semaphore with `res_count` resources. Return its id.
+pub fn semaphore_create(res_count: usize) -> isize {
+    sys_semaphore_create(res_count)
+}
+pub fn semaphore_up(sem_id: usize) -> isize {
+    sys_semaphore_up(sem_id)
+}
+pub fn semaphore_down(sem_id: usize) -> isize {
+    sys_semaphore_down(sem_id)
+}
+/// Create a condition variable. Return its id.
+pub fn condvar_create() -> isize {
+    sys_condvar_create()
+}
+pub fn condvar_signal(condvar_id: usize) -> isize {
+    sys_condvar_signal(condvar_id)
+}
+/// Release `mutex_id`, wait for a signal on `condvar_id` and take the mutex
+/// again. The wait may also end without a signal.
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    sys_condvar_wait(condvar_id, mutex_id)
+}
+/// Have taking a mutex or semaphore fail with -EDEADLK where waiting could
+/// go on forever, or stop that
+pub fn enable_deadlock_detect(enabled: bool) -> isize {
+    sys_enable_deadlock_detect(enabled as usize)
+}
 pub fn sleep(period_ms: usize) {
     let start = sys_get_time();
     while sys_get_time() < start + period_ms as isize {
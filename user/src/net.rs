@@ -0,0 +1,170 @@
+use super::syscall::*;
+use core::mem::size_of;
+use core::ptr;
+
+pub const AF_UNIX: usize = 1;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+pub const SOCK_CLOEXEC: usize = 0x80000;
+pub const MSG_NOSIGNAL: u32 = 0x4000;
+
+const SOL_SOCKET: i32 = 1;
+const SCM_RIGHTS: i32 = 1;
+/// Most fds `recv_fds` takes in one go
+const MAX_RECV_FDS: usize = 16;
+
+/// `struct sockaddr_un`, the address of a Unix domain socket
+#[repr(C)]
+pub struct SockAddrUn {
+    family: u16,
+    path: [u8; 108],
+}
+
+impl SockAddrUn {
+    pub fn new(path: &str) -> Self {
+        let mut addr = Self {
+            family: AF_UNIX as u16,
+            path: [0; 108],
+        };
+        // paths in this library usually end with a NUL already
+        let path = path.trim_end_matches('\0').as_bytes();
+        let len = path.len().min(addr.path.len() - 1);
+        addr.path[..len].copy_from_slice(&path[..len]);
+        addr
+    }
+    /// The path of the socket, empty for an unbound one
+    pub fn path(&self) -> &str {
+        let len = self.path.iter().position(|c| *c == 0).unwrap_or(108);
+        core::str::from_utf8(&self.path[..len]).unwrap_or("")
+    }
+    fn as_ptr(&self) -> *const u8 {
+        self as *const Self as *const u8
+    }
+}
+
+#[repr(C)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+#[repr(C)]
+struct MsgHdr {
+    name: usize,
+    name_len: u32,
+    iov: *const IoVec,
+    iov_len: usize,
+    control: *mut u8,
+    control_len: usize,
+    flags: i32,
+}
+
+#[repr(C)]
+struct CmsgHdr {
+    len: usize,
+    level: i32,
+    kind: i32,
+}
+
+/// A control message with room for `MAX_RECV_FDS` fds
+#[repr(C)]
+struct Rights {
+    header: CmsgHdr,
+    fds: [i32; MAX_RECV_FDS],
+}
+
+pub fn socket(domain: usize, kind: usize) -> isize {
+    sys_socket(domain, kind, 0)
+}
+pub fn bind(fd: usize, path: &str) -> isize {
+    let addr = SockAddrUn::new(path);
+    sys_bind(fd, addr.as_ptr(), size_of::<SockAddrUn>())
+}
+pub fn listen(fd: usize, backlog: usize) -> isize {
+    sys_listen(fd, backlog)
+}
+pub fn accept(fd: usize) -> isize {
+    sys_accept(fd, ptr::null_mut(), ptr::null_mut())
+}
+pub fn connect(fd: usize, path: &str) -> isize {
+    let addr = SockAddrUn::new(path);
+    sys_connect(fd, addr.as_ptr(), size_of::<SockAddrUn>())
+}
+pub fn send(fd: usize, buf: &[u8], flags: u32) -> isize {
+    sys_sendto(fd, buf, flags, ptr::null(), 0)
+}
+pub fn recv(fd: usize, buf: &mut [u8]) -> isize {
+    sys_recvfrom(fd, buf, 0, ptr::null_mut(), ptr::null_mut())
+}
+pub fn sendto(fd: usize, buf: &[u8], path: &str) -> isize {
+    let addr = SockAddrUn::new(path);
+    sys_sendto(fd, buf, 0, addr.as_ptr(), size_of::<SockAddrUn>())
+}
+/// Receive a message into `buf` and the address of its sender into `from`
+pub fn recvfrom(fd: usize, buf: &mut [u8], from: &mut SockAddrUn) -> isize {
+    let mut addr_len = size_of::<SockAddrUn>() as u32;
+    *from = SockAddrUn::new("");
+    sys_recvfrom(fd, buf, 0, from as *mut _ as *mut u8, &mut addr_len)
+}
+/// Send `buf` over a stream socket, passing the files open at `fds` along
+pub fn send_fds(fd: usize, buf: &[u8], fds: &[usize]) -> isize {
+    let iov = IoVec {
+        base: buf.as_ptr() as usize,
+        len: buf.len(),
+    };
+    let mut rights = Rights {
+        header: CmsgHdr {
+            len: size_of::<CmsgHdr>() + fds.len().min(MAX_RECV_FDS) * size_of::<i32>(),
+            level: SOL_SOCKET,
+            kind: SCM_RIGHTS,
+        },
+        fds: [0; MAX_RECV_FDS],
+    };
+    for (slot, fd) in rights.fds.iter_mut().zip(fds) {
+        *slot = *fd as i32;
+    }
+    let msg = MsgHdr {
+        name: 0,
+        name_len: 0,
+        iov: &iov,
+        iov_len: 1,
+        control: &mut rights as *mut _ as *mut u8,
+        control_len: rights.header.len,
+        flags: 0,
+    };
+    sys_sendmsg(fd, &msg as *const _ as *const u8, 0)
+}
+/// Receive into `buf` from a stream socket, and put the fds of the files
+/// passed along into `fds`. Return the number of bytes and the number of fds.
+pub fn recv_fds(fd: usize, buf: &mut [u8], fds: &mut [usize]) -> (isize, usize) {
+    let iov = IoVec {
+        base: buf.as_mut_ptr() as usize,
+        len: buf.len(),
+    };
+    let mut rights = Rights {
+        header: CmsgHdr {
+            len: 0,
+            level: 0,
+            kind: 0,
+        },
+        fds: [0; MAX_RECV_FDS],
+    };
+    let mut msg = MsgHdr {
+        name: 0,
+        name_len: 0,
+        iov: &iov,
+        iov_len: 1,
+        control: &mut rights as *mut _ as *mut u8,
+        control_len: size_of::<CmsgHdr>() + fds.len().min(MAX_RECV_FDS) * size_of::<i32>(),
+        flags: 0,
+    };
+    let len = sys_recvmsg(fd, &mut msg as *mut _ as *mut u8, 0);
+    if len < 0 || msg.control_len == 0 {
+        return (len, 0);
+    }
+    let count = (rights.header.len - size_of::<CmsgHdr>()) / size_of::<i32>();
+    for (slot, fd) in fds.iter_mut().zip(&rights.fds[..count]) {
+        *slot = *fd as usize;
+    }
+    (len, count)
+}
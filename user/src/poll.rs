@@ -0,0 +1,102 @@
+use super::syscall::*;
+use core::ptr;
+
+bitflags! {
+    pub struct PollEvents: u32 {
+        const IN = 1 << 0;
+        const OUT = 1 << 2;
+        const ERR = 1 << 3;
+        const HUP = 1 << 4;
+        const NVAL = 1 << 5;
+    }
+}
+
+pub const EPOLL_CLOEXEC: usize = 0x80000;
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+
+/// `struct pollfd`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: PollEvents) -> Self {
+        Self {
+            fd: fd as i32,
+            events: events.bits as i16,
+            revents: 0,
+        }
+    }
+    pub fn revents(&self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.revents as u16 as u32)
+    }
+}
+
+/// `struct epoll_event`
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+impl EpollEvent {
+    pub fn new(events: PollEvents, data: u64) -> Self {
+        Self {
+            events: events.bits,
+            data,
+        }
+    }
+    pub fn events(&self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.events)
+    }
+}
+
+#[repr(C)]
+pub(crate) struct TimeSpec {
+    sec: usize,
+    nsec: usize,
+}
+
+impl TimeSpec {
+    pub(crate) fn from_ms(ms: usize) -> Self {
+        Self {
+            sec: ms / 1000,
+            nsec: ms % 1000 * 1_000_000,
+        }
+    }
+}
+
+/// Wait for any of `fds` to be ready, at most `timeout_ms` if it isn't
+/// negative. Return the number of ready fds.
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    let timeout = TimeSpec::from_ms(timeout_ms as usize);
+    let timeout = if timeout_ms < 0 {
+        ptr::null()
+    } else {
+        &timeout as *const _ as *const u8
+    };
+    sys_ppoll(fds.as_mut_ptr() as *mut u8, fds.len(), timeout)
+}
+pub fn epoll_create(flags: usize) -> isize {
+    sys_epoll_create1(flags)
+}
+pub fn epoll_ctl(epfd: usize, op: usize, fd: usize, event: &EpollEvent) -> isize {
+    sys_epoll_ctl(epfd, op, fd, event as *const _ as *const u8)
+}
+/// Wait for the fds watched by `epfd`, at most `timeout_ms` if it isn't
+/// negative. Return the number of events put into `events`.
+pub fn epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> isize {
+    sys_epoll_pwait(
+        epfd,
+        events.as_mut_ptr() as *mut u8,
+        events.len(),
+        timeout_ms,
+    )
+}
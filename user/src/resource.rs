@@ -0,0 +1,89 @@
+use super::syscall::*;
+
+/// Clock ticks per second of [`times`]
+pub const CLK_TCK: usize = 100;
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+pub const RUSAGE_THREAD: isize = 1;
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+/// No limit
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// `struct tms`, CPU times in clock ticks
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct Tms {
+    pub utime: usize,
+    pub stime: usize,
+    pub cutime: usize,
+    pub cstime: usize,
+}
+
+/// `struct timeval`
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+impl TimeVal {
+    pub fn as_us(&self) -> usize {
+        self.sec * 1_000_000 + self.usec
+    }
+}
+
+/// `struct rusage`, the kernel only fills in the CPU times, page faults
+/// and context switches
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct RUsage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    pub maxrss: usize,
+    pub ixrss: usize,
+    pub idrss: usize,
+    pub isrss: usize,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nswap: usize,
+    pub inblock: usize,
+    pub oublock: usize,
+    pub msgsnd: usize,
+    pub msgrcv: usize,
+    pub nsignals: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+/// `struct rlimit`, the soft limit enforced and the hard limit it may be
+/// raised to
+#[repr(C)]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct RLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+/// Fill in the CPU times of the caller and of the children it waited for,
+/// return the clock ticks since boot
+pub fn times(tms: &mut Tms) -> isize {
+    sys_times(tms as *mut _ as *mut u8)
+}
+/// What `who` used, one of `RUSAGE_SELF`, `RUSAGE_CHILDREN` for the
+/// children waited for, and `RUSAGE_THREAD`
+pub fn getrusage(who: isize, usage: &mut RUsage) -> isize {
+    sys_getrusage(who, usage as *mut _ as *mut u8)
+}
+/// The limit of `resource`, one of the `RLIMIT_*`
+pub fn getrlimit(resource: usize, rlim: &mut RLimit) -> isize {
+    sys_getrlimit(resource, rlim as *mut _ as *mut u8)
+}
+/// Set the limit of `resource`, raising the hard limit takes root
+pub fn setrlimit(resource: usize, rlim: &RLimit) -> isize {
+    sys_setrlimit(resource, rlim as *const _ as *const u8)
+}
@@ -0,0 +1,96 @@
+use super::syscall::*;
+use core::ptr;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGXCPU: usize = 24;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// The signal set holding only `signum`
+pub const fn sigmask(signum: usize) -> u32 {
+    1 << signum
+}
+
+/// `struct sigaction`. A handler is an `extern "C" fn(usize)` taking the
+/// signal number, its address goes in `handler`.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub restorer: usize,
+    pub mask: u32,
+}
+
+impl SignalAction {
+    pub fn new(handler: usize, mask: u32) -> Self {
+        Self {
+            handler,
+            restorer: 0,
+            mask,
+        }
+    }
+}
+
+/// Where handlers return to
+extern "C" fn restore() -> ! {
+    sys_sigreturn();
+    unreachable!("sigreturn never returns to the restorer");
+}
+
+/// Send `signum` to the task `pid`, or to process group `-pid` if it's
+/// negative, 0 meaning the caller's group
+pub fn kill(pid: isize, signum: usize) -> isize {
+    sys_kill(pid, signum)
+}
+/// Set the action of `signum` unless `action` is `None`, and store the old
+/// one in `old_action` unless it's `None`
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    let action = action.map(|action| SignalAction {
+        restorer: restore as usize,
+        ..*action
+    });
+    sys_sigaction(
+        signum,
+        action
+            .as_ref()
+            .map_or(ptr::null(), |action| action as *const _ as *const u8),
+        old_action.map_or(ptr::null_mut(), |old| old as *mut _ as *mut u8),
+    )
+}
+/// Change the blocked signals by `how`, return the old set or a negated
+/// errno
+pub fn sigprocmask(how: usize, set: u32) -> isize {
+    let mut old = 0u32;
+    match sys_sigprocmask(how, &set, &mut old) {
+        0 => old as isize,
+        errno => errno,
+    }
+}
@@ -0,0 +1,132 @@
+use super::poll::TimeSpec;
+use super::syscall::*;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+
+/// Sleep while `word` holds `expected`, at most `timeout_ms` if it isn't
+/// negative, until a `futex_wake` on it. Return 0 when woken, -EAGAIN if
+/// `word` didn't hold `expected` and -ETIMEDOUT when the time is up.
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout_ms: isize) -> isize {
+    let timeout = TimeSpec::from_ms(timeout_ms as usize);
+    let timeout = if timeout_ms < 0 {
+        ptr::null()
+    } else {
+        &timeout as *const _ as *const u8
+    };
+    sys_futex(word.as_ptr() as usize, FUTEX_WAIT, expected, timeout)
+}
+/// Wake up to `count` threads sleeping on `word`, return how many woke
+pub fn futex_wake(word: &AtomicU32, count: usize) -> isize {
+    sys_futex(
+        word.as_ptr() as usize,
+        FUTEX_WAKE,
+        count as u32,
+        ptr::null(),
+    )
+}
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// Locked, and someone may be sleeping on the lock
+const CONTENDED: u32 = 2;
+
+/// A mutex on a futex, which only makes a syscall when the lock is contended
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // whoever takes the lock this way can't tell if it was the only
+            // sleeper, so it leaves the lock contended
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                futex_wait(&self.state, CONTENDED, -1);
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// A condition variable on a futex, whose notifications are counted so a
+/// waiter can't miss one sent between unlocking the mutex and sleeping
+pub struct Condvar {
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+        }
+    }
+    /// Release the lock of `guard` while sleeping until notified, which may
+    /// also happen spuriously, and take it again
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        futex_wait(&self.seq, seq, -1);
+        mutex.lock()
+    }
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.seq, 1);
+    }
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.seq, u32::MAX as usize);
+    }
+}